@@ -0,0 +1,33 @@
+// src/accents.rs
+//
+// Accent-insensitive matching. Index keys keep their accents; the build adds a
+// second FST (unaccented section) from accent-stripped key -> postings for every
+// key that changes when stripped, so "merida" reaches the "mérida" postings.
+// Queries look up both: ids under the exact key are exact-accent matches, ids
+// found only through the stripped form are ranked down (ranking.accent_mismatch)
+// when the query itself carried accents. Accentless input counts every match
+// as exact, since it usually just means the client could not type them.
+//
+// Stripping = NFD, drop combining marks, plus the few Latin letters that have
+// no decomposition (ø, ł, đ, ħ, æ, œ).
+
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// Accent-stripped form of an already case-folded key.
+pub fn strip(key: &str) -> String {
+    let mut out = String::with_capacity(key.len());
+    for c in key.nfd() {
+        match c {
+            c if is_combining_mark(c) => {}
+            'ø' => out.push('o'),
+            'ł' => out.push('l'),
+            'đ' => out.push('d'),
+            'ħ' => out.push('h'),
+            'æ' => out.push_str("ae"),
+            'œ' => out.push_str("oe"),
+            c => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,116 @@
+// src/admin.rs
+//
+// Admin code names: records carry raw GeoNames codes (country "US", admin1
+// "CA", admin2 "075"); `geodb build --admin1-codes admin1CodesASCII.txt
+// --admin2-codes admin2Codes.txt` resolves them to names and stores the ones
+// the DB's records use in the admin names section, as JSON:
+//   { "US.CA": "California", "US.CA.075": "San Francisco County", ... }
+// Keys are the GeoNames code column as-is (country.admin1[.admin2]). Responses
+// then carry `admin1_name` / `admin2_name` next to the codes.
+
+use anyhow::{anyhow, Context, Result};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use crate::build::{read_line_lossy, GeoRecord};
+
+fn admin1_key(country: &str, admin1: &str) -> String {
+    format!("{country}.{admin1}")
+}
+
+fn admin2_key(country: &str, admin1: &str, admin2: &str) -> String {
+    format!("{country}.{admin1}.{admin2}")
+}
+
+/// Build the section bytes from either file (both optional); codes no record
+/// refers to are dropped.
+pub fn build_section(
+    records: &[GeoRecord],
+    admin1: Option<&Path>,
+    admin2: Option<&Path>,
+) -> Result<Vec<u8>> {
+    if admin1.is_none() && admin2.is_none() {
+        return Ok(Vec::new());
+    }
+    let mut used: HashSet<String> = HashSet::new();
+    for r in records.iter().filter(|r| !r.admin1.is_empty()) {
+        used.insert(admin1_key(&r.country, &r.admin1));
+        if !r.admin2.is_empty() {
+            used.insert(admin2_key(&r.country, &r.admin1, &r.admin2));
+        }
+    }
+
+    let mut table: BTreeMap<String, String> = BTreeMap::new();
+    for path in [admin1, admin2].into_iter().flatten() {
+        read_codes(path, |code, name| {
+            if used.contains(code) {
+                table.insert(code.to_string(), name.to_string());
+            }
+        })?;
+    }
+    eprintln!(
+        "[admin] names={} (codes in use={})",
+        table.len(),
+        used.len()
+    );
+    if table.is_empty() {
+        return Ok(Vec::new());
+    }
+    Ok(serde_json::to_vec(&table)?)
+}
+
+/// code<TAB>name<TAB>asciiname<TAB>geonameid, the layout of both files.
+fn read_codes(path: &Path, mut f: impl FnMut(&str, &str)) -> Result<()> {
+    let file = File::open(path).with_context(|| format!("open {}", path.display()))?;
+    let mut r = BufReader::new(file);
+    let mut buf = Vec::new();
+    let mut n = 0;
+    while let Some(line) = read_line_lossy(&mut r, &mut buf)? {
+        n += 1;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut cols = line.split('\t');
+        let (Some(code), Some(name)) = (cols.next(), cols.next()) else {
+            return Err(anyhow!("{}:{n}: expected code<TAB>name", path.display()));
+        };
+        f(code.trim(), name.trim());
+    }
+    Ok(())
+}
+
+pub struct AdminNames {
+    table: BTreeMap<String, String>,
+}
+
+impl AdminNames {
+    /// `None` for DBs built without admin code files.
+    pub fn from_section(section: &[u8]) -> Result<Option<Self>> {
+        if section.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            table: serde_json::from_slice(section)?,
+        }))
+    }
+
+    pub fn admin1(&self, country: &str, admin1: &str) -> Option<&str> {
+        if admin1.is_empty() {
+            return None;
+        }
+        self.table
+            .get(&admin1_key(country, admin1))
+            .map(String::as_str)
+    }
+
+    pub fn admin2(&self, country: &str, admin1: &str, admin2: &str) -> Option<&str> {
+        if admin1.is_empty() || admin2.is_empty() {
+            return None;
+        }
+        self.table
+            .get(&admin2_key(country, admin1, admin2))
+            .map(String::as_str)
+    }
+}
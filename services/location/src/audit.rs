@@ -0,0 +1,402 @@
+// src/audit.rs
+//
+// Sampled query audit export for offline ranking analysis (feature "audit").
+// - Sampling is deterministic (golden-ratio sequence), so a rate of 0.01 means
+//   exactly every ~100th query, without an RNG dependency.
+// - Records are batched in a background task and written as Parquet objects to
+//   s3://, gs:// or file:// destinations, partitioned by day.
+// - Schema is PII-free: normalized key + chosen result + score factors only.
+//   No client address, headers, raw query string, or focus coordinates.
+// - Editor corrections (`POST /feedback`) go to the same sink under feedback/,
+//   unsampled, one row per (chosen, rejected) pair with both feature codes, so
+//   the ranking-prior training job can read them as pairwise preferences.
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::ranking::ScoreBreakdown;
+#[cfg(feature = "audit")]
+use crate::store;
+use crate::units::Duration;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuditConfig {
+    /// Fraction of queries exported, 0.0 (off) ..= 1.0.
+    pub sample_rate: f64,
+    /// Destination prefix, e.g. "s3://bucket/geodb-audit" or "gs://bucket/audit".
+    pub sink: Option<String>,
+    /// Rows per Parquet object.
+    pub batch_rows: usize,
+    /// Flush a partial batch after this long ("5m").
+    #[serde(alias = "flush_secs")]
+    pub flush_interval: Duration,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            sample_rate: 0.0,
+            sink: None,
+            batch_rows: 10_000,
+            flush_interval: Duration::from_secs(300),
+        }
+    }
+}
+
+impl AuditConfig {
+    /// A sink is configured. Queries are exported only when sample_rate > 0;
+    /// feedback always is.
+    pub fn enabled(&self) -> bool {
+        self.sink.is_some()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.sample_rate) {
+            return Err("sample_rate must be within 0.0..=1.0".into());
+        }
+        if self.sample_rate > 0.0 && self.sink.is_none() {
+            return Err("sample_rate > 0 requires a sink".into());
+        }
+        if self.batch_rows == 0 {
+            return Err("batch_rows must be > 0".into());
+        }
+        if self.flush_interval.is_zero() {
+            return Err("flush_interval must be > 0".into());
+        }
+        Ok(())
+    }
+}
+
+/// One sampled query. Field names are the Parquet column names.
+#[derive(Clone, Debug)]
+pub struct AuditRecord {
+    pub ts_ms: u64,
+    pub key: String,
+    pub candidates: u32,
+    pub chosen_id: Option<u32>,
+    pub score: Option<ScoreBreakdown>,
+}
+
+impl AuditRecord {
+    pub fn new(key: &str, candidates: usize, chosen: Option<(u32, ScoreBreakdown)>) -> Self {
+        Self {
+            ts_ms: now_ms(),
+            key: key.to_string(),
+            candidates: candidates as u32,
+            chosen_id: chosen.map(|c| c.0),
+            score: chosen.map(|c| c.1),
+        }
+    }
+}
+
+/// One (chosen, rejected) pair from an editor correction. `rejected_*` are
+/// None when the correction named no rejected candidates.
+#[derive(Clone, Debug)]
+pub struct FeedbackRecord {
+    pub ts_ms: u64,
+    pub key: String,
+    pub article_id: Option<String>,
+    pub chosen_id: u32,
+    pub chosen_feature: String,
+    pub rejected_id: Option<u32>,
+    pub rejected_feature: Option<String>,
+}
+
+/// Candidate as stored in feedback rows: id and feature class and code joined
+/// as "P.PPLC" (`feature_priors` is keyed by either part).
+pub struct FeedbackCandidate {
+    pub id: u32,
+    pub feature: String,
+}
+
+impl FeedbackRecord {
+    pub fn pairs(
+        key: &str,
+        article_id: Option<&str>,
+        chosen: &FeedbackCandidate,
+        rejected: &[FeedbackCandidate],
+    ) -> Vec<Self> {
+        let ts_ms = now_ms();
+        let row = |r: Option<&FeedbackCandidate>| Self {
+            ts_ms,
+            key: key.to_string(),
+            article_id: article_id.map(str::to_string),
+            chosen_id: chosen.id,
+            chosen_feature: chosen.feature.clone(),
+            rejected_id: r.map(|r| r.id),
+            rejected_feature: r.map(|r| r.feature.clone()),
+        };
+        if rejected.is_empty() {
+            return vec![row(None)];
+        }
+        rejected.iter().map(|r| row(Some(r))).collect()
+    }
+}
+
+#[derive(Clone)]
+#[cfg_attr(not(feature = "audit"), allow(dead_code))]
+pub struct Auditor {
+    rate: f64,
+    seq: Arc<AtomicU64>,
+    tx: tokio::sync::mpsc::Sender<AuditRecord>,
+    feedback_tx: tokio::sync::mpsc::Sender<FeedbackRecord>,
+}
+
+impl Auditor {
+    /// Cheap check on the request path; only call `record` when this is true.
+    pub fn should_sample(&self) -> bool {
+        let n = self.seq.fetch_add(1, Ordering::Relaxed);
+        let x = (n as f64 * 0.618_033_988_749_895).fract();
+        x < self.rate
+    }
+
+    /// Never blocks the request: drops the record if the writer is behind.
+    pub fn record(&self, rec: AuditRecord) {
+        let _ = self.tx.try_send(rec);
+    }
+
+    /// Corrections are rare and not sampled, so wait for the writer rather than drop.
+    pub async fn feedback(&self, rows: Vec<FeedbackRecord>) -> Result<()> {
+        for row in rows {
+            if self.feedback_tx.send(row).await.is_err() {
+                bail!("audit writer stopped");
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "audit"))]
+pub fn start(cfg: &AuditConfig) -> Result<Option<Auditor>> {
+    if cfg.enabled() {
+        bail!("audit sink configured but geodb was built without the `audit` feature");
+    }
+    Ok(None)
+}
+
+#[cfg(feature = "audit")]
+pub fn start(cfg: &AuditConfig) -> Result<Option<Auditor>> {
+    use anyhow::anyhow;
+
+    cfg.validate().map_err(|e| anyhow!("audit: {e}"))?;
+    if !cfg.enabled() {
+        return Ok(None);
+    }
+    let sink = cfg.sink.as_deref().unwrap_or_default();
+    let (store, prefix) = store::open(sink, "audit sink")?;
+
+    let flush_every = cfg.flush_interval.0;
+    let (tx, rx) = tokio::sync::mpsc::channel(cfg.batch_rows * 2);
+    tokio::spawn(sink::run(
+        rx,
+        store.clone(),
+        prefix.clone(),
+        cfg.batch_rows,
+        flush_every,
+        sink::encode_queries,
+    ));
+    let (feedback_tx, feedback_rx) = tokio::sync::mpsc::channel(cfg.batch_rows * 2);
+    tokio::spawn(sink::run(
+        feedback_rx,
+        store,
+        prefix.child("feedback"),
+        cfg.batch_rows,
+        flush_every,
+        sink::encode_feedback,
+    ));
+
+    eprintln!(
+        "[audit] sampling {} of queries and all feedback to {}",
+        cfg.sample_rate, sink
+    );
+    Ok(Some(Auditor {
+        rate: cfg.sample_rate,
+        seq: Arc::new(AtomicU64::new(0)),
+        tx,
+        feedback_tx,
+    }))
+}
+
+#[cfg(feature = "audit")]
+mod sink {
+    use super::*;
+    use arrow::array::{ArrayRef, Float64Array, StringArray, UInt32Array, UInt64Array};
+    use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+    use arrow::record_batch::RecordBatch;
+    use object_store::{path::Path as ObjectPath, ObjectStore};
+    use parquet::arrow::ArrowWriter;
+    use parquet::basic::Compression;
+    use parquet::file::properties::WriterProperties;
+    use std::time::Duration;
+
+    pub async fn run<T: Send + 'static>(
+        mut rx: tokio::sync::mpsc::Receiver<T>,
+        store: Arc<dyn ObjectStore>,
+        prefix: ObjectPath,
+        batch_rows: usize,
+        flush_every: Duration,
+        encode: fn(&[T]) -> Result<Vec<u8>>,
+    ) {
+        let mut buf: Vec<T> = Vec::with_capacity(batch_rows);
+        let mut tick = tokio::time::interval(flush_every);
+        let mut seq: u64 = 0;
+
+        loop {
+            let closed = tokio::select! {
+                rec = rx.recv() => match rec {
+                    Some(r) => {
+                        buf.push(r);
+                        if buf.len() < batch_rows {
+                            continue;
+                        }
+                        false
+                    }
+                    None => true,
+                },
+                _ = tick.tick() => false,
+            };
+
+            if !buf.is_empty() {
+                seq += 1;
+                let rows = std::mem::take(&mut buf);
+                let res = match encode(&rows) {
+                    Ok(bytes) => flush(&*store, &prefix, seq, bytes).await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = res {
+                    eprintln!("[audit] dropped {} rows: {e:#}", rows.len());
+                }
+            }
+            if closed {
+                break;
+            }
+        }
+    }
+
+    async fn flush(
+        store: &dyn ObjectStore,
+        prefix: &ObjectPath,
+        seq: u64,
+        bytes: Vec<u8>,
+    ) -> Result<()> {
+        let ts = now_ms();
+        let day = store::civil_date(ts / 1000);
+        let path = prefix
+            .child(format!("dt={day}"))
+            .child(format!("{ts}-{seq:06}.parquet"));
+        store.put(&path, bytes.into()).await?;
+        Ok(())
+    }
+
+    fn schema() -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("ts_ms", DataType::UInt64, false),
+            Field::new("key", DataType::Utf8, false),
+            Field::new("candidates", DataType::UInt32, false),
+            Field::new("chosen_id", DataType::UInt32, true),
+            Field::new("score_total", DataType::Float64, true),
+            Field::new("score_prior", DataType::Float64, true),
+            Field::new("score_population", DataType::Float64, true),
+            Field::new("score_distance", DataType::Float64, true),
+        ]))
+    }
+
+    pub fn encode_queries(rows: &[AuditRecord]) -> Result<Vec<u8>> {
+        let schema = schema();
+        let score = |f: fn(&ScoreBreakdown) -> f64| -> ArrayRef {
+            Arc::new(Float64Array::from(
+                rows.iter()
+                    .map(|r| r.score.as_ref().map(f))
+                    .collect::<Vec<_>>(),
+            ))
+        };
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(UInt64Array::from(
+                rows.iter().map(|r| r.ts_ms).collect::<Vec<_>>(),
+            )),
+            Arc::new(StringArray::from(
+                rows.iter().map(|r| r.key.as_str()).collect::<Vec<_>>(),
+            )),
+            Arc::new(UInt32Array::from(
+                rows.iter().map(|r| r.candidates).collect::<Vec<_>>(),
+            )),
+            Arc::new(UInt32Array::from(
+                rows.iter().map(|r| r.chosen_id).collect::<Vec<_>>(),
+            )),
+            score(|s| s.total),
+            score(|s| s.prior),
+            score(|s| s.population),
+            score(|s| s.distance),
+        ];
+        write_parquet(schema, columns)
+    }
+
+    fn feedback_schema() -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("ts_ms", DataType::UInt64, false),
+            Field::new("key", DataType::Utf8, false),
+            Field::new("article_id", DataType::Utf8, true),
+            Field::new("chosen_id", DataType::UInt32, false),
+            Field::new("chosen_feature", DataType::Utf8, false),
+            Field::new("rejected_id", DataType::UInt32, true),
+            Field::new("rejected_feature", DataType::Utf8, true),
+        ]))
+    }
+
+    pub fn encode_feedback(rows: &[FeedbackRecord]) -> Result<Vec<u8>> {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(UInt64Array::from(
+                rows.iter().map(|r| r.ts_ms).collect::<Vec<_>>(),
+            )),
+            Arc::new(StringArray::from(
+                rows.iter().map(|r| r.key.as_str()).collect::<Vec<_>>(),
+            )),
+            Arc::new(StringArray::from(
+                rows.iter()
+                    .map(|r| r.article_id.as_deref())
+                    .collect::<Vec<_>>(),
+            )),
+            Arc::new(UInt32Array::from(
+                rows.iter().map(|r| r.chosen_id).collect::<Vec<_>>(),
+            )),
+            Arc::new(StringArray::from(
+                rows.iter()
+                    .map(|r| r.chosen_feature.as_str())
+                    .collect::<Vec<_>>(),
+            )),
+            Arc::new(UInt32Array::from(
+                rows.iter().map(|r| r.rejected_id).collect::<Vec<_>>(),
+            )),
+            Arc::new(StringArray::from(
+                rows.iter()
+                    .map(|r| r.rejected_feature.as_deref())
+                    .collect::<Vec<_>>(),
+            )),
+        ];
+        write_parquet(feedback_schema(), columns)
+    }
+
+    fn write_parquet(schema: SchemaRef, columns: Vec<ArrayRef>) -> Result<Vec<u8>> {
+        let batch = RecordBatch::try_new(schema.clone(), columns)?;
+
+        let props = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let mut out: Vec<u8> = Vec::new();
+        let mut w = ArrowWriter::try_new(&mut out, schema, Some(props))?;
+        w.write(&batch)?;
+        w.close()?;
+        Ok(out)
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
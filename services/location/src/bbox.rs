@@ -0,0 +1,63 @@
+// src/bbox.rs
+//
+// `bbox=minLon,minLat,maxLon,maxLat` filter for /query and `geodb query`: the
+// globe restricts lookups to the visible viewport. Coordinates are degrees in
+// the usual WMS / GeoJSON order. A viewport across the antimeridian comes in
+// with minLon > maxLon ("170,-50,-170,-30") and wraps.
+
+use anyhow::{bail, Result};
+
+use crate::GeoRecordRef;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BBox {
+    min_lon: f32,
+    min_lat: f32,
+    max_lon: f32,
+    max_lat: f32,
+}
+
+impl BBox {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let v: Vec<f32> = spec
+            .split(',')
+            .map(|p| p.trim().parse::<f32>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| anyhow::anyhow!("expected minLon,minLat,maxLon,maxLat, got {spec:?}"))?;
+        let [min_lon, min_lat, max_lon, max_lat] = v[..] else {
+            bail!("expected minLon,minLat,maxLon,maxLat, got {spec:?}");
+        };
+        for lon in [min_lon, max_lon] {
+            if !(-180.0..=180.0).contains(&lon) {
+                bail!("longitude {lon} out of range");
+            }
+        }
+        for lat in [min_lat, max_lat] {
+            if !(-90.0..=90.0).contains(&lat) {
+                bail!("latitude {lat} out of range");
+            }
+        }
+        if min_lat > max_lat {
+            bail!("minLat {min_lat} is above maxLat {max_lat}");
+        }
+        Ok(Self {
+            min_lon,
+            min_lat,
+            max_lon,
+            max_lat,
+        })
+    }
+
+    pub fn contains(&self, rec: &GeoRecordRef<'_>) -> bool {
+        self.contains_point(rec.lat, rec.lon)
+    }
+
+    pub fn contains_point(&self, lat: f32, lon: f32) -> bool {
+        let lon = if self.min_lon <= self.max_lon {
+            (self.min_lon..=self.max_lon).contains(&lon)
+        } else {
+            lon >= self.min_lon || lon <= self.max_lon
+        };
+        lon && (self.min_lat..=self.max_lat).contains(&lat)
+    }
+}
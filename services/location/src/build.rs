@@ -5,6 +5,12 @@
 // - Still streams directly from ZIP members (no extract-to-disk).
 // - Still case-insensitive (lowercased index keys) + min_pop filtering.
 // - VERSION bumped to 2.
+// - VERSION 3: fifth section, cross-source id concordance (see concordance.rs).
+// - VERSION 4: tagged section table instead of fixed lengths (see format.rs).
+// - VERSION 5: keys are NFKC-normalized before folding (see casefold.rs).
+// - VERSION 6: records carry the IANA timezone (allCountries column 17).
+// - VERSION 7: postings lists start with an encoding tag; long ones are
+//   roaring bitmaps (see postings.rs).
 
 use anyhow::{anyhow, bail, Context, Result};
 use byteorder::{LittleEndian, WriteBytesExt};
@@ -12,18 +18,31 @@ use fst::MapBuilder;
 use rayon::prelude::*;
 use std::fs::File;
 use std::io::{BufRead, BufReader, BufWriter, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Instant;
 use zip::ZipArchive;
 
+use crate::accents;
+use crate::admin;
+use crate::casefold;
+use crate::concordance::{self, ExternalRef};
+use crate::diagnostics::{BuildReport, DiagnosticsOptions, KeyDiagnostics, SourceSummary};
+use crate::nameflags::{self, NameFlags};
+use crate::sanitize::SanitizeConfig;
+use crate::spill::{KeySink, RunMerge, SpillOptions, Spilled};
+use crate::{
+    countries, csv_source, disputed, format, h3, hierarchy, hot, labels, langs, locales, osm,
+    postings, reverse, subdivision, synonyms, tokens, transport, wof, GeoRecordRef,
+};
+
 // fast hashmaps
 use ahash::RandomState;
 use hashbrown::{HashMap, HashSet};
 use smallvec::SmallVec;
 
 pub const MAGIC: &[u8; 7] = b"GEODB1\0";
-pub const VERSION: u32 = 2;
+pub const VERSION: u32 = 7;
 
 const CHUNK_LINES: usize = 200_000;
 const ZIP_BUF_BYTES: usize = 8 * 1024 * 1024;
@@ -41,16 +60,18 @@ pub struct GeoRecord {
     pub feat_class: u8,
     pub feat_code: String,
     pub population: u32,
+    /// IANA zone ("Europe/Paris"); empty when the source has none.
+    pub timezone: String,
 }
 
-struct Progress {
+pub struct Progress {
     label: &'static str,
     start: Instant,
     every: u64,
     last_printed: AtomicU64,
 }
 impl Progress {
-    fn new(label: &'static str, every: u64) -> Self {
+    pub fn new(label: &'static str, every: u64) -> Self {
         Self {
             label,
             start: Instant::now(),
@@ -58,11 +79,11 @@ impl Progress {
             last_printed: AtomicU64::new(u64::MAX),
         }
     }
-    fn tick(&self, n: u64, extra: &str) {
+    pub fn tick(&self, n: u64, extra: &str) {
         if n == 0 {
             return;
         }
-        if self.every == 0 || (n % self.every != 0) {
+        if self.every == 0 || !n.is_multiple_of(self.every) {
             return;
         }
         let prev = self.last_printed.swap(n, Ordering::Relaxed);
@@ -77,7 +98,7 @@ impl Progress {
             extra
         );
     }
-    fn done(&self, n: u64, extra: &str) {
+    pub fn done(&self, n: u64, extra: &str) {
         eprintln!(
             "[{:<14}] {:>12}  t={:>7.2}s  DONE  {}",
             self.label,
@@ -89,30 +110,29 @@ impl Progress {
 }
 
 // Convenience types
-type FastBuildMap = HashMap<String, SmallVec<[u32; 2]>, RandomState>;
-type FastIdSet = HashSet<u32, RandomState>;
+pub type FastBuildMap = HashMap<String, SmallVec<[u32; 2]>, RandomState>;
+pub type FastIdSet = HashSet<u32, RandomState>;
 
 #[inline]
-fn norm_key(s: &str) -> Option<String> {
+pub fn norm_key(s: &str) -> Option<String> {
     let t = s.trim();
     if t.is_empty() {
         None
     } else {
-        Some(t.to_lowercase())
+        Some(casefold::fold(t))
     }
 }
 
 /// Open a specific member from a ZIP and run a function over a buffered reader for that member.
 /// Avoids extracting the uncompressed text to disk.
-fn with_zip_member<Rv>(
+pub fn with_zip_member<Rv>(
     zip_path: &Path,
     member_name: &str,
     f: impl for<'a> FnOnce(BufReader<zip::read::ZipFile<'a>>) -> Result<Rv>,
 ) -> Result<Rv> {
-    let file = File::open(zip_path)
-        .with_context(|| format!("open zip: {}", zip_path.display()))?;
-    let mut zip = ZipArchive::new(file)
-        .with_context(|| format!("read zip: {}", zip_path.display()))?;
+    let file = File::open(zip_path).with_context(|| format!("open zip: {}", zip_path.display()))?;
+    let mut zip =
+        ZipArchive::new(file).with_context(|| format!("read zip: {}", zip_path.display()))?;
 
     let member = zip
         .by_name(member_name)
@@ -122,22 +142,308 @@ fn with_zip_member<Rv>(
     f(reader)
 }
 
-pub fn build_db(all_zip: &Path, alt_zip: &Path, out_db: &Path, min_pop: u32) -> Result<()> {
-    eprintln!(
-        "[build] all={} alt={} out={} min_pop={}",
-        all_zip.display(),
-        alt_zip.display(),
-        out_db.display(),
-        min_pop
-    );
+/// Member holding the records of a GeoNames dump zip: allCountries.txt, or
+/// for the extracts (cities15000.zip, US.zip) the file named after the zip.
+pub fn geonames_member(zip_path: &Path) -> String {
+    match zip_path.file_stem().and_then(|s| s.to_str()) {
+        Some(stem) if stem != "allCountries" => format!("{stem}.txt"),
+        _ => "allCountries.txt".to_string(),
+    }
+}
+
+/* -------------------------
+   source adapters
+-------------------------- */
+
+/// What an adapter yields: records with ids already assigned, plus extra names
+/// the source carries inline (OSM name:* tags, WOF/CSV alternate names) and
+/// the external ids it knows for its records (for the concordance section).
+#[derive(Default)]
+pub struct SourceRecords {
+    pub records: Vec<GeoRecord>,
+    pub alt_names: Vec<(String, u32)>,
+    pub refs: Vec<(u32, ExternalRef)>,
+}
+
+/// A gazetteer input. Adapters own their native layout; the index and format
+/// writer below only ever see `GeoRecord`s and (name, id) pairs.
+pub trait SourceAdapter: Sync {
+    /// Short label for logs, e.g. "geonames:/data/allCountries.zip".
+    fn describe(&self) -> String;
+
+    /// Parse records with population >= `min_pop`. Sources without native u32
+    /// ids take theirs from `ids`.
+    fn load(&self, min_pop: u32, ids: &mut SyntheticIds) -> Result<SourceRecords>;
+
+    /// Names that can only be filtered once every source is loaded (GeoNames
+    /// alternateNames references ids across the whole dump). Language-tagged,
+    /// historic / colloquial and dated names also go to `lang_keys` (langs.rs,
+    /// nameflags.rs, periods.rs), external ids found on the way to `refs`.
+    /// Default: none.
+    fn merge_names(
+        &self,
+        _id_present: &FastIdSet,
+        _key_to_ids: &mut KeySink<'_>,
+        _lang_keys: &mut KeySink<'_>,
+        _refs: &mut Vec<(u32, ExternalRef)>,
+    ) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Parse `--source kind:path` (kinds: geonames, osm, wof, csv).
+pub fn parse_source(spec: &str) -> Result<Box<dyn SourceAdapter>> {
+    let (kind, path) = spec
+        .split_once(':')
+        .ok_or_else(|| anyhow!("--source expects kind:path, got {spec:?}"))?;
+    let path = PathBuf::from(path);
+    Ok(match kind {
+        "geonames" => Box::new(GeoNamesSource {
+            all: path,
+            alt: None,
+            skip: NameFlags::default(),
+        }),
+        "osm" => Box::new(osm::OsmSource { path }),
+        "wof" => Box::new(wof::WofSource { path }),
+        "csv" => Box::new(csv_source::CsvSource { path }),
+        other => bail!("unknown source kind {other:?} (geonames, osm, wof, csv)"),
+    })
+}
+
+/// Synthetic ids for sources whose native ids are not GeoNames-compatible u32s.
+/// They live in the upper half of the id space (GeoNames ids stay far below 2^31)
+/// and are a hash of (namespace, native id), so they are stable across builds.
+/// Collisions are resolved by linear probing in call order; adapters call
+/// `assign` in sorted native-id order so probing is deterministic too.
+#[derive(Default)]
+pub struct SyntheticIds {
+    used: std::collections::HashSet<u32>,
+    probes: u64,
+}
+
+impl SyntheticIds {
+    pub const BASE: u32 = 0x8000_0000;
+
+    pub fn assign(&mut self, namespace: u8, native_id: i64) -> u32 {
+        let mixed = splitmix64(((namespace as u64) << 56) ^ native_id as u64);
+        let mut id = Self::BASE | (mixed as u32 & !Self::BASE);
+        while !self.used.insert(id) {
+            self.probes += 1;
+            id = Self::BASE | (id.wrapping_add(1) & !Self::BASE);
+        }
+        id
+    }
+}
+
+pub fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// GeoNames dump: allCountries.zip (+ optional alternateNamesV2.zip).
+pub struct GeoNamesSource {
+    pub all: PathBuf,
+    pub alt: Option<PathBuf>,
+    /// Alternate names with these flags are left out (nameflags.rs).
+    pub skip: NameFlags,
+}
+
+impl SourceAdapter for GeoNamesSource {
+    fn describe(&self) -> String {
+        match &self.alt {
+            Some(alt) => format!("geonames:{} +alt:{}", self.all.display(), alt.display()),
+            None => format!("geonames:{}", self.all.display()),
+        }
+    }
+
+    fn load(&self, min_pop: u32, _ids: &mut SyntheticIds) -> Result<SourceRecords> {
+        let records = with_zip_member(&self.all, &geonames_member(&self.all), |reader| {
+            parse_allcountries_chunked_reader(reader, min_pop)
+        })?;
+        Ok(SourceRecords {
+            records,
+            ..Default::default()
+        })
+    }
+
+    fn merge_names(
+        &self,
+        id_present: &FastIdSet,
+        key_to_ids: &mut KeySink<'_>,
+        lang_keys: &mut KeySink<'_>,
+        refs: &mut Vec<(u32, ExternalRef)>,
+    ) -> Result<()> {
+        if let Some(alt) = &self.alt {
+            with_zip_member(alt, "alternateNamesV2.txt", |reader| {
+                merge_altnames_chunked_reader(
+                    reader, id_present, self.skip, key_to_ids, lang_keys, refs,
+                )
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/* -------------------------
+   build
+-------------------------- */
+
+/// Feature class / code sets. At build time (`--exclude-feature-class H,R,U
+/// --exclude-feature-code STM,STMI`) records that never enter the index, nor
+/// their alternate names; at query time (`feature_class=P&feature_code=PPL`) the
+/// candidates kept.
+#[derive(Clone, Debug, Default)]
+pub struct FeatureFilter {
+    classes: Vec<u8>,
+    codes: Vec<String>,
+}
 
-    // 1) Parse allCountries directly from ZIP
-    let records = with_zip_member(all_zip, "allCountries.txt", |reader| {
-        parse_allcountries_chunked_reader(reader, min_pop)
+impl FeatureFilter {
+    pub fn new(classes: &[String], codes: &[String]) -> Result<Self> {
+        let mut out = Self::default();
+        for c in classes.iter().map(|c| c.trim()).filter(|c| !c.is_empty()) {
+            match c.as_bytes() {
+                [b] if b.is_ascii_alphabetic() => out.classes.push(b.to_ascii_uppercase()),
+                _ => bail!("feature classes are single letters (A,H,L,P,R,S,T,U,V), got {c:?}"),
+            }
+        }
+        out.codes = codes
+            .iter()
+            .map(|c| c.trim().to_ascii_uppercase())
+            .filter(|c| !c.is_empty())
+            .collect();
+        Ok(out)
+    }
+
+    pub fn class(c: u8) -> Self {
+        Self {
+            classes: vec![c.to_ascii_uppercase()],
+            codes: Vec::new(),
+        }
+    }
+
+    pub fn codes(codes: &[&str]) -> Self {
+        Self {
+            classes: Vec::new(),
+            codes: codes.iter().map(|c| c.to_ascii_uppercase()).collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.classes.is_empty() && self.codes.is_empty()
+    }
+
+    /// Build-time sense: in any listed class or code.
+    pub fn excludes(&self, r: &GeoRecord) -> bool {
+        self.classes.contains(&r.feat_class.to_ascii_uppercase())
+            || self
+                .codes
+                .iter()
+                .any(|c| c.eq_ignore_ascii_case(&r.feat_code))
+    }
+
+    /// Query-time sense: in one of the classes (if any are listed) and one of
+    /// the codes (if any are listed).
+    pub fn admits(&self, r: &GeoRecordRef<'_>) -> bool {
+        (self.classes.is_empty() || self.classes.contains(&r.feat_class.to_ascii_uppercase()))
+            && (self.codes.is_empty()
+                || self
+                    .codes
+                    .iter()
+                    .any(|c| c.eq_ignore_ascii_case(r.feat_code)))
+    }
+}
+
+pub struct BuildOptions {
+    pub min_pop: u32,
+    pub exclude: FeatureFilter,
+    /// Dedicated pool for input decoding; None = global rayon pool.
+    pub decode_threads: Option<usize>,
+    /// H3 cell per record at this resolution (h3.rs); None = no section.
+    pub h3_resolution: Option<u8>,
+    /// Records copied into the hot section (hot.rs); 0 = none.
+    pub hot_records: usize,
+    /// GeoNames admin1CodesASCII.txt / admin2Codes.txt (admin.rs).
+    pub admin1_codes: Option<PathBuf>,
+    pub admin2_codes: Option<PathBuf>,
+    /// GeoNames countryInfo.txt (locales.rs, countries.rs).
+    pub country_info: Option<PathBuf>,
+    /// Disputed-territory labeling policy (disputed.rs).
+    pub disputed_policy: Option<PathBuf>,
+    /// GeoNames hierarchy.zip: parent chains (hierarchy.rs).
+    pub hierarchy: Option<PathBuf>,
+    /// Postings lists this long are roaring bitmaps (postings.rs); 0 = never.
+    pub roaring_threshold: usize,
+    /// Spill key maps to sorted runs on disk past a memory budget (spill.rs).
+    pub spill: Option<SpillOptions>,
+    pub diagnostics: DiagnosticsOptions,
+    pub sanitize: SanitizeConfig,
+    /// Also write a labels DB here (labels.rs).
+    pub emit_labels: Option<PathBuf>,
+}
+
+pub fn build_db(
+    sources: &[Box<dyn SourceAdapter>],
+    out_db: &Path,
+    opts: &BuildOptions,
+) -> Result<()> {
+    let min_pop = opts.min_pop;
+    eprintln!("[build] out={} min_pop={}", out_db.display(), min_pop);
+    if sources.is_empty() {
+        bail!("nothing to build: pass --all, --osm and/or --source kind:path");
+    }
+
+    let decode_pool = match opts.decode_threads {
+        Some(n) => Some(
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(n)
+                .thread_name(|i| format!("decode-{i}"))
+                .build()?,
+        ),
+        None => None,
+    };
+
+    // 1) Load every source; inline alternate names are merged after seeding
+    let mut ids = SyntheticIds::default();
+    let mut records: Vec<GeoRecord> = Vec::new();
+    let mut extra_names: Vec<(String, u32)> = Vec::new();
+    let mut refs: Vec<(u32, ExternalRef)> = Vec::new();
+    let mut report = BuildReport::default();
+    decode(decode_pool.as_ref(), || -> Result<()> {
+        for src in sources {
+            eprintln!("[source] {}", src.describe());
+            let loaded = src.load(min_pop, &mut ids)?;
+            eprintln!(
+                "[source] records={} inline_names={}",
+                loaded.records.len(),
+                loaded.alt_names.len()
+            );
+            report.sources.push(SourceSummary {
+                source: src.describe(),
+                records: loaded.records.len(),
+                inline_names: loaded.alt_names.len(),
+            });
+            records.extend(loaded.records);
+            extra_names.extend(loaded.alt_names);
+            refs.extend(loaded.refs);
+        }
+        Ok(())
     })?;
+    if ids.probes > 0 {
+        eprintln!("[source] synthetic id collisions probed={}", ids.probes);
+    }
+    if !opts.exclude.is_empty() {
+        let before = records.len();
+        records.retain(|r| !opts.exclude.excludes(r));
+        report.excluded_records = before - records.len();
+        eprintln!("[filter] excluded_records={}", report.excluded_records);
+    }
     if records.is_empty() {
-        bail!("no records parsed from allCountries (min_pop too high?)");
+        bail!("no records parsed from inputs (min_pop too high or everything excluded?)");
     }
+    opts.sanitize.records(&mut records, &mut report.sanitize);
 
     // 2) id presence set (only for kept records)
     let mut id_present: FastIdSet =
@@ -146,20 +452,28 @@ pub fn build_db(all_zip: &Path, alt_zip: &Path, out_db: &Path, min_pop: u32) ->
         id_present.insert(r.id);
     }
 
-    // 3) key -> postings
-    let mut key_to_ids: FastBuildMap =
-        HashMap::with_capacity_and_hasher(records.len() * 2, RandomState::new());
+    // 3) key -> postings; with --spill-dir, sorted runs on disk (spill.rs)
+    let spill = opts.spill.as_ref();
+    let mut key_to_ids =
+        KeySink::new("keys", spill)?.prepare(|m| opts.sanitize.keys(m, &mut report.sanitize));
+    let mut lang_keys = KeySink::new("lang_keys", spill)?.prepare(|m| {
+        for ids in m.values_mut() {
+            ids.sort_unstable();
+            ids.dedup();
+        }
+        nameflags::unflag_own_names(m, &records);
+    });
 
-    // 4) Seed from primary names (lowercased keys)
+    // 4) Seed from primary names (case-folded keys, see casefold.rs)
     {
         let prog = Progress::new("seed_names", 1_000_000);
         let mut n: u64 = 0;
         for r in &records {
             if let Some(k) = norm_key(&r.name) {
-                key_to_ids.entry(k).or_default().push(r.id);
+                key_to_ids.push(k, r.id)?;
             }
             if let Some(k) = norm_key(&r.ascii_name) {
-                key_to_ids.entry(k).or_default().push(r.id);
+                key_to_ids.push(k, r.id)?;
             }
             n += 1;
             prog.tick(n, &format!("keys={}", key_to_ids.len()));
@@ -167,64 +481,221 @@ pub fn build_db(all_zip: &Path, alt_zip: &Path, out_db: &Path, min_pop: u32) ->
         prog.done(n, &format!("keys={}", key_to_ids.len()));
     }
 
-    // 5) Merge alternate names directly from ZIP (lowercased keys)
-    with_zip_member(alt_zip, "alternateNamesV2.txt", |reader| {
-        merge_altnames_chunked_reader(reader, &id_present, &mut key_to_ids)
+    // 5) Merge alternate names: inline source names, then per-source extras
+    for (name, id) in extra_names {
+        if !id_present.contains(&id) {
+            continue;
+        }
+        if let Some(k) = norm_key(&name) {
+            key_to_ids.push(k, id)?;
+        }
+    }
+    decode(decode_pool.as_ref(), || -> Result<()> {
+        for src in sources {
+            src.merge_names(&id_present, &mut key_to_ids, &mut lang_keys, &mut refs)?;
+        }
+        Ok(())
     })?;
+    let key_to_ids = key_to_ids.finish()?;
+    let lang_keys = lang_keys.finish()?;
+
+    // 6) Concordance between source ids, per-record sections
+    eprintln!("[concordance] refs={}", refs.len());
+    let concordance = concordance::build_section(&refs, |id| id_present.contains(&id))?;
+    let synonyms = synonyms::build_section(&records)?;
+    let subdivisions = subdivision::build_section(&records)?;
+    let locales = locales::build_section(&records, opts.country_info.as_deref())?;
+    let countries = countries::build_section(&records, opts.country_info.as_deref())?;
+    let disputed = disputed::build_section(&records, opts.disputed_policy.as_deref())?;
+    let hierarchy = hierarchy::build_section(&records)?;
+    let parents = hierarchy::build_parents_section(&records, opts.hierarchy.as_deref())?;
+    let transport = transport::build_section(&records, &refs)?;
+    let spatial = reverse::build_section(&records)?;
+    let hot = hot::build_section(&records, opts.hot_records)?;
+    let admin_names = admin::build_section(
+        &records,
+        opts.admin1_codes.as_deref(),
+        opts.admin2_codes.as_deref(),
+    )?;
+    let h3_cells = match opts.h3_resolution {
+        Some(res) => h3::build_section(&records, res)?,
+        None => Vec::new(),
+    };
 
-    // 6) Sort + dedup postings
-    {
-        let prog = Progress::new("dedup", 2_000_000);
-        let mut i: u64 = 0;
-        for ids in key_to_ids.values_mut() {
-            if ids.len() > 1 {
-                ids.sort_unstable();
-                ids.dedup();
+    // 7) Key FSTs + index diagnostics
+    let mut postings = PostingsWriter::new(opts.roaring_threshold);
+    let mut fsts = match key_to_ids {
+        Spilled::Memory(mut key_to_ids) => {
+            let prog = Progress::new("dedup", 2_000_000);
+            let mut i: u64 = 0;
+            for ids in key_to_ids.values_mut() {
+                if ids.len() > 1 {
+                    ids.sort_unstable();
+                    ids.dedup();
+                }
+                i += 1;
+                prog.tick(i, "");
             }
-            i += 1;
-            prog.tick(i, "");
+            prog.done(i, &format!("keys={}", key_to_ids.len()));
+
+            let total_postings: usize = key_to_ids.values().map(|v| v.len()).sum();
+            eprintln!(
+                "[index] keys={} total_postings={} records={}",
+                key_to_ids.len(),
+                total_postings,
+                records.len()
+            );
+
+            let unaccented = unaccented_keys(&key_to_ids);
+            let tokens = tokens::build_keys(&key_to_ids);
+            report.diagnose(&records, &key_to_ids, &opts.diagnostics);
+            DbKeys {
+                keys: postings.write_keys(sorted_keys(&key_to_ids))?,
+                unaccented: postings.write_optional_keys(sorted_keys(&unaccented))?,
+                tokens: postings.write_optional_keys(sorted_keys(&tokens))?,
+                lang_names: Vec::new(),
+            }
+        }
+        Spilled::Runs(runs) => {
+            write_spilled_keys(&mut postings, runs, &records, opts, &mut report)?
         }
-        prog.done(i, &format!("keys={}", key_to_ids.len()));
+    };
+    fsts.lang_names = postings.write_optional_keys(lang_keys.into_sorted())?;
+    report.write(&opts.diagnostics.report)?;
+
+    if let Some(path) = &opts.emit_labels {
+        labels::write(path, &records)?;
     }
 
-    let total_postings: usize = key_to_ids.values().map(|v| v.len()).sum();
+    // 8) Write DB
+    finish_db(
+        out_db,
+        postings,
+        &fsts,
+        &records,
+        &[
+            (format::SECTION_CONCORDANCE, &concordance),
+            (format::SECTION_SYNONYMS, &synonyms),
+            (format::SECTION_H3, &h3_cells),
+            (format::SECTION_SPATIAL, &spatial),
+            (format::SECTION_HOT, &hot),
+            (format::SECTION_ADMIN_NAMES, &admin_names),
+            (format::SECTION_SUBDIVISIONS, &subdivisions),
+            (format::SECTION_LOCALES, &locales),
+            (format::SECTION_COUNTRIES, &countries),
+            (format::SECTION_DISPUTED, &disputed),
+            (format::SECTION_HIERARCHY, &hierarchy),
+            (format::SECTION_PARENTS, &parents),
+            (format::SECTION_TRANSPORT, &transport),
+        ],
+    )
+}
+
+/// Key FSTs of a spilled build: accent-stripped keys and tokens are collected
+/// (into sinks of their own) while the merged runs stream into the main FST.
+fn write_spilled_keys(
+    postings: &mut PostingsWriter,
+    runs: RunMerge,
+    records: &[GeoRecord],
+    opts: &BuildOptions,
+    report: &mut BuildReport,
+) -> Result<DbKeys> {
+    let spill = opts.spill.as_ref();
+    let mut unaccented = KeySink::new("unaccented", spill)?;
+    let mut token_keys = KeySink::new("tokens", spill)?;
+    let mut diag = KeyDiagnostics::new(records, &opts.diagnostics);
+    let keys = postings.write_keys(runs.map(|entry| {
+        let (k, ids) = entry?;
+        diag.observe(&k, &ids);
+        let bare = accents::strip(&k);
+        if bare != k {
+            unaccented.extend(bare, &ids)?;
+        }
+        for t in tokens::tokens(&k) {
+            token_keys.extend(t.to_string(), &ids)?;
+        }
+        Ok((k, ids))
+    }))?;
+    report.finish_diagnostics(diag, records, &opts.diagnostics);
     eprintln!(
         "[index] keys={} total_postings={} records={}",
-        key_to_ids.len(),
-        total_postings,
+        report.keys,
+        report.total_postings,
         records.len()
     );
 
-    // 7) Write DB
-    write_db(out_db, &key_to_ids, &records)?;
-    Ok(())
+    let unaccented = postings.write_optional_keys(unaccented.finish()?.into_sorted())?;
+    let tokens = postings.write_optional_keys(token_keys.finish()?.into_sorted().filter(|e| {
+        !e.as_ref()
+            .is_ok_and(|(_, ids)| ids.len() > tokens::MAX_POSTINGS)
+    }))?;
+    Ok(DbKeys {
+        keys,
+        unaccented,
+        tokens,
+        lang_names: Vec::new(),
+    })
+}
+
+/// Accent-stripped keys -> union of the postings of every key stripping to them.
+pub fn unaccented_keys(key_to_ids: &FastBuildMap) -> FastBuildMap {
+    let mut unaccented: FastBuildMap = HashMap::with_hasher(RandomState::new());
+    for (k, ids) in key_to_ids {
+        let bare = accents::strip(k);
+        if bare != *k {
+            unaccented.entry(bare).or_default().extend_from_slice(ids);
+        }
+    }
+    for ids in unaccented.values_mut() {
+        ids.sort_unstable();
+        ids.dedup();
+    }
+    eprintln!("[index] unaccented_keys={}", unaccented.len());
+    unaccented
+}
+
+/// Run source decoding on the --decode-threads pool when there is one.
+fn decode<R: Send>(pool: Option<&rayon::ThreadPool>, f: impl FnOnce() -> R + Send) -> R {
+    match pool {
+        Some(p) => p.install(f),
+        None => f(),
+    }
 }
 
 /* -------------------------
    parse allCountries (chunked + parallel per chunk)
 -------------------------- */
 
+/// One line without its terminator. Invalid UTF-8 is replaced (U+FFFD) rather
+/// than failing the whole build; sanitize counts and drops it later.
+pub fn read_line_lossy<R: BufRead>(r: &mut R, buf: &mut Vec<u8>) -> Result<Option<String>> {
+    buf.clear();
+    if r.read_until(b'\n', buf)? == 0 {
+        return Ok(None);
+    }
+    if buf.ends_with(b"\n") {
+        buf.pop();
+        if buf.ends_with(b"\r") {
+            buf.pop();
+        }
+    }
+    Ok(Some(String::from_utf8_lossy(buf).into_owned()))
+}
+
 fn parse_allcountries_chunked_reader<R: BufRead>(mut r: R, min_pop: u32) -> Result<Vec<GeoRecord>> {
     let prog = Progress::new("all_lines", 1_000_000);
     let mut out: Vec<GeoRecord> = Vec::new();
     let mut total_lines: u64 = 0;
     let mut kept: u64 = 0;
 
+    let mut buf: Vec<u8> = Vec::new();
     loop {
         let mut chunk: Vec<String> = Vec::with_capacity(CHUNK_LINES);
         for _ in 0..CHUNK_LINES {
-            let mut line = String::new();
-            let n = r.read_line(&mut line)?;
-            if n == 0 {
-                break;
+            match read_line_lossy(&mut r, &mut buf)? {
+                Some(line) => chunk.push(line),
+                None => break,
             }
-            if line.ends_with('\n') {
-                line.pop();
-                if line.ends_with('\r') {
-                    line.pop();
-                }
-            }
-            chunk.push(line);
         }
 
         if chunk.is_empty() {
@@ -249,8 +720,8 @@ fn parse_allcountries_chunked_reader<R: BufRead>(mut r: R, min_pop: u32) -> Resu
 
 // Minimal columns used (tab-separated):
 // 0 id, 1 name, 2 asciiname, 4 lat, 5 lon, 6 feat_class, 7 feat_code,
-// 8 country, 10 admin1, 11 admin2, 14 population
-fn parse_allcountries_line(line: &str, min_pop: u32) -> Result<GeoRecord> {
+// 8 country, 10 admin1, 11 admin2, 14 population, 17 timezone
+pub fn parse_allcountries_line(line: &str, min_pop: u32) -> Result<GeoRecord> {
     let mut it = line.split('\t');
 
     let id_s = it.next().ok_or_else(|| anyhow!("missing id"))?;
@@ -277,10 +748,13 @@ fn parse_allcountries_line(line: &str, min_pop: u32) -> Result<GeoRecord> {
 
     let population_s = it.next().ok_or_else(|| anyhow!("missing population"))?;
 
+    // skip 15, 16; timezone is empty in some rows and missing in old dumps
+    let timezone = it.nth(2).unwrap_or_default();
+
     let id: u32 = id_s.parse()?;
     let lat: f32 = lat_s.parse::<f32>()?;
     let lon: f32 = lon_s.parse::<f32>()?;
-    let feat_class = feat_class_s.as_bytes().get(0).copied().unwrap_or(b'?');
+    let feat_class = feat_class_s.as_bytes().first().copied().unwrap_or(b'?');
     let population: u32 = population_s.parse().unwrap_or(0);
 
     if population < min_pop {
@@ -299,6 +773,7 @@ fn parse_allcountries_line(line: &str, min_pop: u32) -> Result<GeoRecord> {
         feat_class,
         feat_code: feat_code.to_string(),
         population,
+        timezone: timezone.to_string(),
     })
 }
 
@@ -309,27 +784,24 @@ fn parse_allcountries_line(line: &str, min_pop: u32) -> Result<GeoRecord> {
 fn merge_altnames_chunked_reader<R: BufRead>(
     mut r: R,
     id_present: &FastIdSet,
-    key_to_ids: &mut FastBuildMap,
+    skip: NameFlags,
+    key_to_ids: &mut KeySink<'_>,
+    lang_keys: &mut KeySink<'_>,
+    refs: &mut Vec<(u32, ExternalRef)>,
 ) -> Result<()> {
     let prog = Progress::new("alt_lines", 1_000_000);
     let mut total_lines: u64 = 0;
     let mut kept_pairs: u64 = 0;
+    let mut skipped: u64 = 0;
 
+    let mut buf: Vec<u8> = Vec::new();
     loop {
         let mut chunk: Vec<String> = Vec::with_capacity(CHUNK_LINES);
         for _ in 0..CHUNK_LINES {
-            let mut line = String::new();
-            let n = r.read_line(&mut line)?;
-            if n == 0 {
-                break;
-            }
-            if line.ends_with('\n') {
-                line.pop();
-                if line.ends_with('\r') {
-                    line.pop();
-                }
+            match read_line_lossy(&mut r, &mut buf)? {
+                Some(line) => chunk.push(line),
+                None => break,
             }
-            chunk.push(line);
         }
 
         if chunk.is_empty() {
@@ -342,25 +814,55 @@ fn merge_altnames_chunked_reader<R: BufRead>(
             &format!("kept_pairs={} keys={}", kept_pairs, key_to_ids.len()),
         );
 
-        let pairs: Vec<(String, u32)> = chunk
+        let pairs: Vec<(String, u32, &str, NameFlags)> = chunk
             .par_iter()
             .filter_map(|line| parse_alt_pair(line, id_present).ok().flatten())
             .collect();
 
         kept_pairs += pairs.len() as u64;
-        for (k, id) in pairs {
-            key_to_ids.entry(k).or_default().push(id);
+        for (k, id, iso, flags) in pairs {
+            if flags.skipped_by(skip) {
+                skipped += 1;
+                continue;
+            }
+            match iso {
+                "wkdt" => refs.extend(concordance::wikidata_ref(&k).map(|q| (id, q))),
+                "iata" => refs.push((id, ExternalRef::Iata(k.to_ascii_uppercase()))),
+                "icao" => refs.push((id, ExternalRef::Icao(k.to_ascii_uppercase()))),
+                _ => {}
+            }
+            if let Some(lk) = langs::lang_key(iso, &k) {
+                lang_keys.push(lk, id)?;
+            }
+            for tag in flags.tags() {
+                lang_keys.push(nameflags::tag_key(tag, &k), id)?;
+            }
+            if !flags.period.is_empty() {
+                lang_keys.push(flags.period.tag_key(&k), id)?;
+            }
+            key_to_ids.push(k, id)?;
         }
     }
 
     prog.done(
         total_lines,
-        &format!("kept_pairs={} keys={}", kept_pairs, key_to_ids.len()),
+        &format!(
+            "kept_pairs={} skipped={} keys={}",
+            kept_pairs,
+            skipped,
+            key_to_ids.len()
+        ),
     );
     Ok(())
 }
 
-fn parse_alt_pair(line: &str, id_present: &FastIdSet) -> Result<Option<(String, u32)>> {
+/// (key, geoname id, isolanguage column: an ISO 639 code for most names,
+/// "wkdt" rows carry a Wikidata QID, "iata" / "icao" rows airport codes;
+/// flags columns)
+pub fn parse_alt_pair<'l>(
+    line: &'l str,
+    id_present: &FastIdSet,
+) -> Result<Option<(String, u32, &'l str, NameFlags)>> {
     let mut it = line.split('\t');
 
     let _alt_id = match it.next() {
@@ -371,7 +873,7 @@ fn parse_alt_pair(line: &str, id_present: &FastIdSet) -> Result<Option<(String,
         Some(v) => v,
         None => return Ok(None),
     };
-    let _iso = match it.next() {
+    let iso = match it.next() {
         Some(v) => v,
         None => return Ok(None),
     };
@@ -388,8 +890,9 @@ fn parse_alt_pair(line: &str, id_present: &FastIdSet) -> Result<Option<(String,
         return Ok(None);
     }
 
+    let flags = NameFlags::parse(&it.take(6).collect::<Vec<_>>());
     match norm_key(alt_name) {
-        Some(k) => Ok(Some((k, geoname_id))),
+        Some(k) => Ok(Some((k, geoname_id, iso, flags))),
         None => Ok(None),
     }
 }
@@ -398,45 +901,44 @@ fn parse_alt_pair(line: &str, id_present: &FastIdSet) -> Result<Option<(String,
    write db
 -------------------------- */
 
-fn write_db(out: &Path, key_to_ids: &FastBuildMap, records: &[GeoRecord]) -> Result<()> {
-    // keys sorted for FST builder
-    let mut keys: Vec<(&str, &SmallVec<[u32; 2]>)> =
-        key_to_ids.iter().map(|(k, v)| (k.as_str(), v)).collect();
-    keys.sort_unstable_by(|a, b| a.0.cmp(b.0));
-
-    let mut postings_blob: Vec<u8> = Vec::new();
-    let mut fst_bytes: Vec<u8> = Vec::new();
-
-    eprintln!("[fst] building for {} keys", keys.len());
-    let fst_start = Instant::now();
-    {
-        let mut b = MapBuilder::new(&mut fst_bytes)?;
-        let prog = Progress::new("post+fst", 1_000_000);
-
-        for (i, (k, ids)) in keys.iter().enumerate() {
-            let off = postings_blob.len() as u64;
-
-            let enc = encode_delta_varints(ids);
-            write_var_u32(&mut postings_blob, enc.len() as u32);
-            postings_blob.extend_from_slice(&enc);
+#[allow(clippy::too_many_arguments)]
+pub fn write_db(
+    out: &Path,
+    key_to_ids: &FastBuildMap,
+    unaccented: &FastBuildMap,
+    tokens: &FastBuildMap,
+    lang_names: &FastBuildMap,
+    records: &[GeoRecord],
+    optional: &[(u32, &[u8])],
+    roaring_threshold: usize,
+) -> Result<()> {
+    let mut postings = PostingsWriter::new(roaring_threshold);
+    let fsts = DbKeys {
+        keys: postings.write_keys(sorted_keys(key_to_ids))?,
+        unaccented: postings.write_optional_keys(sorted_keys(unaccented))?,
+        tokens: postings.write_optional_keys(sorted_keys(tokens))?,
+        lang_names: postings.write_optional_keys(sorted_keys(lang_names))?,
+    };
+    finish_db(out, postings, &fsts, records, optional)
+}
 
-            b.insert(k, off)?;
-            prog.tick(
-                i as u64,
-                &format!("keys={} post_bytes={}", i + 1, postings_blob.len()),
-            );
-        }
-        b.finish()?;
-        prog.done(keys.len() as u64, &format!("post_bytes={}", postings_blob.len()));
-    }
-    eprintln!(
-        "[fst] bytes={} build_t={:.2}s",
-        fst_bytes.len(),
-        fst_start.elapsed().as_secs_f64()
-    );
+/// FSTs of a DB; empty = section absent.
+struct DbKeys {
+    keys: Vec<u8>,
+    unaccented: Vec<u8>,
+    tokens: Vec<u8>,
+    lang_names: Vec<u8>,
+}
 
+fn finish_db(
+    out: &Path,
+    postings: PostingsWriter,
+    fsts: &DbKeys,
+    records: &[GeoRecord],
+    optional: &[(u32, &[u8])],
+) -> Result<()> {
     // records sorted by id + offsets table
-    let mut recs = records.to_vec();
+    let mut recs: Vec<&GeoRecord> = records.iter().collect();
     recs.sort_by_key(|r| r.id);
 
     let mut ids: Vec<u32> = Vec::with_capacity(recs.len());
@@ -462,35 +964,117 @@ fn write_db(out: &Path, key_to_ids: &FastBuildMap, records: &[GeoRecord]) -> Res
         offsets_blob.write_u64::<LittleEndian>(*off)?;
     }
 
-    // file layout: MAGIC + VERSION + lens + sections
+    // file layout: MAGIC + VERSION + section table + sections (see format.rs)
+    let postings_info = postings::info_section(postings.roaring_threshold);
     let mut w = BufWriter::new(File::create(out)?);
-    w.write_all(MAGIC)?;
-    w.write_u32::<LittleEndian>(VERSION)?;
-    w.write_u64::<LittleEndian>(fst_bytes.len() as u64)?;
-    w.write_u64::<LittleEndian>(postings_blob.len() as u64)?;
-    w.write_u64::<LittleEndian>(records_blob.len() as u64)?;
-    w.write_u64::<LittleEndian>(offsets_blob.len() as u64)?;
-    w.write_all(&fst_bytes)?;
-    w.write_all(&postings_blob)?;
-    w.write_all(&records_blob)?;
-    w.write_all(&offsets_blob)?;
+    let mut sections: Vec<(u32, &[u8])> = vec![
+        (format::SECTION_FST, &fsts.keys),
+        (format::SECTION_POSTINGS, &postings.blob),
+        (format::SECTION_POSTINGS_INFO, &postings_info),
+        (format::SECTION_RECORDS, &records_blob),
+        (format::SECTION_OFFSETS, &offsets_blob),
+        (format::SECTION_UNACCENTED, &fsts.unaccented),
+        (format::SECTION_TOKENS, &fsts.tokens),
+        (format::SECTION_LANG_NAMES, &fsts.lang_names),
+    ];
+    sections.extend_from_slice(optional);
+    format::write_sections(&mut w, &sections)?;
     w.flush()?;
     Ok(())
 }
 
-fn write_record(buf: &mut Vec<u8>, r: &GeoRecord) -> Result<()> {
+/// (key, ids) of `map` in key order, as the FST builder wants them.
+fn sorted_keys(map: &FastBuildMap) -> impl Iterator<Item = Result<(&str, &[u32])>> {
+    let mut keys: Vec<(&str, &[u32])> = map
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_slice()))
+        .collect();
+    keys.sort_unstable_by(|a, b| a.0.cmp(b.0));
+    keys.into_iter().map(Ok)
+}
+
+/// The postings blob all FSTs of a DB point into.
+struct PostingsWriter {
+    blob: Vec<u8>,
+    roaring_threshold: usize,
+}
+
+impl PostingsWriter {
+    fn new(roaring_threshold: usize) -> Self {
+        Self {
+            blob: Vec::new(),
+            roaring_threshold,
+        }
+    }
+
+    /// Append postings for `keys` (sorted); returns the FST of key -> offset.
+    fn write_keys<K, V>(&mut self, keys: impl Iterator<Item = Result<(K, V)>>) -> Result<Vec<u8>>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u32]>,
+    {
+        let mut fst_bytes: Vec<u8> = Vec::new();
+
+        eprintln!("[fst] building");
+        let fst_start = Instant::now();
+        let mut n: u64 = 0;
+        {
+            let mut b = MapBuilder::new(&mut fst_bytes)?;
+            let prog = Progress::new("post+fst", 1_000_000);
+
+            for entry in keys {
+                let (k, ids) = entry?;
+                let off = self.blob.len() as u64;
+
+                postings::write_list(&mut self.blob, ids.as_ref(), self.roaring_threshold)?;
+
+                b.insert(k, off)?;
+                n += 1;
+                prog.tick(n, &format!("keys={} post_bytes={}", n, self.blob.len()));
+            }
+            b.finish()?;
+            prog.done(n, &format!("post_bytes={}", self.blob.len()));
+        }
+        eprintln!(
+            "[fst] keys={} bytes={} build_t={:.2}s",
+            n,
+            fst_bytes.len(),
+            fst_start.elapsed().as_secs_f64()
+        );
+        Ok(fst_bytes)
+    }
+
+    /// write_keys, but no FST at all (an absent section) when there are no keys.
+    fn write_optional_keys<K, V>(
+        &mut self,
+        keys: impl Iterator<Item = Result<(K, V)>>,
+    ) -> Result<Vec<u8>>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u32]>,
+    {
+        let mut keys = keys.peekable();
+        if keys.peek().is_none() {
+            return Ok(Vec::new());
+        }
+        self.write_keys(keys)
+    }
+}
+
+pub fn write_record(buf: &mut Vec<u8>, r: &GeoRecord) -> Result<()> {
     buf.write_u32::<LittleEndian>(r.id)?;
     buf.write_f32::<LittleEndian>(r.lat)?;
     buf.write_f32::<LittleEndian>(r.lon)?;
     buf.write_u32::<LittleEndian>(r.population)?;
     buf.push(r.feat_class);
 
-    // Stored values keep original casing (only index keys are lowercased)
+    // Stored values keep original casing (only index keys are case-folded)
     write_lp_str(buf, &r.name);
     write_lp_str(buf, &r.country);
     write_lp_str(buf, &r.admin1);
     write_lp_str(buf, &r.admin2);
     write_lp_str(buf, &r.feat_code);
+    write_lp_str(buf, &r.timezone);
     Ok(())
 }
 
@@ -504,7 +1088,7 @@ fn write_lp_str(buf: &mut Vec<u8>, s: &str) {
    compact postings encoding
 -------------------------- */
 
-fn encode_delta_varints(ids: &[u32]) -> Vec<u8> {
+pub fn encode_delta_varints(ids: &[u32]) -> Vec<u8> {
     let mut out = Vec::new();
     let mut prev = 0u32;
     for &id in ids {
@@ -515,10 +1099,10 @@ fn encode_delta_varints(ids: &[u32]) -> Vec<u8> {
     out
 }
 
-fn write_var_u32(buf: &mut Vec<u8>, mut v: u32) {
+pub fn write_var_u32(buf: &mut Vec<u8>, mut v: u32) {
     while v >= 0x80 {
         buf.push(((v as u8) & 0x7F) | 0x80);
         v >>= 7;
     }
     buf.push(v as u8);
-}
\ No newline at end of file
+}
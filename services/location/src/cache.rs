@@ -0,0 +1,76 @@
+// src/cache.rs
+//
+// LRU caches of response bodies (/query, /suggest). News cycles hammer the
+// same few hundred keys ("gaza", "kyiv", "washington"); a hit skips postings
+// decoding, record materialization, ranking and serialization. Entries are keyed by the
+// singleflight flight key (folded key + every option, build hash, ranking
+// generation), so after a reload or a ranking change old entries are never
+// asked for again and simply age out. Failed lookups are not cached.
+// Capacity is [server] query_cache_entries in geodb.toml (0 = off); hits and
+// misses are exported on /metrics. /suggest has its own cache
+// (suggest_cache_entries), keyed by build, limit and prefix and primed with
+// the likely next keystrokes (suggest::next_prefixes).
+
+use axum::body::Bytes;
+use lru::LruCache;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+pub const DEFAULT_ENTRIES: usize = 4096;
+
+pub struct ResponseCache {
+    entries: Mutex<LruCache<String, Bytes>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl ResponseCache {
+    /// None when `capacity` is 0.
+    pub fn new(capacity: usize) -> Option<Self> {
+        Some(Self {
+            entries: Mutex::new(LruCache::new(NonZeroUsize::new(capacity)?)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        })
+    }
+
+    pub fn get(&self, key: &str) -> Option<Bytes> {
+        let hit = self
+            .entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(key)
+            .cloned();
+        let counter = if hit.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        hit
+    }
+
+    /// Like `get`, without counting or refreshing the entry.
+    pub fn contains(&self, key: &str) -> bool {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains(key)
+    }
+
+    pub fn put(&self, key: String, body: Bytes) {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .put(key, body);
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,78 @@
+// src/casefold.rs
+//
+// Index / query key folding. `str::to_lowercase` is not a caseless match:
+// - "İ" (U+0130) lowercases to "i̇" (i + U+0307), so "İzmir" never met "izmir";
+//   Turkish clients sending "IZMIR" or "ızmir" (dotless ı) missed it as well.
+// - "Σ" lowercases to "ς" or "σ" depending on position, so "ΟΔΗΣΣΟΣ" and
+//   "Οδησσος" produced different keys.
+// - "ß" stays "ß" while "STRASSE" becomes "strasse".
+// Folding here is locale-independent: every I-variant becomes "i", both sigmas
+// become "σ", ß becomes "ss", anything else goes through char-wise lowercasing
+// (which never looks at context). Index and query must fold the same way, so
+// both go through `fold`.
+//
+// Input is NFKC-normalized first, so decomposed accents ("Zu\u{308}rich"),
+// fullwidth forms ("ＰＡＲＩＳ") and ligatures ("ﬁ") give the same key as their
+// plain spellings. Accents themselves are kept; accent-insensitive matching is
+// a separate index (accents.rs).
+
+use unicode_normalization::UnicodeNormalization;
+
+const COMBINING_DOT_ABOVE: char = '\u{307}';
+
+pub fn fold(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.nfkc() {
+        match c {
+            'İ' | 'ı' => out.push('i'),
+            // decomposed İ: "I" + U+0307
+            COMBINING_DOT_ABOVE if out.ends_with('i') => {}
+            'ς' => out.push('σ'),
+            'ß' | 'ẞ' => out.push_str("ss"),
+            c => out.extend(c.to_lowercase()),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fold;
+
+    #[test]
+    fn dotted_and_dotless_i() {
+        for s in ["İzmir", "IZMIR", "izmir", "ızmir", "I\u{307}zmir"] {
+            assert_eq!(fold(s), "izmir", "{s:?}");
+        }
+        assert_eq!(fold("DİYARBAKIR"), "diyarbakir");
+        assert_eq!(fold("Diyarbakır"), "diyarbakir");
+    }
+
+    #[test]
+    fn final_sigma() {
+        assert_eq!(fold("ΟΔΗΣΣΟΣ"), "οδησσοσ");
+        assert_eq!(fold("Οδησσος"), "οδησσοσ");
+        assert_eq!(fold("Σ"), "σ");
+    }
+
+    #[test]
+    fn sharp_s() {
+        assert_eq!(fold("Straße"), fold("STRASSE"));
+        assert_eq!(fold("GROẞ"), "gross");
+    }
+
+    #[test]
+    fn compatibility_forms() {
+        assert_eq!(fold("Zu\u{308}rich"), fold("Zürich"));
+        assert_eq!(fold("São Paulo"), fold("Sa\u{303}o Paulo"));
+        assert_eq!(fold("ＰＡＲＩＳ"), "paris");
+        assert_eq!(fold("ﬁnland"), "finland");
+    }
+
+    #[test]
+    fn plain_lowercasing_unchanged() {
+        assert_eq!(fold("San José"), "san josé");
+        assert_eq!(fold("MÜNCHEN"), "münchen");
+        assert_eq!(fold("東京"), "東京");
+    }
+}
@@ -0,0 +1,232 @@
+// src/codes.rs
+//
+// Location codes partners exchange instead of lat/lon:
+// - Open Location Code ("plus code"): full codes only, e.g. "8FWR6963+4V".
+//   Short codes ("6963+4V Wien") need a reference point and are not accepted.
+// - Geohash: base32, longitude bit first. Bare geohashes look like words, so
+//   query input needs a "geohash:" / "gh:" prefix.
+// Both decode to the cell center.
+
+use crate::coords::Coordinate;
+
+/* -------------------------
+   open location code
+-------------------------- */
+
+const OLC_ALPHABET: &[u8; 20] = b"23456789CFGHJMPQRVWX";
+const OLC_SEPARATOR_POS: usize = 8;
+/// 1 / 8000 degree: resolution of the 10th digit.
+const OLC_PAIR_SCALE: f64 = 8000.0;
+
+/// 10-digit plus code (~14 m cell).
+pub fn plus_code_encode(at: Coordinate) -> String {
+    let lat = (at.lat as f64).clamp(-90.0, 90.0) + 90.0;
+    let lon = (at.lon as f64 + 180.0).rem_euclid(360.0);
+    // integer grid avoids float drift in the digit divisions
+    let mut lat_i = ((lat * OLC_PAIR_SCALE).floor() as i64).min(180 * 8000 - 1);
+    let mut lon_i = (lon * OLC_PAIR_SCALE).floor() as i64;
+
+    let mut digits = [0u8; 10];
+    for pair in (0..5).rev() {
+        digits[pair * 2] = OLC_ALPHABET[(lat_i % 20) as usize];
+        digits[pair * 2 + 1] = OLC_ALPHABET[(lon_i % 20) as usize];
+        lat_i /= 20;
+        lon_i /= 20;
+    }
+
+    let mut out = String::with_capacity(11);
+    for (i, d) in digits.iter().enumerate() {
+        if i == OLC_SEPARATOR_POS {
+            out.push('+');
+        }
+        out.push(*d as char);
+    }
+    out
+}
+
+/// Center of a full plus code; `None` for short or malformed codes.
+pub fn plus_code_decode(code: &str) -> Option<Coordinate> {
+    let code = code.trim().to_ascii_uppercase();
+    if code.find('+') != Some(OLC_SEPARATOR_POS) || code.matches('+').count() != 1 {
+        return None;
+    }
+
+    // padded codes ("8FWR0000+") cover a larger cell; padding ends the digits
+    let digits: Vec<u8> = code.bytes().filter(|b| *b != b'+').collect();
+    let unpadded = digits
+        .iter()
+        .position(|b| *b == b'0')
+        .unwrap_or(digits.len());
+    if unpadded < 2 || (unpadded % 2 == 1 && unpadded < OLC_SEPARATOR_POS) {
+        return None;
+    }
+    if digits[unpadded..].iter().any(|b| *b != b'0') {
+        return None;
+    }
+    let values: Vec<u8> = digits[..unpadded]
+        .iter()
+        .map(|b| OLC_ALPHABET.iter().position(|a| a == b).map(|v| v as u8))
+        .collect::<Option<_>>()?;
+
+    let (mut lat, mut lon) = (-90.0f64, -180.0f64);
+    let mut res = 20.0f64;
+    let pairs = values.len().min(10) / 2;
+    for p in 0..pairs {
+        lat += values[p * 2] as f64 * res;
+        lon += values[p * 2 + 1] as f64 * res;
+        if p + 1 < pairs {
+            res /= 20.0;
+        }
+    }
+    let (mut lat_res, mut lon_res) = (res, res);
+
+    // digits past 10 refine a 5 (rows) x 4 (cols) grid
+    for v in values.iter().skip(10) {
+        lat_res /= 5.0;
+        lon_res /= 4.0;
+        lat += (v / 4) as f64 * lat_res;
+        lon += (v % 4) as f64 * lon_res;
+    }
+
+    let lat = lat + lat_res / 2.0;
+    let lon = lon + lon_res / 2.0;
+    (lat.abs() <= 90.0 && lon.abs() <= 180.0).then_some(Coordinate {
+        lat: lat as f32,
+        lon: lon as f32,
+    })
+}
+
+/* -------------------------
+   geohash
+-------------------------- */
+
+const GEOHASH_ALPHABET: &[u8; 32] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+pub const GEOHASH_PRECISION: usize = 9;
+
+pub fn geohash_encode(at: Coordinate, precision: usize) -> String {
+    let (mut lat_lo, mut lat_hi) = (-90.0f64, 90.0f64);
+    let (mut lon_lo, mut lon_hi) = (-180.0f64, 180.0f64);
+    let (lat, lon) = (at.lat as f64, at.lon as f64);
+
+    let mut out = String::with_capacity(precision);
+    let mut even = true;
+    for _ in 0..precision {
+        let mut v = 0usize;
+        for _ in 0..5 {
+            let (lo, hi, x) = if even {
+                (&mut lon_lo, &mut lon_hi, lon)
+            } else {
+                (&mut lat_lo, &mut lat_hi, lat)
+            };
+            let mid = (*lo + *hi) / 2.0;
+            v <<= 1;
+            if x >= mid {
+                v |= 1;
+                *lo = mid;
+            } else {
+                *hi = mid;
+            }
+            even = !even;
+        }
+        out.push(GEOHASH_ALPHABET[v] as char);
+    }
+    out
+}
+
+pub fn geohash_decode(hash: &str) -> Option<Coordinate> {
+    let hash = hash.trim();
+    if hash.is_empty() || hash.len() > 12 {
+        return None;
+    }
+    let (mut lat_lo, mut lat_hi) = (-90.0f64, 90.0f64);
+    let (mut lon_lo, mut lon_hi) = (-180.0f64, 180.0f64);
+
+    let mut even = true;
+    for c in hash.bytes().map(|b| b.to_ascii_lowercase()) {
+        let v = GEOHASH_ALPHABET.iter().position(|a| *a == c)?;
+        for bit in (0..5).rev() {
+            let (lo, hi) = if even {
+                (&mut lon_lo, &mut lon_hi)
+            } else {
+                (&mut lat_lo, &mut lat_hi)
+            };
+            let mid = (*lo + *hi) / 2.0;
+            if (v >> bit) & 1 == 1 {
+                *lo = mid;
+            } else {
+                *hi = mid;
+            }
+            even = !even;
+        }
+    }
+
+    Some(Coordinate {
+        lat: ((lat_lo + lat_hi) / 2.0) as f32,
+        lon: ((lon_lo + lon_hi) / 2.0) as f32,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VIENNA: Coordinate = Coordinate {
+        lat: 48.2082,
+        lon: 16.3738,
+    };
+
+    #[test]
+    fn plus_codes_round_trip() {
+        let code = plus_code_encode(VIENNA);
+        assert_eq!(code.len(), 11);
+        assert!(code.starts_with("8FWR"), "{code}");
+        let c = plus_code_decode(&code).unwrap();
+        assert!((c.lat - VIENNA.lat).abs() < 1e-3 && (c.lon - VIENNA.lon).abs() < 1e-3);
+        assert_eq!(plus_code_decode(&code.to_ascii_lowercase()), Some(c));
+    }
+
+    #[test]
+    fn padded_plus_codes_decode_to_the_larger_cell() {
+        let c = plus_code_decode("8FWR0000+").unwrap();
+        assert_eq!(
+            c,
+            Coordinate {
+                lat: 48.5,
+                lon: 16.5
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_plus_codes() {
+        for code in [
+            "",
+            "6963+4V",
+            "8FWR6963",
+            "8FWR69+634V",
+            "8FWR6963+4V+",
+            "8FWR00A0+",
+            "8FWRIIII+",
+            "8+",
+        ] {
+            assert_eq!(plus_code_decode(code), None, "{code:?}");
+        }
+    }
+
+    #[test]
+    fn geohashes_round_trip() {
+        let hash = geohash_encode(VIENNA, GEOHASH_PRECISION);
+        assert_eq!(hash.len(), GEOHASH_PRECISION);
+        assert!(hash.starts_with("u2edk"), "{hash}");
+        let c = geohash_decode(&hash).unwrap();
+        assert!((c.lat - VIENNA.lat).abs() < 1e-4 && (c.lon - VIENNA.lon).abs() < 1e-4);
+        assert_eq!(geohash_decode("U2EDK8"), geohash_decode("u2edk8"));
+    }
+
+    #[test]
+    fn rejects_malformed_geohashes() {
+        for hash in ["", "  ", "u2edka", "u2ed!8", "u2edk8u2edk8u"] {
+            assert_eq!(geohash_decode(hash), None, "{hash:?}");
+        }
+    }
+}
@@ -0,0 +1,281 @@
+// src/concordance.rs
+//
+// Cross-source ID concordance (geoname_id <-> wikidata <-> WOF id <-> OSM id).
+// Build: sources report external ids per record; records sharing any external
+// id (a Wikidata QID, or a GeoNames id referenced by another source) are merged
+// into one entry with union-find. Entries that only know their own id are dropped.
+// Section layout:
+//   [u64 fst_len][fst: id string -> entry offset][entries: varint len + JSON]
+// Lookup keys: "Q64", "gn:2950159", "wof:101748799", "osm:node/240109189", and
+// "id:<geodb record id>".
+
+use anyhow::{anyhow, bail, Result};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+
+use crate::build::SyntheticIds;
+
+#[derive(Clone, Debug)]
+pub enum ExternalRef {
+    GeoNames(u32),
+    Wikidata(String),
+    Wof(i64),
+    /// "node/123" or "relation/456"
+    Osm(String),
+    /// Airport codes; not linked here, they feed the transport section
+    /// (transport.rs).
+    Iata(String),
+    Icao(String),
+}
+
+impl ExternalRef {
+    fn key(&self) -> String {
+        match self {
+            ExternalRef::GeoNames(id) => format!("gn:{id}"),
+            ExternalRef::Wikidata(q) => q.to_ascii_uppercase(),
+            ExternalRef::Wof(id) => format!("wof:{id}"),
+            ExternalRef::Osm(s) => format!("osm:{s}"),
+            ExternalRef::Iata(c) => format!("iata:{c}"),
+            ExternalRef::Icao(c) => format!("icao:{c}"),
+        }
+    }
+}
+
+/// Accepts "Q123" / "q123" as Wikidata; anything else is ignored.
+pub fn wikidata_ref(s: &str) -> Option<ExternalRef> {
+    let s = s.trim();
+    let digits = s.strip_prefix('Q').or_else(|| s.strip_prefix('q'))?;
+    (!digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()))
+        .then(|| ExternalRef::Wikidata(format!("Q{digits}")))
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ConcordanceEntry {
+    pub records: Vec<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub geonames: Vec<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub wikidata: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub wof: Vec<i64>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub osm: Vec<String>,
+}
+
+/* -------------------------
+   build
+-------------------------- */
+
+struct UnionFind {
+    parent: Vec<usize>,
+}
+
+impl UnionFind {
+    fn find(&mut self, mut x: usize) -> usize {
+        while self.parent[x] != x {
+            self.parent[x] = self.parent[self.parent[x]];
+            x = self.parent[x];
+        }
+        x
+    }
+    fn add(&mut self) -> usize {
+        self.parent.push(self.parent.len());
+        self.parent.len() - 1
+    }
+    fn union(&mut self, a: usize, b: usize) {
+        let (ra, rb) = (self.find(a), self.find(b));
+        if ra != rb {
+            self.parent[ra.max(rb)] = ra.min(rb);
+        }
+    }
+}
+
+/// Build the section bytes; `is_present` says whether a record id was kept.
+/// Returns an empty Vec when there is nothing to link.
+pub fn build_section(
+    refs: &[(u32, ExternalRef)],
+    is_present: impl Fn(u32) -> bool,
+) -> Result<Vec<u8>> {
+    if refs.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // nodes: record ids and external keys share one union-find
+    let mut node_of_record: HashMap<u32, usize> = HashMap::new();
+    let mut node_of_key: HashMap<String, usize> = HashMap::new();
+    let mut uf = UnionFind { parent: Vec::new() };
+
+    for (rec, r) in refs {
+        if !is_present(*rec) || matches!(r, ExternalRef::Iata(_) | ExternalRef::Icao(_)) {
+            continue;
+        }
+        let rn = *node_of_record.entry(*rec).or_insert_with(|| uf.add());
+        let kn = *node_of_key.entry(r.key()).or_insert_with(|| uf.add());
+        uf.union(rn, kn);
+
+        // a GeoNames id referenced by another source is that GeoNames record
+        if let ExternalRef::GeoNames(gid) = r {
+            if *gid < SyntheticIds::BASE && is_present(*gid) {
+                let gn = *node_of_record.entry(*gid).or_insert_with(|| uf.add());
+                uf.union(kn, gn);
+            }
+        }
+    }
+
+    let mut clusters: HashMap<usize, (BTreeSet<u32>, BTreeSet<String>)> = HashMap::new();
+    for (rec, n) in &node_of_record {
+        let root = uf.find(*n);
+        clusters.entry(root).or_default().0.insert(*rec);
+    }
+    for (key, n) in &node_of_key {
+        let root = uf.find(*n);
+        clusters.entry(root).or_default().1.insert(key.clone());
+    }
+
+    // (lookup key, entry offset)
+    let mut index: Vec<(String, u64)> = Vec::new();
+    let mut entries: Vec<u8> = Vec::new();
+    for (records, keys) in clusters.values() {
+        let mut e = ConcordanceEntry {
+            records: records.iter().copied().collect(),
+            ..Default::default()
+        };
+        for k in keys {
+            if let Some(v) = k.strip_prefix("gn:") {
+                e.geonames.push(v.parse()?);
+            } else if let Some(v) = k.strip_prefix("wof:") {
+                e.wof.push(v.parse()?);
+            } else if let Some(v) = k.strip_prefix("osm:") {
+                e.osm.push(v.to_string());
+            } else {
+                e.wikidata.push(k.clone());
+            }
+        }
+        for r in &e.records {
+            if *r < SyntheticIds::BASE && !e.geonames.contains(r) {
+                e.geonames.push(*r);
+            }
+        }
+        e.geonames.sort_unstable();
+
+        let sources = [!e.geonames.is_empty(), !e.wof.is_empty(), !e.osm.is_empty()]
+            .iter()
+            .filter(|x| **x)
+            .count();
+        if e.wikidata.is_empty() && sources < 2 {
+            continue;
+        }
+
+        let off = entries.len() as u64;
+        let json = serde_json::to_vec(&e)?;
+        write_var_u32(&mut entries, json.len() as u32);
+        entries.extend_from_slice(&json);
+
+        for k in keys {
+            index.push((k.clone(), off));
+        }
+        for r in &e.records {
+            index.push((format!("id:{r}"), off));
+        }
+    }
+
+    index.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    index.dedup_by(|a, b| a.0 == b.0);
+
+    let mut fst_bytes: Vec<u8> = Vec::new();
+    {
+        let mut b = fst::MapBuilder::new(&mut fst_bytes)?;
+        for (k, off) in &index {
+            b.insert(k, *off)?;
+        }
+        b.finish()?;
+    }
+
+    eprintln!(
+        "[concordance] entries_bytes={} keys={}",
+        entries.len(),
+        index.len()
+    );
+
+    let mut out = Vec::with_capacity(8 + fst_bytes.len() + entries.len());
+    out.write_u64::<LittleEndian>(fst_bytes.len() as u64)?;
+    out.extend_from_slice(&fst_bytes);
+    out.extend_from_slice(&entries);
+    Ok(out)
+}
+
+fn write_var_u32(buf: &mut Vec<u8>, mut v: u32) {
+    while v >= 0x80 {
+        buf.push(((v as u8) & 0x7F) | 0x80);
+        v >>= 7;
+    }
+    buf.push(v as u8);
+}
+
+/* -------------------------
+   read
+-------------------------- */
+
+pub struct Concordance {
+    fst: fst::Map<Vec<u8>>,
+    entries: Vec<u8>,
+}
+
+impl Concordance {
+    /// `None` for DBs built without any cross-source ids.
+    pub fn from_section(section: &[u8]) -> Result<Option<Self>> {
+        if section.is_empty() {
+            return Ok(None);
+        }
+        let mut cur = std::io::Cursor::new(section);
+        let fst_len = cur.read_u64::<LittleEndian>()? as usize;
+        if 8 + fst_len > section.len() {
+            bail!("corrupt concordance section");
+        }
+        let fst = fst::Map::new(section[8..8 + fst_len].to_vec())
+            .map_err(|e| anyhow!("concordance fst load: {e}"))?;
+        Ok(Some(Self {
+            fst,
+            entries: section[8 + fst_len..].to_vec(),
+        }))
+    }
+
+    pub fn lookup(&self, id: &str) -> Result<Option<ConcordanceEntry>> {
+        let Some(key) = normalize_lookup(id) else {
+            return Ok(None);
+        };
+        let Some(off) = self.fst.get(&key) else {
+            return Ok(None);
+        };
+        let off = off as usize;
+        if off >= self.entries.len() {
+            bail!("concordance offset out of bounds");
+        }
+        let (len, n) = crate::read_var_u32(&self.entries[off..])?;
+        let start = off + n;
+        let end = start + len as usize;
+        if end > self.entries.len() {
+            bail!("concordance entry out of bounds");
+        }
+        Ok(Some(serde_json::from_slice(&self.entries[start..end])?))
+    }
+}
+
+/// "Q64" | "gn:123" | "wof:123" | "osm:node/1" | "123" (geodb record id)
+fn normalize_lookup(id: &str) -> Option<String> {
+    let id = id.trim();
+    if let Some(ExternalRef::Wikidata(q)) = wikidata_ref(id) {
+        return Some(q);
+    }
+    if !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit()) {
+        return Some(format!("id:{id}"));
+    }
+    let (prefix, rest) = id.split_once(':')?;
+    let prefix = prefix.to_ascii_lowercase();
+    match prefix.as_str() {
+        "gn" | "wof" | "id" => Some(format!("{prefix}:{rest}")),
+        "osm" => Some(format!("osm:{}", rest.to_ascii_lowercase())),
+        _ => None,
+    }
+}
@@ -0,0 +1,105 @@
+// src/config.rs
+//
+// Optional geodb.toml. Missing file = defaults; the server writes it back when
+// an admin endpoint is asked to persist a change. Durations and sizes are
+// typed (units.rs). Every section validates itself; `check` then rejects
+// combinations that only fail once the server is under load.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::audit::AuditConfig;
+use crate::jobs::JobsConfig;
+use crate::preflight::PreflightConfig;
+use crate::ranking::RankingWeights;
+use crate::reload::ReloadConfig;
+use crate::sanitize::SanitizeConfig;
+use crate::scripting::ScriptingConfig;
+use crate::server::ServerConfig;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub ranking: RankingWeights,
+    pub audit: AuditConfig,
+    pub scripting: ScriptingConfig,
+    /// Build-time only; see sanitize.rs.
+    pub sanitize: SanitizeConfig,
+    pub jobs: JobsConfig,
+    /// `geodb preflight` only; see preflight.rs.
+    pub preflight: PreflightConfig,
+    pub server: ServerConfig,
+    /// `POST /admin/reload` guards; see reload.rs.
+    pub reload: ReloadConfig,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("read config: {}", path.display()))?;
+        let cfg: Config =
+            toml::from_str(&text).with_context(|| format!("parse config: {}", path.display()))?;
+        cfg.ranking
+            .validate()
+            .map_err(|e| anyhow::anyhow!("config {}: ranking: {e}", path.display()))?;
+        cfg.audit
+            .validate()
+            .map_err(|e| anyhow::anyhow!("config {}: audit: {e}", path.display()))?;
+        cfg.sanitize
+            .validate()
+            .map_err(|e| anyhow::anyhow!("config {}: sanitize: {e}", path.display()))?;
+        cfg.jobs
+            .validate()
+            .map_err(|e| anyhow::anyhow!("config {}: jobs: {e}", path.display()))?;
+        cfg.preflight
+            .validate()
+            .map_err(|e| anyhow::anyhow!("config {}: preflight: {e}", path.display()))?;
+        cfg.server
+            .validate()
+            .map_err(|e| anyhow::anyhow!("config {}: server: {e}", path.display()))?;
+        cfg.reload
+            .validate()
+            .map_err(|e| anyhow::anyhow!("config {}: reload: {e}", path.display()))?;
+        cfg.check()
+            .map_err(|e| anyhow::anyhow!("config {}: {e}", path.display()))?;
+        Ok(cfg)
+    }
+
+    /// Cross-section constraints. `geodb serve` runs this again after its
+    /// flags are applied.
+    pub fn check(&self) -> Result<(), String> {
+        if let Some(limit) = self.server.memory_limit {
+            if self.reload.min_free >= limit {
+                return Err(format!(
+                    "reload.min_free ({}) must be below server.memory_limit ({limit}); no \
+                     reload could ever pass",
+                    self.reload.min_free
+                ));
+            }
+        }
+        if let Some(n) = self.server.blocking_threads {
+            if self.jobs.max_active >= n {
+                return Err(format!(
+                    "jobs.max_active ({}) must be below server.blocking_threads ({n}); running \
+                     jobs would leave no threads for /query/batch, /extract or reloads",
+                    self.jobs.max_active
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Write atomically (tmp file + rename) so a crash never leaves half a config.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let text = toml::to_string_pretty(self)?;
+        let tmp = path.with_extension("toml.tmp");
+        std::fs::write(&tmp, text).with_context(|| format!("write config: {}", tmp.display()))?;
+        std::fs::rename(&tmp, path)
+            .with_context(|| format!("replace config: {}", path.display()))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,287 @@
+// src/coords.rs
+//
+// Recognize coordinates pasted into the search box so /query can answer them by
+// reverse geocoding instead of an FST lookup that can never hit.
+// Accepted forms (lat first unless hemisphere letters say otherwise):
+//   48.2082, 16.3738        48.2082 16.3738        -33.86;151.21
+//   geo:48.2082,16.3738;u=35                        (RFC 5870)
+//   48°12'29.5"N 16°22'25.7"E     N 48° 12.49' E 16° 22.43'
+//   48 12 29.5 N, 16 22 25.7 E    16°22'25.7"E 48°12'29.5"N
+//   8FWR6963+4V (plus code)       geohash:u2edk8  gh:u2edk8   (see codes.rs)
+// Bare integers ("1984 2020", "12, 34") are not coordinates: a decimal point,
+// a degree/minute/second mark, a hemisphere letter or "geo:" is required.
+
+use serde::Serialize;
+
+use crate::codes;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Coordinate {
+    pub lat: f32,
+    pub lon: f32,
+}
+
+impl Coordinate {
+    /// Finite, |lat| <= 90 and |lon| <= 180.
+    pub fn in_range(&self) -> bool {
+        validate(self.lat.into(), self.lon.into()).is_some()
+    }
+}
+
+pub fn parse(input: &str) -> Option<Coordinate> {
+    let s = input.trim();
+    if s.len() > 64 {
+        return None;
+    }
+
+    if s.contains('+') {
+        if let Some(c) = codes::plus_code_decode(s) {
+            return Some(c);
+        }
+    }
+    if let Some(rest) = strip_prefix_ci(s, "geohash:").or_else(|| strip_prefix_ci(s, "gh:")) {
+        return codes::geohash_decode(rest);
+    }
+
+    if let Some(rest) = strip_prefix_ci(s, "geo:") {
+        // geo:lat,lon[,alt][;params][?query]
+        let body = rest.split([';', '?']).next()?;
+        let mut it = body.split(',');
+        let lat: f64 = it.next()?.trim().parse().ok()?;
+        let lon: f64 = it.next()?.trim().parse().ok()?;
+        return validate(lat, lon);
+    }
+
+    // anything besides digits, marks, separators and hemisphere letters is a name
+    let allowed = |c: char| {
+        c.is_ascii_digit()
+            || c.is_whitespace()
+            || matches!(c, '.' | ',' | ';' | '-' | '+' | '\'' | '"')
+            || is_mark(c)
+            || hemisphere(c).is_some()
+    };
+    if !s.chars().all(allowed) {
+        return None;
+    }
+    let marked = s.contains('.') || s.chars().any(|c| is_mark(c) || hemisphere(c).is_some());
+    if !marked {
+        return None;
+    }
+
+    let (a, b) = split_pair(s)?;
+    let (va, ha) = parse_angle(a)?;
+    let (vb, hb) = parse_angle(b)?;
+
+    let (lat, lon) = match (ha, hb) {
+        (Some(Axis::Lon), Some(Axis::Lat)) | (Some(Axis::Lon), None) | (None, Some(Axis::Lat)) => {
+            (vb, va)
+        }
+        (Some(x), Some(y)) if x == y => return None,
+        _ => (va, vb),
+    };
+    validate(lat, lon)
+}
+
+fn validate(lat: f64, lon: f64) -> Option<Coordinate> {
+    let ok = lat.is_finite() && lon.is_finite() && lat.abs() <= 90.0 && lon.abs() <= 180.0;
+    ok.then_some(Coordinate {
+        lat: lat as f32,
+        lon: lon as f32,
+    })
+}
+
+fn strip_prefix_ci<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    let n = prefix.len();
+    s.get(..n)
+        .filter(|head| head.eq_ignore_ascii_case(prefix))
+        .map(|_| &s[n..])
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Axis {
+    Lat,
+    Lon,
+}
+
+/// Hemisphere letter -> (axis, sign).
+fn hemisphere(c: char) -> Option<(Axis, f64)> {
+    match c.to_ascii_uppercase() {
+        'N' => Some((Axis::Lat, 1.0)),
+        'S' => Some((Axis::Lat, -1.0)),
+        'E' => Some((Axis::Lon, 1.0)),
+        'W' => Some((Axis::Lon, -1.0)),
+        _ => None,
+    }
+}
+
+/// Degree / minute / second marks, including the typographic primes.
+fn is_mark(c: char) -> bool {
+    matches!(c, '°' | 'º' | '′' | '″' | '’' | '”')
+}
+
+/// Split into (first, second) coordinate halves.
+fn split_pair(s: &str) -> Option<(&str, &str)> {
+    // explicit separator
+    for sep in [',', ';'] {
+        if s.matches(sep).count() == 1 {
+            return s.split_once(sep);
+        }
+    }
+
+    // hemisphere letters: "48N 16E" (suffix) or "N 48 E 16" (prefix)
+    let letters: Vec<usize> = s
+        .char_indices()
+        .filter(|(_, c)| hemisphere(*c).is_some())
+        .map(|(i, _)| i)
+        .collect();
+    if letters.len() == 2 {
+        let cut = if letters[0] == 0 {
+            letters[1]
+        } else {
+            letters[0] + 1
+        };
+        return Some((&s[..cut], &s[cut..]));
+    }
+    if !letters.is_empty() {
+        return None;
+    }
+
+    // "48°12' 16°22'": cut before the second degree mark's number
+    let degs: Vec<usize> = s
+        .char_indices()
+        .filter(|(_, c)| matches!(c, '°' | 'º'))
+        .map(|(i, _)| i)
+        .collect();
+    if degs.len() == 2 {
+        let cut =
+            s[..degs[1]].trim_end_matches(|c: char| c.is_ascii_digit() || c == '.' || c == '-');
+        return Some((&s[..cut.len()], &s[cut.len()..]));
+    }
+
+    // two plain decimals separated by whitespace
+    let mut parts = s.split_whitespace();
+    let (a, b) = (parts.next()?, parts.next()?);
+    parts.next().is_none().then_some((a, b))
+}
+
+/// "48°12'29.5\"N", "-48.2082", "N 48 12.49" -> (signed degrees, axis if lettered)
+fn parse_angle(s: &str) -> Option<(f64, Option<Axis>)> {
+    let mut sign = 1.0;
+    let mut axis = None;
+    let mut nums: Vec<f64> = Vec::with_capacity(3);
+    let mut cur = String::new();
+
+    for c in s.chars() {
+        if c.is_ascii_digit() || c == '.' {
+            cur.push(c);
+        } else if matches!(c, '-' | '+') && nums.is_empty() && cur.is_empty() {
+            if c == '-' {
+                sign = -sign;
+            }
+        } else if let Some((ax, sg)) = hemisphere(c) {
+            if axis.is_some() {
+                return None;
+            }
+            axis = Some(ax);
+            sign *= sg;
+            flush(&mut cur, &mut nums)?;
+        } else if c.is_whitespace() || is_mark(c) || c == '\'' || c == '"' {
+            flush(&mut cur, &mut nums)?;
+        } else {
+            return None;
+        }
+    }
+    flush(&mut cur, &mut nums)?;
+
+    let value = match nums[..] {
+        [d] => d,
+        [d, m] if m < 60.0 => d + m / 60.0,
+        [d, m, sec] if m < 60.0 && sec < 60.0 => d + m / 60.0 + sec / 3600.0,
+        _ => return None,
+    };
+    Some((sign * value, axis))
+}
+
+fn flush(cur: &mut String, nums: &mut Vec<f64>) -> Option<()> {
+    if !cur.is_empty() {
+        nums.push(cur.parse().ok()?);
+        cur.clear();
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn near(c: Option<Coordinate>, lat: f32, lon: f32) -> bool {
+        c.is_some_and(|c| (c.lat - lat).abs() < 1e-3 && (c.lon - lon).abs() < 1e-3)
+    }
+
+    #[test]
+    fn parses_decimal_pairs() {
+        assert!(near(parse("48.2082, 16.3738"), 48.2082, 16.3738));
+        assert!(near(parse("48.2082 16.3738"), 48.2082, 16.3738));
+        assert!(near(parse("-33.86;151.21"), -33.86, 151.21));
+        assert!(near(parse("geo:48.2082,16.3738;u=35"), 48.2082, 16.3738));
+        assert!(near(parse("GEO:48.2082,16.3738"), 48.2082, 16.3738));
+    }
+
+    #[test]
+    fn parses_degrees_minutes_seconds() {
+        let (lat, lon) = (48.2082, 16.3738);
+        assert!(near(parse("48°12'29.5\"N 16°22'25.7\"E"), lat, lon));
+        assert!(near(parse("16°22'25.7\"E 48°12'29.5\"N"), lat, lon));
+        assert!(near(parse("N 48° 12.49' E 16° 22.43'"), lat, lon));
+        assert!(near(parse("48 12 29.5 N, 16 22 25.7 E"), lat, lon));
+        assert!(near(parse("33°52'S 151°12'E"), -33.8667, 151.2));
+    }
+
+    #[test]
+    fn accepts_location_codes() {
+        assert!(parse("8FWR6963+4V").is_some());
+        assert!(parse("geohash:u2edk8").is_some());
+        assert!(parse("gh:u2edk8").is_some());
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        for input in [
+            "",
+            "vienna",
+            "1984 2020",
+            "12, 34",
+            "48.2 16.3 12.1",
+            "91.0, 10.0",
+            "48.0, 181.0",
+            "48N 16N",
+            "48°61' 16°10'",
+            "48.2082, 16.3738 Wien",
+            "geo:abc,def",
+            "geo:48.2",
+            "geohash:",
+            "48.208200000000000000000000000000000000000000000000000000000, 16.3738",
+        ] {
+            assert_eq!(parse(input), None, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn in_range_checks_bounds() {
+        assert!(Coordinate {
+            lat: 90.0,
+            lon: -180.0
+        }
+        .in_range());
+        assert!(!Coordinate {
+            lat: 90.5,
+            lon: 0.0
+        }
+        .in_range());
+        assert!(!Coordinate {
+            lat: 0.0,
+            lon: f32::NAN
+        }
+        .in_range());
+    }
+}
@@ -0,0 +1,132 @@
+// src/countries.rs
+//
+// Country metadata: `geodb build --country-info countryInfo.txt` also stores
+// the GeoNames country table (ISO codes, name, capital, continent,
+// population, languages) in the countries section as JSON, keyed by ISO
+// 3166-1 alpha-2. `GET /country/:iso` returns an entry (alpha-2 or alpha-3);
+// candidates carry `country_name`. Countries without records are dropped,
+// as for locales.rs.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use crate::build::{read_line_lossy, GeoRecord};
+
+// countryInfo.txt columns
+const ISO_COL: usize = 0;
+const ISO3_COL: usize = 1;
+const ISO_NUMERIC_COL: usize = 2;
+const NAME_COL: usize = 4;
+const CAPITAL_COL: usize = 5;
+const POPULATION_COL: usize = 7;
+const CONTINENT_COL: usize = 8;
+const LANGUAGES_COL: usize = 15;
+const GEONAME_ID_COL: usize = 16;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CountryInfo {
+    pub iso: String,
+    pub iso3: String,
+    pub iso_numeric: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capital: Option<String>,
+    /// GeoNames continent code: AF, AN, AS, EU, NA, OC, SA.
+    pub continent: String,
+    pub population: u64,
+    /// Most used first, as in locales.rs.
+    pub languages: Vec<String>,
+    /// The country's own record.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub geoname_id: Option<u32>,
+}
+
+fn parse_line(line: &str) -> Option<CountryInfo> {
+    let cols: Vec<&str> = line.split('\t').map(str::trim).collect();
+    let col = |i: usize| cols.get(i).copied().unwrap_or("");
+    if col(ISO_COL).len() != 2 || col(NAME_COL).is_empty() {
+        return None;
+    }
+    Some(CountryInfo {
+        iso: col(ISO_COL).to_string(),
+        iso3: col(ISO3_COL).to_string(),
+        iso_numeric: col(ISO_NUMERIC_COL).to_string(),
+        name: col(NAME_COL).to_string(),
+        capital: Some(col(CAPITAL_COL))
+            .filter(|c| !c.is_empty())
+            .map(str::to_string),
+        continent: col(CONTINENT_COL).to_string(),
+        population: col(POPULATION_COL).parse().unwrap_or(0),
+        languages: col(LANGUAGES_COL)
+            .split(',')
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .map(str::to_string)
+            .collect(),
+        geoname_id: col(GEONAME_ID_COL).parse().ok(),
+    })
+}
+
+/// Build the section bytes; countries without records are dropped.
+pub fn build_section(records: &[GeoRecord], country_info: Option<&Path>) -> Result<Vec<u8>> {
+    let Some(path) = country_info else {
+        return Ok(Vec::new());
+    };
+    let used: HashSet<&str> = records.iter().map(|r| r.country.as_str()).collect();
+
+    let file = File::open(path).with_context(|| format!("open {}", path.display()))?;
+    let mut r = BufReader::new(file);
+    let mut buf = Vec::new();
+    let mut table: BTreeMap<String, CountryInfo> = BTreeMap::new();
+    while let Some(line) = read_line_lossy(&mut r, &mut buf)? {
+        if line.starts_with('#') {
+            continue;
+        }
+        let Some(info) = parse_line(&line) else {
+            continue;
+        };
+        if used.contains(info.iso.as_str()) {
+            table.insert(info.iso.clone(), info);
+        }
+    }
+    eprintln!("[countries] countries={}", table.len());
+    if table.is_empty() {
+        return Ok(Vec::new());
+    }
+    Ok(serde_json::to_vec(&table)?)
+}
+
+pub struct Countries {
+    table: BTreeMap<String, CountryInfo>,
+}
+
+impl Countries {
+    /// `None` for DBs built without --country-info, or before the section
+    /// existed.
+    pub fn from_section(section: &[u8]) -> Result<Option<Self>> {
+        if section.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            table: serde_json::from_slice(section)?,
+        }))
+    }
+
+    /// By ISO alpha-2 ("SI") or alpha-3 ("SVN"), any case.
+    pub fn get(&self, code: &str) -> Option<&CountryInfo> {
+        let code = code.trim().to_ascii_uppercase();
+        match code.len() {
+            2 => self.table.get(&code),
+            3 => self.table.values().find(|c| c.iso3 == code),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self, iso: &str) -> Option<&str> {
+        self.table.get(iso).map(|c| c.name.as_str())
+    }
+}
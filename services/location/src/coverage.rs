@@ -0,0 +1,141 @@
+// src/coverage.rs
+//
+// `geodb coverage --db x.db --corpus names.txt`: how much of a name corpus
+// (place names pulled from articles, a partner's list, ...) the DB resolves,
+// to size gazetteer gaps before adding sources (OSM, WOF). Corpus: one name
+// per line, optionally `name<TAB>CC` with the ISO country the name is meant
+// to be in; blank lines and lines starting with '#' are skipped. A name is
+// - exact: resolved by the standard pipeline without fuzzy matching
+//   (accent-insensitive keys, aliases and segmentation count as exact);
+// - fuzzy: resolved only with `--fuzzy` edits (fuzzy.rs);
+// - missed: neither.
+// With a country, a name only counts as resolved when some candidate is in
+// that country; per-country totals list where the misses are, with examples.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use crate::geocoder::{Answer, Geocoder, LookupOptions};
+
+/// Missed names listed per country.
+const MISS_EXAMPLES: usize = 10;
+
+#[derive(Serialize)]
+pub struct CoverageJson {
+    pub names: usize,
+    pub exact: usize,
+    pub fuzzy: usize,
+    pub missed: usize,
+    /// (exact + fuzzy) / names.
+    pub coverage: f64,
+    pub exact_ratio: f64,
+    /// Countries of corpus lines that carry one, most misses first.
+    pub by_country: Vec<CountryJson>,
+}
+
+#[derive(Serialize)]
+pub struct CountryJson {
+    pub country: String,
+    pub names: usize,
+    pub missed: usize,
+    pub coverage: f64,
+    pub missed_examples: Vec<String>,
+}
+
+#[derive(Default)]
+struct CountryTally {
+    names: usize,
+    missed: usize,
+    examples: Vec<String>,
+}
+
+fn ratio(n: usize, of: usize) -> f64 {
+    if of == 0 {
+        0.0
+    } else {
+        n as f64 / of as f64
+    }
+}
+
+fn resolves(answer: &Answer, country: Option<&str>) -> bool {
+    match country {
+        Some(cc) => answer
+            .candidates
+            .iter()
+            .any(|c| c.country.eq_ignore_ascii_case(cc)),
+        None => !answer.candidates.is_empty(),
+    }
+}
+
+pub fn run(geo: &Geocoder, corpus: &Path, max_edits: u32) -> Result<CoverageJson> {
+    let file = File::open(corpus).with_context(|| format!("open corpus: {}", corpus.display()))?;
+    let exact_opts = LookupOptions::default();
+    let fuzzy_opts = LookupOptions {
+        fuzzy: Some(max_edits),
+        ..Default::default()
+    };
+
+    let (mut names, mut exact, mut fuzzy) = (0usize, 0usize, 0usize);
+    let mut countries: BTreeMap<String, CountryTally> = BTreeMap::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.with_context(|| format!("read corpus: {}", corpus.display()))?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (name, country) = match line.split_once('\t') {
+            Some((n, cc)) if !cc.trim().is_empty() => (n.trim(), Some(cc.trim())),
+            Some((n, _)) => (n.trim(), None),
+            None => (line, None),
+        };
+        names += 1;
+
+        let resolved = if resolves(&geo.lookup(name, &exact_opts)?, country) {
+            exact += 1;
+            true
+        } else if max_edits > 0 && resolves(&geo.lookup(name, &fuzzy_opts)?, country) {
+            fuzzy += 1;
+            true
+        } else {
+            false
+        };
+        if let Some(cc) = country {
+            let t = countries.entry(cc.to_ascii_uppercase()).or_default();
+            t.names += 1;
+            if !resolved {
+                t.missed += 1;
+                if t.examples.len() < MISS_EXAMPLES {
+                    t.examples.push(name.to_string());
+                }
+            }
+        }
+    }
+
+    let mut by_country: Vec<CountryJson> = countries
+        .into_iter()
+        .map(|(country, t)| CountryJson {
+            country,
+            names: t.names,
+            missed: t.missed,
+            coverage: ratio(t.names - t.missed, t.names),
+            missed_examples: t.examples,
+        })
+        .collect();
+    // stable: ties stay in country order
+    by_country.sort_by_key(|c| Reverse(c.missed));
+
+    Ok(CoverageJson {
+        names,
+        exact,
+        fuzzy,
+        missed: names - exact - fuzzy,
+        coverage: ratio(exact + fuzzy, names),
+        exact_ratio: ratio(exact, names),
+        by_country,
+    })
+}
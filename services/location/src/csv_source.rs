@@ -0,0 +1,140 @@
+// src/csv_source.rs
+//
+// Plain CSV adapter for custom gazetteers. Header row required.
+// Required columns: id, name, lat, lon
+// Optional columns: country, admin1, admin2, feature_class, feature_code,
+//                   population, alt_names ("|"-separated),
+//                   wikidata, geoname_id (for the id concordance)
+// The `id` column is the source's own identifier; records get synthetic ids
+// (see build::SyntheticIds) so they can never collide with GeoNames.
+
+use anyhow::{anyhow, Context, Result};
+use std::path::PathBuf;
+
+use crate::build::{GeoRecord, SourceAdapter, SourceRecords, SyntheticIds};
+use crate::concordance::{self, ExternalRef};
+
+/// SyntheticIds namespace for custom CSV ids.
+const CSV_NAMESPACE: u8 = 4;
+
+pub struct CsvSource {
+    pub path: PathBuf,
+}
+
+impl SourceAdapter for CsvSource {
+    fn describe(&self) -> String {
+        format!("csv:{}", self.path.display())
+    }
+
+    fn load(&self, min_pop: u32, ids: &mut SyntheticIds) -> Result<SourceRecords> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .flexible(true)
+            .from_path(&self.path)
+            .with_context(|| format!("open csv: {}", self.path.display()))?;
+        let headers = rdr.headers()?.clone();
+        let col = |name: &str| headers.iter().position(|h| h.trim() == name);
+        let need = |name: &str| col(name).ok_or_else(|| anyhow!("csv: missing {name} column"));
+
+        let c_id = need("id")?;
+        let c_name = need("name")?;
+        let c_lat = need("lat")?;
+        let c_lon = need("lon")?;
+        let c_country = col("country");
+        let c_admin1 = col("admin1");
+        let c_admin2 = col("admin2");
+        let c_class = col("feature_class");
+        let c_code = col("feature_code");
+        let c_population = col("population");
+        let c_alt = col("alt_names");
+        let c_wikidata = col("wikidata");
+        let c_geonames = col("geoname_id");
+
+        let mut rows: Vec<(i64, GeoRecord, Vec<String>, Vec<ExternalRef>)> = Vec::new();
+        let mut skipped: u64 = 0;
+        for (line, row) in rdr.records().enumerate() {
+            let row = row?;
+            let get = |c: Option<usize>| c.and_then(|i| row.get(i)).unwrap_or("").trim();
+
+            let parsed = (
+                get(Some(c_id)).parse::<i64>(),
+                get(Some(c_lat)).parse::<f32>(),
+                get(Some(c_lon)).parse::<f32>(),
+            );
+            let (Ok(native_id), Ok(lat), Ok(lon)) = parsed else {
+                if skipped < 10 {
+                    eprintln!("[csv] skipping line {}: bad id/lat/lon", line + 2);
+                }
+                skipped += 1;
+                continue;
+            };
+            let name = get(Some(c_name));
+            if name.is_empty() {
+                skipped += 1;
+                continue;
+            }
+
+            let population: u32 = get(c_population).parse().unwrap_or(0);
+            if population < min_pop {
+                continue;
+            }
+
+            let feat_class = get(c_class).as_bytes().first().copied().unwrap_or(b'P');
+            let feat_code = match get(c_code) {
+                "" => "PPL",
+                code => code,
+            };
+            let alts: Vec<String> = get(c_alt)
+                .split('|')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+                .collect();
+            let mut refs: Vec<ExternalRef> = concordance::wikidata_ref(get(c_wikidata))
+                .into_iter()
+                .collect();
+            if let Ok(gid) = get(c_geonames).parse::<u32>() {
+                refs.push(ExternalRef::GeoNames(gid));
+            }
+
+            rows.push((
+                native_id,
+                GeoRecord {
+                    id: 0,
+                    name: name.to_string(),
+                    ascii_name: String::new(),
+                    country: get(c_country).to_ascii_uppercase(),
+                    admin1: get(c_admin1).to_string(),
+                    admin2: get(c_admin2).to_string(),
+                    lat,
+                    lon,
+                    feat_class,
+                    feat_code: feat_code.to_string(),
+                    population,
+                    timezone: String::new(),
+                },
+                alts,
+                refs,
+            ));
+        }
+
+        rows.sort_by_key(|(native, _, _, _)| *native);
+        let mut out = SourceRecords {
+            records: Vec::with_capacity(rows.len()),
+            ..Default::default()
+        };
+        for (native, mut rec, alts, refs) in rows {
+            rec.id = ids.assign(CSV_NAMESPACE, native);
+            out.alt_names.extend(alts.into_iter().map(|a| (a, rec.id)));
+            out.refs.extend(refs.into_iter().map(|r| (rec.id, r)));
+            out.records.push(rec);
+        }
+
+        eprintln!(
+            "[csv] records={} alt_names={} skipped={}",
+            out.records.len(),
+            out.alt_names.len(),
+            skipped
+        );
+        Ok(out)
+    }
+}
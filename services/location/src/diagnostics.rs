@@ -0,0 +1,203 @@
+// src/diagnostics.rs
+//
+// Build report: index-quality diagnostics that are otherwise invisible.
+// - heavy keys: postings longer than a threshold ("san jose" is fine, "church" is noise)
+// - normalization collisions: keys that several distinct primary names fold into
+//   ("Nice" / "NICE" / " nice"); only multi-posting keys are inspected
+// - unindexed records: records reachable under no key at all
+// - sanitize: strings cleaned by sanitize.rs
+// Written as JSON next to the DB (or to --report) and summarized on stderr.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use crate::build::{norm_key, FastBuildMap, GeoRecord};
+use crate::sanitize::SanitizeCounts;
+
+pub struct DiagnosticsOptions {
+    /// Report keys with more postings than this.
+    pub heavy_postings: usize,
+    /// Max examples kept per diagnostic.
+    pub sample: usize,
+    pub report: PathBuf,
+}
+
+impl DiagnosticsOptions {
+    pub fn for_db(out_db: &Path) -> Self {
+        let mut report = out_db.as_os_str().to_owned();
+        report.push(".report.json");
+        Self {
+            heavy_postings: 500,
+            sample: 100,
+            report: PathBuf::from(report),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct SourceSummary {
+    pub source: String,
+    pub records: usize,
+    pub inline_names: usize,
+}
+
+#[derive(Serialize)]
+pub struct HeavyKey {
+    pub key: String,
+    pub postings: usize,
+}
+
+#[derive(Serialize)]
+pub struct Collision {
+    pub key: String,
+    pub forms: Vec<String>,
+}
+
+#[derive(Serialize, Default)]
+pub struct BuildReport {
+    pub sources: Vec<SourceSummary>,
+    /// Dropped by --exclude-feature-class / --exclude-feature-code.
+    pub excluded_records: usize,
+    pub records: usize,
+    pub keys: usize,
+    pub total_postings: usize,
+    pub heavy_postings_threshold: usize,
+    pub heavy_keys_total: usize,
+    pub heavy_keys: Vec<HeavyKey>,
+    pub collisions_total: usize,
+    pub collisions: Vec<Collision>,
+    pub unindexed_records_total: usize,
+    pub unindexed_records: Vec<u32>,
+    pub sanitize: SanitizeCounts,
+}
+
+/// Key-side diagnostics gathered one key at a time, so a spilled build
+/// (spill.rs) can report without the whole key map in memory.
+pub struct KeyDiagnostics<'r> {
+    heavy_postings: usize,
+    keys: usize,
+    total_postings: usize,
+    heavy: Vec<HeavyKey>,
+    /// Primary-name keys with several distinct forms, not yet seen.
+    forms: HashMap<String, BTreeSet<&'r str>>,
+    collisions: BTreeMap<String, BTreeSet<&'r str>>,
+    indexed: HashSet<u32>,
+}
+
+impl<'r> KeyDiagnostics<'r> {
+    pub fn new(records: &'r [GeoRecord], opts: &DiagnosticsOptions) -> Self {
+        let mut forms: HashMap<String, BTreeSet<&str>> = HashMap::new();
+        for r in records {
+            for raw in [r.name.as_str(), r.ascii_name.as_str()] {
+                if let Some(k) = norm_key(raw) {
+                    forms.entry(k).or_default().insert(raw);
+                }
+            }
+        }
+        forms.retain(|_, f| f.len() > 1);
+        Self {
+            heavy_postings: opts.heavy_postings,
+            keys: 0,
+            total_postings: 0,
+            heavy: Vec::new(),
+            forms,
+            collisions: BTreeMap::new(),
+            indexed: HashSet::new(),
+        }
+    }
+
+    /// One key of the final index with its deduped ids.
+    pub fn observe(&mut self, key: &str, ids: &[u32]) {
+        self.keys += 1;
+        self.total_postings += ids.len();
+        if ids.len() > self.heavy_postings {
+            self.heavy.push(HeavyKey {
+                key: key.to_string(),
+                postings: ids.len(),
+            });
+        }
+        // normalization collisions: only multi-posting keys are inspected
+        if ids.len() > 1 {
+            if let Some(f) = self.forms.remove(key) {
+                self.collisions.insert(key.to_string(), f);
+            }
+        }
+        self.indexed.extend(ids);
+    }
+}
+
+impl BuildReport {
+    /// Fill the diagnostic fields from the final (deduped) index.
+    pub fn diagnose(
+        &mut self,
+        records: &[GeoRecord],
+        key_to_ids: &FastBuildMap,
+        opts: &DiagnosticsOptions,
+    ) {
+        let mut diag = KeyDiagnostics::new(records, opts);
+        for (k, ids) in key_to_ids {
+            diag.observe(k, ids);
+        }
+        self.finish_diagnostics(diag, records, opts);
+    }
+
+    /// Fill the diagnostic fields from keys observed one by one.
+    pub fn finish_diagnostics(
+        &mut self,
+        diag: KeyDiagnostics<'_>,
+        records: &[GeoRecord],
+        opts: &DiagnosticsOptions,
+    ) {
+        self.records = records.len();
+        self.keys = diag.keys;
+        self.total_postings = diag.total_postings;
+        self.heavy_postings_threshold = opts.heavy_postings;
+
+        // heavy keys, largest first
+        let mut heavy = diag.heavy;
+        heavy.sort_unstable_by(|a, b| b.postings.cmp(&a.postings).then(a.key.cmp(&b.key)));
+        self.heavy_keys_total = heavy.len();
+        heavy.truncate(opts.sample);
+        self.heavy_keys = heavy;
+
+        self.collisions_total = diag.collisions.len();
+        self.collisions = diag
+            .collisions
+            .into_iter()
+            .take(opts.sample)
+            .map(|(key, f)| Collision {
+                key,
+                forms: f.into_iter().map(str::to_string).collect(),
+            })
+            .collect();
+
+        // records no key points at
+        let mut unindexed: Vec<u32> = records
+            .iter()
+            .map(|r| r.id)
+            .filter(|id| !diag.indexed.contains(id))
+            .collect();
+        unindexed.sort_unstable();
+        self.unindexed_records_total = unindexed.len();
+        unindexed.truncate(opts.sample);
+        self.unindexed_records = unindexed;
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let f = File::create(path).with_context(|| format!("create report: {}", path.display()))?;
+        serde_json::to_writer_pretty(BufWriter::new(f), self)?;
+        eprintln!(
+            "[report] {} heavy_keys={} collisions={} unindexed_records={} sanitize={:?}",
+            path.display(),
+            self.heavy_keys_total,
+            self.collisions_total,
+            self.unindexed_records_total,
+            self.sanitize
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+// src/disambiguate.rs
+//
+// Context-aware disambiguation for `POST /resolve`: the mentions of one
+// article are resolved together, so "Springfield" next to "Illinois" and
+// "Chicago" picks Springfield, IL. Each mention is looked up on its own first
+// (pipeline.rs ranking); its best candidate is its anchor. A candidate's
+// context score is its ranking total times
+//   country   COUNTRY_BOOST when another mention's anchor is in its country
+//   admin1    ADMIN1_BOOST when one is in its first-level region
+//   proximity 1 + PROXIMITY_WEIGHT * exp(-km / PROXIMITY_KM) to the nearest
+//             other anchor (countries excluded: their centroid says nothing)
+// After rescoring, anchors move to the new best candidates and the scores are
+// recomputed, ROUNDS times; one mention alone keeps its plain ranking.
+
+use serde::Serialize;
+
+use crate::build::GeoRecord;
+use crate::ranking::{haversine_km, ScoreBreakdown};
+
+const COUNTRY_BOOST: f64 = 1.5;
+const ADMIN1_BOOST: f64 = 3.0;
+const PROXIMITY_WEIGHT: f64 = 2.0;
+const PROXIMITY_KM: f64 = 200.0;
+const ROUNDS: usize = 2;
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct ContextScore {
+    pub country: f64,
+    pub admin1: f64,
+    pub proximity: f64,
+    /// Nearest other anchor, when there is one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nearest_km: Option<f64>,
+    /// Ranking total times the factors above.
+    pub total: f64,
+}
+
+pub struct Scored {
+    pub rec: GeoRecord,
+    pub score: ScoreBreakdown,
+    pub context: ContextScore,
+}
+
+/// Countries and dependencies (PCLI, PCLD, ...) vouch for their country only.
+fn is_country(rec: &GeoRecord) -> bool {
+    rec.feat_class == b'A' && rec.feat_code.starts_with("PCL")
+}
+
+fn context(rec: &GeoRecord, base: f64, anchors: &[&GeoRecord]) -> ContextScore {
+    let mut country = 1.0;
+    let mut admin1 = 1.0;
+    let mut nearest_km: Option<f64> = None;
+    for a in anchors.iter().filter(|a| a.id != rec.id) {
+        if a.country != rec.country {
+            continue;
+        }
+        country = COUNTRY_BOOST;
+        if is_country(a) {
+            continue;
+        }
+        if !a.admin1.is_empty() && a.admin1 == rec.admin1 {
+            admin1 = ADMIN1_BOOST;
+        }
+        let km = haversine_km(rec.lat, rec.lon, a.lat, a.lon);
+        nearest_km = Some(nearest_km.map_or(km, |n| n.min(km)));
+    }
+    let proximity = nearest_km.map_or(1.0, |km| {
+        1.0 + PROXIMITY_WEIGHT * (-km / PROXIMITY_KM).exp()
+    });
+    ContextScore {
+        country,
+        admin1,
+        proximity,
+        nearest_km,
+        total: base * country * admin1 * proximity,
+    }
+}
+
+/// Rescore each mention's ranked candidates against the others'; every list
+/// comes back sorted by context total, best first.
+pub fn resolve(mentions: Vec<Vec<(GeoRecord, ScoreBreakdown)>>) -> Vec<Vec<Scored>> {
+    let mut out: Vec<Vec<Scored>> = mentions
+        .into_iter()
+        .map(|ranked| {
+            ranked
+                .into_iter()
+                .map(|(rec, score)| Scored {
+                    context: ContextScore {
+                        country: 1.0,
+                        admin1: 1.0,
+                        proximity: 1.0,
+                        nearest_km: None,
+                        total: score.total,
+                    },
+                    rec,
+                    score,
+                })
+                .collect()
+        })
+        .collect();
+
+    for _ in 0..ROUNDS {
+        // anchors of the previous round, per mention
+        let anchors: Vec<Option<GeoRecord>> = out
+            .iter()
+            .map(|c| c.first().map(|s| s.rec.clone()))
+            .collect();
+        for (i, cands) in out.iter_mut().enumerate() {
+            let others: Vec<&GeoRecord> = anchors
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .filter_map(|(_, a)| a.as_ref())
+                .collect();
+            for s in cands.iter_mut() {
+                s.context = context(&s.rec, s.score.total, &others);
+            }
+            cands.sort_by(|a, b| b.context.total.total_cmp(&a.context.total));
+        }
+    }
+    out
+}
@@ -0,0 +1,152 @@
+// src/disputed.rs
+//
+// Disputed / special-status territories: `geodb build --disputed-policy
+// policy.toml` stores a newsroom's labeling policy in the disputed section, and
+// candidates inside a listed territory carry `disputed: true`, the policy's
+// `territory` label and, where the policy names the record itself, its
+// `display_name`. Which places count and what they are called is editorial,
+// not GeoNames', so it lives in a file the desk owns:
+//
+//   policy = "style-guide-2026"
+//
+//   [[territory]]
+//   name = "Crimea (annexed by Russia)"
+//   country = "UA"
+//   admin1 = "11"            # optional; default the whole country code
+//   ids = [694423]           # optional; records coded elsewhere
+//
+//   [display_names]
+//   694423 = "Sevastopol, Crimea"
+//
+// Matching is by the record's country / admin1 codes at query time, so the
+// section is the policy as written (JSON), checked at build.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+use crate::build::GeoRecord;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    /// Label for logs, e.g. the style guide edition.
+    #[serde(default)]
+    pub policy: Option<String>,
+    #[serde(default, rename = "territory")]
+    pub territories: Vec<Territory>,
+    /// Record id -> name to show instead of the GeoNames one (TOML keys are
+    /// strings, hence not u32).
+    #[serde(default)]
+    pub display_names: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Territory {
+    /// The policy's label for the territory.
+    pub name: String,
+    #[serde(default)]
+    pub country: Option<String>,
+    #[serde(default)]
+    pub admin1: Option<String>,
+    #[serde(default)]
+    pub ids: Vec<u32>,
+}
+
+impl Territory {
+    fn contains(&self, id: u32, country: &str, admin1: &str) -> bool {
+        if self.ids.contains(&id) {
+            return true;
+        }
+        self.country.as_deref() == Some(country) && self.admin1.iter().all(|a| a == admin1)
+    }
+}
+
+impl Policy {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text =
+            std::fs::read_to_string(path).with_context(|| format!("open {}", path.display()))?;
+        let policy: Policy =
+            toml::from_str(&text).with_context(|| format!("parse {}", path.display()))?;
+        policy
+            .validate()
+            .with_context(|| path.display().to_string())?;
+        Ok(policy)
+    }
+
+    fn validate(&self) -> Result<()> {
+        for id in self.display_names.keys() {
+            if id.parse::<u32>().is_err() {
+                bail!("display_names: expected a record id, got {id:?}");
+            }
+        }
+        for t in &self.territories {
+            if t.name.trim().is_empty() {
+                bail!("territory without a name");
+            }
+            if t.country.is_none() && t.ids.is_empty() {
+                bail!("territory {:?} needs a country or ids", t.name);
+            }
+            if t.admin1.is_some() && t.country.is_none() {
+                bail!("territory {:?}: admin1 without country", t.name);
+            }
+        }
+        Ok(())
+    }
+
+    /// `None` for DBs built without --disputed-policy.
+    pub fn from_section(section: &[u8]) -> Result<Option<Self>> {
+        if section.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_slice(section)?))
+    }
+
+    /// None when the record is in no listed territory; the first listed
+    /// territory containing it wins.
+    pub fn mark(&self, id: u32, country: &str, admin1: &str) -> Option<Marking<'_>> {
+        let t = self
+            .territories
+            .iter()
+            .find(|t| t.contains(id, country, admin1))?;
+        Some(Marking {
+            territory: &t.name,
+            display_name: self.display_names.get(&id.to_string()).map(String::as_str),
+        })
+    }
+}
+
+/// Build the section bytes; empty without --disputed-policy.
+pub fn build_section(records: &[GeoRecord], policy: Option<&Path>) -> Result<Vec<u8>> {
+    let Some(path) = policy else {
+        return Ok(Vec::new());
+    };
+    let policy = Policy::load(path)?;
+    let known: HashSet<u32> = records.iter().map(|r| r.id).collect();
+    let missing = policy
+        .territories
+        .iter()
+        .flat_map(|t| &t.ids)
+        .copied()
+        .chain(policy.display_names.keys().filter_map(|id| id.parse().ok()))
+        .filter(|id| !known.contains(id))
+        .count();
+    let affected = records
+        .iter()
+        .filter(|r| policy.mark(r.id, &r.country, &r.admin1).is_some())
+        .count();
+    eprintln!(
+        "[disputed] policy={} territories={} records={affected} unknown_ids={missing}",
+        policy.policy.as_deref().unwrap_or("-"),
+        policy.territories.len(),
+    );
+    Ok(serde_json::to_vec(&policy)?)
+}
+
+/// Annotations for one candidate.
+pub struct Marking<'a> {
+    pub territory: &'a str,
+    pub display_name: Option<&'a str>,
+}
@@ -0,0 +1,232 @@
+// src/estimate.rs
+//
+// `geodb estimate`: predict what `geodb build --all [--alt] --min-pop N` would
+// produce, without building it. Honours the same --exclude-feature-* filters.
+// - records / offsets / spatial: exact. Hot section: average record size times
+//   --hot-records. Every allCountries line is parsed and filtered as
+//   the build does, then sized and dropped instead of kept.
+// - keys / postings: keys are sampled by hash (one in `key_sample`) and every
+//   posting of a sampled key is collected, so distinct-key counts and postings
+//   bytes scale up without bias.
+// - FST: built for the sample and for every other sampled key; bytes per key are
+//   extrapolated along the power law those two points give (prefix sharing
+//   improves with density, so a linear scale-up would overshoot).
+// Concordance / synonyms sections are a few KB and left out; so is the
+// unaccented-key FST and its postings (typically well under the main FST). Only the GeoNames
+// dump is supported; OSM / WOF / CSV inputs still need a trial build.
+
+use anyhow::{bail, Result};
+use fst::MapBuilder;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Instant;
+
+use crate::build::{
+    geonames_member, norm_key, parse_allcountries_line, parse_alt_pair, read_line_lossy,
+    splitmix64, with_zip_member, write_record, FastIdSet, FeatureFilter, Progress, MAGIC,
+};
+use crate::{fnv1a64, postings, FNV_OFFSET};
+
+/// Sections every GeoNames build writes (fst, postings, records, offsets, spatial).
+const CORE_SECTIONS: usize = 5;
+const SECTION_ENTRY_LEN: usize = 24;
+
+/// Below this many keys in the half sample the FST slope is noise; scale linearly.
+const MIN_SLOPE_KEYS: usize = 1_000;
+
+pub struct EstimateOptions {
+    pub min_pop: u32,
+    pub exclude: FeatureFilter,
+    /// Sample one key in this many.
+    pub key_sample: u64,
+    pub hot_records: usize,
+}
+
+#[derive(Serialize)]
+pub struct Estimate {
+    pub lines: u64,
+    pub records: u64,
+    pub alt_names: u64,
+    pub key_sample: u64,
+    pub sampled_keys: usize,
+    pub keys: u64,
+    pub total_postings: u64,
+    pub fst_bytes: u64,
+    pub postings_bytes: u64,
+    pub records_bytes: u64,
+    pub offsets_bytes: u64,
+    pub spatial_bytes: u64,
+    pub hot_bytes: u64,
+    pub db_bytes: u64,
+    pub elapsed_secs: f64,
+}
+
+struct KeySample {
+    every: u64,
+    keys: BTreeMap<String, Vec<u32>>,
+}
+
+impl KeySample {
+    fn offer(&mut self, key: String, id: u32) {
+        if splitmix64(fnv1a64(FNV_OFFSET, key.as_bytes())).is_multiple_of(self.every) {
+            self.keys.entry(key).or_default().push(id);
+        }
+    }
+}
+
+pub fn estimate(all: &Path, alt: Option<&Path>, opts: &EstimateOptions) -> Result<Estimate> {
+    if opts.key_sample == 0 {
+        bail!("--key-sample must be >= 1");
+    }
+    let start = Instant::now();
+    let mut sample = KeySample {
+        every: opts.key_sample,
+        keys: BTreeMap::new(),
+    };
+
+    // 1) allCountries: exact record stats, sampled primary-name keys
+    let mut lines = 0u64;
+    let mut records = 0u64;
+    let mut records_bytes = 0u64;
+    let mut id_present = FastIdSet::default();
+    with_zip_member(all, &geonames_member(all), |mut reader| {
+        let prog = Progress::new("est_all", 1_000_000);
+        let mut buf = Vec::new();
+        let mut rec_buf = Vec::new();
+        while let Some(line) = read_line_lossy(&mut reader, &mut buf)? {
+            lines += 1;
+            prog.tick(lines, "");
+            let Ok(r) = parse_allcountries_line(&line, opts.min_pop) else {
+                continue;
+            };
+            if opts.exclude.excludes(&r) {
+                continue;
+            }
+            rec_buf.clear();
+            write_record(&mut rec_buf, &r)?;
+            records_bytes += rec_buf.len() as u64;
+            records += 1;
+            if alt.is_some() {
+                id_present.insert(r.id);
+            }
+            for name in [&r.name, &r.ascii_name] {
+                if let Some(k) = norm_key(name) {
+                    sample.offer(k, r.id);
+                }
+            }
+        }
+        prog.done(lines, &format!("kept={records}"));
+        Ok(())
+    })?;
+    if records == 0 {
+        bail!("no records parsed from input (min_pop too high?)");
+    }
+
+    // 2) alternateNames: sampled keys for names of kept records
+    let mut alt_names = 0u64;
+    if let Some(alt) = alt {
+        with_zip_member(alt, "alternateNamesV2.txt", |mut reader| {
+            let prog = Progress::new("est_alt", 1_000_000);
+            let mut n = 0u64;
+            let mut buf = Vec::new();
+            while let Some(line) = read_line_lossy(&mut reader, &mut buf)? {
+                n += 1;
+                prog.tick(n, "");
+                if let Some((k, id, _, _)) = parse_alt_pair(&line, &id_present)? {
+                    alt_names += 1;
+                    sample.offer(k, id);
+                }
+            }
+            prog.done(n, &format!("kept_pairs={alt_names}"));
+            Ok(())
+        })?;
+    }
+
+    // 3) postings of the sampled keys, encoded as write_db does
+    let every = opts.key_sample;
+    let mut postings_bytes = 0u64;
+    let mut total_postings = 0u64;
+    let mut offsets = Vec::with_capacity(sample.keys.len());
+    let mut list = Vec::new();
+    for ids in sample.keys.values_mut() {
+        ids.sort_unstable();
+        ids.dedup();
+        // offsets as they would be in the full postings blob
+        offsets.push(postings_bytes * every);
+        list.clear();
+        postings::write_list(&mut list, ids, postings::DEFAULT_ROARING_THRESHOLD)?;
+        postings_bytes += list.len() as u64;
+        total_postings += ids.len() as u64;
+    }
+
+    // 4) FST: two sample densities -> bytes-per-key slope
+    let n_full = sample.keys.len();
+    let fst_full = fst_len(sample.keys.keys().zip(offsets.iter().copied()))?;
+    let fst_half = fst_len(sample.keys.keys().zip(offsets.iter().copied()).step_by(2))?;
+    let n_half = n_full.div_ceil(2);
+    let per_key = fst_full as f64 / n_full.max(1) as f64;
+    let slope = if n_half >= MIN_SLOPE_KEYS {
+        let per_key_half = fst_half as f64 / n_half as f64;
+        ((per_key_half / per_key).ln() / (n_full as f64 / n_half as f64).ln()).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let keys = n_full as u64 * every;
+    let fst_bytes = (per_key * (every as f64).powf(-slope) * keys as f64) as u64;
+
+    let postings_bytes = postings_bytes * every;
+    let total_postings = total_postings * every;
+    let offsets_bytes = 4 + records * (4 + 8);
+    let spatial_bytes = 4 + records * 12;
+    let hot = (opts.hot_records as u64).min(records);
+    let hot_bytes = match hot {
+        0 => 0,
+        h => 4 + h * 8 + records_bytes * h / records,
+    };
+    let sections = CORE_SECTIONS + usize::from(hot > 0);
+    let header = (MAGIC.len() + 4 + 4 + sections * SECTION_ENTRY_LEN) as u64;
+    let db_bytes = header
+        + fst_bytes
+        + postings_bytes
+        + records_bytes
+        + offsets_bytes
+        + spatial_bytes
+        + hot_bytes;
+
+    let est = Estimate {
+        lines,
+        records,
+        alt_names,
+        key_sample: every,
+        sampled_keys: n_full,
+        keys,
+        total_postings,
+        fst_bytes,
+        postings_bytes,
+        records_bytes,
+        offsets_bytes,
+        spatial_bytes,
+        hot_bytes,
+        db_bytes,
+        elapsed_secs: start.elapsed().as_secs_f64(),
+    };
+    eprintln!(
+        "[estimate] records={} keys~{} postings~{} db~{:.1} MiB (fst slope {slope:.3})",
+        est.records,
+        est.keys,
+        est.total_postings,
+        est.db_bytes as f64 / (1024.0 * 1024.0)
+    );
+    Ok(est)
+}
+
+fn fst_len<'a>(keys: impl Iterator<Item = (&'a String, u64)>) -> Result<usize> {
+    let mut bytes = Vec::new();
+    let mut b = MapBuilder::new(&mut bytes)?;
+    for (k, off) in keys {
+        b.insert(k, off)?;
+    }
+    b.finish()?;
+    Ok(bytes.len())
+}
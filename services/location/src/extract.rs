@@ -0,0 +1,276 @@
+// src/extract.rs
+//
+// Toponym extraction for `POST /extract`: scans raw article text for place
+// names in the index. Text is split into word tokens ("Saint-Denis" and
+// "d'Ivoire" stay one token); from each token the longest run of up to
+// MAX_SPAN_TOKENS tokens whose text is an index key (or alias) wins and the
+// scan continues after it. Only spans starting with a capital (or an uncased
+// letter, e.g. CJK) are tried, which keeps "nice weather" and "may" out.
+// Matching is exact / accent-insensitive plus aliases; fuzzy and segmentation
+// fallbacks would fire on ordinary words.
+// Social posts often carry a place only as a hashtag or a flag:
+// - "#Gaza", "#TelAviv", "#riodejaneiro" need no capital; the tag is tried as
+//   written, split at case changes, then segmented against the keys
+//   (segment.rs).
+// - Emoji flags (a pair of regional indicators, "🇺🇦") name their country
+//   through the countries section, so they need a DB built with
+//   --country-info.
+
+use anyhow::Result;
+
+use crate::build::GeoRecord;
+use crate::casefold;
+use crate::countries::Countries;
+use crate::pipeline::{Casefold, Index, NamePrefs, Origin, Outcome, Pipeline, Scorer};
+use crate::pipeline::{ExactSource, SynonymSource};
+use crate::ranking::ScoreBreakdown;
+use crate::segment;
+
+const MAX_SPAN_TOKENS: usize = 5;
+/// REGIONAL INDICATOR SYMBOL LETTER A; the rest follow in order up to Z.
+const REGIONAL_A: u32 = 0x1F1E6;
+
+pub struct Mention {
+    /// Byte range in the input text.
+    pub start: usize,
+    pub end: usize,
+    /// Normalized key that matched.
+    pub key: String,
+    /// Set when the span was a historical alias.
+    pub expanded: bool,
+    /// Best first, at most `limit`.
+    pub ranked: Vec<(GeoRecord, ScoreBreakdown)>,
+}
+
+/// Pipeline for extraction: aliases and exact keys only.
+pub fn pipeline<'a, D: AsRef<[u8]> + 'a>(scorer: &'a dyn Scorer) -> Pipeline<'a, D> {
+    Pipeline {
+        normalizer: &Casefold,
+        sources: vec![Box::new(SynonymSource), Box::new(ExactSource)],
+        filters: Vec::new(),
+        scorer,
+        names: NamePrefs::default(),
+        combine: false,
+    }
+}
+
+/// Byte ranges of word tokens: alphanumeric runs, joined across a single
+/// hyphen or apostrophe between two alphanumerics.
+fn tokens(text: &str) -> Vec<(usize, usize)> {
+    let mut out = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c.is_alphanumeric() {
+            start.get_or_insert(i);
+            continue;
+        }
+        let joins = matches!(c, '-' | '\'' | '’')
+            && start.is_some()
+            && chars.peek().is_some_and(|(_, n)| n.is_alphanumeric());
+        if !joins {
+            if let Some(s) = start.take() {
+                out.push((s, i));
+            }
+        }
+    }
+    if let Some(s) = start {
+        out.push((s, text.len()));
+    }
+    out
+}
+
+fn starts_capitalized(token: &str) -> bool {
+    token
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_alphabetic() && !c.is_lowercase())
+}
+
+/// Span text with whitespace runs (line breaks included) collapsed to one space.
+fn span_key(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// "TelAviv" -> "Tel Aviv"; lowercase and all-caps tags come back as they are.
+fn split_camel(tag: &str) -> String {
+    let mut out = String::with_capacity(tag.len() + 4);
+    let mut after_lower = false;
+    for c in tag.chars() {
+        if c.is_uppercase() && after_lower {
+            out.push(' ');
+        }
+        after_lower = c.is_lowercase();
+        out.push(c);
+    }
+    out
+}
+
+/// Byte ranges and ISO alpha-2 codes of the emoji flags in `text`.
+fn flags(text: &str) -> Vec<(usize, usize, String)> {
+    let letter = |c: char| {
+        let d = (c as u32).wrapping_sub(REGIONAL_A);
+        (d < 26).then(|| char::from(b'A' + d as u8))
+    };
+    let mut out = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let Some(a) = letter(c) else {
+            continue;
+        };
+        if let Some(b) = chars.peek().and_then(|&(_, n)| letter(n)) {
+            let (j, n) = chars.next().expect("peeked");
+            out.push((i, j + n.len_utf8(), format!("{a}{b}")));
+        }
+    }
+    out
+}
+
+/// The pipeline's answer for `key`, when something matched.
+fn matched<D: AsRef<[u8]>>(
+    idx: &Index<'_, D>,
+    pipeline: &Pipeline<'_, D>,
+    key: &str,
+) -> Result<Option<Outcome>> {
+    let outcome = pipeline.run(idx, key)?;
+    Ok((outcome.origin.is_some() && !outcome.ranked.is_empty()).then_some(outcome))
+}
+
+fn mention(start: usize, end: usize, outcome: Outcome, limit: usize) -> Mention {
+    let mut ranked = outcome.ranked;
+    if limit != 0 {
+        ranked.truncate(limit);
+    }
+    Mention {
+        start,
+        end,
+        key: outcome.key,
+        expanded: matches!(outcome.origin, Some(Origin::Synonym)),
+        ranked,
+    }
+}
+
+/// `tag` (without the '#') as written, split at case changes, then
+/// segmented; the first that names a place.
+fn hashtag<D: AsRef<[u8]>>(
+    idx: &Index<'_, D>,
+    pipeline: &Pipeline<'_, D>,
+    tag: &str,
+) -> Result<Option<Outcome>> {
+    let mut keys = vec![tag.to_string(), split_camel(tag)];
+    keys.extend(segment::segment(idx.fst, &casefold::fold(tag)).map(|(k, _)| k));
+    keys.dedup();
+    for key in keys {
+        if let Some(outcome) = matched(idx, pipeline, &key)? {
+            return Ok(Some(outcome));
+        }
+    }
+    Ok(None)
+}
+
+/// The country `iso` names, its own record ranked first.
+fn flag<D: AsRef<[u8]>>(
+    idx: &Index<'_, D>,
+    pipeline: &Pipeline<'_, D>,
+    countries: &Countries,
+    iso: &str,
+) -> Result<Option<Outcome>> {
+    let Some(info) = countries.get(iso) else {
+        return Ok(None);
+    };
+    let Some(mut outcome) = matched(idx, pipeline, &info.name)? else {
+        return Ok(None);
+    };
+    if let Some(pos) = outcome
+        .ranked
+        .iter()
+        .position(|(r, _)| Some(r.id) == info.geoname_id)
+    {
+        outcome.ranked[..=pos].rotate_right(1);
+    }
+    Ok(Some(outcome))
+}
+
+/// Mentions in `text`, in text order; flags are only read with `countries`.
+pub fn extract<D: AsRef<[u8]>>(
+    idx: &Index<'_, D>,
+    pipeline: &Pipeline<'_, D>,
+    countries: Option<&Countries>,
+    text: &str,
+    limit: usize,
+) -> Result<Vec<Mention>> {
+    let toks = tokens(text);
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < toks.len() {
+        let (start, first_end) = toks[i];
+        if text[..start].ends_with('#') {
+            if let Some(outcome) = hashtag(idx, pipeline, &text[start..first_end])? {
+                out.push(mention(start - 1, first_end, outcome, limit));
+            }
+            i += 1;
+            continue;
+        }
+        if !starts_capitalized(&text[start..first_end]) {
+            i += 1;
+            continue;
+        }
+        // spans stop at punctuation: "Paris, Texas" would be read as a
+        // compound query, "Paris. London" is two mentions
+        let mut span = 1;
+        while span < MAX_SPAN_TOKENS.min(toks.len() - i)
+            && text[toks[i + span - 1].1..toks[i + span].0]
+                .chars()
+                .all(char::is_whitespace)
+        {
+            span += 1;
+        }
+        let mut found = None;
+        for n in (1..=span).rev() {
+            let end = toks[i + n - 1].1;
+            if let Some(outcome) = matched(idx, pipeline, &span_key(&text[start..end]))? {
+                found = Some((n, end, outcome));
+                break;
+            }
+        }
+        let Some((n, end, outcome)) = found else {
+            i += 1;
+            continue;
+        };
+        out.push(mention(start, end, outcome, limit));
+        i += n;
+    }
+    if let Some(countries) = countries {
+        for (start, end, iso) in flags(text) {
+            if let Some(outcome) = flag(idx, pipeline, countries, &iso)? {
+                out.push(mention(start, end, outcome, limit));
+            }
+        }
+        out.sort_by_key(|m| m.start);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{flags, split_camel};
+
+    #[test]
+    fn hashtags_split_at_case_changes() {
+        assert_eq!(split_camel("TelAviv"), "Tel Aviv");
+        assert_eq!(split_camel("RioDeJaneiro"), "Rio De Janeiro");
+        assert_eq!(split_camel("Gaza"), "Gaza");
+        assert_eq!(split_camel("NYC"), "NYC");
+        assert_eq!(split_camel("telaviv"), "telaviv");
+    }
+
+    #[test]
+    fn flags_are_regional_indicator_pairs() {
+        let text = "Kyiv 🇺🇦 and 🇫🇷🇩🇪, not 🇺";
+        let found = flags(text);
+        let isos: Vec<_> = found.iter().map(|(_, _, iso)| iso.as_str()).collect();
+        assert_eq!(isos, ["UA", "FR", "DE"]);
+        let (s, e, _) = found[0];
+        assert_eq!(&text[s..e], "🇺🇦");
+    }
+}
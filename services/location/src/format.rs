@@ -0,0 +1,173 @@
+// src/format.rs
+//
+// Section table (format VERSION 4+):
+//   MAGIC | VERSION u32 | count u32 | count x entry | section bytes...
+//   entry = id u32, codec u32, offset u64 (from file start), length u64
+// Readers look sections up by id and ignore ids they don't know, so optional
+// sections (spatial index, shapes, hierarchy, dictionaries, ...) can be added
+// without a version bump. A known section with an unknown codec is an error.
+// Files from MIN_READ_VERSION on are readable; what changed in each version
+// is listed at the top of build.rs, and tests/golden.rs pins the results.
+
+use anyhow::{bail, Result};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{Read, Write};
+
+use crate::build::{MAGIC, VERSION};
+
+/// Oldest format version the reader still opens. VERSION 5 records have no
+/// timezone.
+pub const MIN_READ_VERSION: u32 = 5;
+
+pub const SECTION_FST: u32 = 1;
+pub const SECTION_POSTINGS: u32 = 2;
+pub const SECTION_RECORDS: u32 = 3;
+pub const SECTION_OFFSETS: u32 = 4;
+pub const SECTION_CONCORDANCE: u32 = 5;
+pub const SECTION_SYNONYMS: u32 = 6;
+/// FST of accent-stripped keys; values are offsets into the postings section.
+pub const SECTION_UNACCENTED: u32 = 7;
+/// Sorted (H3 cell, record id) pairs, see h3.rs.
+pub const SECTION_H3: u32 = 8;
+/// Record points sorted by reverse-geocoding grid cell, see reverse.rs.
+pub const SECTION_SPATIAL: u32 = 9;
+/// Copy of the most populous records, see hot.rs.
+pub const SECTION_HOT: u32 = 10;
+/// Admin1 / admin2 code -> name, see admin.rs.
+pub const SECTION_ADMIN_NAMES: u32 = 11;
+/// ISO 3166-2 code -> ADM1 record id, see subdivision.rs.
+pub const SECTION_SUBDIVISIONS: u32 = 12;
+/// Country -> language list, see locales.rs.
+pub const SECTION_LOCALES: u32 = 13;
+/// IATA / ICAO code -> transport hub ids, see transport.rs.
+pub const SECTION_TRANSPORT: u32 = 14;
+/// FST of single words of multi-word keys; values are offsets into the
+/// postings section, see tokens.rs.
+pub const SECTION_TOKENS: u32 = 15;
+/// FST of "<lang>:<key>" for language-tagged alternate names; values are
+/// offsets into the postings section, see langs.rs.
+pub const SECTION_LANG_NAMES: u32 = 16;
+/// Disputed-territory labeling policy as JSON, see disputed.rs.
+pub const SECTION_DISPUTED: u32 = 17;
+/// Country / admin1 / admin2 code -> admin record id and point, see
+/// hierarchy.rs.
+pub const SECTION_HIERARCHY: u32 = 18;
+/// Sorted (child id, parent id) pairs from GeoNames hierarchy.zip, see
+/// hierarchy.rs.
+pub const SECTION_PARENTS: u32 = 19;
+/// Country metadata from countryInfo.txt as JSON, see countries.rs.
+pub const SECTION_COUNTRIES: u32 = 20;
+/// Roaring threshold the postings lists were written with, u32, see
+/// postings.rs.
+pub const SECTION_POSTINGS_INFO: u32 = 21;
+
+/// Stored as-is.
+pub const CODEC_RAW: u32 = 0;
+
+/// Label for `geodb inspect`; "unknown" for ids this reader predates.
+pub fn section_name(id: u32) -> &'static str {
+    match id {
+        SECTION_FST => "fst",
+        SECTION_POSTINGS => "postings",
+        SECTION_RECORDS => "records",
+        SECTION_OFFSETS => "offsets",
+        SECTION_CONCORDANCE => "concordance",
+        SECTION_SYNONYMS => "synonyms",
+        SECTION_UNACCENTED => "unaccented",
+        SECTION_H3 => "h3",
+        SECTION_SPATIAL => "spatial",
+        SECTION_HOT => "hot",
+        SECTION_ADMIN_NAMES => "admin_names",
+        SECTION_SUBDIVISIONS => "subdivisions",
+        SECTION_LOCALES => "locales",
+        SECTION_TRANSPORT => "transport",
+        SECTION_TOKENS => "tokens",
+        SECTION_LANG_NAMES => "lang_names",
+        SECTION_DISPUTED => "disputed",
+        SECTION_HIERARCHY => "hierarchy",
+        SECTION_PARENTS => "parents",
+        SECTION_COUNTRIES => "countries",
+        SECTION_POSTINGS_INFO => "postings_info",
+        _ => "unknown",
+    }
+}
+
+const ENTRY_LEN: usize = 4 + 4 + 8 + 8;
+
+#[derive(Clone, Copy, Debug)]
+pub struct SectionEntry {
+    pub id: u32,
+    pub codec: u32,
+    pub offset: usize,
+    pub len: usize,
+}
+
+impl SectionEntry {
+    pub fn range(&self) -> std::ops::Range<usize> {
+        self.offset..self.offset + self.len
+    }
+}
+
+/// Write header + table + sections. Empty sections are left out of the table.
+pub fn write_sections<W: Write>(w: &mut W, sections: &[(u32, &[u8])]) -> Result<()> {
+    let present: Vec<&(u32, &[u8])> = sections.iter().filter(|(_, b)| !b.is_empty()).collect();
+
+    w.write_all(MAGIC)?;
+    w.write_u32::<LittleEndian>(VERSION)?;
+    w.write_u32::<LittleEndian>(present.len() as u32)?;
+
+    let mut offset = MAGIC.len() + 4 + 4 + present.len() * ENTRY_LEN;
+    for (id, bytes) in &present {
+        w.write_u32::<LittleEndian>(*id)?;
+        w.write_u32::<LittleEndian>(CODEC_RAW)?;
+        w.write_u64::<LittleEndian>(offset as u64)?;
+        w.write_u64::<LittleEndian>(bytes.len() as u64)?;
+        offset += bytes.len();
+    }
+    for (_, bytes) in &present {
+        w.write_all(bytes)?;
+    }
+    Ok(())
+}
+
+/// Parse and bounds-check the table. Returns the entries, where the table
+/// ends and the file's format version.
+pub fn read_sections(bytes: &[u8]) -> Result<(Vec<SectionEntry>, usize, u32)> {
+    let mut cur = std::io::Cursor::new(bytes);
+
+    let mut magic = [0u8; 7];
+    cur.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        bail!("bad magic");
+    }
+    let ver = cur.read_u32::<LittleEndian>()?;
+    if !(MIN_READ_VERSION..=VERSION).contains(&ver) {
+        bail!("unsupported version {ver} (readable: {MIN_READ_VERSION}..={VERSION})");
+    }
+
+    let count = cur.read_u32::<LittleEndian>()? as usize;
+    let mut entries = Vec::with_capacity(count.min(64));
+    for _ in 0..count {
+        let e = SectionEntry {
+            id: cur.read_u32::<LittleEndian>()?,
+            codec: cur.read_u32::<LittleEndian>()?,
+            offset: cur.read_u64::<LittleEndian>()? as usize,
+            len: cur.read_u64::<LittleEndian>()? as usize,
+        };
+        if !matches!(e.offset.checked_add(e.len), Some(end) if end <= bytes.len()) {
+            bail!("corrupt section table: section {} out of bounds", e.id);
+        }
+        entries.push(e);
+    }
+    Ok((entries, cur.position() as usize, ver))
+}
+
+/// Byte range of a known section; `None` if absent. Only raw sections are
+/// readable so far.
+pub fn find(entries: &[SectionEntry], id: u32) -> Result<Option<std::ops::Range<usize>>> {
+    match entries.iter().find(|e| e.id == id) {
+        Some(e) if e.codec != CODEC_RAW => bail!("section {id}: unsupported codec {}", e.codec),
+        Some(e) => Ok(Some(e.range())),
+        None => Ok(None),
+    }
+}
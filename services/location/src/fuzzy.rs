@@ -0,0 +1,71 @@
+// src/fuzzy.rs
+//
+// `fuzzy=<max_edits>`: misspelled keys ("ljubjana") resolved through a
+// Levenshtein automaton over the existing FST. Only consulted when the exact
+// lookup misses. The allowed distance also shrinks with key length, since one
+// edit on a 3-letter key matches half the index:
+//   1-3 chars: exact only, 4-7 chars: 1 edit, 8+ chars: up to 2 edits.
+// The automaton only answers "within k edits"; the actual distance per key is
+// computed here so clients can rank by it.
+
+use anyhow::{anyhow, Result};
+use fst::automaton::Levenshtein;
+use fst::{IntoStreamer, Streamer};
+
+/// Hard cap; automaton size grows steeply with the distance.
+pub const MAX_EDITS: u32 = 2;
+/// Keys kept per query, closest first.
+const MAX_KEYS: usize = 64;
+
+fn edits_for_len(chars: usize) -> u32 {
+    match chars {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    }
+}
+
+/// Index keys within `max_edits` of `key` (excluding `key` itself) with their
+/// FST value and edit distance, closest first.
+pub fn search<D: AsRef<[u8]>>(
+    fst: &fst::Map<D>,
+    key: &str,
+    max_edits: u32,
+) -> Result<Vec<(String, u64, u32)>> {
+    let edits = max_edits
+        .min(MAX_EDITS)
+        .min(edits_for_len(key.chars().count()));
+    if edits == 0 {
+        return Ok(Vec::new());
+    }
+
+    let lev = Levenshtein::new(key, edits).map_err(|e| anyhow!("fuzzy: {e}"))?;
+    let mut stream = fst.search(&lev).into_stream();
+    let mut out = Vec::new();
+    while let Some((k, off)) = stream.next() {
+        let k = String::from_utf8_lossy(k).into_owned();
+        let d = distance(key, &k);
+        if d > 0 {
+            out.push((k, off, d));
+        }
+    }
+    out.sort_by(|a, b| a.2.cmp(&b.2).then_with(|| a.0.cmp(&b.0)));
+    out.truncate(MAX_KEYS);
+    Ok(out)
+}
+
+/// Levenshtein distance over chars (the automaton counts chars, not bytes).
+fn distance(a: &str, b: &str) -> u32 {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<u32> = (0..=b.len() as u32).collect();
+    let mut cur = vec![0u32; b.len() + 1];
+    for (i, ca) in a.chars().enumerate() {
+        cur[0] = i as u32 + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let sub = prev[j] + u32::from(ca != *cb);
+            cur[j + 1] = sub.min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    prev[b.len()]
+}
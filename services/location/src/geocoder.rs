@@ -0,0 +1,652 @@
+// src/geocoder.rs
+//
+// In-process API for services that embed the reader instead of calling the
+// HTTP server; `geodb query / reverse / suggest` and the server's handlers are
+// thin wrappers over it.
+//   let geo = Geocoder::open(Some(path), OpenOptions::default())?;
+//   let answer = geo.lookup("vienna", &LookupOptions::default())?;
+// `Geocoder::from_bytes` opens a DB that is already in memory instead.
+// Results serialize to the JSON the CLI prints. A Geocoder is Send + Sync;
+// share one per process (its FSTs read the DB bytes in place).
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+use crate::admin::AdminNames;
+use crate::bbox::BBox;
+use crate::build::{FeatureFilter, GeoRecord};
+use crate::casefold;
+use crate::codes;
+use crate::config::Config;
+use crate::coords::{self, Coordinate};
+use crate::countries::Countries;
+use crate::disambiguate::ContextScore;
+use crate::disputed::Policy;
+use crate::hierarchy::{Hierarchy, Parents};
+use crate::hints::{self, DisplayHint};
+use crate::locales::CountryLocales;
+use crate::localtime::{self, LocalTime};
+use crate::nameflags::NameUse;
+use crate::overlay::Overlay;
+use crate::pipeline::{Filter, Index, NamePrefs, Origin, Outcome, Pipeline, Ranker};
+use crate::precision::Precision;
+use crate::ranking::{RankingWeights, ScoreBreakdown};
+use crate::region::Region;
+use crate::reverse::{self, ReverseIndex};
+use crate::scripting::{self, Script};
+use crate::subdivision;
+use crate::suggest::{self, SuggestJson};
+use crate::synonyms::Synonyms;
+use crate::{
+    edit_distance, load_db, read_record_by_id, read_record_ref_by_id, Db, OpenOptions, Section,
+};
+
+pub struct Geocoder {
+    db: Db,
+    fst: fst::Map<Section>,
+    unaccented: Option<fst::Map<Section>>,
+    tokens: Option<fst::Map<Section>>,
+    lang_names: Option<fst::Map<Section>>,
+    synonyms: Option<Synonyms>,
+    admin_names: Option<AdminNames>,
+    locales: Option<CountryLocales>,
+    countries: Option<Countries>,
+    disputed: Option<Policy>,
+    hierarchy: Option<Hierarchy>,
+    /// Parent edges from hierarchy.zip; None for DBs built without.
+    parents: Option<Parents>,
+    weights: RankingWeights,
+    script: Option<Arc<Script>>,
+    /// Built on the first reverse lookup.
+    reverse: OnceLock<ReverseIndex>,
+}
+
+/// Per-lookup settings; the default is the CLI's (no limit, no focus).
+#[derive(Clone, Debug, Default)]
+pub struct LookupOptions {
+    /// 0 = every candidate (reverse lookups: reverse::DEFAULT_LIMIT).
+    pub limit: usize,
+    /// Candidates skipped before `limit`; `Answer::total` still counts them.
+    pub offset: usize,
+    /// Rank by distance to this (lat, lon).
+    pub focus: Option<(f32, f32)>,
+    /// Include per-candidate score breakdowns and the weights used.
+    pub explain: bool,
+    /// On a miss, accept keys within this many edits (see fuzzy.rs).
+    pub fuzzy: Option<u32>,
+    /// On a miss, match single words of multi-word names (tokens.rs).
+    pub tokens: bool,
+    /// Answer from every match path, each place once (`Pipeline::combine`).
+    pub combine: bool,
+    /// Keep only these feature classes / codes; empty = all.
+    pub features: FeatureFilter,
+    /// Keep only candidates inside this box (bbox.rs).
+    pub bbox: Option<BBox>,
+    /// Keep only candidates in this country / admin unit (region.rs).
+    pub within: Option<Region>,
+    /// Reverse lookups: nothing farther than this; None = widen until found.
+    pub max_km: Option<f64>,
+    /// Prefer candidates the key names in this language, e.g. "de" (langs.rs).
+    pub lang: Option<String>,
+    /// Demote / drop candidates matched by a historic or colloquial
+    /// alternate name (nameflags.rs).
+    pub historic: NameUse,
+    pub colloquial: NameUse,
+    /// Year of the article date: prefer names valid then (periods.rs).
+    pub as_of: Option<i32>,
+    /// Add plus_code / geohash to every candidate.
+    pub codes: bool,
+    /// Add each candidate's parent chain.
+    pub hierarchy: bool,
+    /// Add each candidate's UTC offset and DST flag at this instant.
+    pub now: Option<DateTime<Utc>>,
+    /// Coarsen returned points (precision.rs).
+    pub precision: Precision,
+    /// Ranking weights for this lookup instead of the geocoder's.
+    pub weights: Option<RankingWeights>,
+    /// Names served ahead of the DB (overlay.rs).
+    pub overlay: Option<Arc<Overlay>>,
+}
+
+#[derive(Serialize)]
+pub struct Candidate {
+    /// Stable candidate id (the geoname id as a string).
+    pub id: String,
+    /// Ranking score (`score_breakdown.total`); absent for reverse lookups.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+    /// Deprecated: numeric form of `id`, kept for v1 clients.
+    pub geoname_id: u32,
+    pub name: String,
+    pub country: String,
+    /// For DBs built with --country-info (countries.rs).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub country_name: Option<String>,
+    pub admin1: String,
+    pub admin2: String,
+    /// Resolved admin names; absent when the DB has none for the codes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admin1_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admin2_name: Option<String>,
+    /// ISO 3166-2 code of admin1, where known (subdivision.rs).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iso3166_2: Option<String>,
+    /// Country flag ("FR" -> 🇫🇷); absent for non-ISO country codes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flag_emoji: Option<String>,
+    /// Country's primary language tag ("fr-FR"), for DBs built with
+    /// --country-info (locales.rs).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    /// IANA timezone ("Europe/Paris"), for localizing timestamps.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    /// utc_offset / utc_offset_seconds / dst at `LookupOptions::now`.
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub local_time: Option<LocalTime>,
+    /// In a territory of the disputed policy (disputed.rs).
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub disputed: bool,
+    /// The policy's label for that territory.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub territory: Option<String>,
+    /// The policy's name for this record, to show instead of `name`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    pub lat: f32,
+    pub lon: f32,
+    /// Admin record whose point replaced the candidate's (`Precision::Snap`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snapped_to: Option<u32>,
+    /// Parent chain, nearest first, with `LookupOptions::hierarchy`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hierarchy: Option<Vec<Parent>>,
+    pub feature_class: char,
+    pub feature_code: String,
+    pub population: u32,
+    /// Set for fuzzy matches.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub edit_distance: Option<u32>,
+    /// zoom_level / importance for the globe.
+    #[serde(flatten)]
+    pub hint: DisplayHint,
+    /// Factors behind `score`, with explain=true.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score_breakdown: Option<ScoreBreakdown>,
+    /// Set for reverse lookups.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance_km: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plus_code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub geohash: Option<String>,
+    /// Co-mention factors behind `score`, for /resolve (disambiguate.rs).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context: Option<ContextScore>,
+}
+
+#[derive(Serialize)]
+pub struct Parent {
+    pub id: u32,
+    pub name: String,
+    pub feature_code: String,
+}
+
+impl Candidate {
+    fn new(rec: GeoRecord, geo: &Geocoder) -> Self {
+        let admin_names = geo.admin_names.as_ref();
+        let hint = hints::display_hint(&rec);
+        let iso3166_2 = subdivision::iso_code(&rec.country, &rec.admin1);
+        let flag_emoji = hints::flag_emoji(&rec.country);
+        let country_name = geo
+            .countries
+            .as_ref()
+            .and_then(|c| c.name(&rec.country))
+            .map(str::to_string);
+        let admin1_name = admin_names
+            .and_then(|n| n.admin1(&rec.country, &rec.admin1))
+            .map(str::to_string);
+        let admin2_name = admin_names
+            .and_then(|n| n.admin2(&rec.country, &rec.admin1, &rec.admin2))
+            .map(str::to_string);
+        let locale = geo
+            .locales
+            .as_ref()
+            .and_then(|l| l.primary(&rec.country))
+            .map(str::to_string);
+        let marking = geo
+            .disputed
+            .as_ref()
+            .and_then(|p| p.mark(rec.id, &rec.country, &rec.admin1));
+        Self {
+            id: rec.id.to_string(),
+            score: None,
+            geoname_id: rec.id,
+            name: rec.name,
+            country: rec.country,
+            country_name,
+            admin1: rec.admin1,
+            admin2: rec.admin2,
+            admin1_name,
+            admin2_name,
+            iso3166_2,
+            flag_emoji,
+            locale,
+            timezone: (!rec.timezone.is_empty()).then(|| rec.timezone.clone()),
+            local_time: None,
+            disputed: marking.is_some(),
+            territory: marking.as_ref().map(|m| m.territory.to_string()),
+            display_name: marking.and_then(|m| m.display_name).map(str::to_string),
+            lat: rec.lat,
+            lon: rec.lon,
+            snapped_to: None,
+            hierarchy: None,
+            feature_class: rec.feat_class as char,
+            feature_code: rec.feat_code,
+            population: rec.population,
+            edit_distance: None,
+            hint,
+            score_breakdown: None,
+            distance_km: None,
+            plus_code: None,
+            geohash: None,
+            context: None,
+        }
+    }
+
+    /// `score` as the candidate's score; its breakdown too with `explain`.
+    pub fn with_score(mut self, score: ScoreBreakdown, explain: bool) -> Self {
+        self.score = Some(score.total);
+        self.score_breakdown = explain.then_some(score);
+        self
+    }
+}
+
+#[derive(Serialize)]
+pub struct Answer {
+    pub key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub segmented: Option<String>,
+    /// Set when the key was a historical alias expanded to current countries.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expanded_from: Option<String>,
+    /// The parsed point when the key was a coordinate.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coordinates: Option<Coordinate>,
+    /// "token" when only single words of the candidates' names matched.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matched: Option<&'static str>,
+    pub count: usize,
+    /// Candidates before `offset` / `limit`; absent for reverse lookups.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<usize>,
+    pub candidates: Vec<Candidate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ranking: Option<RankingWeights>,
+}
+
+/// A name key's answer before its candidates are built (`Geocoder::rank`):
+/// `answer` has every field but `candidates`, `page` is what they are built
+/// from, so a caller can stream them one at a time.
+pub struct Ranked {
+    pub answer: Answer,
+    pub page: Vec<(GeoRecord, ScoreBreakdown)>,
+    /// Only set for fuzzy matches, see `Outcome::edits`.
+    pub edits: Vec<(u32, u32)>,
+    /// The normalized key that matched (`Outcome::key`).
+    pub matched_key: String,
+    /// Best candidate before `offset`.
+    pub best: Option<(u32, ScoreBreakdown)>,
+    /// Candidate ids the source returned, before filters.
+    pub postings: usize,
+}
+
+impl Geocoder {
+    /// `path` = None opens the DB compiled in with feature "embed". The DB is
+    /// checked for consistency first (strict unless `opts.lenient`).
+    pub fn open(path: Option<&Path>, opts: OpenOptions) -> Result<Self> {
+        Self::from_db(load_db(path, opts)?)
+    }
+
+    /// A DB already in memory (`include_bytes!`, a downloaded build), without
+    /// feature "embed"; nothing is copied. Checked as `open` checks a
+    /// file; `opts.mmap` does not apply.
+    pub fn from_bytes(bytes: &'static [u8], opts: OpenOptions) -> Result<Self> {
+        Self::from_db(Db::from_bytes(bytes)?.checked(opts)?)
+    }
+
+    fn from_db(db: Db) -> Result<Self> {
+        // Over the DB bytes themselves: nothing is copied, and a mapped DB
+        // pages FST nodes in as lookups touch them.
+        let load = |range: &std::ops::Range<usize>,
+                    name: &str|
+         -> Result<Option<fst::Map<Section>>> {
+            if range.is_empty() {
+                return Ok(None);
+            }
+            let map = fst::Map::new(db.section(range)).map_err(|e| anyhow!("{name} load: {e}"))?;
+            Ok(Some(map))
+        };
+        let fst = load(&db.fst, "fst")?.ok_or_else(|| anyhow!("fst load: empty section"))?;
+        let unaccented = load(&db.unaccented, "unaccented fst")?;
+        let tokens = load(&db.tokens, "tokens fst")?;
+        let lang_names = load(&db.lang_names, "lang_names fst")?;
+        let synonyms = Synonyms::from_section(db.synonyms_slice())?;
+        let admin_names = AdminNames::from_section(db.admin_names_slice())?;
+        let locales = CountryLocales::from_section(db.locales_slice())?;
+        let countries = Countries::from_section(db.countries_slice())?;
+        let disputed = Policy::from_section(db.disputed_slice())?;
+        let hierarchy = Hierarchy::from_section(db.hierarchy_slice())?;
+        let parents = Parents::from_section(db.parents_slice())?;
+        Ok(Self {
+            db,
+            fst,
+            unaccented,
+            tokens,
+            lang_names,
+            synonyms,
+            admin_names,
+            locales,
+            countries,
+            disputed,
+            hierarchy,
+            parents,
+            weights: RankingWeights::default(),
+            script: None,
+            reverse: OnceLock::new(),
+        })
+    }
+
+    /// Ranking weights and scoring script from geodb.toml.
+    pub fn with_config(mut self, cfg: &Config) -> Result<Self> {
+        self.weights = cfg.ranking.clone();
+        self.script = scripting::load(&cfg.scripting)?.map(Arc::new);
+        Ok(self)
+    }
+
+    /// A scoring script already loaded, e.g. one shared across DB reloads.
+    pub fn with_script(mut self, script: Option<Arc<Script>>) -> Self {
+        self.script = script;
+        self
+    }
+
+    pub fn db(&self) -> &Db {
+        &self.db
+    }
+
+    /// Every key of the DB, folded (build.rs).
+    pub fn fst(&self) -> &fst::Map<Section> {
+        &self.fst
+    }
+
+    /// countryInfo.txt metadata; None for DBs built without --country-info.
+    pub fn countries(&self) -> Option<&Countries> {
+        self.countries.as_ref()
+    }
+
+    /// What the pipeline reads for `opts` (its overlay, if any).
+    pub fn index<'a>(&'a self, opts: &'a LookupOptions) -> Index<'a, Section> {
+        Index {
+            db: &self.db,
+            fst: &self.fst,
+            unaccented: self.unaccented.as_ref(),
+            tokens: self.tokens.as_ref(),
+            lang_names: self.lang_names.as_ref(),
+            synonyms: self.synonyms.as_ref(),
+            overlay: opts.overlay.as_deref(),
+        }
+    }
+
+    /// Scores candidates with `opts.weights` (else the geocoder's) and focus.
+    pub fn ranker<'a>(&'a self, opts: &'a LookupOptions) -> Ranker<'a> {
+        Ranker {
+            weights: self.weights(opts),
+            script: self.script.as_deref(),
+            focus: opts.focus,
+        }
+    }
+
+    fn weights<'a>(&'a self, opts: &'a LookupOptions) -> &'a RankingWeights {
+        opts.weights.as_ref().unwrap_or(&self.weights)
+    }
+
+    /// Forward lookup: exact / accent-insensitive key, aliases, then fuzzy and
+    /// segmentation fallbacks (pipeline.rs). `key` is folded here. Coordinate
+    /// keys ("48.2082, 16.3738", plus codes, geohashes) are reverse lookups,
+    /// of populated places unless `opts.features` says otherwise (as on
+    /// /reverse) and with `opts.limit` 0 meaning reverse::DEFAULT_LIMIT.
+    pub fn lookup(&self, key: &str, opts: &LookupOptions) -> Result<Answer> {
+        if let Some(at) = coords::parse(key) {
+            let populated;
+            let opts = if opts.features.is_empty() {
+                populated = LookupOptions {
+                    features: reverse::populated(),
+                    ..opts.clone()
+                };
+                &populated
+            } else {
+                opts
+            };
+            return Ok(Answer {
+                key: key.to_string(),
+                ..self.reverse(at, opts)?
+            });
+        }
+        let ranked = self.rank(key, opts)?;
+        self.answer(ranked, opts)
+    }
+
+    /// Lookup, ranking and paging of a name key, without building candidates.
+    pub fn rank(&self, key: &str, opts: &LookupOptions) -> Result<Ranked> {
+        let ranker = self.ranker(opts);
+        let features = (!opts.features.is_empty()).then_some(&opts.features as &dyn Filter);
+        let mut pipeline = Pipeline::standard(&ranker, opts.fuzzy);
+        if opts.tokens {
+            pipeline = pipeline.with_tokens();
+        }
+        let Outcome {
+            key: matched_key,
+            origin,
+            mut ranked,
+            edits,
+            postings,
+            ..
+        } = pipeline
+            .combine(opts.combine)
+            .names(NamePrefs {
+                lang: opts.lang.as_deref(),
+                historic: opts.historic,
+                colloquial: opts.colloquial,
+                as_of: opts.as_of,
+            })
+            .filter(opts.within.as_ref().map(|r| r as &dyn Filter))
+            .filter(opts.bbox.as_ref().map(|b| b as &dyn Filter))
+            .filter(features)
+            .run(&self.index(opts), key)?;
+        let matched = matches!(origin, Some(Origin::Token)).then_some("token");
+        let (segmented, expanded_from) = match origin {
+            Some(Origin::Segmented(k)) => (Some(k), None),
+            Some(Origin::Synonym) => (None, Some(matched_key.clone())),
+            _ => (None, None),
+        };
+
+        // Rank before paging so `limit` keeps the best candidates.
+        let total = ranked.len();
+        let best = ranked.first().map(|(r, s)| (r.id, *s));
+        ranked.drain(..opts.offset.min(total));
+        if opts.limit != 0 {
+            ranked.truncate(opts.limit);
+        }
+        Ok(Ranked {
+            answer: Answer {
+                key: key.to_string(),
+                segmented,
+                expanded_from,
+                coordinates: None,
+                matched,
+                count: ranked.len(),
+                total: Some(total),
+                candidates: Vec::new(),
+                ranking: opts.explain.then(|| self.weights(opts).clone()),
+            },
+            page: ranked,
+            edits,
+            matched_key,
+            best,
+            postings,
+        })
+    }
+
+    /// `ranked`'s answer with its candidates built.
+    pub fn answer(&self, ranked: Ranked, opts: &LookupOptions) -> Result<Answer> {
+        let Ranked {
+            answer,
+            page,
+            edits,
+            ..
+        } = ranked;
+        let candidates = page
+            .into_iter()
+            .map(|(rec, score)| self.scored_candidate(rec, score, &edits, opts))
+            .collect::<Result<_>>()?;
+        Ok(Answer {
+            candidates,
+            ..answer
+        })
+    }
+
+    /// A ranked record as a candidate; `edits` as in `Ranked::edits`.
+    pub fn scored_candidate(
+        &self,
+        rec: GeoRecord,
+        score: ScoreBreakdown,
+        edits: &[(u32, u32)],
+        opts: &LookupOptions,
+    ) -> Result<Candidate> {
+        let mut c = self.candidate(rec, opts)?.with_score(score, opts.explain);
+        c.edit_distance = edit_distance(edits, c.geoname_id);
+        Ok(c)
+    }
+
+    /// `rec` with the DB's names and labels and the extras `opts` asks for:
+    /// parent chain, local time, coarsened point and codes for that point.
+    pub fn candidate(&self, rec: GeoRecord, opts: &LookupOptions) -> Result<Candidate> {
+        let mut c = Candidate::new(rec, self);
+        if opts.hierarchy {
+            c.hierarchy = Some(self.parents_of(&c)?);
+        }
+        if let (Some(now), Some(tz)) = (opts.now, &c.timezone) {
+            c.local_time = localtime::at(tz, now);
+        }
+        if !opts.precision.is_exact() {
+            let ((lat, lon), snapped) = opts.precision.apply(
+                self.hierarchy.as_ref(),
+                (c.lat, c.lon),
+                (&c.country, &c.admin1, &c.admin2),
+            );
+            c.lat = lat;
+            c.lon = lon;
+            c.snapped_to = snapped;
+        }
+        if opts.codes {
+            let at = Coordinate {
+                lat: c.lat,
+                lon: c.lon,
+            };
+            c.plus_code = Some(codes::plus_code_encode(at));
+            c.geohash = Some(codes::geohash_encode(at, codes::GEOHASH_PRECISION));
+        }
+        Ok(c)
+    }
+
+    /// hierarchy.zip edges where the DB has them for `c`, else the admin units
+    /// its codes name.
+    fn parents_of(&self, c: &Candidate) -> Result<Vec<Parent>> {
+        let ids = match self.parents.as_ref().map(|p| p.chain(c.geoname_id)) {
+            Some(ids) if !ids.is_empty() => ids,
+            _ => self
+                .hierarchy
+                .as_ref()
+                .map(|h| h.chain(&c.country, &c.admin1, &c.admin2))
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|&id| id != c.geoname_id)
+                .collect(),
+        };
+        let mut chain = Vec::with_capacity(ids.len());
+        for id in ids {
+            if let Some(rec) = read_record_by_id(&self.db, id)? {
+                chain.push(Parent {
+                    id: rec.id,
+                    name: rec.name,
+                    feature_code: rec.feat_code,
+                });
+            }
+        }
+        Ok(chain)
+    }
+
+    /// Nearest places to `at`, closest first, that pass `opts`' within /
+    /// bbox / feature filters; `opts.limit` 0 = reverse::DEFAULT_LIMIT. The
+    /// search widens until it finds them unless `opts.max_km` stops it.
+    pub fn reverse(&self, at: Coordinate, opts: &LookupOptions) -> Result<Answer> {
+        if !at.in_range() {
+            bail!("coordinates out of range: {},{}", at.lat, at.lon);
+        }
+        let limit = if opts.limit == 0 {
+            reverse::DEFAULT_LIMIT
+        } else {
+            opts.limit
+        };
+        // nearest first: read offset + limit places, drop the first offset
+        let k = limit.saturating_add(opts.offset);
+        let nearest = self.reverse_index()?.nearest(at, k, opts.max_km, |id| {
+            Ok(read_record_ref_by_id(&self.db, id)?.is_some_and(|rec| {
+                opts.within.as_ref().is_none_or(|r| r.contains(&rec))
+                    && opts.bbox.as_ref().is_none_or(|b| b.contains(&rec))
+                    && opts.features.admits(&rec)
+            }))
+        })?;
+
+        let mut candidates = Vec::new();
+        for (id, km) in nearest.into_iter().skip(opts.offset) {
+            let Some(rec) = read_record_ref_by_id(&self.db, id)? else {
+                continue;
+            };
+            let mut c = self.candidate(rec.to_record(), opts)?;
+            c.distance_km = Some(km);
+            candidates.push(c);
+        }
+
+        Ok(Answer {
+            key: format!("{},{}", at.lat, at.lon),
+            segmented: None,
+            expanded_from: None,
+            coordinates: Some(at),
+            matched: None,
+            count: candidates.len(),
+            total: None,
+            candidates,
+            ranking: None,
+        })
+    }
+
+    /// Typeahead keys starting with `prefix`, most populous first.
+    pub fn suggest(&self, prefix: &str, limit: usize) -> Result<SuggestJson> {
+        suggest::suggest(&self.db, &self.fst, &casefold::fold(prefix.trim()), limit)
+    }
+
+    /// The reverse geocoding grid, built on first use.
+    pub fn reverse_index(&self) -> Result<&ReverseIndex> {
+        if self.reverse.get().is_none() {
+            let built = ReverseIndex::build(&self.db)?;
+            // a concurrent caller may have won; either index is the same
+            let _ = self.reverse.set(built);
+        }
+        Ok(self.reverse.get().expect("set above"))
+    }
+}
@@ -0,0 +1,132 @@
+// src/h3.rs
+//
+// Optional H3 cell per record (`geodb build --h3-resolution R`), for
+// `GET /h3/:cell/places`. Section layout:
+//   resolution u32 | count u32 | count x (cell u64, record id u32), sorted
+// All cells share one resolution, so the descendants of a coarser query cell
+// are one contiguous run in that order (unused digits are 7, child digits
+// 0..=6); lookups are two binary searches over the mapped bytes.
+
+use anyhow::{anyhow, bail, Result};
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+use h3o::{CellIndex, LatLng, Resolution};
+
+use crate::build::GeoRecord;
+
+const HEADER_LEN: usize = 8;
+const ENTRY_LEN: usize = 12;
+
+const RES_OFFSET: u32 = 52;
+const RES_MASK: u64 = 0xf << RES_OFFSET;
+
+pub fn build_section(records: &[GeoRecord], resolution: u8) -> Result<Vec<u8>> {
+    let res = Resolution::try_from(resolution)
+        .map_err(|_| anyhow!("--h3-resolution must be 0..=15, got {resolution}"))?;
+
+    let mut cells: Vec<(u64, u32)> = Vec::with_capacity(records.len());
+    let mut skipped = 0usize;
+    for r in records {
+        match LatLng::new(r.lat as f64, r.lon as f64) {
+            Ok(ll) => cells.push((u64::from(ll.to_cell(res)), r.id)),
+            Err(_) => skipped += 1,
+        }
+    }
+    cells.sort_unstable();
+    eprintln!(
+        "[h3] resolution={resolution} cells={} skipped={skipped}",
+        cells.len()
+    );
+
+    let mut out = Vec::with_capacity(HEADER_LEN + cells.len() * ENTRY_LEN);
+    out.write_u32::<LittleEndian>(resolution as u32)?;
+    out.write_u32::<LittleEndian>(cells.len() as u32)?;
+    for (cell, id) in cells {
+        out.write_u64::<LittleEndian>(cell)?;
+        out.write_u32::<LittleEndian>(id)?;
+    }
+    Ok(out)
+}
+
+pub struct H3Section<'a> {
+    pub resolution: u8,
+    entries: &'a [u8],
+}
+
+impl<'a> H3Section<'a> {
+    /// `None` for DBs built without --h3-resolution.
+    pub fn parse(section: &'a [u8]) -> Result<Option<Self>> {
+        if section.is_empty() {
+            return Ok(None);
+        }
+        if section.len() < HEADER_LEN {
+            bail!("corrupt h3 section");
+        }
+        let resolution = LittleEndian::read_u32(&section[0..4]) as u8;
+        let count = LittleEndian::read_u32(&section[4..8]) as usize;
+        let entries = &section[HEADER_LEN..];
+        if entries.len() != count * ENTRY_LEN {
+            bail!(
+                "corrupt h3 section: {count} entries, {} bytes",
+                entries.len()
+            );
+        }
+        Ok(Some(Self {
+            resolution,
+            entries,
+        }))
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len() / ENTRY_LEN
+    }
+
+    fn cell_at(&self, i: usize) -> u64 {
+        LittleEndian::read_u64(&self.entries[i * ENTRY_LEN..])
+    }
+
+    fn id_at(&self, i: usize) -> u32 {
+        LittleEndian::read_u32(&self.entries[i * ENTRY_LEN + 8..])
+    }
+
+    /// First index whose cell is >= `cell`.
+    fn lower_bound(&self, cell: u64) -> usize {
+        let (mut lo, mut hi) = (0, self.len());
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if self.cell_at(mid) < cell {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        lo
+    }
+
+    /// Record ids in `cell`, which may be coarser than the index resolution
+    /// but not finer.
+    pub fn ids_in(&self, cell: CellIndex) -> Result<Vec<u32>> {
+        let cell_res = u8::from(cell.resolution());
+        if cell_res > self.resolution {
+            bail!(
+                "cell resolution {cell_res} is finer than the index resolution {}",
+                self.resolution
+            );
+        }
+        let (first, last) = descendant_range(u64::from(cell), cell_res, self.resolution);
+        let start = self.lower_bound(first);
+        let end = self.lower_bound(last.saturating_add(1));
+        Ok((start..end).map(|i| self.id_at(i)).collect())
+    }
+}
+
+/// Smallest and largest possible descendants of `cell` at `res` (raw indexes).
+fn descendant_range(cell: u64, cell_res: u8, res: u8) -> (u64, u64) {
+    let base = (cell & !RES_MASK) | ((res as u64) << RES_OFFSET);
+    let (mut lo, mut hi) = (base, base);
+    for r in cell_res + 1..=res {
+        let off = (15 - r as u32) * 3;
+        lo &= !(0b111 << off);
+        hi = (hi & !(0b111 << off)) | (0b110 << off);
+    }
+    (lo, hi)
+}
@@ -0,0 +1,250 @@
+// src/hierarchy.rs
+//
+// Admin hierarchy points: the record of each country / ADM1 / ADM2 the DB's
+// records sit in, keyed by the GeoNames code column as admin.rs keys names
+// ("US", "US.CA", "US.CA.075"), with its id and coordinates. Stored as JSON in
+// the hierarchy section; `snap=` on /query moves candidates to these points
+// (precision.rs). Where a code has several admin records (ADM1 and ADM1H, or
+// duplicates), the most populous current one wins.
+//
+// Parent chains: `geodb build --hierarchy hierarchy.zip` stores GeoNames'
+// "ADM" parent edges (child -> parent id) between records in the DB in the
+// parents section, sorted (child u32, parent u32) little-endian pairs.
+// `include=hierarchy` on /query walks them up to the continent ("Maribor" ->
+// Podravska -> Slovenia -> Europe) for breadcrumbs. Records without an edge,
+// or DBs built without the file, get the chain the admin codes imply
+// (admin2 -> admin1 -> country) from the table above.
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+
+use crate::build::{read_line_lossy, with_zip_member, GeoRecord};
+
+/// Longest chain followed; GeoNames chains are at most ADM5 -> continent.
+const MAX_DEPTH: usize = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Country,
+    Admin1,
+    Admin2,
+}
+
+impl Level {
+    pub fn parse(s: &str) -> Result<Self> {
+        Ok(match s.trim() {
+            "country" => Self::Country,
+            "admin1" => Self::Admin1,
+            "admin2" => Self::Admin2,
+            other => bail!("expected country, admin1 or admin2, got {other:?}"),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct AdminPoint {
+    pub id: u32,
+    pub lat: f32,
+    pub lon: f32,
+}
+
+fn code(level: Level, country: &str, admin1: &str, admin2: &str) -> Option<String> {
+    match level {
+        Level::Country if !country.is_empty() => Some(country.to_string()),
+        Level::Admin1 if !country.is_empty() && !admin1.is_empty() => {
+            Some(format!("{country}.{admin1}"))
+        }
+        Level::Admin2 if !country.is_empty() && !admin1.is_empty() && !admin2.is_empty() => {
+            Some(format!("{country}.{admin1}.{admin2}"))
+        }
+        _ => None,
+    }
+}
+
+/// Level an admin record stands for; None for everything else.
+fn level_of(r: &GeoRecord) -> Option<(Level, bool)> {
+    if r.feat_class != b'A' {
+        return None;
+    }
+    match r.feat_code.as_str() {
+        "PCLH" => Some((Level::Country, true)),
+        c if c.starts_with("PCL") => Some((Level::Country, false)),
+        "ADM1" => Some((Level::Admin1, false)),
+        "ADM1H" => Some((Level::Admin1, true)),
+        "ADM2" => Some((Level::Admin2, false)),
+        "ADM2H" => Some((Level::Admin2, true)),
+        _ => None,
+    }
+}
+
+/// Build the section bytes; codes no record refers to are dropped.
+pub fn build_section(records: &[GeoRecord]) -> Result<Vec<u8>> {
+    let mut used: HashSet<String> = HashSet::new();
+    for r in records {
+        for level in [Level::Country, Level::Admin1, Level::Admin2] {
+            used.extend(code(level, &r.country, &r.admin1, &r.admin2));
+        }
+    }
+
+    // code -> ((current, population), point) of the best admin record
+    let mut best: BTreeMap<String, ((bool, u32), AdminPoint)> = BTreeMap::new();
+    for r in records {
+        let Some((level, historic)) = level_of(r) else {
+            continue;
+        };
+        let Some(c) = code(level, &r.country, &r.admin1, &r.admin2) else {
+            continue;
+        };
+        if !used.contains(&c) {
+            continue;
+        }
+        let rank = (!historic, r.population);
+        let point = AdminPoint {
+            id: r.id,
+            lat: r.lat,
+            lon: r.lon,
+        };
+        let e = best.entry(c).or_insert((rank, point));
+        if rank > e.0 {
+            *e = (rank, point);
+        }
+    }
+
+    let table: BTreeMap<String, AdminPoint> = best.into_iter().map(|(c, (_, p))| (c, p)).collect();
+    eprintln!("[hierarchy] admin_points={}", table.len());
+    if table.is_empty() {
+        return Ok(Vec::new());
+    }
+    Ok(serde_json::to_vec(&table)?)
+}
+
+pub struct Hierarchy {
+    table: BTreeMap<String, AdminPoint>,
+}
+
+impl Hierarchy {
+    /// `None` for DBs built before the hierarchy section existed.
+    pub fn from_section(section: &[u8]) -> Result<Option<Self>> {
+        if section.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            table: serde_json::from_slice(section)?,
+        }))
+    }
+
+    /// Point of the finest admin unit at or above `level` containing a
+    /// record with these codes.
+    pub fn containing(
+        &self,
+        level: Level,
+        country: &str,
+        admin1: &str,
+        admin2: &str,
+    ) -> Option<AdminPoint> {
+        [Level::Admin2, Level::Admin1, Level::Country]
+            .into_iter()
+            .filter(|l| *l <= level)
+            .find_map(|l| self.table.get(&code(l, country, admin1, admin2)?).copied())
+    }
+
+    /// Admin records above one with these codes, finest first: admin2,
+    /// admin1, country, where the DB has them.
+    pub fn chain(&self, country: &str, admin1: &str, admin2: &str) -> Vec<u32> {
+        [Level::Admin2, Level::Admin1, Level::Country]
+            .into_iter()
+            .filter_map(|l| self.table.get(&code(l, country, admin1, admin2)?))
+            .map(|p| p.id)
+            .collect()
+    }
+}
+
+/* -------------------------
+   parent chains
+-------------------------- */
+
+/// Parents section bytes from hierarchy.zip; empty without one. Edges to or
+/// from records the DB does not hold are dropped; a child with several ADM
+/// parents keeps the first listed.
+pub fn build_parents_section(records: &[GeoRecord], zip: Option<&Path>) -> Result<Vec<u8>> {
+    let Some(zip) = zip else {
+        return Ok(Vec::new());
+    };
+    let present: HashSet<u32> = records.iter().map(|r| r.id).collect();
+    let mut parent_of: HashMap<u32, u32> = HashMap::new();
+    with_zip_member(zip, "hierarchy.txt", |mut r| {
+        let mut buf = Vec::new();
+        while let Some(line) = read_line_lossy(&mut r, &mut buf)? {
+            // parentId, childId, type
+            let mut cols = line.split('\t');
+            let (Some(parent), Some(child), Some("ADM")) = (
+                cols.next().and_then(|c| c.trim().parse::<u32>().ok()),
+                cols.next().and_then(|c| c.trim().parse::<u32>().ok()),
+                cols.next().map(str::trim),
+            ) else {
+                continue;
+            };
+            if present.contains(&child) && present.contains(&parent) {
+                parent_of.entry(child).or_insert(parent);
+            }
+        }
+        Ok(())
+    })?;
+
+    let mut pairs: Vec<(u32, u32)> = parent_of.into_iter().collect();
+    pairs.sort_unstable();
+    eprintln!("[hierarchy] parent_edges={}", pairs.len());
+    let mut out = Vec::with_capacity(pairs.len() * 8);
+    for (child, parent) in pairs {
+        out.extend_from_slice(&child.to_le_bytes());
+        out.extend_from_slice(&parent.to_le_bytes());
+    }
+    Ok(out)
+}
+
+pub struct Parents {
+    pairs: Vec<(u32, u32)>,
+}
+
+impl Parents {
+    /// `None` for DBs built without --hierarchy.
+    pub fn from_section(section: &[u8]) -> Result<Option<Self>> {
+        if section.is_empty() {
+            return Ok(None);
+        }
+        if !section.len().is_multiple_of(8) {
+            bail!("corrupt parents section: {} bytes", section.len());
+        }
+        let pairs = section
+            .chunks_exact(8)
+            .map(|c| {
+                (
+                    u32::from_le_bytes([c[0], c[1], c[2], c[3]]),
+                    u32::from_le_bytes([c[4], c[5], c[6], c[7]]),
+                )
+            })
+            .collect();
+        Ok(Some(Self { pairs }))
+    }
+
+    pub fn parent(&self, id: u32) -> Option<u32> {
+        let i = self.pairs.binary_search_by_key(&id, |&(c, _)| c).ok()?;
+        Some(self.pairs[i].1)
+    }
+
+    /// Ancestors of `id`, nearest first; stops at a repeated id.
+    pub fn chain(&self, id: u32) -> Vec<u32> {
+        let mut out = Vec::new();
+        let mut cur = id;
+        while let Some(p) = self.parent(cur) {
+            if p == id || out.contains(&p) || out.len() == MAX_DEPTH {
+                break;
+            }
+            out.push(p);
+            cur = p;
+        }
+        out
+    }
+}
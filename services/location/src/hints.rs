@@ -0,0 +1,85 @@
+// src/hints.rs
+//
+// Display hints for the globe: a suggested zoom level (web-map scale, 0 = whole
+// world .. 18 = buildings) and an importance in [0, 1], from feature code and
+// population. Kept server-side so clients don't each grow their own table.
+// Same for `flag_emoji`: the country's regional-indicator pair ("FR" -> 🇫🇷).
+
+use serde::Serialize;
+
+use crate::build::GeoRecord;
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct DisplayHint {
+    pub zoom_level: u8,
+    pub importance: f32,
+}
+
+pub fn display_hint(rec: &GeoRecord) -> DisplayHint {
+    let pop = rec.population;
+    let zoom = zoom_level(rec.feat_class, &rec.feat_code, pop);
+
+    // importance: coarser features and bigger populations matter more
+    let scale = 1.0 - (zoom as f32 - 2.0) / 16.0;
+    let pop_term = ((pop as f32 + 1.0).log10() / 8.0).min(1.0);
+    DisplayHint {
+        zoom_level: zoom,
+        importance: (0.6 * scale + 0.4 * pop_term).clamp(0.0, 1.0),
+    }
+}
+
+/// The zoom part of `display_hint`, from the fields it reads (so callers
+/// holding a borrowed record need not allocate one).
+pub fn zoom_level(feat_class: u8, feat_code: &str, pop: u32) -> u8 {
+    match (feat_class, feat_code) {
+        (_, "CONT") => 2,
+        (_, "OCN") => 3,
+        (b'A', "PCLI" | "PCLD" | "PCLF" | "PCLS" | "PCLIX" | "PCL") => {
+            by_pop(pop, &[(50_000_000, 3), (5_000_000, 4)], 5)
+        }
+        (b'A', "ADM1" | "ADM1H") => 6,
+        (b'A', "ADM2" | "ADM2H") => 8,
+        (b'A', "ADM3" | "ADM3H") => 10,
+        (b'A', _) => 11,
+        (_, "SEA" | "GULF" | "BAY") => 5,
+        (_, "RGN" | "ISLS") => 6,
+        (b'P', "PPLX") => 14,
+        (b'P', _) => by_pop(
+            pop,
+            &[
+                (5_000_000, 9),
+                (1_000_000, 10),
+                (100_000, 11),
+                (10_000, 12),
+                (1_000, 13),
+            ],
+            14,
+        ),
+        (b'T', "ISL") => by_pop(pop, &[(1_000_000, 8), (10_000, 10)], 12),
+        (b'T' | b'H' | b'L', _) => 11,
+        (b'S', _) => 16,
+        _ => 12,
+    }
+}
+
+/// First zoom whose population threshold is met, else `default`.
+fn by_pop(pop: u32, steps: &[(u32, u8)], default: u8) -> u8 {
+    steps
+        .iter()
+        .find(|(min, _)| pop >= *min)
+        .map(|(_, z)| *z)
+        .unwrap_or(default)
+}
+
+/// Flag emoji for an ISO 3166-1 alpha-2 code; None for anything else
+/// (synthetic or missing country codes).
+pub fn flag_emoji(country: &str) -> Option<String> {
+    const REGIONAL_INDICATOR_A: u32 = 0x1F1E6;
+    let b = country.as_bytes();
+    if b.len() != 2 || !b.iter().all(u8::is_ascii_alphabetic) {
+        return None;
+    }
+    b.iter()
+        .map(|c| char::from_u32(REGIONAL_INDICATOR_A + u32::from(c.to_ascii_uppercase() - b'A')))
+        .collect()
+}
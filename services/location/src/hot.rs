@@ -0,0 +1,101 @@
+// src/hot.rs
+//
+// Hot shard: the most populous records (`geodb build --hot-records N`, default
+// 200k) copied into their own section. With --mmap the reader loads just this
+// section into RAM and looks records up there first, so the places most
+// queries resolve to never wait on a page fault; everything else is read from
+// the mapped records section as before. Section layout:
+//   count u32 | count x id u32 (sorted) | count x blob offset u32 | record blob
+// Records use the records-section encoding (build::write_record).
+
+use anyhow::{bail, Result};
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use crate::build::{write_record, GeoRecord};
+use crate::read_u32_le_at;
+
+pub const DEFAULT_RECORDS: usize = 200_000;
+
+pub fn build_section(records: &[GeoRecord], n: usize) -> Result<Vec<u8>> {
+    if n == 0 || records.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut top: Vec<&GeoRecord> = records.iter().collect();
+    if top.len() > n {
+        top.select_nth_unstable_by(n - 1, |a, b| {
+            b.population.cmp(&a.population).then(a.id.cmp(&b.id))
+        });
+        top.truncate(n);
+    }
+    top.sort_unstable_by_key(|r| r.id);
+
+    let mut blob = Vec::new();
+    let mut offsets = Vec::with_capacity(top.len());
+    for r in &top {
+        offsets.push(blob.len() as u32);
+        write_record(&mut blob, r)?;
+    }
+
+    let mut out = Vec::with_capacity(4 + top.len() * 8 + blob.len());
+    out.write_u32::<LittleEndian>(top.len() as u32)?;
+    for r in &top {
+        out.write_u32::<LittleEndian>(r.id)?;
+    }
+    for off in offsets {
+        out.write_u32::<LittleEndian>(off)?;
+    }
+    out.extend_from_slice(&blob);
+    eprintln!(
+        "[hot] records={} bytes={} (min population {})",
+        top.len(),
+        out.len(),
+        top.iter().map(|r| r.population).min().unwrap_or(0)
+    );
+    Ok(out)
+}
+
+/// Owned copy of the hot section.
+pub struct HotRecords {
+    ids: Vec<u32>,
+    offsets: Vec<u32>,
+    blob: Vec<u8>,
+}
+
+impl HotRecords {
+    /// `None` for DBs built without a hot section.
+    pub fn load(section: &[u8]) -> Result<Option<Self>> {
+        if section.is_empty() {
+            return Ok(None);
+        }
+        if section.len() < 4 {
+            bail!("corrupt hot section");
+        }
+        let n = read_u32_le_at(section, 0) as usize;
+        let blob_start = 4 + n * 8;
+        if blob_start > section.len() {
+            bail!("corrupt hot section: {n} records, {} bytes", section.len());
+        }
+        let table = |start: usize| -> Vec<u32> {
+            (0..n)
+                .map(|i| read_u32_le_at(section, start + i * 4))
+                .collect()
+        };
+        let hot = Self {
+            ids: table(4),
+            offsets: table(4 + n * 4),
+            blob: section[blob_start..].to_vec(),
+        };
+        eprintln!(
+            "[hot] loaded {} records ({} bytes)",
+            hot.ids.len(),
+            section.len()
+        );
+        Ok(Some(hot))
+    }
+
+    /// The encoded record for `id`, if it is hot.
+    pub fn get(&self, id: u32) -> Option<&[u8]> {
+        let i = self.ids.binary_search(&id).ok()?;
+        self.blob.get(self.offsets[i] as usize..)
+    }
+}
@@ -0,0 +1,136 @@
+// src/idempotency.rs
+//
+// Idempotency-Key replay for POST /query/batch and /jobs/geocode: a pipeline
+// that times out and retries with the same key gets the first response again
+// instead of a second batch run or a second job.
+// - Entries are keyed by endpoint + header and remember a hash of the body;
+//   the same key with another body is refused (422), and so is a retry while
+//   the first request is still running (409).
+// - Only 2xx responses are kept, for [server] idempotency_ttl, at most
+//   idempotency_entries of them (0 turns replay off). A failed request frees
+//   its key, so the retry runs again.
+// The server's `idempotent` middleware does the HTTP side.
+
+use axum::body::Bytes;
+use axum::http::{HeaderMap, StatusCode};
+use lru::LruCache;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::fnv1a64;
+
+pub const DEFAULT_ENTRIES: usize = 256;
+pub const DEFAULT_TTL: Duration = Duration::from_secs(600);
+/// Longest Idempotency-Key accepted.
+pub const MAX_KEY_LEN: usize = 255;
+
+/// A response as first sent, replayed whole.
+#[derive(Clone)]
+pub struct Stored {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+enum Entry {
+    Running {
+        body: u64,
+    },
+    Done {
+        body: u64,
+        at: Instant,
+        response: Stored,
+    },
+}
+
+/// What `Replays::claim` decided for a request.
+pub enum Claim {
+    /// First time (or the last one expired): run it, then `Pending::finish`.
+    Fresh(Pending),
+    Replay(Stored),
+    /// The first request with this key has not answered yet.
+    InFlight,
+    /// The key was used with another body.
+    Mismatch,
+}
+
+pub struct Replays {
+    ttl: Duration,
+    entries: Mutex<LruCache<String, Entry>>,
+}
+
+impl Replays {
+    /// None when `capacity` is 0.
+    pub fn new(capacity: usize, ttl: Duration) -> Option<Self> {
+        Some(Self {
+            ttl,
+            entries: Mutex::new(LruCache::new(NonZeroUsize::new(capacity)?)),
+        })
+    }
+
+    /// Look up `key` (already scoped to its endpoint) for a request whose
+    /// body is `body`; a fresh key is marked running until the returned
+    /// `Pending` is finished or dropped.
+    pub fn claim(self: &Arc<Self>, key: String, body: &[u8]) -> Claim {
+        let body = fnv1a64(0, body);
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        match entries.get(&key) {
+            Some(Entry::Done { at, .. }) if at.elapsed() >= self.ttl => {}
+            Some(Entry::Running { body: b } | Entry::Done { body: b, .. }) if *b != body => {
+                return Claim::Mismatch
+            }
+            Some(Entry::Running { .. }) => return Claim::InFlight,
+            Some(Entry::Done { response, .. }) => return Claim::Replay(response.clone()),
+            None => {}
+        }
+        entries.put(key.clone(), Entry::Running { body });
+        Claim::Fresh(Pending {
+            replays: self.clone(),
+            key,
+            body,
+            done: false,
+        })
+    }
+}
+
+/// A claimed key. Dropped without `finish` (an error response, or the client
+/// went away) it is released for the next retry.
+pub struct Pending {
+    replays: Arc<Replays>,
+    key: String,
+    body: u64,
+    done: bool,
+}
+
+impl Pending {
+    pub fn finish(mut self, response: Stored) {
+        let entry = Entry::Done {
+            body: self.body,
+            at: Instant::now(),
+            response,
+        };
+        self.replays
+            .entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .put(std::mem::take(&mut self.key), entry);
+        self.done = true;
+    }
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        let mut entries = self
+            .replays
+            .entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if matches!(entries.peek(&self.key), Some(Entry::Running { .. })) {
+            entries.pop(&self.key);
+        }
+    }
+}
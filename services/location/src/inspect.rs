@@ -0,0 +1,153 @@
+// src/inspect.rs
+//
+// `geodb inspect --db file`: what a build contains, for a sanity check before
+// deploying it. Prints JSON: format version, build hash, size of every
+// section, key and record counts, a postings-length histogram and the keys
+// with the longest postings lists (usually generic names or unsanitized
+// junk that should not ship). Opens leniently and counts undecodable
+// postings instead of failing, so a broken build can still be looked at;
+// `geodb preflight` is the strict gate.
+
+use anyhow::{anyhow, Result};
+use fst::Streamer;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::path::Path;
+
+use crate::format;
+use crate::{build_hash, load_db, read_postings_strict, read_u32_le_at, OpenOptions};
+
+pub const TOP_KEYS: usize = 20;
+
+/// Upper bounds of the histogram buckets; the last bucket is open-ended.
+const BUCKETS: [usize; 5] = [1, 10, 100, 1_000, 10_000];
+
+#[derive(Serialize)]
+pub struct InspectJson {
+    pub version: u32,
+    pub build: String,
+    pub file_bytes: usize,
+    pub sections: Vec<SectionJson>,
+    pub keys: usize,
+    pub records: usize,
+    pub postings: PostingsJson,
+    /// Keys with the most ids, longest first.
+    pub top_keys: Vec<KeyJson>,
+}
+
+#[derive(Serialize)]
+pub struct SectionJson {
+    pub id: u32,
+    pub name: &'static str,
+    pub bytes: usize,
+}
+
+#[derive(Serialize)]
+pub struct PostingsJson {
+    pub min: usize,
+    pub max: usize,
+    pub avg: f64,
+    /// Lists per length range, e.g. "2-10".
+    pub histogram: Vec<BucketJson>,
+    /// Lists that failed to decode.
+    pub bad: usize,
+}
+
+#[derive(Serialize)]
+pub struct BucketJson {
+    pub range: String,
+    pub lists: usize,
+}
+
+#[derive(Serialize)]
+pub struct KeyJson {
+    pub key: String,
+    pub ids: usize,
+}
+
+pub fn inspect(path: &Path) -> Result<InspectJson> {
+    // mapped: streaming the postings pages them in, but never all at once
+    let db = load_db(
+        Some(path),
+        OpenOptions {
+            lenient: true,
+            mmap: true,
+        },
+    )?;
+
+    let (entries, _, version) = format::read_sections(&db.bytes)?;
+    let sections = entries
+        .iter()
+        .map(|e| SectionJson {
+            id: e.id,
+            name: format::section_name(e.id),
+            bytes: e.len,
+        })
+        .collect();
+
+    let map = fst::Map::new(db.fst_slice()).map_err(|e| anyhow!("fst load: {e}"))?;
+    let mut counts = [0usize; BUCKETS.len() + 1];
+    let (mut min, mut max, mut total, mut lists, mut bad) = (usize::MAX, 0, 0u64, 0usize, 0);
+    let mut top: BinaryHeap<Reverse<(usize, String)>> = BinaryHeap::with_capacity(TOP_KEYS + 1);
+    let mut stream = map.stream();
+    while let Some((k, off)) = stream.next() {
+        let Ok(list) = read_postings_strict(&db, off as usize) else {
+            bad += 1;
+            continue;
+        };
+        let n = list.len() as usize;
+        lists += 1;
+        total += n as u64;
+        min = min.min(n);
+        max = max.max(n);
+        counts[BUCKETS.partition_point(|&b| b < n)] += 1;
+        if top.len() < TOP_KEYS || top.peek().is_some_and(|Reverse((m, _))| n > *m) {
+            top.push(Reverse((n, String::from_utf8_lossy(k).into_owned())));
+            if top.len() > TOP_KEYS {
+                top.pop();
+            }
+        }
+    }
+
+    let histogram = counts
+        .iter()
+        .enumerate()
+        .map(|(i, &lists)| {
+            let lo = if i == 0 { 1 } else { BUCKETS[i - 1] + 1 };
+            let range = match BUCKETS.get(i) {
+                Some(&hi) if hi == lo => format!("{hi}"),
+                Some(&hi) => format!("{lo}-{hi}"),
+                None => format!("{lo}+"),
+            };
+            BucketJson { range, lists }
+        })
+        .collect();
+    let mut top_keys: Vec<KeyJson> = top
+        .into_iter()
+        .map(|Reverse((ids, key))| KeyJson { key, ids })
+        .collect();
+    top_keys.sort_by(|a, b| b.ids.cmp(&a.ids).then_with(|| a.key.cmp(&b.key)));
+
+    let offsets = db.offsets_slice();
+    Ok(InspectJson {
+        version,
+        build: build_hash(&db),
+        file_bytes: db.bytes.len(),
+        sections,
+        keys: map.len(),
+        records: read_u32_le_at(offsets, 0) as usize,
+        postings: PostingsJson {
+            min: if lists == 0 { 0 } else { min },
+            max,
+            avg: if lists == 0 {
+                0.0
+            } else {
+                total as f64 / lists as f64
+            },
+            histogram,
+            bad,
+        },
+        top_keys,
+    })
+}
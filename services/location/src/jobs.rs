@@ -0,0 +1,379 @@
+// src/jobs.rs
+//
+// Background geocoding jobs for inputs too large for one request.
+// - POST /jobs/geocode {"keys": [...]} or {"file": "batch.txt"} (one key per
+//   line, relative to [jobs] input_dir; file references are refused without it)
+// - GET /jobs/:id          status, progress and per-status counts
+// - GET /jobs/:id/results  NDJSON once done: one line per input key with
+//   status ok / not_found / error, so one bad key does not fail the job
+// Results are files under [jobs] dir; finished jobs expire after [jobs] ttl.
+// The store knows nothing about lookups: the server hands it a resolver.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::units;
+
+/// `JobStore::spawn` refused because [jobs] max_active jobs are running; the
+/// only refusal worth retrying, so the server answers it with 503.
+#[derive(Debug)]
+pub struct Busy {
+    pub active: usize,
+}
+
+impl std::fmt::Display for Busy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} jobs already running; retry later", self.active)
+    }
+}
+
+impl std::error::Error for Busy {}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JobsConfig {
+    /// Where results are written; default: <tmp>/geodb-jobs.
+    pub dir: Option<PathBuf>,
+    /// Directory `{"file": ...}` references are resolved against.
+    pub input_dir: Option<PathBuf>,
+    /// Finished jobs (and their result files) are dropped after this long.
+    #[serde(alias = "ttl_secs")]
+    pub ttl: units::Duration,
+    /// Upper bound on keys per job.
+    pub max_keys: usize,
+    /// Jobs running or queued at once.
+    pub max_active: usize,
+}
+
+impl Default for JobsConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            input_dir: None,
+            ttl: units::Duration::from_secs(3600),
+            max_keys: 5_000_000,
+            max_active: 4,
+        }
+    }
+}
+
+impl JobsConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_keys == 0 {
+            return Err("max_keys must be > 0".into());
+        }
+        if self.max_active == 0 {
+            return Err("max_active must be > 0".into());
+        }
+        if self.ttl.is_zero() {
+            return Err("ttl must be > 0".into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GeocodeJobRequest {
+    #[serde(default)]
+    pub keys: Option<Vec<String>>,
+    #[serde(default)]
+    pub file: Option<String>,
+    /// Candidates per key; default 5.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Running,
+    Done,
+    Failed,
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct JobSummary {
+    pub ok: usize,
+    pub not_found: usize,
+    pub error: usize,
+}
+
+struct JobInner {
+    status: JobStatus,
+    error: Option<String>,
+    summary: JobSummary,
+    finished: Option<Instant>,
+}
+
+pub struct Job {
+    id: u64,
+    total: AtomicUsize,
+    processed: AtomicUsize,
+    results: PathBuf,
+    inner: Mutex<JobInner>,
+}
+
+#[derive(Serialize)]
+pub struct JobView {
+    pub id: String,
+    pub status: JobStatus,
+    pub total: usize,
+    pub processed: usize,
+    pub summary: JobSummary,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub results: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ResultLine<'a, T: Serialize> {
+    key: &'a str,
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    candidates: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Job {
+    fn lock(&self) -> std::sync::MutexGuard<'_, JobInner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn view(&self) -> JobView {
+        let inner = self.lock();
+        JobView {
+            id: format_id(self.id),
+            status: inner.status,
+            total: self.total.load(Ordering::Relaxed),
+            processed: self.processed.load(Ordering::Relaxed),
+            summary: inner.summary,
+            error: inner.error.clone(),
+            results: (inner.status == JobStatus::Done)
+                .then(|| format!("/v1/jobs/{}/results", format_id(self.id))),
+        }
+    }
+
+    pub fn status(&self) -> JobStatus {
+        self.lock().status
+    }
+
+    pub fn results_path(&self) -> &Path {
+        &self.results
+    }
+}
+
+pub struct JobStore {
+    cfg: JobsConfig,
+    dir: PathBuf,
+    next: AtomicU64,
+    jobs: Mutex<HashMap<u64, Arc<Job>>>,
+}
+
+impl JobStore {
+    pub fn new(cfg: &JobsConfig) -> Result<Self> {
+        let dir = cfg
+            .dir
+            .clone()
+            .unwrap_or_else(|| std::env::temp_dir().join("geodb-jobs"));
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("create jobs dir: {}", dir.display()))?;
+        // ids stay unique across restarts without persisting a counter
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0)
+            << 16;
+        Ok(Self {
+            cfg: cfg.clone(),
+            dir,
+            next: AtomicU64::new(seed),
+            jobs: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn jobs(&self) -> std::sync::MutexGuard<'_, HashMap<u64, Arc<Job>>> {
+        self.jobs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Jobs not yet done or failed.
+    pub fn running(&self) -> usize {
+        self.jobs()
+            .values()
+            .filter(|j| j.status() == JobStatus::Running)
+            .count()
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<Job>> {
+        let id = u64::from_str_radix(id, 16).ok()?;
+        self.jobs().get(&id).cloned()
+    }
+
+    /// Validate the request, register the job and start it on a blocking thread.
+    /// `resolve` returns Some(candidates) or None (not found) per key.
+    pub fn spawn<T, F>(&self, req: GeocodeJobRequest, resolve: F) -> Result<Arc<Job>>
+    where
+        T: Serialize + 'static,
+        F: Fn(&str, usize) -> Result<Option<T>> + Send + 'static,
+    {
+        let input = match (req.keys, req.file) {
+            (Some(keys), None) => {
+                if keys.len() > self.cfg.max_keys {
+                    bail!("{} keys exceeds max_keys {}", keys.len(), self.cfg.max_keys);
+                }
+                Input::Keys(keys)
+            }
+            (None, Some(file)) => Input::File(self.input_path(&file)?),
+            _ => bail!("pass exactly one of \"keys\" or \"file\""),
+        };
+        let limit = req.limit.filter(|l| *l > 0).unwrap_or(5);
+
+        self.expire();
+        let active = self
+            .jobs()
+            .values()
+            .filter(|j| j.status() == JobStatus::Running)
+            .count();
+        if active >= self.cfg.max_active {
+            bail!(Busy { active });
+        }
+
+        let id = self.next.fetch_add(1, Ordering::Relaxed);
+        let job = Arc::new(Job {
+            id,
+            total: AtomicUsize::new(0),
+            processed: AtomicUsize::new(0),
+            results: self.dir.join(format!("{}.ndjson", format_id(id))),
+            inner: Mutex::new(JobInner {
+                status: JobStatus::Running,
+                error: None,
+                summary: JobSummary::default(),
+                finished: None,
+            }),
+        });
+        self.jobs().insert(id, job.clone());
+
+        let worker = job.clone();
+        let max_keys = self.cfg.max_keys;
+        tokio::task::spawn_blocking(move || {
+            let res = run(&worker, input, limit, max_keys, resolve);
+            let mut inner = worker.lock();
+            inner.finished = Some(Instant::now());
+            match res {
+                Ok(()) => inner.status = JobStatus::Done,
+                Err(e) => {
+                    eprintln!("[jobs] {} failed: {e:#}", format_id(worker.id));
+                    inner.status = JobStatus::Failed;
+                    inner.error = Some(format!("{e:#}"));
+                }
+            }
+        });
+        Ok(job)
+    }
+
+    fn input_path(&self, file: &str) -> Result<PathBuf> {
+        let base = self
+            .cfg
+            .input_dir
+            .as_ref()
+            .ok_or_else(|| anyhow!("file references need [jobs] input_dir in the config"))?;
+        let rel = Path::new(file);
+        if !rel.components().all(|c| matches!(c, Component::Normal(_))) {
+            bail!("file must be a relative path inside input_dir");
+        }
+        Ok(base.join(rel))
+    }
+
+    /// Drop finished jobs past their TTL, with their result files.
+    fn expire(&self) {
+        let ttl = self.cfg.ttl.0;
+        self.jobs().retain(|_, job| {
+            let expired = job.lock().finished.is_some_and(|t| t.elapsed() > ttl);
+            if expired {
+                let _ = std::fs::remove_file(&job.results);
+            }
+            !expired
+        });
+    }
+}
+
+enum Input {
+    Keys(Vec<String>),
+    File(PathBuf),
+}
+
+fn run<T, F>(job: &Job, input: Input, limit: usize, max_keys: usize, resolve: F) -> Result<()>
+where
+    T: Serialize,
+    F: Fn(&str, usize) -> Result<Option<T>>,
+{
+    let keys = match input {
+        Input::Keys(keys) => keys,
+        Input::File(path) => {
+            let f = File::open(&path).with_context(|| format!("open {}", path.display()))?;
+            let mut keys = Vec::new();
+            for line in BufReader::new(f).lines() {
+                let line = line?;
+                if !line.trim().is_empty() {
+                    keys.push(line);
+                }
+                if keys.len() > max_keys {
+                    bail!("input exceeds max_keys {max_keys}");
+                }
+            }
+            keys
+        }
+    };
+    job.total.store(keys.len(), Ordering::Relaxed);
+
+    let out =
+        File::create(&job.results).with_context(|| format!("create {}", job.results.display()))?;
+    let mut w = BufWriter::new(out);
+    for key in &keys {
+        let line = match resolve(key, limit) {
+            Ok(Some(c)) => ResultLine {
+                key,
+                status: "ok",
+                candidates: Some(c),
+                error: None,
+            },
+            Ok(None) => ResultLine {
+                key,
+                status: "not_found",
+                candidates: None,
+                error: None,
+            },
+            Err(e) => ResultLine {
+                key,
+                status: "error",
+                candidates: None,
+                error: Some(format!("{e:#}")),
+            },
+        };
+        {
+            let mut inner = job.lock();
+            match line.status {
+                "ok" => inner.summary.ok += 1,
+                "not_found" => inner.summary.not_found += 1,
+                _ => inner.summary.error += 1,
+            }
+        }
+        serde_json::to_writer(&mut w, &line)?;
+        w.write_all(b"\n")?;
+        job.processed.fetch_add(1, Ordering::Relaxed);
+    }
+    w.flush()?;
+    Ok(())
+}
+
+fn format_id(id: u64) -> String {
+    format!("{id:016x}")
+}
@@ -0,0 +1,197 @@
+// src/labels.rs
+//
+// Labels DB (`geodb build --emit-labels labels.db`): id, name, point,
+// population and country of every record, in a file of its own for the
+// frontend's tile / label rendering, which needs nothing else of a record and
+// should not page through the records section (or load the FSTs) for it.
+// Layout:
+//   LABELS_MAGIC | LABELS_VERSION u32 | count u32 | count x row | names
+//   row = id u32, lat f32, lon f32, population u32, country [u8; 2],
+//         name length u16, name offset u32 (into names); 24 bytes
+// Rows are sorted by population, largest first (ties by id), so the labels a
+// view should draw first are a prefix of any scan (`in_bbox`). Lookups by id
+// go through an (id, row) index built on load.
+
+use anyhow::{bail, Context, Result};
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::bbox::BBox;
+use crate::build::GeoRecord;
+use crate::{read_u32_le_at, read_u64_le_at};
+
+pub const LABELS_MAGIC: &[u8; 8] = b"GEOLBL1\0";
+pub const LABELS_VERSION: u32 = 1;
+
+const HEADER: usize = 16;
+const ROW: usize = 24;
+
+/// Writes `records` as a labels DB at `out`.
+pub fn write(out: &Path, records: &[GeoRecord]) -> Result<()> {
+    let mut rows: Vec<&GeoRecord> = records.iter().collect();
+    rows.sort_unstable_by(|a, b| b.population.cmp(&a.population).then(a.id.cmp(&b.id)));
+
+    let mut names = Vec::new();
+    let mut table = Vec::with_capacity(rows.len() * ROW);
+    for r in &rows {
+        let len = u16::try_from(r.name.len())
+            .with_context(|| format!("labels: name of {} is {} bytes", r.id, r.name.len()))?;
+        table.write_u32::<LittleEndian>(r.id)?;
+        table.write_f32::<LittleEndian>(r.lat)?;
+        table.write_f32::<LittleEndian>(r.lon)?;
+        table.write_u32::<LittleEndian>(r.population)?;
+        table.extend_from_slice(&country_bytes(&r.country));
+        table.write_u16::<LittleEndian>(len)?;
+        table.write_u32::<LittleEndian>(names.len() as u32)?;
+        names.extend_from_slice(r.name.as_bytes());
+    }
+    if u32::try_from(names.len()).is_err() {
+        bail!("labels: names take {} bytes, more than 4 GiB", names.len());
+    }
+
+    let mut w = BufWriter::new(
+        File::create(out).with_context(|| format!("create labels: {}", out.display()))?,
+    );
+    w.write_all(LABELS_MAGIC)?;
+    w.write_u32::<LittleEndian>(LABELS_VERSION)?;
+    w.write_u32::<LittleEndian>(rows.len() as u32)?;
+    w.write_all(&table)?;
+    w.write_all(&names)?;
+    w.flush()?;
+    eprintln!(
+        "[labels] out={} records={} bytes={}",
+        out.display(),
+        rows.len(),
+        HEADER + table.len() + names.len()
+    );
+    Ok(())
+}
+
+/// "FR" as stored; anything but two ASCII letters is stored as none.
+fn country_bytes(country: &str) -> [u8; 2] {
+    match country.as_bytes() {
+        &[a, b] if a.is_ascii_alphabetic() && b.is_ascii_alphabetic() => {
+            [a.to_ascii_uppercase(), b.to_ascii_uppercase()]
+        }
+        _ => [0, 0],
+    }
+}
+
+/// One row, borrowing the file bytes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Label<'a> {
+    pub id: u32,
+    pub name: &'a str,
+    pub lat: f32,
+    pub lon: f32,
+    pub population: u32,
+    /// ISO 3166-1 alpha-2; empty when the record has none.
+    pub country: &'a str,
+}
+
+/// A labels DB read into memory.
+pub struct Labels {
+    bytes: Vec<u8>,
+    count: usize,
+    /// (id, row) sorted by id.
+    by_id: Vec<(u32, u32)>,
+}
+
+impl Labels {
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes =
+            std::fs::read(path).with_context(|| format!("read labels: {}", path.display()))?;
+        Self::from_bytes(bytes).with_context(|| format!("labels {}", path.display()))
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        if bytes.len() < HEADER || &bytes[..8] != LABELS_MAGIC {
+            bail!("not a labels DB");
+        }
+        let version = read_u32_le_at(&bytes, 8);
+        if version != LABELS_VERSION {
+            bail!("labels version {version}; this reader knows {LABELS_VERSION}");
+        }
+        let count = read_u32_le_at(&bytes, 12) as usize;
+        let names_start = HEADER + count * ROW;
+        if names_start > bytes.len() {
+            bail!("corrupt labels: {count} rows, {} bytes", bytes.len());
+        }
+        let mut labels = Self {
+            bytes,
+            count,
+            by_id: Vec::with_capacity(count),
+        };
+        for i in 0..count {
+            let (len, off) = labels.name_span(i);
+            if names_start + off + len > labels.bytes.len() {
+                bail!("corrupt labels: name of row {i} out of bounds");
+            }
+            std::str::from_utf8(&labels.bytes[names_start + off..][..len])
+                .with_context(|| format!("corrupt labels: name of row {i}"))?;
+            labels.by_id.push((labels.row(i, 0), i as u32));
+        }
+        labels.by_id.sort_unstable();
+        Ok(labels)
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn get(&self, id: u32) -> Option<Label<'_>> {
+        let i = self.by_id.binary_search_by_key(&id, |(id, _)| *id).ok()?;
+        Some(self.label(self.by_id[i].1 as usize))
+    }
+
+    /// Every label, most populous first.
+    pub fn iter(&self) -> impl Iterator<Item = Label<'_>> {
+        (0..self.count).map(|i| self.label(i))
+    }
+
+    /// The `limit` most populous labels inside `bbox`.
+    pub fn in_bbox(&self, bbox: &BBox, limit: usize) -> Vec<Label<'_>> {
+        self.iter()
+            .filter(|l| bbox.contains_point(l.lat, l.lon))
+            .take(limit)
+            .collect()
+    }
+
+    fn label(&self, i: usize) -> Label<'_> {
+        let at = HEADER + i * ROW;
+        let country = &self.bytes[at + 16..at + 18];
+        let (len, off) = self.name_span(i);
+        let names_start = HEADER + self.count * ROW;
+        // both checked in from_bytes
+        let name = std::str::from_utf8(&self.bytes[names_start + off..][..len]).unwrap_or("");
+        Label {
+            id: self.row(i, 0),
+            name,
+            lat: f32::from_bits(self.row(i, 4)),
+            lon: f32::from_bits(self.row(i, 8)),
+            population: self.row(i, 12),
+            country: if country == [0, 0] {
+                ""
+            } else {
+                std::str::from_utf8(country).unwrap_or("")
+            },
+        }
+    }
+
+    /// u32 at byte `field` of row `i`.
+    fn row(&self, i: usize, field: usize) -> u32 {
+        read_u32_le_at(&self.bytes, HEADER + i * ROW + field)
+    }
+
+    /// (length, offset into names) of row `i`'s name.
+    fn name_span(&self, i: usize) -> (usize, usize) {
+        let packed = read_u64_le_at(&self.bytes, HEADER + i * ROW + 16);
+        ((packed >> 16) as u16 as usize, (packed >> 32) as usize)
+    }
+}
@@ -0,0 +1,50 @@
+// src/langs.rs
+//
+// Alternate-name languages. alternateNamesV2 tags most names with an ISO 639
+// code ("de" for "Mailand"); the build keeps every such name a second time in
+// the lang-names FST under "<lang>:<key>" ("de:mailand"), over the shared
+// postings blob. `lang=de` on /query then boosts candidates whose German name
+// is the key by ranking.lang_boost: a German article's "Mailand" prefers
+// Milan even where another place is called Mailand too. It is a preference,
+// not a filter: names most languages share ("Paris") are rarely tagged, and
+// dropping their untagged candidates would lose the obvious answer.
+// Pseudo-codes (wkdt, iata, post, link, fr_1793, ...) are 4+ characters and
+// never pass `is_language`.
+
+use anyhow::{bail, Result};
+
+use crate::{read_key_postings, Db};
+
+/// ISO 639-1 / 639-3 style tag: 2-3 lowercase letters.
+pub fn is_language(iso: &str) -> bool {
+    (2..=3).contains(&iso.len()) && iso.bytes().all(|b| b.is_ascii_lowercase())
+}
+
+/// FST key of `key` named in `lang`.
+pub fn lang_key(lang: &str, key: &str) -> Option<String> {
+    is_language(lang).then(|| format!("{lang}:{key}"))
+}
+
+/// Normalize a `lang=` query parameter ("DE" -> "de").
+pub fn parse(lang: &str) -> Result<String> {
+    let lang = lang.trim().to_ascii_lowercase();
+    if !is_language(&lang) {
+        bail!("expected a 2-3 letter language code like de, got {lang:?}");
+    }
+    Ok(lang)
+}
+
+/// Sorted ids that have `key` as a name in `lang`.
+pub fn ids<D: AsRef<[u8]>>(
+    db: &Db,
+    lang_names: &fst::Map<D>,
+    lang: &str,
+    key: &str,
+) -> Result<Vec<u32>> {
+    let Some(k) = lang_key(lang, key) else {
+        return Ok(Vec::new());
+    };
+    Ok(read_key_postings(db, lang_names, None, &k)?
+        .map(|h| h.ids)
+        .unwrap_or_default())
+}
@@ -0,0 +1,1029 @@
+// src/lib.rs
+// geodb-core: reader, builder and HTTP server behind the `geodb` binary.
+// Services that want lookups in-process use `Geocoder` (geocoder.rs); the
+// modules are public for the CLI (main.rs) and are not a stable interface.
+// Exact-match index (no tokenization); keys are folded, see casefold.rs.
+// The only unsafe is the read-only mmap behind --mmap (see `map_db`).
+
+use anyhow::{anyhow, bail, Result};
+use byteorder::{LittleEndian, ReadBytesExt};
+use fst::Streamer;
+use std::fs::File;
+use std::io::Read;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+pub mod accents;
+pub mod admin;
+pub mod audit;
+pub mod bbox;
+pub mod build;
+pub mod cache;
+pub mod casefold;
+pub mod codes;
+pub mod concordance;
+pub mod config;
+pub mod coords;
+pub mod countries;
+pub mod coverage;
+pub mod csv_source;
+pub mod diagnostics;
+pub mod disambiguate;
+pub mod disputed;
+pub mod estimate;
+pub mod extract;
+pub mod format;
+pub mod fuzzy;
+pub mod geocoder;
+pub mod h3;
+pub mod hierarchy;
+pub mod hints;
+pub mod hot;
+pub mod idempotency;
+pub mod inspect;
+pub mod jobs;
+pub mod labels;
+pub mod langs;
+pub mod locales;
+pub mod localtime;
+pub mod metrics;
+pub mod nameflags;
+pub mod osm;
+pub mod overlay;
+pub mod periods;
+pub mod pipeline;
+pub mod postings;
+pub mod precision;
+pub mod preflight;
+pub mod qualifier;
+pub mod ranking;
+pub mod region;
+pub mod registry;
+pub mod reload;
+pub mod reverse;
+pub mod sanitize;
+pub mod scripting;
+pub mod segment;
+pub mod server;
+pub mod sets;
+pub mod singleflight;
+pub mod smoke;
+pub mod spill;
+#[cfg(any(feature = "audit", feature = "registry"))]
+pub mod store;
+pub mod subdivision;
+pub mod suggest;
+pub mod synonyms;
+pub mod throttle;
+pub mod tiles;
+pub mod tokens;
+pub mod transport;
+pub mod units;
+pub mod update;
+pub mod validate;
+pub mod wof;
+
+use build::GeoRecord;
+pub use geocoder::{Answer, Candidate, Geocoder, LookupOptions};
+
+/* -------------------------
+   DB reader
+-------------------------- */
+
+/// An open DB file (see `load_db`); embedders go through `Geocoder`.
+pub struct Db {
+    /// End of the section table; section bytes follow.
+    header_len: usize,
+    /// Format version of the file; decides the record layout.
+    version: u32,
+    fst: Range<usize>,
+    postings: Range<usize>,
+    records: Range<usize>,
+    offsets: Range<usize>,
+    /// Empty when the DB has no concordance section.
+    concordance: Range<usize>,
+    /// Empty when the DB has no synonyms section.
+    synonyms: Range<usize>,
+    /// Empty when the DB has no unaccented-key FST.
+    unaccented: Range<usize>,
+    /// Empty when the DB was built without --h3-resolution.
+    h3: Range<usize>,
+    /// Empty for DBs built before the spatial section existed.
+    spatial: Range<usize>,
+    /// Empty when built with --hot-records 0.
+    hot: Range<usize>,
+    /// Empty when built without --admin1-codes / --admin2-codes.
+    admin_names: Range<usize>,
+    /// Empty for DBs built before the subdivisions section existed.
+    subdivisions: Range<usize>,
+    /// Empty when built without --country-info.
+    locales: Range<usize>,
+    /// Empty when built without --country-info, or before it existed.
+    countries: Range<usize>,
+    /// Empty when built without alternate names (no codes to index).
+    transport: Range<usize>,
+    /// Empty for DBs built before the tokens FST existed.
+    tokens: Range<usize>,
+    /// Empty when built without alternate names, or before it existed.
+    lang_names: Range<usize>,
+    /// Empty when built without --disputed-policy.
+    disputed: Range<usize>,
+    /// Empty for DBs built before the hierarchy section existed.
+    hierarchy: Range<usize>,
+    /// Empty when built without --hierarchy.
+    parents: Range<usize>,
+    /// Empty for DBs built before it existed.
+    postings_info: Range<usize>,
+    /// Shared so FSTs can be built over a section without copying it
+    /// (`Db::section`).
+    bytes: Arc<DbBytes>,
+    /// Hot section copied into RAM; only loaded for mapped DBs, where it saves
+    /// page faults on the records most lookups return.
+    hot_records: Option<hot::HotRecords>,
+    /// Lenient open mode: undecodable postings / records are logged and
+    /// skipped instead of failing the lookup.
+    lenient: bool,
+    /// Undecodable reads seen in lenient mode.
+    bad_reads: AtomicU64,
+}
+
+impl Db {
+    fn fst_slice(&self) -> &[u8] {
+        &self.bytes[self.fst.clone()]
+    }
+    fn postings_slice(&self) -> &[u8] {
+        &self.bytes[self.postings.clone()]
+    }
+    fn concordance_slice(&self) -> &[u8] {
+        &self.bytes[self.concordance.clone()]
+    }
+    fn synonyms_slice(&self) -> &[u8] {
+        &self.bytes[self.synonyms.clone()]
+    }
+    fn unaccented_slice(&self) -> &[u8] {
+        &self.bytes[self.unaccented.clone()]
+    }
+    fn h3_slice(&self) -> &[u8] {
+        &self.bytes[self.h3.clone()]
+    }
+    fn hot_slice(&self) -> &[u8] {
+        &self.bytes[self.hot.clone()]
+    }
+    fn admin_names_slice(&self) -> &[u8] {
+        &self.bytes[self.admin_names.clone()]
+    }
+    fn subdivisions_slice(&self) -> &[u8] {
+        &self.bytes[self.subdivisions.clone()]
+    }
+    fn locales_slice(&self) -> &[u8] {
+        &self.bytes[self.locales.clone()]
+    }
+    fn countries_slice(&self) -> &[u8] {
+        &self.bytes[self.countries.clone()]
+    }
+    fn transport_slice(&self) -> &[u8] {
+        &self.bytes[self.transport.clone()]
+    }
+    fn tokens_slice(&self) -> &[u8] {
+        &self.bytes[self.tokens.clone()]
+    }
+    fn lang_names_slice(&self) -> &[u8] {
+        &self.bytes[self.lang_names.clone()]
+    }
+    fn disputed_slice(&self) -> &[u8] {
+        &self.bytes[self.disputed.clone()]
+    }
+    fn hierarchy_slice(&self) -> &[u8] {
+        &self.bytes[self.hierarchy.clone()]
+    }
+    fn parents_slice(&self) -> &[u8] {
+        &self.bytes[self.parents.clone()]
+    }
+    fn postings_info_slice(&self) -> &[u8] {
+        &self.bytes[self.postings_info.clone()]
+    }
+    fn spatial_slice(&self) -> &[u8] {
+        &self.bytes[self.spatial.clone()]
+    }
+    fn records_slice(&self) -> &[u8] {
+        &self.bytes[self.records.clone()]
+    }
+    fn offsets_slice(&self) -> &[u8] {
+        &self.bytes[self.offsets.clone()]
+    }
+    /// `range` of the DB bytes as an owned handle that shares them.
+    fn section(&self, range: &Range<usize>) -> Section {
+        Section {
+            bytes: Arc::clone(&self.bytes),
+            range: range.clone(),
+        }
+    }
+}
+
+/// A section of the DB bytes, held without copying them (mapped or not), so
+/// an `fst::Map` over it can live next to the `Db` it came from.
+#[derive(Clone)]
+pub struct Section {
+    bytes: Arc<DbBytes>,
+    range: Range<usize>,
+}
+
+impl AsRef<[u8]> for Section {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes[self.range.clone()]
+    }
+}
+
+/// Where the DB bytes live; accessors slice through `Deref` either way.
+enum DbBytes {
+    Owned(Vec<u8>),
+    /// `include_bytes!` (feature "embed") and tests.
+    #[cfg_attr(not(any(feature = "embed", test)), allow(dead_code))]
+    Static(&'static [u8]),
+    /// Read-only mapping; the OS pages sections in on first access.
+    Mapped(memmap2::Mmap),
+}
+
+impl std::ops::Deref for DbBytes {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        match self {
+            DbBytes::Owned(v) => v,
+            DbBytes::Static(b) => b,
+            DbBytes::Mapped(m) => m,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OpenOptions {
+    /// Open an inconsistent DB anyway; lookups skip unreadable entries
+    /// instead of failing (see `Db::lenient`).
+    pub lenient: bool,
+    /// Map the file instead of reading it into memory.
+    pub mmap: bool,
+}
+
+fn open_db(path: &Path) -> Result<Db> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+    Db::parse(DbBytes::Owned(bytes))
+}
+
+fn map_db(path: &Path) -> Result<Db> {
+    let file = File::open(path)?;
+    // SAFETY: the mapping is read-only and DB files are written once by
+    // `geodb build` (to a new path) and never modified in place. Truncating a
+    // mapped file would fault on access, as it would for any mmap reader.
+    let map = unsafe { memmap2::Mmap::map(&file)? };
+    let mut db = Db::parse(DbBytes::Mapped(map))?;
+    db.hot_records = hot::HotRecords::load(db.hot_slice())?;
+    Ok(db)
+}
+
+/// `--db` when given, else the DB compiled in with feature "embed". Checked
+/// for consistency before use (`Db::check`).
+fn load_db(path: Option<&Path>, opts: OpenOptions) -> Result<Db> {
+    let db = match path {
+        Some(p) if opts.mmap => map_db(p)?,
+        Some(p) => open_db(p)?,
+        None => embedded_db()?,
+    };
+    db.checked(opts)
+}
+
+#[cfg(feature = "embed")]
+fn embedded_db() -> Result<Db> {
+    Db::from_bytes(include_bytes!(env!("GEODB_EMBED_DB")))
+}
+
+#[cfg(not(feature = "embed"))]
+fn embedded_db() -> Result<Db> {
+    bail!(
+        "--db is required (no DB embedded; build with --features embed and GEODB_EMBED_DB=<path>)"
+    )
+}
+
+impl Db {
+    /// A DB that is already in memory, e.g. `include_bytes!`; nothing is copied.
+    /// Not checked yet: `Geocoder::from_bytes` checks it as `load_db` does.
+    pub fn from_bytes(bytes: &'static [u8]) -> Result<Self> {
+        Self::parse(DbBytes::Static(bytes))
+    }
+
+    /// `self` once `check` passes, strict unless `opts.lenient`.
+    fn checked(mut self, opts: OpenOptions) -> Result<Self> {
+        self.lenient = opts.lenient;
+        self.check()?;
+        Ok(self)
+    }
+
+    fn parse(bytes: DbBytes) -> Result<Self> {
+        let (sections, header_len, version) = format::read_sections(&bytes)?;
+        let required = |id: u32, name: &str| -> Result<Range<usize>> {
+            format::find(&sections, id)?.ok_or_else(|| anyhow!("missing {name} section"))
+        };
+
+        Ok(Db {
+            header_len,
+            version,
+            fst: required(format::SECTION_FST, "fst")?,
+            postings: required(format::SECTION_POSTINGS, "postings")?,
+            records: required(format::SECTION_RECORDS, "records")?,
+            offsets: required(format::SECTION_OFFSETS, "offsets")?,
+            concordance: format::find(&sections, format::SECTION_CONCORDANCE)?.unwrap_or(0..0),
+            synonyms: format::find(&sections, format::SECTION_SYNONYMS)?.unwrap_or(0..0),
+            unaccented: format::find(&sections, format::SECTION_UNACCENTED)?.unwrap_or(0..0),
+            h3: format::find(&sections, format::SECTION_H3)?.unwrap_or(0..0),
+            spatial: format::find(&sections, format::SECTION_SPATIAL)?.unwrap_or(0..0),
+            hot: format::find(&sections, format::SECTION_HOT)?.unwrap_or(0..0),
+            admin_names: format::find(&sections, format::SECTION_ADMIN_NAMES)?.unwrap_or(0..0),
+            subdivisions: format::find(&sections, format::SECTION_SUBDIVISIONS)?.unwrap_or(0..0),
+            locales: format::find(&sections, format::SECTION_LOCALES)?.unwrap_or(0..0),
+            countries: format::find(&sections, format::SECTION_COUNTRIES)?.unwrap_or(0..0),
+            transport: format::find(&sections, format::SECTION_TRANSPORT)?.unwrap_or(0..0),
+            tokens: format::find(&sections, format::SECTION_TOKENS)?.unwrap_or(0..0),
+            lang_names: format::find(&sections, format::SECTION_LANG_NAMES)?.unwrap_or(0..0),
+            disputed: format::find(&sections, format::SECTION_DISPUTED)?.unwrap_or(0..0),
+            hierarchy: format::find(&sections, format::SECTION_HIERARCHY)?.unwrap_or(0..0),
+            parents: format::find(&sections, format::SECTION_PARENTS)?.unwrap_or(0..0),
+            postings_info: format::find(&sections, format::SECTION_POSTINGS_INFO)?.unwrap_or(0..0),
+            bytes: Arc::new(bytes),
+            hot_records: None,
+            lenient: false,
+            bad_reads: AtomicU64::new(0),
+        })
+    }
+
+    /// Every FST value must point at a postings list whose length fits the
+    /// postings section and every record offset into the records section.
+    /// Bounds only: nothing is decoded, so opening (and /admin/reload) stays
+    /// cheap; `geodb validate` decodes everything. Strict mode fails on the
+    /// first inconsistency; lenient mode reports them and opens anyway.
+    fn check(&self) -> Result<()> {
+        let postings = self.postings_slice();
+        let mut bad = 0usize;
+        let mut first: Option<String> = None;
+        let mut note = |what: String| {
+            bad += 1;
+            first.get_or_insert(what);
+        };
+
+        for (name, bytes) in [
+            ("fst", self.fst_slice()),
+            ("unaccented", self.unaccented_slice()),
+            ("tokens", self.tokens_slice()),
+            ("lang_names", self.lang_names_slice()),
+        ] {
+            if bytes.is_empty() {
+                continue;
+            }
+            let map = fst::Map::new(bytes).map_err(|e| anyhow!("{name} load: {e}"))?;
+            let mut stream = map.stream();
+            while let Some((k, off)) = stream.next() {
+                if let Err(e) = postings_span(postings, off as usize) {
+                    note(format!(
+                        "{name} key {:?} -> postings {off}: {e}",
+                        String::from_utf8_lossy(k)
+                    ));
+                }
+            }
+        }
+
+        let offsets = self.offsets_slice();
+        if offsets.len() < 4 {
+            bail!("corrupt offsets");
+        }
+        let n = read_u32_le_at(offsets, 0) as usize;
+        if 4 + n * 12 > offsets.len() {
+            bail!("corrupt offsets: {n} records, {} bytes", offsets.len());
+        }
+        let records_len = self.records_slice().len();
+        for i in 0..n {
+            let off = read_u64_le_at(offsets, 4 + n * 4 + i * 8);
+            if off as usize >= records_len {
+                let id = read_u32_le_at(offsets, 4 + i * 4);
+                note(format!("record {id} at offset {off} past records end"));
+            }
+        }
+
+        let Some(first) = first else {
+            return Ok(());
+        };
+        if !self.lenient {
+            bail!("inconsistent DB: {bad} bad entries, first: {first} (--lenient serves the rest)");
+        }
+        eprintln!("[db] lenient: {bad} bad entries, first: {first}");
+        Ok(())
+    }
+
+    /// In lenient mode, log `r`'s error and continue with `fallback`. Logs the
+    /// first few and then every 1000th, so a bad region cannot flood stderr.
+    fn tolerate<T>(&self, r: Result<T>, fallback: T, what: impl FnOnce() -> String) -> Result<T> {
+        match r {
+            Ok(v) => Ok(v),
+            Err(e) if self.lenient => {
+                let n = self.bad_reads.fetch_add(1, Ordering::Relaxed) + 1;
+                if n <= 10 || n.is_multiple_of(1000) {
+                    eprintln!("[db] skipped {} ({n} so far): {e:#}", what());
+                }
+                Ok(fallback)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/* -------------------------
+   build fingerprint
+-------------------------- */
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+const HASH_STRIDE: usize = 64 * 1024;
+const HASH_CHUNK: usize = 64;
+
+/// Stable identifier for a DB build, used for `x-geodb-build` and ETags.
+/// Hashes the header, file length, and a 64-byte sample every 64 KiB plus the
+/// tail, so it is cheap on multi-GB files but changes with any rebuild.
+fn build_hash(db: &Db) -> String {
+    let b = &db.bytes[..];
+    let mut h = fnv1a64(FNV_OFFSET, &(b.len() as u64).to_le_bytes());
+    h = fnv1a64(h, &b[..db.header_len.min(b.len())]);
+    let mut off = db.header_len;
+    while off < b.len() {
+        let end = (off + HASH_CHUNK).min(b.len());
+        h = fnv1a64(h, &b[off..end]);
+        off += HASH_STRIDE;
+    }
+    h = fnv1a64(h, &b[b.len().saturating_sub(HASH_CHUNK)..]);
+    format!("{h:016x}")
+}
+
+fn fnv1a64(mut h: u64, bytes: &[u8]) -> u64 {
+    for &x in bytes {
+        h ^= x as u64;
+        h = h.wrapping_mul(FNV_PRIME);
+    }
+    h
+}
+
+/* -------------------------
+   postings decode + record load
+-------------------------- */
+
+fn read_postings(db: &Db, postings_offset: usize) -> Result<Vec<u32>> {
+    Ok(read_list(db, postings_offset)?.into_ids())
+}
+
+/// A postings list as decoded: roaring lists stay bitmaps.
+fn read_list(db: &Db, postings_offset: usize) -> Result<postings::List> {
+    db.tolerate(
+        read_postings_strict(db, postings_offset),
+        postings::List::Ids(Vec::new()),
+        || format!("postings at {postings_offset}"),
+    )
+}
+
+fn read_postings_strict(db: &Db, postings_offset: usize) -> Result<postings::List> {
+    let blob = db.postings_slice();
+    let span = postings_span(blob, postings_offset)?;
+    postings::decode_list(&blob[span], db.version)
+}
+
+/// Where the length-prefixed list at `postings_offset` lies in `blob`, if
+/// its offset and length are in bounds.
+fn postings_span(blob: &[u8], postings_offset: usize) -> Result<Range<usize>> {
+    if postings_offset >= blob.len() {
+        bail!("postings offset out of bounds");
+    }
+    let (len, len_bytes) = read_var_u32(&blob[postings_offset..])?;
+    let start = postings_offset + len_bytes;
+    let end = start + len as usize;
+    if end > blob.len() {
+        bail!("postings length out of bounds");
+    }
+    Ok(start..end)
+}
+
+/// Postings for a key plus its accent-insensitive matches (see accents.rs).
+pub struct KeyHit {
+    /// Sorted.
+    pub ids: Vec<u32>,
+    /// Sorted subset of `ids` matched only once accents were stripped; empty
+    /// when the key has none, since every match then counts as exact.
+    pub loose: Vec<u32>,
+    /// (id, edit distance) sorted by id; only set for fuzzy hits.
+    pub edits: Vec<(u32, u32)>,
+}
+
+impl KeyHit {
+    /// Both hits as one, each id listed once with its best match: exact over
+    /// accent-insensitive over fuzzy, and the smaller edit distance.
+    pub fn merge(self, other: KeyHit) -> KeyHit {
+        // 0 exact, 1 accent-insensitive, 2 + d fuzzy at distance d
+        let rank = |hit: &KeyHit, id: u32| {
+            hit.ids.binary_search(&id).ok()?;
+            Some(match edit_distance(&hit.edits, id) {
+                Some(d) => 2 + d,
+                None => u32::from(hit.loose.binary_search(&id).is_ok()),
+            })
+        };
+        let mut ids = self.ids.clone();
+        ids.extend_from_slice(&other.ids);
+        ids.sort_unstable();
+        ids.dedup();
+        let mut loose = Vec::new();
+        let mut edits = Vec::new();
+        for &id in &ids {
+            let best = rank(&self, id).into_iter().chain(rank(&other, id)).min();
+            match best.unwrap_or(0) {
+                0 => {}
+                1 => loose.push(id),
+                d => edits.push((id, d - 2)),
+            }
+        }
+        KeyHit { ids, loose, edits }
+    }
+}
+
+fn read_key_postings<D: AsRef<[u8]>>(
+    db: &Db,
+    fst: &fst::Map<D>,
+    unaccented: Option<&fst::Map<D>>,
+    key: &str,
+) -> Result<Option<KeyHit>> {
+    let mut exact = match fst.get(key) {
+        Some(off) => read_postings(db, off as usize)?,
+        None => Vec::new(),
+    };
+    let mut loose = Vec::new();
+    if let Some(u) = unaccented {
+        let bare = accents::strip(key);
+        let stripped = match u.get(&bare) {
+            Some(off) => read_postings(db, off as usize)?,
+            None => Vec::new(),
+        };
+        if bare == key {
+            exact.extend(stripped);
+        } else {
+            if let Some(off) = fst.get(&bare) {
+                loose.extend(read_postings(db, off as usize)?);
+            }
+            loose.extend(stripped);
+        }
+    }
+    exact.sort_unstable();
+    exact.dedup();
+    loose.sort_unstable();
+    loose.dedup();
+    loose.retain(|id| exact.binary_search(id).is_err());
+    if exact.is_empty() && loose.is_empty() {
+        return Ok(None);
+    }
+    let mut ids = exact;
+    ids.extend_from_slice(&loose);
+    ids.sort_unstable();
+    Ok(Some(KeyHit {
+        ids,
+        loose,
+        edits: Vec::new(),
+    }))
+}
+
+/// `read_key_postings`' ids as one bitmap, for set operations (sets.rs):
+/// roaring lists are merged as they are, never expanded to ids.
+fn read_key_bitmap<D: AsRef<[u8]>>(
+    db: &Db,
+    fst: &fst::Map<D>,
+    unaccented: Option<&fst::Map<D>>,
+    key: &str,
+) -> Result<roaring::RoaringBitmap> {
+    let mut offsets: Vec<u64> = fst.get(key).into_iter().collect();
+    if let Some(u) = unaccented {
+        let bare = accents::strip(key);
+        offsets.extend(u.get(&bare));
+        if bare != key {
+            offsets.extend(fst.get(&bare));
+        }
+    }
+    let mut ids = roaring::RoaringBitmap::new();
+    for off in offsets {
+        ids |= read_list(db, off as usize)?.into_bitmap();
+    }
+    Ok(ids)
+}
+
+/// Fuzzy fallback (see fuzzy.rs): postings of every key within `max_edits`,
+/// each id tagged with the smallest distance it was reached at.
+fn read_fuzzy_postings<D: AsRef<[u8]>>(
+    db: &Db,
+    fst: &fst::Map<D>,
+    key: &str,
+    max_edits: u32,
+) -> Result<Option<KeyHit>> {
+    let mut edits: Vec<(u32, u32)> = Vec::new();
+    for (_, off, d) in fuzzy::search(fst, key, max_edits)? {
+        for id in read_postings(db, off as usize)? {
+            edits.push((id, d));
+        }
+    }
+    if edits.is_empty() {
+        return Ok(None);
+    }
+    // keys arrive closest first and the sort is stable: the first entry per id
+    // carries its smallest distance
+    edits.sort_by_key(|(id, _)| *id);
+    edits.dedup_by_key(|(id, _)| *id);
+    Ok(Some(KeyHit {
+        ids: edits.iter().map(|(id, _)| *id).collect(),
+        loose: Vec::new(),
+        edits,
+    }))
+}
+
+fn edit_distance(edits: &[(u32, u32)], id: u32) -> Option<u32> {
+    edits
+        .binary_search_by_key(&id, |(i, _)| *i)
+        .ok()
+        .map(|i| edits[i].1)
+}
+
+fn read_record_by_id(db: &Db, id: u32) -> Result<Option<GeoRecord>> {
+    Ok(read_record_ref_by_id(db, id)?.map(|r| r.to_record()))
+}
+
+/// The record borrowed from the DB bytes; see GeoRecordRef.
+pub fn read_record_ref_by_id(db: &Db, id: u32) -> Result<Option<GeoRecordRef<'_>>> {
+    db.tolerate(read_record_strict(db, id), None, || format!("record {id}"))
+}
+
+fn read_record_strict(db: &Db, id: u32) -> Result<Option<GeoRecordRef<'_>>> {
+    if let Some(bytes) = db.hot_records.as_ref().and_then(|h| h.get(id)) {
+        return decode_record_ref(bytes, db.version).map(Some);
+    }
+
+    let slice = db.offsets_slice();
+    let mut cur = std::io::Cursor::new(slice);
+
+    let n = cur.read_u32::<LittleEndian>()? as usize;
+    let ids_start = 4;
+    let ids_end = ids_start + n * 4;
+    let offs_start = ids_end;
+    let offs_end = offs_start + n * 8;
+    if offs_end > slice.len() {
+        bail!("corrupt offsets");
+    }
+
+    let ids_bytes = &slice[ids_start..ids_end];
+
+    // binary search
+    let mut lo = 0usize;
+    let mut hi = n;
+    while lo < hi {
+        let mid = (lo + hi) / 2;
+        let mid_id = read_u32_le_at(ids_bytes, mid * 4);
+        if mid_id < id {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    if lo >= n {
+        return Ok(None);
+    }
+    let found_id = read_u32_le_at(ids_bytes, lo * 4);
+    if found_id != id {
+        return Ok(None);
+    }
+
+    let offs_bytes = &slice[offs_start..offs_end];
+    let off = read_u64_le_at(offs_bytes, lo * 8) as usize;
+
+    let rec_blob = db.records_slice();
+    if off >= rec_blob.len() {
+        bail!("record offset out of bounds");
+    }
+    decode_record_ref(&rec_blob[off..], db.version).map(Some)
+}
+
+/// A record read in place: the strings borrow the DB bytes (records or hot
+/// section), so reading one allocates nothing. Filters run on these, and
+/// only the records they keep are copied out (`to_record`); batch lookups of
+/// keys with many candidates in other regions used to allocate five Strings
+/// for each of them.
+#[derive(Clone, Copy, Debug)]
+pub struct GeoRecordRef<'a> {
+    pub id: u32,
+    pub name: &'a str,
+    /// Empty for records read from the DB, as in GeoRecord.
+    pub ascii_name: &'a str,
+    pub country: &'a str,
+    pub admin1: &'a str,
+    pub admin2: &'a str,
+    pub lat: f32,
+    pub lon: f32,
+    pub feat_class: u8,
+    pub feat_code: &'a str,
+    pub population: u32,
+    pub timezone: &'a str,
+}
+
+impl GeoRecordRef<'_> {
+    pub fn to_record(&self) -> GeoRecord {
+        GeoRecord {
+            id: self.id,
+            name: self.name.to_string(),
+            ascii_name: self.ascii_name.to_string(),
+            country: self.country.to_string(),
+            admin1: self.admin1.to_string(),
+            admin2: self.admin2.to_string(),
+            lat: self.lat,
+            lon: self.lon,
+            feat_class: self.feat_class,
+            feat_code: self.feat_code.to_string(),
+            population: self.population,
+            timezone: self.timezone.to_string(),
+        }
+    }
+}
+
+impl<'a> From<&'a GeoRecord> for GeoRecordRef<'a> {
+    fn from(r: &'a GeoRecord) -> Self {
+        Self {
+            id: r.id,
+            name: &r.name,
+            ascii_name: &r.ascii_name,
+            country: &r.country,
+            admin1: &r.admin1,
+            admin2: &r.admin2,
+            lat: r.lat,
+            lon: r.lon,
+            feat_class: r.feat_class,
+            feat_code: &r.feat_code,
+            population: r.population,
+            timezone: &r.timezone,
+        }
+    }
+}
+
+/// One record in the records-section encoding, from the start of `bytes`.
+fn decode_record(bytes: &[u8], version: u32) -> Result<GeoRecord> {
+    decode_record_ref(bytes, version).map(|r| r.to_record())
+}
+
+fn decode_record_ref(bytes: &[u8], version: u32) -> Result<GeoRecordRef<'_>> {
+    let mut c = std::io::Cursor::new(bytes);
+
+    let rid = c.read_u32::<LittleEndian>()?;
+    let lat = c.read_f32::<LittleEndian>()?;
+    let lon = c.read_f32::<LittleEndian>()?;
+    let pop = c.read_u32::<LittleEndian>()?;
+    let mut fc = [0u8; 1];
+    c.read_exact(&mut fc)?;
+
+    let name = read_lp_str_cur(&mut c)?;
+    let country = read_lp_str_cur(&mut c)?;
+    let admin1 = read_lp_str_cur(&mut c)?;
+    let admin2 = read_lp_str_cur(&mut c)?;
+    let feat_code = read_lp_str_cur(&mut c)?;
+    let timezone = if version >= 6 {
+        read_lp_str_cur(&mut c)?
+    } else {
+        ""
+    };
+
+    Ok(GeoRecordRef {
+        id: rid,
+        name,
+        ascii_name: "",
+        country,
+        admin1,
+        admin2,
+        lat,
+        lon,
+        feat_class: fc[0],
+        feat_code,
+        population: pop,
+        timezone,
+    })
+}
+
+fn read_lp_str_cur<'a>(cur: &mut std::io::Cursor<&'a [u8]>) -> Result<&'a str> {
+    let pos = cur.position() as usize;
+    let buf: &'a [u8] = cur.get_ref();
+
+    let (len, len_bytes) = read_var_u32(&buf[pos..])?;
+    let start = pos + len_bytes;
+    let end = start + len as usize;
+    if end > buf.len() {
+        bail!("string out of bounds");
+    }
+
+    let s = std::str::from_utf8(&buf[start..end])?;
+    cur.set_position(end as u64);
+    Ok(s)
+}
+
+/* -------------------------
+   varint + delta decode
+-------------------------- */
+
+fn decode_delta_varints(bytes: &[u8]) -> Vec<u32> {
+    let mut out = Vec::new();
+    let mut i = 0usize;
+    let mut cur = 0u32;
+    while i < bytes.len() {
+        let (v, n) = match read_var_u32(&bytes[i..]) {
+            Ok(x) => x,
+            Err(_) => break,
+        };
+        i += n;
+        cur = cur.wrapping_add(v);
+        out.push(cur);
+    }
+    out
+}
+
+fn read_var_u32(buf: &[u8]) -> Result<(u32, usize)> {
+    let mut v: u32 = 0;
+    let mut shift = 0;
+    for (i, &b) in buf.iter().enumerate().take(5) {
+        let chunk = (b & 0x7F) as u32;
+        v |= chunk << shift;
+        if (b & 0x80) == 0 {
+            return Ok((v, i + 1));
+        }
+        shift += 7;
+    }
+    Err(anyhow!("bad varint"))
+}
+
+/* -------------------------
+   little-endian helpers
+-------------------------- */
+
+fn read_u32_le_at(b: &[u8], off: usize) -> u32 {
+    let x = &b[off..off + 4];
+    u32::from_le_bytes([x[0], x[1], x[2], x[3]])
+}
+
+fn read_u64_le_at(b: &[u8], off: usize) -> u64 {
+    let x = &b[off..off + 8];
+    u64::from_le_bytes([x[0], x[1], x[2], x[3], x[4], x[5], x[6], x[7]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARIS: u32 = 2988507;
+
+    /// A one-record DB ("paris" -> Paris) assembled in memory.
+    fn tiny_db() -> &'static [u8] {
+        let rec = GeoRecord {
+            id: PARIS,
+            name: "Paris".into(),
+            ascii_name: "Paris".into(),
+            country: "FR".into(),
+            admin1: "11".into(),
+            admin2: "75".into(),
+            lat: 48.85341,
+            lon: 2.3488,
+            feat_class: b'P',
+            feat_code: "PPLC".into(),
+            population: 2_138_551,
+            timezone: "Europe/Paris".into(),
+        };
+        let mut records = Vec::new();
+        build::write_record(&mut records, &rec).unwrap();
+
+        let mut postings = Vec::new();
+        postings::write_list(&mut postings, &[PARIS], postings::DEFAULT_ROARING_THRESHOLD).unwrap();
+
+        let mut fst = fst::MapBuilder::memory();
+        fst.insert("paris", 0).unwrap();
+        let fst = fst.into_inner().unwrap();
+
+        let mut offsets = Vec::new();
+        offsets.extend_from_slice(&1u32.to_le_bytes());
+        offsets.extend_from_slice(&PARIS.to_le_bytes());
+        offsets.extend_from_slice(&0u64.to_le_bytes());
+
+        let mut out = Vec::new();
+        format::write_sections(
+            &mut out,
+            &[
+                (format::SECTION_FST, &fst[..]),
+                (format::SECTION_POSTINGS, &postings[..]),
+                (format::SECTION_RECORDS, &records[..]),
+                (format::SECTION_OFFSETS, &offsets[..]),
+            ],
+        )
+        .unwrap();
+        Box::leak(out.into_boxed_slice())
+    }
+
+    #[test]
+    fn from_bytes_needs_no_filesystem() {
+        let db = Db::from_bytes(tiny_db()).unwrap();
+        assert!(matches!(*db.bytes, DbBytes::Static(_)));
+
+        let fst = fst::Map::new(db.fst_slice()).unwrap();
+        let hit = read_key_postings(&db, &fst, None, "paris")
+            .unwrap()
+            .unwrap();
+        assert_eq!(hit.ids, vec![PARIS]);
+        assert!(hit.loose.is_empty());
+
+        let rec = read_record_by_id(&db, PARIS).unwrap().unwrap();
+        assert_eq!(rec.name, "Paris");
+        assert_eq!(rec.feat_code, "PPLC");
+        assert!(read_record_by_id(&db, PARIS + 1).unwrap().is_none());
+
+        // borrowed from the DB bytes, same fields once copied out
+        let r = read_record_ref_by_id(&db, PARIS).unwrap().unwrap();
+        assert_eq!(r.name, "Paris");
+        assert!(db.bytes.as_ptr_range().contains(&r.name.as_ptr()));
+        assert_eq!(r.to_record().feat_code, rec.feat_code);
+    }
+
+    #[test]
+    fn from_bytes_rejects_garbage() {
+        assert!(Db::from_bytes(b"not a geodb").is_err());
+        assert!(Geocoder::from_bytes(b"not a geodb", OpenOptions::default()).is_err());
+    }
+
+    #[test]
+    fn geocoder_from_bytes_answers_lookups() {
+        let bytes = tiny_db();
+        let geo = Geocoder::from_bytes(bytes, OpenOptions::default()).unwrap();
+        let answer = geo.lookup("Paris", &LookupOptions::default()).unwrap();
+        assert_eq!(answer.count, 1);
+        assert_eq!(answer.candidates[0].geoname_id, PARIS);
+        // the FST reads the static bytes in place
+        let fst = geo.fst().as_fst().as_bytes();
+        assert!(bytes.as_ptr_range().contains(&fst.as_ptr()));
+    }
+
+    #[test]
+    fn roaring_lists_decode_to_bitmaps() {
+        let ids: Vec<u32> = (0..10).map(|i| i * 3).collect();
+        let mut blob = Vec::new();
+        postings::write_list(&mut blob, &ids, 5).unwrap();
+        let (len, n) = read_var_u32(&blob).unwrap();
+        let list = postings::decode_list(&blob[n..n + len as usize], build::VERSION).unwrap();
+        assert!(matches!(list, postings::List::Bitmap(_)));
+        assert_eq!(list.len(), 10);
+        assert_eq!(list.into_ids(), ids);
+
+        let mut blob = Vec::new();
+        postings::write_list(&mut blob, &ids[..4], 5).unwrap();
+        let (len, n) = read_var_u32(&blob).unwrap();
+        let list = postings::decode_list(&blob[n..n + len as usize], build::VERSION).unwrap();
+        assert!(matches!(list, postings::List::Ids(_)));
+        assert!(list.into_bitmap().iter().eq(ids[..4].iter().copied()));
+
+        let info = postings::info_section(5);
+        assert_eq!(postings::roaring_threshold(&info).unwrap(), 5);
+        let before = postings::roaring_threshold(&[]).unwrap();
+        assert_eq!(before, postings::DEFAULT_ROARING_THRESHOLD);
+        assert!(postings::roaring_threshold(&[1, 2]).is_err());
+    }
+
+    #[test]
+    fn postings_spans_are_bounds_checked() {
+        let mut blob = Vec::new();
+        postings::write_list(&mut blob, &[1, 2, 3], postings::DEFAULT_ROARING_THRESHOLD).unwrap();
+        assert_eq!(postings_span(&blob, 0).unwrap().end, blob.len());
+        assert!(postings_span(&blob, blob.len()).is_err());
+        assert!(postings_span(&blob[..blob.len() - 1], 0).is_err());
+    }
+
+    #[test]
+    fn merged_hits_keep_the_best_match_per_id() {
+        // 1 exact, 2 accent-insensitive only, 3 exact here and fuzzy there,
+        // 4 fuzzy only at distances 2 and 1
+        let exact = KeyHit {
+            ids: vec![1, 2, 3],
+            loose: vec![2],
+            edits: Vec::new(),
+        };
+        let fuzzy = KeyHit {
+            ids: vec![2, 3, 4],
+            loose: Vec::new(),
+            edits: vec![(2, 1), (3, 1), (4, 2)],
+        };
+        let more = KeyHit {
+            ids: vec![4],
+            loose: Vec::new(),
+            edits: vec![(4, 1)],
+        };
+        let hit = exact.merge(fuzzy).merge(more);
+        assert_eq!(hit.ids, [1, 2, 3, 4]);
+        assert_eq!(hit.loose, [2]);
+        assert_eq!(hit.edits, [(4, 1)]);
+    }
+}
@@ -0,0 +1,80 @@
+// src/locales.rs
+//
+// Country locale hints: `geodb build --country-info countryInfo.txt` stores
+// each country's GeoNames language list (column 16, most used first, e.g.
+// FR -> fr-FR,frp,br,co,ca,eu,oc) in the country locales section as JSON.
+// Candidates carry `locale`, the first entry, so clients can pick display
+// language and number formats without their own country table.
+
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use crate::build::{read_line_lossy, GeoRecord};
+
+const LANGUAGES_COL: usize = 15;
+
+/// Build the section bytes; countries without records are dropped.
+pub fn build_section(records: &[GeoRecord], country_info: Option<&Path>) -> Result<Vec<u8>> {
+    let Some(path) = country_info else {
+        return Ok(Vec::new());
+    };
+    let used: HashSet<&str> = records.iter().map(|r| r.country.as_str()).collect();
+
+    let file = File::open(path).with_context(|| format!("open {}", path.display()))?;
+    let mut r = BufReader::new(file);
+    let mut buf = Vec::new();
+    let mut table: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    while let Some(line) = read_line_lossy(&mut r, &mut buf)? {
+        if line.starts_with('#') {
+            continue;
+        }
+        let cols: Vec<&str> = line.split('\t').collect();
+        let (Some(iso), Some(langs)) = (cols.first(), cols.get(LANGUAGES_COL)) else {
+            continue;
+        };
+        if !used.contains(iso) {
+            continue;
+        }
+        let langs: Vec<String> = langs
+            .split(',')
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .map(str::to_string)
+            .collect();
+        if !langs.is_empty() {
+            table.insert(iso.to_string(), langs);
+        }
+    }
+    eprintln!("[locales] countries={}", table.len());
+    if table.is_empty() {
+        return Ok(Vec::new());
+    }
+    Ok(serde_json::to_vec(&table)?)
+}
+
+pub struct CountryLocales {
+    table: BTreeMap<String, Vec<String>>,
+}
+
+impl CountryLocales {
+    /// `None` for DBs built without --country-info.
+    pub fn from_section(section: &[u8]) -> Result<Option<Self>> {
+        if section.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            table: serde_json::from_slice(section)?,
+        }))
+    }
+
+    /// Most used language of `country`, as a BCP 47 tag ("fr-FR").
+    pub fn primary(&self, country: &str) -> Option<&str> {
+        self.table
+            .get(country)
+            .and_then(|l| l.first())
+            .map(String::as_str)
+    }
+}
@@ -0,0 +1,35 @@
+// src/localtime.rs
+//
+// `include_time=true` on /query: each candidate's current UTC offset and
+// whether daylight saving time is in effect, from its IANA timezone and the
+// tz database compiled into the binary (chrono-tz), so the globe's "local
+// time" badge needs no client-side tz library. Computed per response; such
+// responses bypass the /query cache and ETag revalidation (server.rs), which
+// would serve offsets from before a DST change. Records without a timezone
+// (VERSION 5 DBs, some sources) get no fields.
+
+use chrono::{DateTime, Offset, TimeZone, Utc};
+use chrono_tz::{OffsetComponents, Tz};
+use serde::Serialize;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct LocalTime {
+    /// "+02:00", "-03:30".
+    pub utc_offset: String,
+    pub utc_offset_seconds: i32,
+    pub dst: bool,
+}
+
+/// Offset of `timezone` ("Europe/Paris") at `now`; None for unknown names.
+pub fn at(timezone: &str, now: DateTime<Utc>) -> Option<LocalTime> {
+    let tz: Tz = timezone.parse().ok()?;
+    let offset = tz.offset_from_utc_datetime(&now.naive_utc());
+    let seconds = offset.fix().local_minus_utc();
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let abs = seconds.unsigned_abs();
+    Some(LocalTime {
+        utc_offset: format!("{sign}{:02}:{:02}", abs / 3600, abs % 3600 / 60),
+        utc_offset_seconds: seconds,
+        dst: !offset.dst_offset().is_zero(),
+    })
+}
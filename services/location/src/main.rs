@@ -1,19 +1,21 @@
 // src/main.rs
-// CLI + query-only code. No unsafe. Exact-match query (no normalization, no tokenization).
+// `geodb` CLI: thin wrapper over the geodb_core library (src/lib.rs).
 
 use anyhow::{anyhow, bail, Result};
-use byteorder::{LittleEndian, ReadBytesExt};
-use clap::{Parser, Subcommand};
-use serde::Serialize;
-use std::fs::File;
+use clap::{Parser, Subcommand, ValueEnum};
 use std::io::Read;
 use std::net::SocketAddr;
-use std::path::{Path, PathBuf};
-
-mod build;
-use build::{GeoRecord, MAGIC, VERSION};
-
-mod server;
+use std::path::PathBuf;
+
+use geodb_core::{
+    bbox::BBox,
+    build, config, coords, coverage, diagnostics, disambiguate, estimate, extract, fuzzy,
+    geocoder::Candidate,
+    hot, inspect, langs,
+    nameflags::{NameFlags, NameUse},
+    osm, periods, postings, preflight, ranking, registry, reverse, server, spill, suggest, update,
+    validate, Geocoder, LookupOptions, OpenOptions,
+};
 
 #[derive(Parser)]
 #[command(name = "geodb")]
@@ -22,342 +24,824 @@ struct Cli {
     cmd: Cmd,
 }
 
+/// `geodb tag --format json`, shaped like POST /extract's answer.
+#[derive(serde::Serialize)]
+struct TagJson {
+    count: usize,
+    mentions: Vec<TaggedJson>,
+}
+
+#[derive(serde::Serialize)]
+struct TaggedJson {
+    /// As written, whitespace collapsed; `start`..`end` are byte offsets.
+    text: String,
+    start: usize,
+    end: usize,
+    key: String,
+    candidates: Vec<Candidate>,
+}
+
+/// `geodb tag` output.
+#[derive(Clone, Copy, ValueEnum)]
+enum TagFormat {
+    /// One line per candidate: start, end, mention, geoname id, name,
+    /// country, score
+    Tsv,
+    /// {"count", "mentions": [..]} as POST /extract answers, candidates in
+    /// resolved order
+    Json,
+}
+
 #[derive(Subcommand)]
 enum Cmd {
     Build {
+        /// GeoNames allCountries.zip, or an extract (cities15000.zip, US.zip)
+        #[arg(long)]
+        all: Option<PathBuf>,
+        /// GeoNames alternateNamesV2.zip
+        #[arg(long)]
+        alt: Option<PathBuf>,
+        /// OpenStreetMap .osm.pbf (planet or Geofabrik extract)
+        #[arg(long)]
+        osm: Option<PathBuf>,
+        /// Extra gazetteer inputs as kind:path (geonames, osm, wof, csv); repeatable
+        #[arg(long = "source")]
+        sources: Vec<String>,
+        #[arg(long)]
+        out: PathBuf,
+        #[arg(long, default_value_t = 0)]
+        min_pop: u32,
+        /// Feature classes to leave out, e.g. H,R,U
+        #[arg(long, value_delimiter = ',')]
+        exclude_feature_class: Vec<String>,
+        /// Feature codes to leave out, e.g. STM,STMI
+        #[arg(long, value_delimiter = ',')]
+        exclude_feature_code: Vec<String>,
+        /// Leave out alternate names GeoNames flags as historic ("Constantinople")
+        #[arg(long)]
+        skip_historic: bool,
+        /// Leave out alternate names GeoNames flags as colloquial ("Big Apple")
+        #[arg(long)]
+        skip_colloquial: bool,
+        /// Build report JSON (default: <out>.report.json)
+        #[arg(long)]
+        report: Option<PathBuf>,
+        /// Report keys with more postings than this
+        #[arg(long, default_value_t = 500)]
+        heavy_postings: usize,
+        /// geodb.toml ([sanitize] limits)
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// Size of the global rayon pool (default: one thread per core)
+        #[arg(long)]
+        build_threads: Option<usize>,
+        /// Threads parsing input chunks (default: the --build-threads pool)
+        #[arg(long)]
+        decode_threads: Option<usize>,
+        /// Store an H3 cell per record at this resolution (0-15) for /h3/:cell/places
+        #[arg(long)]
+        h3_resolution: Option<u8>,
+        /// Most populous records kept in RAM by --mmap readers (0 = none)
+        #[arg(long, default_value_t = hot::DEFAULT_RECORDS)]
+        hot_records: usize,
+        /// GeoNames admin1CodesASCII.txt; adds admin1_name to responses
+        #[arg(long)]
+        admin1_codes: Option<PathBuf>,
+        /// GeoNames admin2Codes.txt; adds admin2_name to responses
+        #[arg(long)]
+        admin2_codes: Option<PathBuf>,
+        /// GeoNames countryInfo.txt; adds locale / country_name to responses
+        /// and serves /country/:iso
+        #[arg(long)]
+        country_info: Option<PathBuf>,
+        /// Disputed-territory policy (TOML); marks candidates `disputed`
+        #[arg(long)]
+        disputed_policy: Option<PathBuf>,
+        /// GeoNames hierarchy.zip; parent chains for include=hierarchy
+        #[arg(long)]
+        hierarchy: Option<PathBuf>,
+        /// Store postings lists with at least this many ids as roaring bitmaps (0 = never)
+        #[arg(long, default_value_t = postings::DEFAULT_ROARING_THRESHOLD)]
+        roaring_threshold: usize,
+        /// Spill key maps to sorted runs here instead of holding them in RAM
+        #[arg(long)]
+        spill_dir: Option<PathBuf>,
+        /// Memory budget per key map with --spill-dir, in MiB
+        #[arg(long, default_value_t = 1024)]
+        spill_budget_mb: usize,
+        /// Also write a labels DB (id, name, point, population, country) for
+        /// the frontend's tile / label rendering
+        #[arg(long)]
+        emit_labels: Option<PathBuf>,
+    },
+    Estimate {
+        /// GeoNames allCountries.zip
         #[arg(long)]
         all: PathBuf,
+        /// GeoNames alternateNamesV2.zip
+        #[arg(long)]
+        alt: Option<PathBuf>,
+        #[arg(long, default_value_t = 0)]
+        min_pop: u32,
+        /// Feature classes to leave out, e.g. H,R,U
+        #[arg(long, value_delimiter = ',')]
+        exclude_feature_class: Vec<String>,
+        /// Feature codes to leave out, e.g. STM,STMI
+        #[arg(long, value_delimiter = ',')]
+        exclude_feature_code: Vec<String>,
+        /// Sample one key in N for the key / postings / FST estimates
+        #[arg(long, default_value_t = 32)]
+        key_sample: u64,
+        /// As for build
+        #[arg(long, default_value_t = hot::DEFAULT_RECORDS)]
+        hot_records: usize,
+    },
+    /// Apply a GeoNames daily diff to an existing DB (no full rebuild)
+    Update {
+        #[arg(long)]
+        db: PathBuf,
+        /// GeoNames modifications-YYYY-MM-DD.txt
         #[arg(long)]
-        alt: PathBuf,
+        modifications: Option<PathBuf>,
+        /// GeoNames deletes-YYYY-MM-DD.txt
+        #[arg(long)]
+        deletes: Option<PathBuf>,
         #[arg(long)]
         out: PathBuf,
+        /// As the DB was built with
         #[arg(long, default_value_t = 0)]
         min_pop: u32,
+        /// As the DB was built with
+        #[arg(long, value_delimiter = ',')]
+        exclude_feature_class: Vec<String>,
+        /// As the DB was built with
+        #[arg(long, value_delimiter = ',')]
+        exclude_feature_code: Vec<String>,
+        /// geodb.toml ([sanitize] limits)
+        #[arg(long)]
+        config: Option<PathBuf>,
     },
     Query {
+        /// Optional with feature "embed" (falls back to the compiled-in DB)
         #[arg(long)]
-        db: PathBuf,
+        db: Option<PathBuf>,
         #[arg(long)]
         key: String,
         #[arg(long, default_value_t = 0)]
         limit: usize,
+        /// Rank by distance to this point ("lat,lon")
+        #[arg(long)]
+        near: Option<String>,
+        /// Include per-candidate score breakdown
+        #[arg(long)]
+        explain: bool,
+        /// On a miss, accept keys within this many edits (max 2)
+        #[arg(long)]
+        fuzzy: Option<u32>,
+        /// On a miss, match single words of multi-word names ("janeiro")
+        #[arg(long)]
+        tokens: bool,
+        /// Answer from every match path (aliases, accents, tokens, fuzzy,
+        /// segmentation), each place once with its best match
+        #[arg(long)]
+        combine: bool,
+        /// Keep only candidates in "minLon,minLat,maxLon,maxLat"
+        #[arg(long)]
+        bbox: Option<String>,
+        /// Prefer candidates the key names in this language, e.g. de
+        #[arg(long)]
+        lang: Option<String>,
+        /// Candidates matched by a historic alternate name: demote or exclude
+        #[arg(long)]
+        historic: Option<String>,
+        /// Candidates matched by a colloquial alternate name: demote or exclude
+        #[arg(long)]
+        colloquial: Option<String>,
+        /// Article date (YYYY-MM-DD): prefer names valid then
+        #[arg(long)]
+        as_of: Option<String>,
+        /// Keep only these feature classes, e.g. P
+        #[arg(long, value_delimiter = ',')]
+        feature_class: Vec<String>,
+        /// Keep only these feature codes, e.g. PPL,PPLA
+        #[arg(long, value_delimiter = ',')]
+        feature_code: Vec<String>,
+        /// geodb.toml with ranking weights
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// Open an inconsistent DB anyway and skip unreadable entries
+        #[arg(long)]
+        lenient: bool,
+        /// Map the DB file instead of reading it into memory
+        #[arg(long)]
+        mmap: bool,
+    },
+    /// Deploy gate: open + check the DB, run the configured canaries, time
+    /// cold lookups; prints a JSON verdict and fails unless every check passes
+    Preflight {
+        /// Optional with feature "embed" (falls back to the compiled-in DB)
+        #[arg(long)]
+        db: Option<PathBuf>,
+        /// geodb.toml with [preflight] canaries, ranking and script
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// Fail unless the DB's build hash (x-geodb-build) is this one
+        #[arg(long)]
+        expect_build: Option<String>,
+        /// Open the DB the way `serve --mmap` will
+        #[arg(long)]
+        mmap: bool,
+    },
+    /// Upload a DB and its metadata to a build registry (feature "registry")
+    Publish {
+        #[arg(long)]
+        db: PathBuf,
+        /// s3://bucket/prefix, gs://bucket/prefix or file:///dir
+        #[arg(long)]
+        registry: String,
+    },
+    /// Builds in a registry, newest first
+    ListBuilds {
+        #[arg(long)]
+        registry: String,
+        /// Only this publish date (YYYY-MM-DD)
+        #[arg(long)]
+        date: Option<String>,
+    },
+    /// Nearest populated places to a point
+    Reverse {
+        /// Optional with feature "embed" (falls back to the compiled-in DB)
+        #[arg(long)]
+        db: Option<PathBuf>,
+        #[arg(long, allow_hyphen_values = true)]
+        lat: f32,
+        #[arg(long, allow_hyphen_values = true)]
+        lon: f32,
+        #[arg(long, default_value_t = reverse::DEFAULT_LIMIT)]
+        limit: usize,
+        /// Any feature, not just populated places (class P)
+        #[arg(long)]
+        all: bool,
+        /// Nothing farther than this (default: widen until a place is found)
+        #[arg(long)]
+        max_km: Option<f64>,
     },
-    Serve {
+    /// Share of a name corpus (one name per line, optionally `name<TAB>CC`)
+    /// the DB resolves exactly or fuzzily, with misses per country, as JSON
+    Coverage {
         #[arg(long)]
         db: PathBuf,
+        #[arg(long)]
+        corpus: PathBuf,
+        /// Edits allowed for the fuzzy pass (0 = exact only)
+        #[arg(long, default_value_t = fuzzy::MAX_EDITS)]
+        fuzzy: u32,
+        /// geodb.toml with ranking weights and script
+        #[arg(long)]
+        config: Option<PathBuf>,
+    },
+    /// Version, section sizes, key / record counts, postings histogram and
+    /// the largest keys of a DB, as JSON
+    Inspect {
+        #[arg(long)]
+        db: PathBuf,
+    },
+    /// Integrity walk: every record decodes, every key's postings decode and
+    /// resolve to records; JSON report, fails on any problem
+    Validate {
+        #[arg(long)]
+        db: PathBuf,
+    },
+    /// Keys starting with a prefix, most populous first
+    Suggest {
+        /// Optional with feature "embed" (falls back to the compiled-in DB)
+        #[arg(long)]
+        db: Option<PathBuf>,
+        #[arg(long)]
+        prefix: String,
+        #[arg(long, default_value_t = suggest::DEFAULT_LIMIT)]
+        limit: usize,
+    },
+    /// Place mentions in an article, resolved against each other, as
+    /// POST /extract + /resolve would answer; no server needed
+    Tag {
+        /// Optional with feature "embed" (falls back to the compiled-in DB)
+        #[arg(long)]
+        db: Option<PathBuf>,
+        /// Article text; "-" reads stdin
+        #[arg(long)]
+        input: PathBuf,
+        /// Candidates per mention, best first
+        #[arg(long, default_value_t = 1)]
+        limit: usize,
+        #[arg(long, value_enum, default_value_t = TagFormat::Tsv)]
+        format: TagFormat,
+        /// geodb.toml with ranking weights and script
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// Map the DB file instead of reading it into memory
+        #[arg(long)]
+        mmap: bool,
+    },
+    Serve {
+        /// Optional with feature "embed" (falls back to the compiled-in DB)
+        #[arg(long)]
+        db: Option<PathBuf>,
+        /// Serve the DB compiled into the binary (feature "embed"); no files
+        /// needed. See the Dockerfile's `embedded` target.
+        #[arg(long, conflicts_with_all = ["db", "mmap"])]
+        embedded: bool,
         /// Bind address, e.g. 127.0.0.1:8787
         #[arg(long, default_value = "127.0.0.1:8787")]
         bind: SocketAddr,
+        /// Serve /admin/* on this address only, not on --bind (default:
+        /// [server] admin_bind)
+        #[arg(long)]
+        admin_bind: Option<SocketAddr>,
+        /// geodb.toml; created on first `PUT /admin/ranking?save=true`
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// Tokio worker threads (default: [server] worker_threads, else one per core)
+        #[arg(long)]
+        worker_threads: Option<usize>,
+        /// Upper bound on blocking threads (background jobs, file IO; default:
+        /// [server] blocking_threads)
+        #[arg(long)]
+        blocking_threads: Option<usize>,
+        /// Lookups running at once on the blocking pool (default: [server]
+        /// lookup_threads, else one per core)
+        #[arg(long)]
+        lookup_threads: Option<usize>,
+        /// API requests per second per client IP; over it, 429 (default:
+        /// [server] rate_limit, else unlimited)
+        #[arg(long)]
+        rate_limit: Option<f64>,
+        /// Requests a client may send at once before --rate-limit applies
+        /// (default: [server] rate_burst, else the rate rounded up)
+        #[arg(long)]
+        rate_burst: Option<u32>,
+        /// API requests handled at once; over it, 503 (default: [server]
+        /// max_in_flight, else no cap)
+        #[arg(long)]
+        max_in_flight: Option<usize>,
+        /// Open an inconsistent DB anyway; lookups skip unreadable entries
+        /// instead of failing
+        #[arg(long)]
+        lenient: bool,
+        /// Serve HTTPS with this PEM certificate chain (with --tls-key;
+        /// default: [server] tls_cert; needs --features tls)
+        #[arg(long)]
+        tls_cert: Option<PathBuf>,
+        /// PEM private key for --tls-cert (default: [server] tls_key)
+        #[arg(long)]
+        tls_key: Option<PathBuf>,
+        /// Query this server once it listens, then exit: 1 at once if a
+        /// canary fails, else 0 this many seconds after start (CI image check)
+        #[arg(long, value_name = "SECS")]
+        smoke_test: Option<u64>,
+        /// Map the DB file instead of reading it into memory: instant start,
+        /// the OS pages data in on demand (the hot section is still loaded)
+        #[arg(long)]
+        mmap: bool,
     },
 }
 
-
-#[derive(Serialize)]
-struct OutCandidateOwned {
-    geoname_id: u32,
-    name: String,
-    country: String,
-    admin1: String,
-    admin2: String,
-    lat: f32,
-    lon: f32,
-    feature_class: char,
-    feature_code: String,
-    population: u32,
-}
-
-#[derive(Serialize)]
-struct OutJsonOwned {
-    key: String,
-    count: usize,
-    candidates: Vec<OutCandidateOwned>,
-}
-
-#[tokio::main]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
     let cli = Cli::parse();
     match cli.cmd {
-        Cmd::Build { all, alt, out, min_pop } => build::build_db(&all, &alt, &out, min_pop),
-        Cmd::Query { db, key, limit } => {
-            let json = query_exact(&db, &key, limit)?;
+        Cmd::Build {
+            all,
+            alt,
+            osm,
+            sources,
+            out,
+            min_pop,
+            exclude_feature_class,
+            exclude_feature_code,
+            skip_historic,
+            skip_colloquial,
+            report,
+            heavy_postings,
+            config,
+            build_threads,
+            decode_threads,
+            h3_resolution,
+            hot_records,
+            admin1_codes,
+            admin2_codes,
+            country_info,
+            disputed_policy,
+            hierarchy,
+            roaring_threshold,
+            spill_dir,
+            spill_budget_mb,
+            emit_labels,
+        } => {
+            if let Some(n) = build_threads {
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(n)
+                    .build_global()?;
+            }
+            let mut adapters: Vec<Box<dyn build::SourceAdapter>> = Vec::new();
+            match (all, alt) {
+                (Some(all), alt) => adapters.push(Box::new(build::GeoNamesSource {
+                    all,
+                    alt,
+                    skip: NameFlags {
+                        historic: skip_historic,
+                        colloquial: skip_colloquial,
+                        ..NameFlags::default()
+                    },
+                })),
+                (None, Some(_)) => bail!("--alt requires --all"),
+                (None, None) => {}
+            }
+            if let Some(path) = osm {
+                adapters.push(Box::new(osm::OsmSource { path }));
+            }
+            for spec in &sources {
+                adapters.push(build::parse_source(spec)?);
+            }
+            let mut diag = diagnostics::DiagnosticsOptions::for_db(&out);
+            diag.heavy_postings = heavy_postings;
+            if let Some(report) = report {
+                diag.report = report;
+            }
+            let cfg = match config {
+                Some(p) => config::Config::load(&p)?,
+                None => config::Config::default(),
+            };
+            let opts = build::BuildOptions {
+                min_pop,
+                exclude: build::FeatureFilter::new(&exclude_feature_class, &exclude_feature_code)?,
+                decode_threads,
+                h3_resolution,
+                hot_records,
+                admin1_codes,
+                admin2_codes,
+                country_info,
+                disputed_policy,
+                hierarchy,
+                roaring_threshold,
+                spill: spill_dir.map(|dir| spill::SpillOptions {
+                    dir,
+                    budget_bytes: spill_budget_mb << 20,
+                }),
+                diagnostics: diag,
+                sanitize: cfg.sanitize,
+                emit_labels,
+            };
+            build::build_db(&adapters, &out, &opts)
+        }
+        Cmd::Estimate {
+            all,
+            alt,
+            min_pop,
+            exclude_feature_class,
+            exclude_feature_code,
+            key_sample,
+            hot_records,
+        } => {
+            let opts = estimate::EstimateOptions {
+                min_pop,
+                exclude: build::FeatureFilter::new(&exclude_feature_class, &exclude_feature_code)?,
+                key_sample,
+                hot_records,
+            };
+            let est = estimate::estimate(&all, alt.as_deref(), &opts)?;
+            println!("{}", serde_json::to_string_pretty(&est)?);
+            Ok(())
+        }
+        Cmd::Update {
+            db,
+            modifications,
+            deletes,
+            out,
+            min_pop,
+            exclude_feature_class,
+            exclude_feature_code,
+            config,
+        } => {
+            let cfg = match config {
+                Some(p) => config::Config::load(&p)?,
+                None => config::Config::default(),
+            };
+            let opts = update::UpdateOptions {
+                min_pop,
+                exclude: build::FeatureFilter::new(&exclude_feature_class, &exclude_feature_code)?,
+                sanitize: cfg.sanitize,
+            };
+            update::update_db(
+                &db,
+                modifications.as_deref(),
+                deletes.as_deref(),
+                &out,
+                &opts,
+            )
+        }
+        Cmd::Query {
+            db,
+            key,
+            limit,
+            near,
+            explain,
+            fuzzy,
+            tokens,
+            combine,
+            bbox,
+            lang,
+            historic,
+            colloquial,
+            as_of,
+            feature_class,
+            feature_code,
+            config,
+            lenient,
+            mmap,
+        } => {
+            let cfg = match config {
+                Some(p) => config::Config::load(&p)?,
+                None => config::Config::default(),
+            };
+            let focus = near
+                .as_deref()
+                .map(ranking::parse_focus)
+                .transpose()
+                .map_err(|e| anyhow!("--near: {e}"))?;
+            let geo =
+                Geocoder::open(db.as_deref(), OpenOptions { lenient, mmap })?.with_config(&cfg)?;
+            let json = geo.lookup(
+                &key,
+                &LookupOptions {
+                    limit,
+                    focus,
+                    explain,
+                    fuzzy,
+                    tokens,
+                    combine,
+                    features: build::FeatureFilter::new(&feature_class, &feature_code)?,
+                    bbox: bbox
+                        .as_deref()
+                        .map(BBox::parse)
+                        .transpose()
+                        .map_err(|e| e.context("--bbox"))?,
+                    lang: lang
+                        .as_deref()
+                        .map(langs::parse)
+                        .transpose()
+                        .map_err(|e| e.context("--lang"))?,
+                    historic: NameUse::parse(historic.as_deref().unwrap_or(""))
+                        .map_err(|e| e.context("--historic"))?,
+                    colloquial: NameUse::parse(colloquial.as_deref().unwrap_or(""))
+                        .map_err(|e| e.context("--colloquial"))?,
+                    as_of: as_of
+                        .as_deref()
+                        .map(periods::parse_as_of)
+                        .transpose()
+                        .map_err(|e| e.context("--as-of"))?,
+                    ..LookupOptions::default()
+                },
+            )?;
             println!("{}", serde_json::to_string_pretty(&json)?);
             Ok(())
         }
-        Cmd::Serve { db, bind } => server::serve(db, bind).await,
-    }
-}
-
-/* -------------------------
-   DB reader
--------------------------- */
-
-struct Db {
-    fst_start: usize,
-    fst_len: usize,
-    postings_start: usize,
-    records_start: usize,
-    offsets_start: usize,
-    postings_len: usize,
-    records_len: usize,
-    offsets_len: usize,
-    bytes: Vec<u8>,
-}
-
-impl Db {
-    fn fst_slice(&self) -> &[u8] {
-        &self.bytes[self.fst_start..self.fst_start + self.fst_len]
-    }
-    fn postings_slice(&self) -> &[u8] {
-        &self.bytes[self.postings_start..self.postings_start + self.postings_len]
-    }
-    fn records_slice(&self) -> &[u8] {
-        &self.bytes[self.records_start..self.records_start + self.records_len]
-    }
-    fn offsets_slice(&self) -> &[u8] {
-        &self.bytes[self.offsets_start..self.offsets_start + self.offsets_len]
-    }
-}
-
-fn open_db(path: &Path) -> Result<Db> {
-    let mut bytes = Vec::new();
-    File::open(path)?.read_to_end(&mut bytes)?;
-
-    let mut cur = std::io::Cursor::new(&bytes[..]);
-
-    let mut magic = [0u8; 7];
-    cur.read_exact(&mut magic)?;
-    if &magic != MAGIC {
-        bail!("bad magic");
-    }
-    let ver = cur.read_u32::<LittleEndian>()?;
-    if ver != VERSION {
-        bail!("unsupported version {ver}");
-    }
-
-    let fst_len = cur.read_u64::<LittleEndian>()? as usize;
-    let postings_len = cur.read_u64::<LittleEndian>()? as usize;
-    let records_len = cur.read_u64::<LittleEndian>()? as usize;
-    let offsets_len = cur.read_u64::<LittleEndian>()? as usize;
-
-    let header_len = 7 + 4 + 8 * 4;
-    let fst_start = header_len;
-    let postings_start = fst_start + fst_len;
-    let records_start = postings_start + postings_len;
-    let offsets_start = records_start + records_len;
-
-    if offsets_start + offsets_len > bytes.len() {
-        bail!("corrupt file lengths");
-    }
-
-    Ok(Db {
-        fst_start,
-        fst_len,
-        postings_start,
-        records_start,
-        offsets_start,
-        postings_len,
-        records_len,
-        offsets_len,
-        bytes,
-    })
-}
-
-/* -------------------------
-   exact lookup query
--------------------------- */
-
-fn query_exact(db_path: &Path, key: &str, limit: usize) -> Result<OutJsonOwned> {
-    let db = open_db(db_path)?;
-    let fst = fst::Map::new(db.fst_slice()).map_err(|e| anyhow!("fst load: {e}"))?;
-
-    let mut candidates: Vec<OutCandidateOwned> = Vec::new();
-
-    let lookup_key = key.trim().to_lowercase();
-
-    if let Some(off) = fst.get(&lookup_key) {
-        let mut ids = read_postings(&db, off as usize)?;
-        if limit != 0 && ids.len() > limit {
-            ids.truncate(limit);
+        Cmd::Preflight {
+            db,
+            config,
+            expect_build,
+            mmap,
+        } => {
+            let cfg = match config {
+                Some(p) => config::Config::load(&p)?,
+                None => config::Config::default(),
+            };
+            let verdict = preflight::run(db.as_deref(), mmap, &cfg, expect_build.as_deref())?;
+            println!("{}", serde_json::to_string_pretty(&verdict)?);
+            if !verdict.pass {
+                bail!("preflight failed");
+            }
+            Ok(())
         }
-
-        for id in ids {
-            if let Some(rec) = read_record_by_id(&db, id)? {
-                candidates.push(OutCandidateOwned {
-                    geoname_id: rec.id,
-                    name: rec.name,
-                    country: rec.country,
-                    admin1: rec.admin1,
-                    admin2: rec.admin2,
-                    lat: rec.lat,
-                    lon: rec.lon,
-                    feature_class: rec.feat_class as char,
-                    feature_code: rec.feat_code,
-                    population: rec.population,
-                });
+        Cmd::Publish { db, registry } => {
+            let meta = registry::publish(&db, &registry)?;
+            println!("{}", serde_json::to_string_pretty(&meta)?);
+            Ok(())
+        }
+        Cmd::ListBuilds { registry, date } => {
+            let builds = registry::list_builds(&registry, date.as_deref())?;
+            println!("{}", serde_json::to_string_pretty(&builds)?);
+            Ok(())
+        }
+        Cmd::Reverse {
+            db,
+            lat,
+            lon,
+            limit,
+            all,
+            max_km,
+        } => {
+            let at = coords::Coordinate { lat, lon };
+            let opts = LookupOptions {
+                limit,
+                max_km,
+                features: if all {
+                    build::FeatureFilter::default()
+                } else {
+                    reverse::populated()
+                },
+                ..LookupOptions::default()
+            };
+            let json = Geocoder::open(db.as_deref(), OpenOptions::default())?.reverse(at, &opts)?;
+            println!("{}", serde_json::to_string_pretty(&json)?);
+            Ok(())
+        }
+        Cmd::Coverage {
+            db,
+            corpus,
+            fuzzy,
+            config,
+        } => {
+            let cfg = match config {
+                Some(p) => config::Config::load(&p)?,
+                None => config::Config::default(),
+            };
+            let geo = Geocoder::open(Some(&db), OpenOptions::default())?.with_config(&cfg)?;
+            let json = coverage::run(&geo, &corpus, fuzzy)?;
+            println!("{}", serde_json::to_string_pretty(&json)?);
+            Ok(())
+        }
+        Cmd::Inspect { db } => {
+            let json = inspect::inspect(&db)?;
+            println!("{}", serde_json::to_string_pretty(&json)?);
+            Ok(())
+        }
+        Cmd::Validate { db } => {
+            let json = validate::validate(&db)?;
+            println!("{}", serde_json::to_string_pretty(&json)?);
+            if !json.ok {
+                bail!("validation failed: {} problems", json.problems);
             }
+            Ok(())
         }
-    }
-
-    let count = candidates.len();
-    Ok(OutJsonOwned {
-        key: key.to_string(),
-        count,
-        candidates,
-    })
-}
-
-/* -------------------------
-   postings decode + record load
--------------------------- */
-
-fn read_postings(db: &Db, postings_offset: usize) -> Result<Vec<u32>> {
-    let blob = db.postings_slice();
-    if postings_offset >= blob.len() {
-        bail!("postings offset out of bounds");
-    }
-    let slice = &blob[postings_offset..];
-
-    let (len, len_bytes) = read_var_u32(slice)?;
-    let start = len_bytes;
-    let end = start + len as usize;
-    if end > slice.len() {
-        bail!("postings length out of bounds");
-    }
-    Ok(decode_delta_varints(&slice[start..end]))
-}
-
-fn read_record_by_id(db: &Db, id: u32) -> Result<Option<GeoRecord>> {
-    let slice = db.offsets_slice();
-    let mut cur = std::io::Cursor::new(slice);
-
-    let n = cur.read_u32::<LittleEndian>()? as usize;
-    let ids_start = 4;
-    let ids_end = ids_start + n * 4;
-    let offs_start = ids_end;
-    let offs_end = offs_start + n * 8;
-    if offs_end > slice.len() {
-        bail!("corrupt offsets");
-    }
-
-    let ids_bytes = &slice[ids_start..ids_end];
-
-    // binary search
-    let mut lo = 0usize;
-    let mut hi = n;
-    while lo < hi {
-        let mid = (lo + hi) / 2;
-        let mid_id = read_u32_le_at(ids_bytes, mid * 4);
-        if mid_id < id {
-            lo = mid + 1;
-        } else {
-            hi = mid;
+        Cmd::Suggest { db, prefix, limit } => {
+            let json =
+                Geocoder::open(db.as_deref(), OpenOptions::default())?.suggest(&prefix, limit)?;
+            println!("{}", serde_json::to_string_pretty(&json)?);
+            Ok(())
         }
-    }
-    if lo >= n {
-        return Ok(None);
-    }
-    let found_id = read_u32_le_at(ids_bytes, lo * 4);
-    if found_id != id {
-        return Ok(None);
-    }
-
-    let offs_bytes = &slice[offs_start..offs_end];
-    let off = read_u64_le_at(offs_bytes, lo * 8) as usize;
-
-    let rec_blob = db.records_slice();
-    if off >= rec_blob.len() {
-        bail!("record offset out of bounds");
-    }
-    let mut c = std::io::Cursor::new(&rec_blob[off..]);
-
-    let rid = c.read_u32::<LittleEndian>()?;
-    let lat = c.read_f32::<LittleEndian>()?;
-    let lon = c.read_f32::<LittleEndian>()?;
-    let pop = c.read_u32::<LittleEndian>()?;
-    let mut fc = [0u8; 1];
-    c.read_exact(&mut fc)?;
-
-    let name = read_lp_str_cur(&mut c)?;
-    let country = read_lp_str_cur(&mut c)?;
-    let admin1 = read_lp_str_cur(&mut c)?;
-    let admin2 = read_lp_str_cur(&mut c)?;
-    let feat_code = read_lp_str_cur(&mut c)?;
-
-    Ok(Some(GeoRecord {
-        id: rid,
-        name,
-        ascii_name: String::new(),
-        country,
-        admin1,
-        admin2,
-        lat,
-        lon,
-        feat_class: fc[0],
-        feat_code,
-        population: pop,
-    }))
-}
-
-fn read_lp_str_cur(cur: &mut std::io::Cursor<&[u8]>) -> Result<String> {
-    let pos = cur.position() as usize;
-    let buf = cur.get_ref();
-
-    let (len, len_bytes) = read_var_u32(&buf[pos..])?;
-    let start = pos + len_bytes;
-    let end = start + len as usize;
-    if end > buf.len() {
-        bail!("string out of bounds");
-    }
-
-    let s = std::str::from_utf8(&buf[start..end])?.to_string();
-    cur.set_position(end as u64);
-    Ok(s)
-}
-
-/* -------------------------
-   varint + delta decode
--------------------------- */
-
-fn decode_delta_varints(bytes: &[u8]) -> Vec<u32> {
-    let mut out = Vec::new();
-    let mut i = 0usize;
-    let mut cur = 0u32;
-    while i < bytes.len() {
-        let (v, n) = match read_var_u32(&bytes[i..]) {
-            Ok(x) => x,
-            Err(_) => break,
-        };
-        i += n;
-        cur = cur.wrapping_add(v);
-        out.push(cur);
-    }
-    out
-}
+        Cmd::Tag {
+            db,
+            input,
+            limit,
+            format,
+            config,
+            mmap,
+        } => {
+            let cfg = match config {
+                Some(p) => config::Config::load(&p)?,
+                None => config::Config::default(),
+            };
+            let mut text = String::new();
+            if input.as_os_str() == "-" {
+                std::io::stdin().read_to_string(&mut text)?;
+            } else {
+                text = std::fs::read_to_string(&input)
+                    .map_err(|e| anyhow!("{}: {e}", input.display()))?;
+            }
+            let geo = Geocoder::open(
+                db.as_deref(),
+                OpenOptions {
+                    lenient: false,
+                    mmap,
+                },
+            )?
+            .with_config(&cfg)?;
+            let opts = LookupOptions::default();
+            // every candidate goes into disambiguation; `limit` applies after
+            let mut found = extract::extract(
+                &geo.index(&opts),
+                &extract::pipeline(&geo.ranker(&opts)),
+                geo.countries(),
+                &text,
+                0,
+            )?;
+            let ranked = found
+                .iter_mut()
+                .map(|m| std::mem::take(&mut m.ranked))
+                .collect();
+            let mut mentions = Vec::with_capacity(found.len());
+            for (m, mut scored) in found.into_iter().zip(disambiguate::resolve(ranked)) {
+                scored.truncate(limit);
+                let candidates = scored
+                    .into_iter()
+                    .map(|s| {
+                        let mut c = geo.candidate(s.rec, &opts)?;
+                        c.score = Some(s.context.total);
+                        Ok(c)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let written = text[m.start..m.end].split_whitespace();
+                mentions.push(TaggedJson {
+                    text: written.collect::<Vec<_>>().join(" "),
+                    start: m.start,
+                    end: m.end,
+                    key: m.key,
+                    candidates,
+                });
+            }
+            match format {
+                TagFormat::Tsv => {
+                    for m in &mentions {
+                        for c in &m.candidates {
+                            println!(
+                                "{}\t{}\t{}\t{}\t{}\t{}\t{:.4}",
+                                m.start,
+                                m.end,
+                                m.text,
+                                c.geoname_id,
+                                c.name,
+                                c.country,
+                                c.score.unwrap_or(0.0)
+                            );
+                        }
+                    }
+                }
+                TagFormat::Json => {
+                    let json = TagJson {
+                        count: mentions.len(),
+                        mentions,
+                    };
+                    println!("{}", serde_json::to_string_pretty(&json)?);
+                }
+            }
+            Ok(())
+        }
+        Cmd::Serve {
+            db,
+            embedded,
+            bind,
+            admin_bind,
+            config,
+            worker_threads,
+            blocking_threads,
+            lookup_threads,
+            rate_limit,
+            rate_burst,
+            max_in_flight,
+            smoke_test,
+            tls_cert,
+            tls_key,
+            lenient,
+            mmap,
+        } => {
+            if embedded && !cfg!(feature = "embed") {
+                bail!("--embedded: no DB compiled in (build with --features embed and GEODB_EMBED_DB=<path>)");
+            }
+            // validated here, before the runtime exists: tokio panics on 0 threads
+            let mut cfg = match &config {
+                Some(p) => config::Config::load(p)?,
+                None => config::Config::default(),
+            };
+            cfg.server.worker_threads = worker_threads.or(cfg.server.worker_threads);
+            cfg.server.blocking_threads = blocking_threads.or(cfg.server.blocking_threads);
+            cfg.server.lookup_threads = lookup_threads.or(cfg.server.lookup_threads);
+            cfg.server.rate_limit = rate_limit.or(cfg.server.rate_limit);
+            cfg.server.rate_burst = rate_burst.or(cfg.server.rate_burst);
+            cfg.server.max_in_flight = max_in_flight.or(cfg.server.max_in_flight);
+            cfg.server.tls_cert = tls_cert.or(cfg.server.tls_cert);
+            cfg.server.tls_key = tls_key.or(cfg.server.tls_key);
+            cfg.server
+                .validate()
+                .and_then(|_| cfg.check())
+                .map_err(|e| anyhow!("server: {e}"))?;
+            if cfg.server.tls().is_some() {
+                if !cfg!(feature = "tls") {
+                    bail!("--tls-cert: built without TLS (build with --features tls)");
+                }
+                if smoke_test.is_some() {
+                    bail!("--smoke-test queries over plain HTTP; run it without --tls-cert");
+                }
+            }
 
-fn read_var_u32(buf: &[u8]) -> Result<(u32, usize)> {
-    let mut v: u32 = 0;
-    let mut shift = 0;
-    for (i, &b) in buf.iter().enumerate().take(5) {
-        let chunk = (b & 0x7F) as u32;
-        v |= chunk << shift;
-        if (b & 0x80) == 0 {
-            return Ok((v, i + 1));
+            let mut rt = tokio::runtime::Builder::new_multi_thread();
+            rt.enable_all();
+            if let Some(n) = cfg.server.worker_threads {
+                rt.worker_threads(n);
+            }
+            if let Some(n) = cfg.server.blocking_threads {
+                rt.max_blocking_threads(n);
+            }
+            rt.build()?.block_on(server::serve(
+                db,
+                OpenOptions { lenient, mmap },
+                bind,
+                admin_bind,
+                cfg.server.lookup_threads,
+                cfg.server.throttle(),
+                smoke_test.map(std::time::Duration::from_secs),
+                cfg.server.tls(),
+                config,
+            ))
         }
-        shift += 7;
     }
-    Err(anyhow!("bad varint"))
-}
-
-/* -------------------------
-   little-endian helpers
--------------------------- */
-
-fn read_u32_le_at(b: &[u8], off: usize) -> u32 {
-    let x = &b[off..off + 4];
-    u32::from_le_bytes([x[0], x[1], x[2], x[3]])
 }
-
-fn read_u64_le_at(b: &[u8], off: usize) -> u64 {
-    let x = &b[off..off + 8];
-    u64::from_le_bytes([x[0], x[1], x[2], x[3], x[4], x[5], x[6], x[7]])
-}
\ No newline at end of file
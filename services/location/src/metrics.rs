@@ -0,0 +1,180 @@
+// src/metrics.rs
+//
+// Lookup latency histograms for `GET /metrics` (Prometheus text format),
+// bucketed three ways so the cost of ambiguous keys shows up directly:
+//   geodb_lookup_seconds_by_key_length{key_length="4-7",le=...}
+//   geodb_lookup_seconds_by_postings{postings="100-999",le=...}
+//   geodb_lookup_seconds_by_results{results="2-9",le=...}
+// key_length counts chars of the normalized key, postings the candidate ids a
+// source returned before filters, results the ranked candidates (before
+// `limit`). Plain atomics, no registry: every lookup bumps three counters.
+// DB swap state (reload.rs) is rendered after the histograms.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::reload::SwapState;
+
+/// Upper bounds in seconds; the +Inf bucket is implicit.
+const LATENCY_BOUNDS: [f64; 12] = [
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 1.0,
+];
+
+/// (label value, inclusive upper bound) per dimension; the last is open-ended.
+const KEY_LENGTH: &[(&str, usize)] = &[("1-3", 3), ("4-7", 7), ("8-15", 15), ("16+", usize::MAX)];
+const POSTINGS: &[(&str, usize)] = &[
+    ("0", 0),
+    ("1", 1),
+    ("2-9", 9),
+    ("10-99", 99),
+    ("100-999", 999),
+    ("1000+", usize::MAX),
+];
+const RESULTS: &[(&str, usize)] = &[("0", 0), ("1", 1), ("2-9", 9), ("10+", usize::MAX)];
+
+struct Histogram {
+    /// Non-cumulative; index LATENCY_BOUNDS.len() is +Inf.
+    buckets: [AtomicU64; LATENCY_BOUNDS.len() + 1],
+    sum_us: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Self {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            sum_us: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    fn observe(&self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        let i = LATENCY_BOUNDS
+            .iter()
+            .position(|b| secs <= *b)
+            .unwrap_or(LATENCY_BOUNDS.len());
+        self.buckets[i].fetch_add(1, Ordering::Relaxed);
+        self.sum_us
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// One histogram per bucket of one dimension.
+struct Family {
+    name: &'static str,
+    label: &'static str,
+    help: &'static str,
+    buckets: &'static [(&'static str, usize)],
+    hists: Vec<Histogram>,
+}
+
+impl Family {
+    fn new(
+        name: &'static str,
+        label: &'static str,
+        help: &'static str,
+        buckets: &'static [(&'static str, usize)],
+    ) -> Self {
+        Self {
+            name,
+            label,
+            help,
+            buckets,
+            hists: buckets.iter().map(|_| Histogram::new()).collect(),
+        }
+    }
+
+    fn observe(&self, value: usize, elapsed: Duration) {
+        let i = self
+            .buckets
+            .iter()
+            .position(|(_, max)| value <= *max)
+            .unwrap_or(self.buckets.len() - 1);
+        self.hists[i].observe(elapsed);
+    }
+
+    fn render(&self, out: &mut String) {
+        let (name, label) = (self.name, self.label);
+        let _ = writeln!(out, "# HELP {name} {}", self.help);
+        let _ = writeln!(out, "# TYPE {name} histogram");
+        for ((value, _), h) in self.buckets.iter().zip(&self.hists) {
+            let mut cumulative = 0;
+            for (i, n) in h.buckets.iter().enumerate() {
+                cumulative += n.load(Ordering::Relaxed);
+                let le = LATENCY_BOUNDS
+                    .get(i)
+                    .map_or("+Inf".to_string(), |b| b.to_string());
+                let _ = writeln!(
+                    out,
+                    "{name}_bucket{{{label}=\"{value}\",le=\"{le}\"}} {cumulative}"
+                );
+            }
+            let sum = h.sum_us.load(Ordering::Relaxed) as f64 / 1e6;
+            let _ = writeln!(out, "{name}_sum{{{label}=\"{value}\"}} {sum}");
+            let count = h.count.load(Ordering::Relaxed);
+            let _ = writeln!(out, "{name}_count{{{label}=\"{value}\"}} {count}");
+        }
+    }
+}
+
+pub struct Metrics {
+    by_key_length: Family,
+    by_postings: Family,
+    by_results: Family,
+    pub swap: SwapState,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            by_key_length: Family::new(
+                "geodb_lookup_seconds_by_key_length",
+                "key_length",
+                "Lookup latency by normalized key length in chars.",
+                KEY_LENGTH,
+            ),
+            by_postings: Family::new(
+                "geodb_lookup_seconds_by_postings",
+                "postings",
+                "Lookup latency by candidate ids before filters.",
+                POSTINGS,
+            ),
+            by_results: Family::new(
+                "geodb_lookup_seconds_by_results",
+                "results",
+                "Lookup latency by ranked candidates returned.",
+                RESULTS,
+            ),
+            swap: SwapState::default(),
+        }
+    }
+}
+
+impl Metrics {
+    pub fn observe_lookup(&self, key: &str, postings: usize, results: usize, elapsed: Duration) {
+        self.by_key_length.observe(key.chars().count(), elapsed);
+        self.by_postings.observe(postings, elapsed);
+        self.by_results.observe(results, elapsed);
+    }
+
+    /// Lookups observed since start.
+    pub fn lookups(&self) -> u64 {
+        self.by_key_length
+            .hists
+            .iter()
+            .map(|h| h.count.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for f in [&self.by_key_length, &self.by_postings, &self.by_results] {
+            f.render(&mut out);
+        }
+        self.swap.render(&mut out);
+        out
+    }
+}
@@ -0,0 +1,110 @@
+// src/nameflags.rs
+//
+// alternateNamesV2 flags (columns 5-8: isPreferredName, isShortName,
+// isColloquial, isHistoric; 9-10, the validity period, see periods.rs). Historic and colloquial names are kept in the
+// index by default, since archive text still says "Constantinople", and are
+// also written to the lang-names FST under "historic:<key>" /
+// "colloquial:<key>". The tags are 4+ letters so they never collide with a
+// language. `historic=demote|exclude` and `colloquial=demote|exclude` on
+// /query then scale those candidates by ranking.flagged_name or drop them.
+// `geodb build --skip-historic / --skip-colloquial` leaves them out of the
+// index altogether.
+// A record whose own name is the key is never flagged for it; an id that has
+// the key both as a flagged and as a plain alternate name is flagged.
+
+use anyhow::{bail, Result};
+use hashbrown::HashMap;
+
+use crate::build::{norm_key, FastBuildMap, GeoRecord};
+use crate::periods::Period;
+
+pub const HISTORIC: &str = "historic";
+pub const COLLOQUIAL: &str = "colloquial";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NameFlags {
+    pub preferred: bool,
+    pub short: bool,
+    pub colloquial: bool,
+    pub historic: bool,
+    pub period: Period,
+}
+
+impl NameFlags {
+    /// Columns 5-10 of an alternateNamesV2 line (flags "1" or empty, then
+    /// from / to).
+    pub fn parse(cols: &[&str]) -> Self {
+        let set = |i: usize| cols.get(i).is_some_and(|c| c.trim() == "1");
+        let col = |i: usize| cols.get(i).copied().unwrap_or_default();
+        Self {
+            preferred: set(0),
+            short: set(1),
+            colloquial: set(2),
+            historic: set(3),
+            period: Period::parse(col(4), col(5)),
+        }
+    }
+
+    /// Tags the name is stored under in the lang-names FST.
+    pub fn tags(self) -> impl Iterator<Item = &'static str> {
+        [(self.historic, HISTORIC), (self.colloquial, COLLOQUIAL)]
+            .into_iter()
+            .filter_map(|(on, tag)| on.then_some(tag))
+    }
+
+    /// True when `skip` asks to leave a name with these flags out.
+    pub fn skipped_by(self, skip: NameFlags) -> bool {
+        (skip.historic && self.historic) || (skip.colloquial && self.colloquial)
+    }
+}
+
+pub fn tag_key(tag: &str, key: &str) -> String {
+    format!("{tag}:{key}")
+}
+
+/// Drop flagged entries for records whose own name (or ASCII name) is the
+/// key: a row marking a place's current name historic does not demote it.
+pub fn unflag_own_names(tagged: &mut FastBuildMap, records: &[GeoRecord]) {
+    if !tagged
+        .keys()
+        .any(|k| k.starts_with(HISTORIC) || k.starts_with(COLLOQUIAL))
+    {
+        return;
+    }
+    let by_id: HashMap<u32, &GeoRecord> = records.iter().map(|r| (r.id, r)).collect();
+    for (k, ids) in tagged.iter_mut() {
+        let Some(key) = [HISTORIC, COLLOQUIAL]
+            .iter()
+            .find_map(|t| k.strip_prefix(t).and_then(|r| r.strip_prefix(':')))
+        else {
+            continue;
+        };
+        ids.retain(|id| {
+            !by_id.get(id).is_some_and(|r| {
+                norm_key(&r.name).as_deref() == Some(key)
+                    || norm_key(&r.ascii_name).as_deref() == Some(key)
+            })
+        });
+    }
+    tagged.retain(|_, ids| !ids.is_empty());
+}
+
+/// What a query does with candidates matched by a flagged name.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NameUse {
+    #[default]
+    Include,
+    Demote,
+    Exclude,
+}
+
+impl NameUse {
+    pub fn parse(s: &str) -> Result<Self> {
+        Ok(match s.trim() {
+            "" | "include" => Self::Include,
+            "demote" => Self::Demote,
+            "exclude" => Self::Exclude,
+            other => bail!("expected include, demote or exclude, got {other:?}"),
+        })
+    }
+}
@@ -0,0 +1,266 @@
+// src/osm.rs
+//
+// OpenStreetMap ingestion (planet.osm.pbf or Geofabrik extracts).
+// - Place nodes (place=*) become records directly.
+// - Place/admin relations are placed at their label/admin_centre node, which
+//   needs a second pass over the file to pick up those node coordinates.
+// - OSM ids are 64-bit and shared between element types, so records get
+//   synthetic ids (see build::SyntheticIds), assigned in (type, osm id) order.
+
+use anyhow::{Context, Result};
+use osmpbf::{Element, ElementReader, RelMemberType};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::build::{GeoRecord, SourceAdapter, SourceRecords, SyntheticIds};
+use crate::concordance::{self, ExternalRef};
+
+/// SyntheticIds namespaces; the discriminant is the namespace byte.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+enum OsmType {
+    Node = 1,
+    Relation = 2,
+}
+
+pub struct OsmSource {
+    pub path: PathBuf,
+}
+
+impl SourceAdapter for OsmSource {
+    fn describe(&self) -> String {
+        format!("osm:{}", self.path.display())
+    }
+
+    fn load(&self, min_pop: u32, ids: &mut SyntheticIds) -> Result<SourceRecords> {
+        parse_osm(&self.path, min_pop, ids)
+    }
+}
+
+struct Pending {
+    kind: OsmType,
+    osm_id: i64,
+    tags: Vec<(String, String)>,
+    lat: f64,
+    lon: f64,
+}
+
+fn parse_osm(path: &Path, min_pop: u32, ids: &mut SyntheticIds) -> Result<SourceRecords> {
+    eprintln!(
+        "[osm] pass 1: place nodes + relations from {}",
+        path.display()
+    );
+
+    // pass 1: place nodes with coordinates; relations with their label node id
+    let mut pending: Vec<Pending> = Vec::new();
+    let mut rel_anchor: Vec<(usize, i64)> = Vec::new();
+    let reader = ElementReader::from_path(path)
+        .with_context(|| format!("open osm pbf: {}", path.display()))?;
+    reader.for_each(|el| match el {
+        Element::Node(n) => {
+            if let Some(tags) = place_tags(n.tags()) {
+                pending.push(Pending {
+                    kind: OsmType::Node,
+                    osm_id: n.id(),
+                    tags,
+                    lat: n.lat(),
+                    lon: n.lon(),
+                });
+            }
+        }
+        Element::DenseNode(n) => {
+            if let Some(tags) = place_tags(n.tags()) {
+                pending.push(Pending {
+                    kind: OsmType::Node,
+                    osm_id: n.id(),
+                    tags,
+                    lat: n.lat(),
+                    lon: n.lon(),
+                });
+            }
+        }
+        Element::Relation(r) => {
+            let Some(tags) = place_tags(r.tags()) else {
+                return;
+            };
+            let anchor = r
+                .members()
+                .filter(|m| matches!(m.member_type, RelMemberType::Node))
+                .find(|m| matches!(m.role(), Ok("label") | Ok("admin_centre")))
+                .map(|m| m.member_id);
+            if let Some(node_id) = anchor {
+                rel_anchor.push((pending.len(), node_id));
+                pending.push(Pending {
+                    kind: OsmType::Relation,
+                    osm_id: r.id(),
+                    tags,
+                    lat: f64::NAN,
+                    lon: f64::NAN,
+                });
+            }
+        }
+        Element::Way(_) => {}
+    })?;
+
+    // pass 2: coordinates for relation anchors
+    if !rel_anchor.is_empty() {
+        eprintln!("[osm] pass 2: {} relation anchor nodes", rel_anchor.len());
+        let wanted: HashSet<i64> = rel_anchor.iter().map(|(_, id)| *id).collect();
+        let mut coords: HashMap<i64, (f64, f64)> = HashMap::with_capacity(wanted.len());
+        let reader = ElementReader::from_path(path)?;
+        reader.for_each(|el| match el {
+            Element::Node(n) if wanted.contains(&n.id()) => {
+                coords.insert(n.id(), (n.lat(), n.lon()));
+            }
+            Element::DenseNode(n) if wanted.contains(&n.id()) => {
+                coords.insert(n.id(), (n.lat(), n.lon()));
+            }
+            _ => {}
+        })?;
+        for (idx, node_id) in rel_anchor {
+            if let Some((lat, lon)) = coords.get(&node_id) {
+                pending[idx].lat = *lat;
+                pending[idx].lon = *lon;
+            }
+        }
+    }
+
+    // stable id assignment order
+    pending.retain(|p| p.lat.is_finite() && p.lon.is_finite());
+    pending.sort_by_key(|p| (p.kind, p.osm_id));
+
+    let mut out = SourceRecords {
+        records: Vec::with_capacity(pending.len()),
+        ..Default::default()
+    };
+    let mut below_min_pop: u64 = 0;
+
+    for p in pending {
+        let tag = |k: &str| {
+            p.tags
+                .iter()
+                .find(|(tk, _)| tk == k)
+                .map(|(_, v)| v.as_str())
+        };
+
+        let population: u32 = tag("population").and_then(parse_population).unwrap_or(0);
+        if population < min_pop {
+            below_min_pop += 1;
+            continue;
+        }
+        let Some(name) = tag("name") else {
+            continue;
+        };
+
+        let id = ids.assign(p.kind as u8, p.osm_id);
+        let element = match p.kind {
+            OsmType::Node => "node",
+            OsmType::Relation => "relation",
+        };
+        out.refs
+            .push((id, ExternalRef::Osm(format!("{element}/{}", p.osm_id))));
+        if let Some(q) = tag("wikidata").and_then(concordance::wikidata_ref) {
+            out.refs.push((id, q));
+        }
+
+        let (feat_class, feat_code) = feature_for(&p.tags);
+        let country = tag("ISO3166-1:alpha2")
+            .or_else(|| tag("is_in:country_code"))
+            .or_else(|| tag("addr:country"))
+            .unwrap_or("")
+            .to_ascii_uppercase();
+
+        for (k, v) in &p.tags {
+            let is_alt = k.starts_with("name:")
+                || matches!(
+                    k.as_str(),
+                    "alt_name" | "old_name" | "official_name" | "int_name" | "short_name"
+                );
+            if is_alt {
+                for n in v.split(';') {
+                    out.alt_names.push((n.to_string(), id));
+                }
+            }
+        }
+
+        out.records.push(GeoRecord {
+            id,
+            name: name.to_string(),
+            ascii_name: String::new(),
+            country,
+            admin1: String::new(),
+            admin2: String::new(),
+            lat: p.lat as f32,
+            lon: p.lon as f32,
+            feat_class,
+            feat_code: feat_code.to_string(),
+            population,
+            timezone: String::new(),
+        });
+    }
+
+    eprintln!(
+        "[osm] records={} alt_names={} below_min_pop={}",
+        out.records.len(),
+        out.alt_names.len(),
+        below_min_pop
+    );
+    Ok(out)
+}
+
+/// Keep only elements that describe a named place; returns their tags.
+fn place_tags<'a>(tags: impl Iterator<Item = (&'a str, &'a str)>) -> Option<Vec<(String, String)>> {
+    // Most tagged nodes are not places; only allocate for the ones we keep.
+    let tags: Vec<(&str, &str)> = tags.collect();
+    let is_place = tags
+        .iter()
+        .any(|(k, v)| *k == "place" || (*k == "boundary" && *v == "administrative"));
+    let named = tags.iter().any(|(k, _)| *k == "name");
+    if !(is_place && named) {
+        return None;
+    }
+    Some(
+        tags.into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+    )
+}
+
+/// "12 345", "12,345" and "~12000" all occur in the wild.
+fn parse_population(s: &str) -> Option<u32> {
+    let digits: String = s
+        .split(';')
+        .next()?
+        .chars()
+        .filter(|c| c.is_ascii_digit())
+        .collect();
+    digits.parse().ok()
+}
+
+/// Map OSM place/admin tags onto the closest GeoNames feature class/code.
+fn feature_for(tags: &[(String, String)]) -> (u8, &'static str) {
+    let get = |k: &str| tags.iter().find(|(tk, _)| tk == k).map(|(_, v)| v.as_str());
+
+    if let Some(place) = get("place") {
+        return match place {
+            "city" if get("capital") == Some("yes") => (b'P', "PPLC"),
+            "city" | "town" | "village" | "hamlet" | "isolated_dwelling" => (b'P', "PPL"),
+            "suburb" | "quarter" | "neighbourhood" | "borough" | "city_block" => (b'P', "PPLX"),
+            "country" => (b'A', "PCLI"),
+            "state" | "province" => (b'A', "ADM1"),
+            "county" | "district" => (b'A', "ADM2"),
+            "municipality" => (b'A', "ADM3"),
+            "region" => (b'L', "RGN"),
+            "island" | "islet" => (b'T', "ISL"),
+            "archipelago" => (b'T', "ISLS"),
+            _ => (b'L', "LCTY"),
+        };
+    }
+
+    match get("admin_level") {
+        Some("2") => (b'A', "PCLI"),
+        Some("4") => (b'A', "ADM1"),
+        Some("5") | Some("6") => (b'A', "ADM2"),
+        Some("7") | Some("8") => (b'A', "ADM3"),
+        _ => (b'A', "ADMD"),
+    }
+}
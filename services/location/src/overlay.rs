@@ -0,0 +1,217 @@
+// src/overlay.rs
+//
+// Runtime overlay: names served the day they appear instead of after the next
+// build (a renamed city, a municipality created last week). An entry maps a
+// name to existing record ids and / or records the DB does not have; the
+// overlay source (pipeline.rs) is consulted ahead of every DB source, and an
+// overlay record replaces the DB record with the same id. Names are folded
+// like DB keys (casefold.rs), so "Kyiv" and "KYIV" are one entry.
+// Loaded at startup from [server] overlay (JSON, `{"entries": [...]}`; a
+// missing file is an empty overlay) and edited with /admin/overlay, which
+// rewrites that file. Meant for a handful of entries until the next build
+// picks them up from the sources, not as a second gazetteer.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use crate::build::GeoRecord;
+use crate::casefold;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OverlayEntry {
+    pub name: String,
+    /// DB records the name should find.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ids: Vec<u32>,
+    /// Records the name finds that are not in the DB, or replace the DB
+    /// record with the same id.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub records: Vec<OverlayRecord>,
+}
+
+/// A GeoRecord as JSON; strings other than `name` may be left out.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OverlayRecord {
+    pub id: u32,
+    pub name: String,
+    #[serde(default)]
+    pub ascii_name: String,
+    #[serde(default)]
+    pub country: String,
+    #[serde(default)]
+    pub admin1: String,
+    #[serde(default)]
+    pub admin2: String,
+    pub lat: f32,
+    pub lon: f32,
+    /// GeoNames feature class, e.g. "P".
+    pub feature_class: String,
+    #[serde(default)]
+    pub feature_code: String,
+    #[serde(default)]
+    pub population: u32,
+    #[serde(default)]
+    pub timezone: String,
+}
+
+impl OverlayRecord {
+    fn to_record(&self) -> Result<GeoRecord> {
+        if self.name.trim().is_empty() {
+            bail!("record {}: empty name", self.id);
+        }
+        if !(-90.0..=90.0).contains(&self.lat) || !(-180.0..=180.0).contains(&self.lon) {
+            bail!("record {}: lat/lon out of range", self.id);
+        }
+        let &[class] = self.feature_class.as_bytes() else {
+            bail!(
+                "record {}: feature_class must be one letter, got {:?}",
+                self.id,
+                self.feature_class
+            );
+        };
+        Ok(GeoRecord {
+            id: self.id,
+            name: self.name.clone(),
+            ascii_name: self.ascii_name.clone(),
+            country: self.country.to_ascii_uppercase(),
+            admin1: self.admin1.clone(),
+            admin2: self.admin2.clone(),
+            lat: self.lat,
+            lon: self.lon,
+            feat_class: class.to_ascii_uppercase(),
+            feat_code: self.feature_code.to_ascii_uppercase(),
+            population: self.population,
+            timezone: self.timezone.clone(),
+        })
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct OverlayFile {
+    pub entries: Vec<OverlayEntry>,
+}
+
+#[derive(Debug, Default)]
+pub struct Overlay {
+    /// By folded name.
+    entries: BTreeMap<String, OverlayEntry>,
+    records: HashMap<u32, GeoRecord>,
+}
+
+impl Overlay {
+    /// Later entries for the same name replace earlier ones.
+    pub fn from_entries(entries: Vec<OverlayEntry>) -> Result<Self> {
+        let mut by_key = BTreeMap::new();
+        for entry in entries {
+            let key = casefold::fold(entry.name.trim());
+            if key.is_empty() {
+                bail!("overlay entry with empty name");
+            }
+            if entry.ids.is_empty() && entry.records.is_empty() {
+                bail!("overlay {:?}: no ids or records", entry.name);
+            }
+            by_key.insert(key, entry);
+        }
+        let mut records: HashMap<u32, GeoRecord> = HashMap::new();
+        for entry in by_key.values() {
+            for r in &entry.records {
+                let rec = r
+                    .to_record()
+                    .with_context(|| format!("overlay {:?}", entry.name))?;
+                if records
+                    .get(&rec.id)
+                    .is_some_and(|prev| prev.name != rec.name)
+                {
+                    bail!(
+                        "overlay: record {} defined twice with different names",
+                        rec.id
+                    );
+                }
+                records.insert(rec.id, rec);
+            }
+        }
+        Ok(Self {
+            entries: by_key,
+            records,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("read overlay: {}", path.display()))?;
+        let file: OverlayFile = serde_json::from_str(&text)
+            .with_context(|| format!("parse overlay: {}", path.display()))?;
+        Self::from_entries(file.entries).with_context(|| format!("overlay {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let text = serde_json::to_string_pretty(&OverlayFile {
+            entries: self.entries().cloned().collect(),
+        })?;
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, text).with_context(|| format!("write overlay: {}", tmp.display()))?;
+        std::fs::rename(&tmp, path)
+            .with_context(|| format!("replace overlay: {}", path.display()))?;
+        Ok(())
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &OverlayEntry> {
+        self.entries.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// `self` with `entries` added, replacing entries of the same name.
+    pub fn with(&self, entries: Vec<OverlayEntry>) -> Result<Self> {
+        Self::from_entries(self.entries().cloned().chain(entries).collect())
+    }
+
+    /// `self` without the entry for `name`; None when there is none.
+    pub fn without(&self, name: &str) -> Option<Self> {
+        let key = casefold::fold(name.trim());
+        self.entries.contains_key(&key).then(|| {
+            let mut entries = self.entries.clone();
+            entries.remove(&key);
+            let records = self
+                .records
+                .iter()
+                .filter(|(id, _)| {
+                    entries
+                        .values()
+                        .any(|e| e.records.iter().any(|r| r.id == **id))
+                })
+                .map(|(id, r)| (*id, r.clone()))
+                .collect();
+            Self { entries, records }
+        })
+    }
+
+    /// Sorted ids the folded `key` names: its DB ids and its own records.
+    pub fn ids(&self, key: &str) -> Option<Vec<u32>> {
+        let entry = self.entries.get(key)?;
+        let mut ids: Vec<u32> = entry
+            .ids
+            .iter()
+            .copied()
+            .chain(entry.records.iter().map(|r| r.id))
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+        Some(ids)
+    }
+
+    pub fn record(&self, id: u32) -> Option<&GeoRecord> {
+        self.records.get(&id)
+    }
+}
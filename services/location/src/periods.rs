@@ -0,0 +1,105 @@
+// src/periods.rs
+//
+// `as_of=YYYY-MM-DD`: resolve names as an article of that date meant them.
+// - alternateNamesV2 columns 9-10 give some names a validity period ("from",
+//   "to"; usually years: Leningrad 1924-1991). The build stores them in the
+//   lang-names FST as "period:<key>\t<from>\t<to>" over the ids the name had
+//   then (tabs never occur in keys, so one prefix scan finds every period of a
+//   key). A query with as_of scales candidates whose name was valid at that
+//   date by ranking.period_match and the ones it was not valid for by
+//   ranking.period_mismatch; names without a period are left alone.
+// - Country succession (data/historical_countries.tsv, synonyms.rs): before an
+//   alias's `until` year ("zaire", 1997) the alias still named a current state,
+//   so synonym expansion to the successors is skipped in favour of the
+//   historical record itself (PCLH), when the index has one.
+// Comparisons are by year: GeoNames periods rarely carry more.
+
+use anyhow::{anyhow, bail, Result};
+use fst::automaton::{Automaton, Str};
+use fst::{IntoStreamer, Streamer};
+
+use crate::{read_postings, Db};
+
+pub const TAG: &str = "period";
+
+/// Validity period of a name; open ends are None.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Period {
+    pub from: Option<i32>,
+    pub to: Option<i32>,
+}
+
+impl Period {
+    /// "from" / "to" columns; anything without a leading year is ignored.
+    pub fn parse(from: &str, to: &str) -> Self {
+        Self {
+            from: year_of(from),
+            to: year_of(to),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.from.is_none() && self.to.is_none()
+    }
+
+    pub fn contains(&self, year: i32) -> bool {
+        self.from.is_none_or(|f| f <= year) && self.to.is_none_or(|t| t >= year)
+    }
+
+    pub fn tag_key(&self, key: &str) -> String {
+        let end = |y: Option<i32>| y.map(|y| y.to_string()).unwrap_or_default();
+        format!("{TAG}:{key}\t{}\t{}", end(self.from), end(self.to))
+    }
+}
+
+/// Leading (optionally negative) year of "1924", "1924-05-01", "-44".
+fn year_of(s: &str) -> Option<i32> {
+    let s = s.trim();
+    let digits = s.strip_prefix('-').unwrap_or(s);
+    let end = digits
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(digits.len());
+    if end == 0 {
+        return None;
+    }
+    let year: i32 = digits[..end].parse().ok()?;
+    Some(if s.starts_with('-') { -year } else { year })
+}
+
+/// Year of an `as_of=YYYY-MM-DD` parameter (a bare year is accepted too).
+pub fn parse_as_of(s: &str) -> Result<i32> {
+    let s = s.trim();
+    let mut parts = s.split('-');
+    let year = parts
+        .next()
+        .filter(|y| y.len() == 4)
+        .and_then(|y| y.parse::<i32>().ok())
+        .ok_or_else(|| anyhow!("expected YYYY-MM-DD, got {s:?}"))?;
+    for (part, max) in parts.by_ref().zip([12, 31]) {
+        match part.parse::<u32>() {
+            Ok(v) if (1..=max).contains(&v) && part.len() == 2 => {}
+            _ => bail!("expected YYYY-MM-DD, got {s:?}"),
+        }
+    }
+    if parts.next().is_some() {
+        bail!("expected YYYY-MM-DD, got {s:?}");
+    }
+    Ok(year)
+}
+
+/// (period, sorted ids) of every dated name `key`.
+pub fn periods<D: AsRef<[u8]>>(
+    db: &Db,
+    tagged: &fst::Map<D>,
+    key: &str,
+) -> Result<Vec<(Period, Vec<u32>)>> {
+    let prefix = format!("{TAG}:{key}\t");
+    let mut out = Vec::new();
+    let mut stream = tagged.search(Str::new(&prefix).starts_with()).into_stream();
+    while let Some((k, off)) = stream.next() {
+        let rest = String::from_utf8_lossy(&k[prefix.len()..]).into_owned();
+        let (from, to) = rest.split_once('\t').unwrap_or((&rest, ""));
+        out.push((Period::parse(from, to), read_postings(db, off as usize)?));
+    }
+    Ok(out)
+}
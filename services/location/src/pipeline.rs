@@ -0,0 +1,516 @@
+// src/pipeline.rs
+//
+// Query pipeline shared by `geodb query`, /query, /query/batch and jobs:
+//   normalize -> generate candidates -> materialize -> filter -> score
+// Serializing the outcome stays with the caller. Every stage is a trait, so a
+// new mode is one more implementation instead of another branch in each
+// caller. Candidate sources run in order and the first one that finds anything
+// wins; with `combine` all of them run and a place several found is listed
+// once, with its best match. `Pipeline::standard` is the runtime overlay
+// (overlay.rs), synonyms, exact (+ accent-insensitive), fuzzy when asked for,
+// then segmentation; `with_tokens` adds single-word matches of multi-word
+// names (tokens.rs) right after exact. "X, Y" keys look up X and boost the
+// candidates Y qualifies (qualifier.rs) when the scorer has boosts; `names`
+// boosts the ones named X in the preferred language (langs.rs) and demotes or
+// drops the ones X only names historically / colloquially (nameflags.rs), and
+// with `as_of` prefers the ones X named at that date (periods.rs).
+
+use anyhow::Result;
+
+use crate::bbox::BBox;
+use crate::build::{FeatureFilter, GeoRecord};
+use crate::casefold;
+use crate::langs;
+use crate::nameflags::{self, NameUse};
+use crate::overlay::Overlay;
+use crate::periods;
+use crate::qualifier::{self, Qualifier, QualifierBoosts};
+use crate::ranking::{RankingWeights, ScoreBreakdown};
+use crate::region::Region;
+use crate::scripting::{Script, ScriptCtx};
+use crate::synonyms::Synonyms;
+use crate::{
+    edit_distance, read_fuzzy_postings, read_key_postings, read_postings, read_record_ref_by_id,
+    segment, Db, GeoRecordRef, KeyHit,
+};
+
+/// What the stages read from.
+pub struct Index<'a, D> {
+    pub db: &'a Db,
+    pub fst: &'a fst::Map<D>,
+    pub unaccented: Option<&'a fst::Map<D>>,
+    /// None for DBs built before the tokens FST existed.
+    pub tokens: Option<&'a fst::Map<D>>,
+    /// Language-tagged names (langs.rs); None when built without them.
+    pub lang_names: Option<&'a fst::Map<D>>,
+    pub synonyms: Option<&'a Synonyms>,
+    /// Names added at runtime; None outside the server.
+    pub overlay: Option<&'a Overlay>,
+}
+
+impl<'a, D> Index<'a, D> {
+    /// Overlay records replace DB records with the same id.
+    fn record(&self, id: u32) -> Result<Option<GeoRecordRef<'a>>> {
+        match self.overlay.and_then(|o| o.record(id)) {
+            Some(rec) => Ok(Some(rec.into())),
+            None => read_record_ref_by_id(self.db, id),
+        }
+    }
+}
+
+/* -------------------------
+   stages
+-------------------------- */
+
+pub trait Normalizer {
+    fn normalize(&self, raw: &str) -> String;
+}
+
+pub trait CandidateSource<D> {
+    fn generate(&self, idx: &Index<'_, D>, key: &str) -> Result<Option<(KeyHit, Origin)>>;
+
+    /// Expands historical aliases to successor states; such sources run last
+    /// when `NamePrefs::as_of` predates the alias (periods.rs).
+    fn expands_aliases(&self) -> bool {
+        false
+    }
+}
+
+/// Runs on records in place, before they are copied out of the DB.
+pub trait Filter {
+    fn keep(&self, rec: &GeoRecordRef<'_>) -> bool;
+}
+
+pub trait Scorer {
+    /// Best first. `loose`: sorted ids that matched only without accents.
+    fn score(
+        &self,
+        key: &str,
+        records: Vec<GeoRecord>,
+        loose: &[u32],
+    ) -> Vec<(GeoRecord, ScoreBreakdown)>;
+
+    /// Boosts for the field a compound query's qualifier matched; None leaves
+    /// "X, Y" queries unqualified.
+    fn qualifier_boosts(&self) -> Option<&QualifierBoosts> {
+        None
+    }
+
+    /// Factor for candidates named by the key in the preferred language; 1
+    /// ignores `NamePrefs::lang`.
+    fn lang_boost(&self) -> f64 {
+        1.0
+    }
+
+    /// Factor for candidates the key names only historically / colloquially,
+    /// when `NamePrefs` demotes them.
+    fn flagged_name(&self) -> f64 {
+        1.0
+    }
+
+    /// (valid, not valid) factors for dated names under `NamePrefs::as_of`.
+    fn period_factors(&self) -> (f64, f64) {
+        (1.0, 1.0)
+    }
+}
+
+/// Which source produced the candidates.
+pub enum Origin {
+    /// A name added at runtime (overlay.rs).
+    Overlay,
+    Exact,
+    /// Historical name expanded to current records (synonyms section).
+    Synonym,
+    Fuzzy,
+    /// A single word of multi-word names ("janeiro" -> "rio de janeiro").
+    Token,
+    /// Missing spaces re-inserted; carries the key that matched.
+    Segmented(String),
+}
+
+/* -------------------------
+   standard stages
+-------------------------- */
+
+/// Locale-independent case folding (casefold.rs).
+pub struct Casefold;
+
+impl Normalizer for Casefold {
+    fn normalize(&self, raw: &str) -> String {
+        casefold::fold(raw.trim())
+    }
+}
+
+pub struct OverlaySource;
+
+impl<D: AsRef<[u8]>> CandidateSource<D> for OverlaySource {
+    fn generate(&self, idx: &Index<'_, D>, key: &str) -> Result<Option<(KeyHit, Origin)>> {
+        Ok(idx.overlay.and_then(|o| o.ids(key)).map(|ids| {
+            let hit = KeyHit {
+                ids,
+                loose: Vec::new(),
+                edits: Vec::new(),
+            };
+            (hit, Origin::Overlay)
+        }))
+    }
+}
+
+pub struct SynonymSource;
+
+impl<D: AsRef<[u8]>> CandidateSource<D> for SynonymSource {
+    fn generate(&self, idx: &Index<'_, D>, key: &str) -> Result<Option<(KeyHit, Origin)>> {
+        Ok(idx.synonyms.and_then(|s| s.expand(key)).map(|ids| {
+            let hit = KeyHit {
+                ids: ids.to_vec(),
+                loose: Vec::new(),
+                edits: Vec::new(),
+            };
+            (hit, Origin::Synonym)
+        }))
+    }
+
+    fn expands_aliases(&self) -> bool {
+        true
+    }
+}
+
+pub struct ExactSource;
+
+impl<D: AsRef<[u8]>> CandidateSource<D> for ExactSource {
+    fn generate(&self, idx: &Index<'_, D>, key: &str) -> Result<Option<(KeyHit, Origin)>> {
+        Ok(read_key_postings(idx.db, idx.fst, idx.unaccented, key)?.map(|h| (h, Origin::Exact)))
+    }
+}
+
+pub struct TokenSource;
+
+impl<D: AsRef<[u8]>> CandidateSource<D> for TokenSource {
+    fn generate(&self, idx: &Index<'_, D>, key: &str) -> Result<Option<(KeyHit, Origin)>> {
+        let Some(tokens) = idx.tokens else {
+            return Ok(None);
+        };
+        Ok(read_key_postings(idx.db, tokens, None, key)?.map(|h| (h, Origin::Token)))
+    }
+}
+
+pub struct FuzzySource {
+    pub max_edits: u32,
+}
+
+impl<D: AsRef<[u8]>> CandidateSource<D> for FuzzySource {
+    fn generate(&self, idx: &Index<'_, D>, key: &str) -> Result<Option<(KeyHit, Origin)>> {
+        Ok(read_fuzzy_postings(idx.db, idx.fst, key, self.max_edits)?.map(|h| (h, Origin::Fuzzy)))
+    }
+}
+
+pub struct SegmentSource;
+
+impl<D: AsRef<[u8]>> CandidateSource<D> for SegmentSource {
+    fn generate(&self, idx: &Index<'_, D>, key: &str) -> Result<Option<(KeyHit, Origin)>> {
+        let Some((k, off)) = segment::segment(idx.fst, key) else {
+            return Ok(None);
+        };
+        let hit = KeyHit {
+            ids: read_postings(idx.db, off as usize)?,
+            loose: Vec::new(),
+            edits: Vec::new(),
+        };
+        Ok(Some((hit, Origin::Segmented(k))))
+    }
+}
+
+impl Filter for Region {
+    fn keep(&self, rec: &GeoRecordRef<'_>) -> bool {
+        self.contains(rec)
+    }
+}
+
+impl Filter for BBox {
+    fn keep(&self, rec: &GeoRecordRef<'_>) -> bool {
+        self.contains(rec)
+    }
+}
+
+impl Filter for FeatureFilter {
+    fn keep(&self, rec: &GeoRecordRef<'_>) -> bool {
+        self.admits(rec)
+    }
+}
+
+/// Ranking weights, then the scoring script if one is loaded.
+pub struct Ranker<'a> {
+    pub weights: &'a RankingWeights,
+    pub script: Option<&'a Script>,
+    pub focus: Option<(f32, f32)>,
+}
+
+impl Scorer for Ranker<'_> {
+    fn score(
+        &self,
+        key: &str,
+        records: Vec<GeoRecord>,
+        loose: &[u32],
+    ) -> Vec<(GeoRecord, ScoreBreakdown)> {
+        let mut ranked = self.weights.rank(records, self.focus, loose);
+        if let Some(script) = self.script {
+            script.apply(
+                &mut ranked,
+                &ScriptCtx {
+                    key,
+                    focus: self.focus,
+                },
+            );
+        }
+        ranked
+    }
+
+    fn qualifier_boosts(&self) -> Option<&QualifierBoosts> {
+        Some(&self.weights.qualifier_boosts)
+    }
+
+    fn lang_boost(&self) -> f64 {
+        self.weights.lang_boost
+    }
+
+    fn flagged_name(&self) -> f64 {
+        self.weights.flagged_name
+    }
+
+    fn period_factors(&self) -> (f64, f64) {
+        (self.weights.period_match, self.weights.period_mismatch)
+    }
+}
+
+/* -------------------------
+   pipeline
+-------------------------- */
+
+pub struct Pipeline<'a, D> {
+    pub normalizer: &'a dyn Normalizer,
+    pub sources: Vec<Box<dyn CandidateSource<D> + 'a>>,
+    pub filters: Vec<&'a dyn Filter>,
+    pub scorer: &'a dyn Scorer,
+    pub names: NamePrefs<'a>,
+    /// Run every source and merge their hits instead of stopping at the
+    /// first that finds anything.
+    pub combine: bool,
+}
+
+/// How alternate-name metadata affects ranking (langs.rs, nameflags.rs).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NamePrefs<'a> {
+    /// ISO 639 code from `lang=`; None prefers no language.
+    pub lang: Option<&'a str>,
+    pub historic: NameUse,
+    pub colloquial: NameUse,
+    /// Year of `as_of=`: prefer names valid then (periods.rs).
+    pub as_of: Option<i32>,
+}
+
+pub struct Outcome {
+    /// Normalized key; the part before the comma of a compound query.
+    pub key: String,
+    /// Normalized qualifiers of a compound query ("texas" for "Paris, Texas").
+    pub qualifiers: Vec<String>,
+    /// None when no source matched.
+    pub origin: Option<Origin>,
+    pub ranked: Vec<(GeoRecord, ScoreBreakdown)>,
+    /// Candidate ids the source returned, before filters.
+    pub postings: usize,
+    /// (id, edit distance) sorted by id; only set for fuzzy matches.
+    pub edits: Vec<(u32, u32)>,
+}
+
+impl<'a, D: AsRef<[u8]> + 'a> Pipeline<'a, D> {
+    pub fn standard(scorer: &'a dyn Scorer, fuzzy: Option<u32>) -> Self {
+        let mut sources: Vec<Box<dyn CandidateSource<D> + 'a>> = vec![
+            Box::new(OverlaySource),
+            Box::new(SynonymSource),
+            Box::new(ExactSource),
+        ];
+        if let Some(max_edits) = fuzzy {
+            sources.push(Box::new(FuzzySource { max_edits }));
+        }
+        sources.push(Box::new(SegmentSource));
+        Self {
+            normalizer: &Casefold,
+            sources,
+            filters: Vec::new(),
+            scorer,
+            names: NamePrefs::default(),
+            combine: false,
+        }
+    }
+
+    /// Try the tokens FST when exact and alias lookups miss.
+    pub fn with_tokens(mut self) -> Self {
+        let at = self.sources.len().min(3);
+        self.sources.insert(at, Box::new(TokenSource));
+        self
+    }
+
+    /// Candidates from every source that matches, each place once with its
+    /// best match (`KeyHit::merge`); the outcome's origin is the first
+    /// source's.
+    pub fn combine(mut self, combine: bool) -> Self {
+        self.combine = combine;
+        self
+    }
+
+    pub fn names(mut self, names: NamePrefs<'a>) -> Self {
+        self.names = names;
+        self
+    }
+
+    pub fn filter(mut self, f: Option<&'a dyn Filter>) -> Self {
+        self.filters.extend(f);
+        self
+    }
+
+    pub fn run(&self, idx: &Index<'_, D>, raw_key: &str) -> Result<Outcome> {
+        let (key, qualifiers) = match qualifier::split(raw_key) {
+            Some((head, parts)) if self.scorer.qualifier_boosts().is_some() => (
+                self.normalizer.normalize(head),
+                parts.iter().map(|p| self.normalizer.normalize(p)).collect(),
+            ),
+            _ => (self.normalizer.normalize(raw_key), Vec::new()),
+        };
+
+        // an alias still current at as_of names the historical record itself
+        let aliases_last = self
+            .names
+            .as_of
+            .is_some_and(|y| idx.synonyms.is_some_and(|s| s.current_in(&key, y)));
+        let mut sources: Vec<_> = self.sources.iter().collect();
+        if aliases_last {
+            sources.sort_by_key(|s| s.expands_aliases());
+        }
+        let mut found: Option<(KeyHit, Origin)> = None;
+        for source in sources {
+            let Some((hit, origin)) = source.generate(idx, &key)? else {
+                continue;
+            };
+            found = Some(match found {
+                Some((first, origin)) => (first.merge(hit), origin),
+                None => (hit, origin),
+            });
+            if !self.combine {
+                break;
+            }
+        }
+        let Some((KeyHit { ids, loose, edits }, origin)) = found else {
+            return Ok(Outcome {
+                key,
+                qualifiers,
+                origin: None,
+                ranked: Vec::new(),
+                postings: 0,
+                edits: Vec::new(),
+            });
+        };
+
+        let postings = ids.len();
+        let mut records = Vec::with_capacity(ids.len());
+        for id in ids {
+            if let Some(rec) = idx.record(id)? {
+                if self.filters.iter().all(|f| f.keep(&rec)) {
+                    records.push(rec.to_record());
+                }
+            }
+        }
+
+        let mut ranked = self.scorer.score(&key, records, &loose);
+        if let Some(boosts) = self.scorer.qualifier_boosts() {
+            if !qualifiers.is_empty() {
+                Qualifier::resolve(idx, &qualifiers)?.apply(&mut ranked, boosts);
+            }
+        }
+        if let Some(tagged) = idx.lang_names {
+            self.apply_names(idx, tagged, &key, &mut ranked)?;
+        }
+        // closer spellings first; score order within the same distance
+        if !edits.is_empty() {
+            ranked.sort_by_key(|(r, _)| edit_distance(&edits, r.id));
+        }
+        Ok(Outcome {
+            key,
+            qualifiers,
+            origin: Some(origin),
+            ranked,
+            postings,
+            edits,
+        })
+    }
+
+    /// Language preference, flagged-name demotion / exclusion and as_of
+    /// periods over the tagged names FST; re-sorts when anything changed.
+    fn apply_names(
+        &self,
+        idx: &Index<'_, D>,
+        tagged: &fst::Map<D>,
+        key: &str,
+        ranked: &mut Vec<(GeoRecord, ScoreBreakdown)>,
+    ) -> Result<()> {
+        let mut changed = false;
+        let boost = self.scorer.lang_boost();
+        if let Some(lang) = self.names.lang.filter(|_| boost != 1.0) {
+            let named = langs::ids(idx.db, tagged, lang, key)?;
+            for (r, s) in ranked.iter_mut() {
+                if named.binary_search(&r.id).is_ok() {
+                    s.lang = boost;
+                    s.total *= boost;
+                    changed = true;
+                }
+            }
+        }
+        let demote = self.scorer.flagged_name();
+        for (tag, usage) in [
+            (nameflags::HISTORIC, self.names.historic),
+            (nameflags::COLLOQUIAL, self.names.colloquial),
+        ] {
+            if usage == NameUse::Include {
+                continue;
+            }
+            let Some(hit) = read_key_postings(idx.db, tagged, None, &nameflags::tag_key(tag, key))?
+            else {
+                continue;
+            };
+            let flagged = |id: u32| hit.ids.binary_search(&id).is_ok();
+            match usage {
+                NameUse::Exclude => ranked.retain(|(r, _)| !flagged(r.id)),
+                _ => {
+                    for (_, s) in ranked.iter_mut().filter(|(r, _)| flagged(r.id)) {
+                        s.flagged_name = demote;
+                        s.total *= demote;
+                        changed = true;
+                    }
+                }
+            }
+        }
+        if let Some(year) = self.names.as_of {
+            let (valid, stale) = self.scorer.period_factors();
+            let dated = periods::periods(idx.db, tagged, key)?;
+            for (r, s) in ranked.iter_mut() {
+                let mut hits = dated
+                    .iter()
+                    .filter(|(_, ids)| ids.binary_search(&r.id).is_ok())
+                    .peekable();
+                if hits.peek().is_none() {
+                    continue;
+                }
+                s.period = if hits.any(|(p, _)| p.contains(year)) {
+                    valid
+                } else {
+                    stale
+                };
+                s.total *= s.period;
+                changed = true;
+            }
+        }
+        if changed {
+            ranked.sort_by(|a, b| b.1.total.total_cmp(&a.1.total));
+        }
+        Ok(())
+    }
+}
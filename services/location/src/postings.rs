@@ -0,0 +1,119 @@
+// src/postings.rs
+//
+// Postings list encodings (format VERSION 7+). Every list in the postings
+// section is `var_u32 len | tag u8 | payload`, len counting the tag:
+// - TAG_DELTA: delta varints, what every list was before VERSION 7;
+// - TAG_ROARING: a serialized roaring bitmap, chosen at build time for lists
+//   of at least the roaring threshold ("san jose", "la", common tokens),
+//   where it is smaller and decodes by container instead of one varint at a
+//   time.
+// VERSION 5-6 lists have no tag byte and are always delta varints.
+// Roaring lists decode to the bitmap itself (`List`): set operations run
+// container by container, and only callers that need every id expand it.
+// The threshold a DB was written with is kept in its postings_info section,
+// so `geodb update` writes lists as the source build did.
+
+use anyhow::{bail, Context, Result};
+use roaring::RoaringBitmap;
+
+use crate::build::{encode_delta_varints, write_var_u32};
+use crate::{decode_delta_varints, read_u32_le_at};
+
+pub const TAG_DELTA: u8 = 0;
+pub const TAG_ROARING: u8 = 1;
+
+/// First format version whose lists carry a tag byte.
+pub const TAGGED_VERSION: u32 = 7;
+
+/// Lists with at least this many ids are stored as roaring bitmaps unless
+/// `geodb build --roaring-threshold` says otherwise (0 = never).
+pub const DEFAULT_ROARING_THRESHOLD: usize = 4096;
+
+/// One decoded list.
+pub enum List {
+    /// Sorted ids of a delta-varint list.
+    Ids(Vec<u32>),
+    Bitmap(RoaringBitmap),
+}
+
+impl List {
+    pub fn len(&self) -> u64 {
+        match self {
+            List::Ids(ids) => ids.len() as u64,
+            List::Bitmap(b) => b.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Sorted ids.
+    pub fn into_ids(self) -> Vec<u32> {
+        match self {
+            List::Ids(ids) => ids,
+            List::Bitmap(b) => b.iter().collect(),
+        }
+    }
+
+    pub fn into_bitmap(self) -> RoaringBitmap {
+        match self {
+            // delta lists decode sorted; the fallback only guards corrupt ones
+            List::Ids(ids) => RoaringBitmap::from_sorted_iter(ids.iter().copied())
+                .unwrap_or_else(|_| ids.into_iter().collect()),
+            List::Bitmap(b) => b,
+        }
+    }
+}
+
+/// postings_info section body for lists written with `roaring_threshold`.
+pub fn info_section(roaring_threshold: usize) -> Vec<u8> {
+    u32::try_from(roaring_threshold)
+        .unwrap_or(u32::MAX)
+        .to_le_bytes()
+        .to_vec()
+}
+
+/// Roaring threshold a DB's lists were written with, from its postings_info
+/// section; DEFAULT_ROARING_THRESHOLD for DBs built before the section.
+pub fn roaring_threshold(info: &[u8]) -> Result<usize> {
+    match info.len() {
+        0 => Ok(DEFAULT_ROARING_THRESHOLD),
+        4 => Ok(read_u32_le_at(info, 0) as usize),
+        n => bail!("postings_info section is {n} bytes, expected 4"),
+    }
+}
+
+/// Append the list for sorted, deduplicated `ids` to `blob`.
+pub fn write_list(blob: &mut Vec<u8>, ids: &[u32], roaring_threshold: usize) -> Result<()> {
+    let mut body = Vec::new();
+    if roaring_threshold > 0 && ids.len() >= roaring_threshold {
+        body.push(TAG_ROARING);
+        RoaringBitmap::from_sorted_iter(ids.iter().copied())
+            .context("postings ids not sorted")?
+            .serialize_into(&mut body)?;
+    } else {
+        body.push(TAG_DELTA);
+        body.extend_from_slice(&encode_delta_varints(ids));
+    }
+    write_var_u32(blob, body.len() as u32);
+    blob.extend_from_slice(&body);
+    Ok(())
+}
+
+/// One list body (the bytes after its length) in a `version` file.
+pub fn decode_list(body: &[u8], version: u32) -> Result<List> {
+    if version < TAGGED_VERSION {
+        return Ok(List::Ids(decode_delta_varints(body)));
+    }
+    let Some((&tag, payload)) = body.split_first() else {
+        bail!("empty postings list");
+    };
+    match tag {
+        TAG_DELTA => Ok(List::Ids(decode_delta_varints(payload))),
+        TAG_ROARING => Ok(List::Bitmap(
+            RoaringBitmap::deserialize_from(payload).context("roaring postings")?,
+        )),
+        t => bail!("unknown postings encoding {t}"),
+    }
+}
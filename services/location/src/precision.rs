@@ -0,0 +1,66 @@
+// src/precision.rs
+//
+// Coarse coordinates for privacy-sensitive outputs (syndication partners that
+// must not get village-level points):
+// - `precision=N` rounds returned lat / lon to N decimals (0-6; 1 ~ 11 km,
+//   2 ~ 1.1 km at the equator);
+// - `snap=admin2|admin1|country` first moves each candidate to the point of
+//   the admin unit containing it (hierarchy.rs), falling back to the next
+//   level up when the DB has no record for that unit; candidates then carry
+//   `snapped_to`, that record's id.
+// Plus codes / geohashes are computed from the coarsened point. Snapping needs
+// a DB with the hierarchy section; older DBs leave points unsnapped.
+
+use anyhow::{bail, Result};
+
+use crate::hierarchy::{AdminPoint, Hierarchy, Level};
+
+pub const MAX_DECIMALS: u8 = 6;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Precision {
+    pub decimals: Option<u8>,
+    pub snap: Option<Level>,
+}
+
+impl Precision {
+    pub fn parse(decimals: Option<u8>, snap: Option<&str>) -> Result<Self> {
+        if decimals.is_some_and(|d| d > MAX_DECIMALS) {
+            bail!("precision: at most {MAX_DECIMALS} decimals");
+        }
+        let snap = match snap.map(str::trim) {
+            None | Some("") => None,
+            Some(s) => Some(Level::parse(s)?),
+        };
+        Ok(Self { decimals, snap })
+    }
+
+    pub fn is_exact(&self) -> bool {
+        self.decimals.is_none() && self.snap.is_none()
+    }
+
+    /// The point to return for a record at (lat, lon) with these admin codes,
+    /// and the admin record it was snapped to.
+    pub fn apply(
+        &self,
+        hierarchy: Option<&Hierarchy>,
+        (lat, lon): (f32, f32),
+        (country, admin1, admin2): (&str, &str, &str),
+    ) -> ((f32, f32), Option<u32>) {
+        let snapped: Option<AdminPoint> = self
+            .snap
+            .zip(hierarchy)
+            .and_then(|(level, h)| h.containing(level, country, admin1, admin2));
+        let (lat, lon) = snapped.map_or((lat, lon), |p| (p.lat, p.lon));
+        let point = match self.decimals {
+            Some(d) => (round(lat, d), round(lon, d)),
+            None => (lat, lon),
+        };
+        (point, snapped.map(|p| p.id))
+    }
+}
+
+pub fn round(v: f32, decimals: u8) -> f32 {
+    let scale = 10f64.powi(decimals as i32);
+    ((v as f64 * scale).round() / scale) as f32
+}
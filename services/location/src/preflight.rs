@@ -0,0 +1,207 @@
+// src/preflight.rs
+//
+// `geodb preflight --db new.db --config geodb.toml`: deploy gate run before
+// traffic moves to a new DB. Prints one JSON verdict on stdout and exits
+// non-zero when any check fails. Checks:
+// - open: the DB parses and passes the strict consistency check (`Db::check`:
+//   postings and record offsets in bounds; `geodb validate` decodes them).
+//   The format has no per-section checksums; --expect-build additionally
+//   compares the content hash served as `x-geodb-build` (e.g. the registry
+//   entry being deployed).
+// - canary:<key>: every [[preflight.canaries]] entry from geodb.toml, run
+//   through the query pipeline with the configured ranking and script.
+// - cold_lookup: the slowest canary lookup, the first lookups after open,
+//   stays under preflight.max_cold_lookup_ms.
+// An empty canary list fails the gate rather than passing it vacuously.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Instant;
+
+use crate::config::Config;
+use crate::pipeline::{Index, Pipeline, Ranker};
+use crate::scripting;
+use crate::synonyms::Synonyms;
+use crate::{build_hash, load_db, OpenOptions};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PreflightConfig {
+    pub canaries: Vec<Canary>,
+    pub max_cold_lookup_ms: f64,
+}
+
+impl Default for PreflightConfig {
+    fn default() -> Self {
+        Self {
+            canaries: Vec::new(),
+            max_cold_lookup_ms: 50.0,
+        }
+    }
+}
+
+impl PreflightConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_cold_lookup_ms.is_nan() || self.max_cold_lookup_ms <= 0.0 {
+            return Err("max_cold_lookup_ms must be > 0".into());
+        }
+        if let Some(c) = self.canaries.iter().find(|c| c.key.trim().is_empty()) {
+            return Err(format!(
+                "canary with empty key (expect_id {:?})",
+                c.expect_id
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A key that must resolve; with `expect_id`, to that record first.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Canary {
+    pub key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expect_id: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct Check {
+    pub name: String,
+    pub pass: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ms: Option<f64>,
+}
+
+#[derive(Serialize)]
+pub struct Verdict {
+    pub pass: bool,
+    /// Content hash (`x-geodb-build`); absent when the DB did not open.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build: Option<String>,
+    pub checks: Vec<Check>,
+}
+
+fn ms_since(t: Instant) -> f64 {
+    t.elapsed().as_secs_f64() * 1000.0
+}
+
+pub fn run(
+    db_path: Option<&Path>,
+    mmap: bool,
+    cfg: &Config,
+    expect_build: Option<&str>,
+) -> Result<Verdict> {
+    let mut checks = Vec::new();
+
+    let t = Instant::now();
+    // strict: a DB that needs --lenient is not fit to deploy
+    let opened = load_db(
+        db_path,
+        OpenOptions {
+            lenient: false,
+            mmap,
+        },
+    );
+    let open_ms = ms_since(t);
+    let db = match opened {
+        Ok(db) => db,
+        Err(e) => {
+            checks.push(Check {
+                name: "open".into(),
+                pass: false,
+                detail: Some(format!("{e:#}")),
+                ms: Some(open_ms),
+            });
+            return Ok(Verdict {
+                pass: false,
+                build: None,
+                checks,
+            });
+        }
+    };
+    let build = build_hash(&db);
+    let (pass, detail) = match expect_build {
+        Some(want) if want != build => (false, Some(format!("expected build {want}"))),
+        _ => (true, None),
+    };
+    checks.push(Check {
+        name: "open".into(),
+        pass,
+        detail,
+        ms: Some(open_ms),
+    });
+
+    let fst = fst::Map::new(db.fst_slice()).map_err(|e| anyhow!("fst load: {e}"))?;
+    let unaccented = match db.unaccented_slice() {
+        [] => None,
+        b => Some(fst::Map::new(b).map_err(|e| anyhow!("unaccented fst load: {e}"))?),
+    };
+    let synonyms = Synonyms::from_section(db.synonyms_slice())?;
+    let script = scripting::load(&cfg.scripting)?;
+    let index = Index {
+        db: &db,
+        fst: &fst,
+        unaccented: unaccented.as_ref(),
+        tokens: None,
+        lang_names: None,
+        synonyms: synonyms.as_ref(),
+        overlay: None,
+    };
+    let ranker = Ranker {
+        weights: &cfg.ranking,
+        script: script.as_ref(),
+        focus: None,
+    };
+    let pipeline = Pipeline::standard(&ranker, None);
+
+    let mut slowest: Option<f64> = None;
+    for canary in &cfg.preflight.canaries {
+        let t = Instant::now();
+        let outcome = pipeline.run(&index, &canary.key);
+        let ms = ms_since(t);
+        slowest = Some(slowest.map_or(ms, |s| s.max(ms)));
+        let top = outcome
+            .as_ref()
+            .ok()
+            .and_then(|o| o.ranked.first())
+            .map(|(r, _)| r.id.to_string());
+        let (pass, detail) = match (&outcome, &canary.expect_id, top) {
+            (Err(e), _, _) => (false, Some(format!("{e:#}"))),
+            (Ok(_), _, None) => (false, Some("no results".to_string())),
+            (Ok(_), Some(want), Some(got)) if *want != got => {
+                (false, Some(format!("top result {got}, expected {want}")))
+            }
+            (Ok(_), _, Some(_)) => (true, None),
+        };
+        checks.push(Check {
+            name: format!("canary:{}", canary.key),
+            pass,
+            detail,
+            ms: Some(ms),
+        });
+    }
+
+    let limit = cfg.preflight.max_cold_lookup_ms;
+    checks.push(match slowest {
+        None => Check {
+            name: "cold_lookup".into(),
+            pass: false,
+            detail: Some("no [[preflight.canaries]] configured".into()),
+            ms: None,
+        },
+        Some(ms) => Check {
+            name: "cold_lookup".into(),
+            pass: ms <= limit,
+            detail: (ms > limit).then(|| format!("slowest canary over {limit} ms")),
+            ms: Some(ms),
+        },
+    });
+
+    Ok(Verdict {
+        pass: checks.iter().all(|c| c.pass),
+        build: Some(build),
+        checks,
+    })
+}
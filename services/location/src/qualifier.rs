@@ -0,0 +1,161 @@
+// src/qualifier.rs
+//
+// Compound queries: "Paris, Texas", "Portland, OR", "Springfield, Sangamon
+// County, Illinois". The text before the first comma is the key; each part
+// after it is a qualifier, resolved through the index itself: parts naming a
+// country (PCL*), first- or second-level division (ADM1 / ADM2) record, or
+// equal to a country / admin1 code ("FR", "TX"). Candidates in a qualified
+// region are boosted by the field matched, per [ranking.qualifier_boosts];
+// when several fields match the largest boost wins. Which field should win
+// differs between deployments: "CA" is both a US state and Canada.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::build::GeoRecord;
+use crate::pipeline::Index;
+use crate::ranking::ScoreBreakdown;
+use crate::{read_key_postings, read_record_by_id};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QualifierBoosts {
+    pub admin1: f64,
+    pub country: f64,
+    pub admin2: f64,
+}
+
+impl Default for QualifierBoosts {
+    fn default() -> Self {
+        Self {
+            admin1: 3.0,
+            country: 2.0,
+            admin2: 1.5,
+        }
+    }
+}
+
+impl QualifierBoosts {
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, v) in [
+            ("admin1", self.admin1),
+            ("country", self.country),
+            ("admin2", self.admin2),
+        ] {
+            if !v.is_finite() || v < 0.0 {
+                return Err(format!(
+                    "qualifier_boosts.{name} must be a finite number >= 0"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn get(&self, field: Field) -> f64 {
+        match field {
+            Field::Admin1 => self.admin1,
+            Field::Country => self.country,
+            Field::Admin2 => self.admin2,
+        }
+    }
+}
+
+/// Which field of the candidate a qualifier matched.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Field {
+    Admin1,
+    Country,
+    Admin2,
+}
+
+/// ("Paris", ["Texas"]) for "Paris, Texas"; None without a comma or when
+/// either side is empty.
+pub fn split(raw: &str) -> Option<(&str, Vec<&str>)> {
+    let (head, rest) = raw.split_once(',')?;
+    let parts: Vec<&str> = rest
+        .split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .collect();
+    (!head.trim().is_empty() && !parts.is_empty()).then_some((head, parts))
+}
+
+/// The regions a compound query's qualifiers name.
+#[derive(Default)]
+pub struct Qualifier {
+    countries: Vec<String>,
+    admin1: Vec<(String, String)>,
+    admin2: Vec<(String, String, String)>,
+    /// Upper-cased parts, compared to country / admin1 codes.
+    codes: Vec<String>,
+}
+
+impl Qualifier {
+    /// `parts` are normalized keys.
+    pub fn resolve<D: AsRef<[u8]>>(idx: &Index<'_, D>, parts: &[String]) -> Result<Self> {
+        let mut q = Self::default();
+        for part in parts {
+            q.codes.push(part.to_uppercase());
+            let Some(hit) = read_key_postings(idx.db, idx.fst, idx.unaccented, part)? else {
+                continue;
+            };
+            for id in hit.ids {
+                let Some(r) = read_record_by_id(idx.db, id)? else {
+                    continue;
+                };
+                if r.feat_class != b'A' {
+                    continue;
+                }
+                match r.feat_code.as_str() {
+                    c if c.starts_with("PCL") => q.countries.push(r.country),
+                    "ADM1" => q.admin1.push((r.country, r.admin1)),
+                    "ADM2" => q.admin2.push((r.country, r.admin1, r.admin2)),
+                    _ => {}
+                }
+            }
+        }
+        Ok(q)
+    }
+
+    /// The matched field with the largest boost, if any.
+    pub fn field(&self, rec: &GeoRecord, boosts: &QualifierBoosts) -> Option<Field> {
+        let code = |s: &str| !s.is_empty() && self.codes.iter().any(|c| c == s);
+        let admin1 = !rec.admin1.is_empty()
+            && (code(&rec.admin1)
+                || self
+                    .admin1
+                    .iter()
+                    .any(|(c, a1)| *c == rec.country && *a1 == rec.admin1));
+        let country = code(&rec.country) || self.countries.contains(&rec.country);
+        let admin2 = !rec.admin2.is_empty()
+            && self
+                .admin2
+                .iter()
+                .any(|(c, a1, a2)| *c == rec.country && *a1 == rec.admin1 && *a2 == rec.admin2);
+        // on equal boosts the earlier field wins
+        let mut best: Option<Field> = None;
+        for (hit, f) in [
+            (admin1, Field::Admin1),
+            (country, Field::Country),
+            (admin2, Field::Admin2),
+        ] {
+            if hit && !best.is_some_and(|b| boosts.get(f) <= boosts.get(b)) {
+                best = Some(f);
+            }
+        }
+        best
+    }
+
+    /// Multiply matching candidates' totals by their field's boost and re-sort.
+    pub fn apply(&self, ranked: &mut [(GeoRecord, ScoreBreakdown)], boosts: &QualifierBoosts) {
+        for (rec, s) in ranked.iter_mut() {
+            if let Some(field) = self.field(rec, boosts) {
+                s.qualifier = boosts.get(field);
+                s.qualifier_field = Some(field);
+                s.total *= s.qualifier;
+            }
+        }
+        ranked.sort_by(|a, b| b.1.total.total_cmp(&a.1.total));
+    }
+}
@@ -0,0 +1,233 @@
+// src/ranking.rs
+//
+// Candidate ranking. Score = feature prior * (population + 1)^exponent * distance decay
+// * accent factor (accent_mismatch for matches that only hit without accents);
+// `lang=` queries multiply in lang_boost for names tagged with that language,
+// `historic=demote` / `colloquial=demote` flagged_name for names GeoNames flags,
+// `as_of=` period_match / period_mismatch for names with a validity period.
+// Weights are plain data so the server can hot-swap them (GET/PUT /admin/ranking)
+// and persist them into the config file.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::build::GeoRecord;
+use crate::qualifier::{Field, QualifierBoosts};
+
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RankingWeights {
+    /// Exponent applied to (population + 1); 0 disables the population signal.
+    pub population_exponent: f64,
+    /// Prior per feature code ("PPLC") or feature class ("P"); codes win over classes.
+    pub feature_priors: BTreeMap<String, f64>,
+    /// Prior for features not listed in `feature_priors`.
+    pub default_prior: f64,
+    /// e-folding distance in km from the focus point; 0 disables distance decay.
+    pub distance_decay_km: f64,
+    /// Factor for candidates that matched only after stripping the query's
+    /// accents (see accents.rs); 1 disables the exact-accent preference.
+    pub accent_mismatch: f64,
+    /// Factors for "X, Y" queries by the field Y matched (qualifier.rs).
+    pub qualifier_boosts: QualifierBoosts,
+    /// Factor for candidates named by the key in the query's `lang=`
+    /// (langs.rs); 1 disables the language preference.
+    pub lang_boost: f64,
+    /// Factor for candidates the key names only historically / colloquially,
+    /// when the query demotes those (nameflags.rs).
+    pub flagged_name: f64,
+    /// Factors for candidates whose matched name had a validity period that
+    /// includes / excludes the query's as_of date (periods.rs).
+    pub period_match: f64,
+    pub period_mismatch: f64,
+}
+
+impl Default for RankingWeights {
+    fn default() -> Self {
+        let feature_priors = [
+            ("A", 1.2),
+            ("P", 1.0),
+            ("L", 0.6),
+            ("T", 0.5),
+            ("H", 0.4),
+            ("S", 0.4),
+            ("V", 0.3),
+            ("R", 0.2),
+            ("U", 0.2),
+            ("PPLC", 1.5),
+            ("PCLI", 2.0),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect();
+
+        Self {
+            population_exponent: 0.5,
+            feature_priors,
+            default_prior: 0.5,
+            distance_decay_km: 500.0,
+            accent_mismatch: 0.5,
+            qualifier_boosts: QualifierBoosts::default(),
+            lang_boost: 2.0,
+            flagged_name: 0.2,
+            period_match: 3.0,
+            period_mismatch: 0.3,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct ScoreBreakdown {
+    pub prior: f64,
+    pub population: f64,
+    pub distance: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance_km: Option<f64>,
+    pub accent: f64,
+    /// Qualifier boost of a compound query; 1 when none matched.
+    pub qualifier: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub qualifier_field: Option<Field>,
+    /// `lang_boost` when the key names the candidate in `lang=`; else 1.
+    pub lang: f64,
+    /// `flagged_name` when a demoted historic / colloquial name matched; else 1.
+    pub flagged_name: f64,
+    /// period_match / period_mismatch under as_of; else 1.
+    pub period: f64,
+    /// Total as returned by the scoring script, when one is loaded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub script: Option<f64>,
+    pub total: f64,
+}
+
+impl RankingWeights {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.population_exponent.is_finite() || self.population_exponent < 0.0 {
+            return Err("population_exponent must be a finite number >= 0".into());
+        }
+        if !self.distance_decay_km.is_finite() || self.distance_decay_km < 0.0 {
+            return Err("distance_decay_km must be a finite number >= 0".into());
+        }
+        if !self.accent_mismatch.is_finite() || self.accent_mismatch < 0.0 {
+            return Err("accent_mismatch must be a finite number >= 0".into());
+        }
+        if !self.lang_boost.is_finite() || self.lang_boost < 0.0 {
+            return Err("lang_boost must be a finite number >= 0".into());
+        }
+        for (name, v) in [
+            ("flagged_name", self.flagged_name),
+            ("period_match", self.period_match),
+            ("period_mismatch", self.period_mismatch),
+        ] {
+            if !v.is_finite() || v < 0.0 {
+                return Err(format!("{name} must be a finite number >= 0"));
+            }
+        }
+        if !self.default_prior.is_finite() || self.default_prior < 0.0 {
+            return Err("default_prior must be a finite number >= 0".into());
+        }
+        for (k, v) in &self.feature_priors {
+            if !v.is_finite() || *v < 0.0 {
+                return Err(format!("feature prior {k} must be a finite number >= 0"));
+            }
+        }
+        self.qualifier_boosts.validate()
+    }
+
+    fn prior(&self, rec: &GeoRecord) -> f64 {
+        if let Some(p) = self.feature_priors.get(rec.feat_code.as_str()) {
+            return *p;
+        }
+        let class = (rec.feat_class as char).to_string();
+        self.feature_priors
+            .get(&class)
+            .copied()
+            .unwrap_or(self.default_prior)
+    }
+
+    /// `accent_exact`: false when the record matched only without accents.
+    pub fn score(
+        &self,
+        rec: &GeoRecord,
+        focus: Option<(f32, f32)>,
+        accent_exact: bool,
+    ) -> ScoreBreakdown {
+        let prior = self.prior(rec);
+        let population = (rec.population as f64 + 1.0).powf(self.population_exponent);
+
+        let distance_km = focus.map(|(lat, lon)| haversine_km(lat, lon, rec.lat, rec.lon));
+        let distance = match distance_km {
+            Some(d) if self.distance_decay_km > 0.0 => (-d / self.distance_decay_km).exp(),
+            _ => 1.0,
+        };
+        let accent = if accent_exact {
+            1.0
+        } else {
+            self.accent_mismatch
+        };
+
+        ScoreBreakdown {
+            prior,
+            population,
+            distance,
+            distance_km,
+            accent,
+            qualifier: 1.0,
+            qualifier_field: None,
+            lang: 1.0,
+            flagged_name: 1.0,
+            period: 1.0,
+            script: None,
+            total: prior * population * distance * accent,
+        }
+    }
+
+    /// Score and sort records best-first (ties keep geoname_id order).
+    /// `loose`: sorted ids that matched only without accents.
+    pub fn rank(
+        &self,
+        records: Vec<GeoRecord>,
+        focus: Option<(f32, f32)>,
+        loose: &[u32],
+    ) -> Vec<(GeoRecord, ScoreBreakdown)> {
+        let mut scored: Vec<(GeoRecord, ScoreBreakdown)> = records
+            .into_iter()
+            .map(|r| {
+                let s = self.score(&r, focus, loose.binary_search(&r.id).is_err());
+                (r, s)
+            })
+            .collect();
+        scored.sort_by(|a, b| b.1.total.total_cmp(&a.1.total));
+        scored
+    }
+}
+
+/// Parse "lat,lon" as used by the `near` query parameter.
+pub fn parse_focus(s: &str) -> Result<(f32, f32), String> {
+    let (lat_s, lon_s) = s
+        .split_once(',')
+        .ok_or_else(|| format!("expected lat,lon but got {s:?}"))?;
+    let lat: f32 = lat_s
+        .trim()
+        .parse()
+        .map_err(|_| format!("bad latitude {lat_s:?}"))?;
+    let lon: f32 = lon_s
+        .trim()
+        .parse()
+        .map_err(|_| format!("bad longitude {lon_s:?}"))?;
+    if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+        return Err(format!("coordinates out of range: {lat},{lon}"));
+    }
+    Ok((lat, lon))
+}
+
+pub fn haversine_km(lat1: f32, lon1: f32, lat2: f32, lon2: f32) -> f64 {
+    let (lat1, lon1) = ((lat1 as f64).to_radians(), (lon1 as f64).to_radians());
+    let (lat2, lon2) = ((lat2 as f64).to_radians(), (lon2 as f64).to_radians());
+    let dlat = lat2 - lat1;
+    let dlon = lon2 - lon1;
+    let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * a.sqrt().min(1.0).asin()
+}
@@ -0,0 +1,81 @@
+// src/region.rs
+//
+// `within=` containment filter for /query. Regions are matched through the
+// admin hierarchy codes every record carries (country, admin1, admin2):
+//   "US"          country (ISO 3166-1 alpha-2)
+//   "US-MO"       country + GeoNames admin1 code
+//   "US-MO-510"   country + admin1 + admin2 code
+//   "4398678"     a record id; must be a country / ADM1 / ADM2 record
+// There is no polygon data in the DB, so regions are never approximated by
+// a bounding box: a non-administrative id is rejected.
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::build::GeoRecord;
+use crate::{read_record_by_id, Db, GeoRecordRef};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Region {
+    country: String,
+    admin1: Option<String>,
+    admin2: Option<String>,
+}
+
+impl Region {
+    pub fn parse(spec: &str, db: &Db) -> Result<Self> {
+        let spec = spec.trim();
+        if !spec.is_empty() && spec.bytes().all(|b| b.is_ascii_digit()) {
+            let id: u32 = spec.parse()?;
+            let rec = read_record_by_id(db, id)?.ok_or_else(|| anyhow!("unknown id {id}"))?;
+            return Self::of_record(&rec);
+        }
+
+        let mut parts = spec.splitn(3, '-');
+        let country = parts.next().unwrap_or("").to_ascii_uppercase();
+        if country.len() != 2 || !country.bytes().all(|b| b.is_ascii_alphabetic()) {
+            bail!("expected ISO country code, COUNTRY-ADMIN1[-ADMIN2] or record id, got {spec:?}");
+        }
+        let code = |p: Option<&str>| {
+            p.map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+        };
+        let admin1 = code(parts.next());
+        let admin2 = code(parts.next());
+        Ok(Self {
+            country,
+            admin1,
+            admin2,
+        })
+    }
+
+    fn of_record(rec: &GeoRecord) -> Result<Self> {
+        let owned = |s: &str| Some(s.to_string());
+        let (admin1, admin2) = match (rec.feat_class, rec.feat_code.as_str()) {
+            (b'A', code) if code.starts_with("PCL") => (None, None),
+            (b'A', "ADM1" | "ADM1H") => (owned(&rec.admin1), None),
+            (b'A', "ADM2" | "ADM2H") => (owned(&rec.admin1), owned(&rec.admin2)),
+            _ => bail!(
+                "id {} is a {} ({}), not a country/ADM1/ADM2 region",
+                rec.id,
+                rec.feat_code,
+                rec.name
+            ),
+        };
+        Ok(Self {
+            country: rec.country.to_ascii_uppercase(),
+            admin1,
+            admin2,
+        })
+    }
+
+    pub fn contains(&self, rec: &GeoRecordRef<'_>) -> bool {
+        let eq = |want: &Option<String>, have: &str| match want {
+            Some(w) => w.eq_ignore_ascii_case(have),
+            None => true,
+        };
+        rec.country.eq_ignore_ascii_case(&self.country)
+            && eq(&self.admin1, rec.admin1)
+            && eq(&self.admin2, rec.admin2)
+    }
+}
@@ -0,0 +1,157 @@
+// src/registry.rs
+//
+// Build registry (feature "registry"): `geodb publish` uploads a DB and its
+// metadata, `geodb list-builds` shows what is there. Layout under the registry
+// prefix (same URL schemes as the audit sink, see store.rs):
+//   builds/<YYYY-MM-DD>/<build>/geodb.db
+//   builds/<YYYY-MM-DD>/<build>/report.json   (build report, when present)
+//   builds/<YYYY-MM-DD>/<build>/meta.json
+// <build> is the build hash served as `x-geodb-build`, so a server's header
+// names its registry entry. meta.json is written last: a build without it is an
+// interrupted upload and is not listed.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BuildMeta {
+    pub build: String,
+    /// Publish date (UTC), the first path component.
+    pub date: String,
+    pub published_ms: u64,
+    pub db_bytes: u64,
+    /// Object paths relative to the registry prefix.
+    pub db: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub report: Option<String>,
+    /// File name the DB was published from.
+    pub source_file: String,
+}
+
+#[cfg(not(feature = "registry"))]
+pub fn publish(_db: &Path, _registry: &str) -> Result<BuildMeta> {
+    anyhow::bail!("geodb was built without the `registry` feature")
+}
+
+#[cfg(not(feature = "registry"))]
+pub fn list_builds(_registry: &str, _date: Option<&str>) -> Result<Vec<BuildMeta>> {
+    anyhow::bail!("geodb was built without the `registry` feature")
+}
+
+#[cfg(feature = "registry")]
+pub use imp::{list_builds, publish};
+
+#[cfg(feature = "registry")]
+mod imp {
+    use super::*;
+    use anyhow::Context;
+    use object_store::path::Path as ObjectPath;
+    use object_store::{ObjectStore, WriteMultipart};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use crate::diagnostics::DiagnosticsOptions;
+    use crate::{build_hash, open_db, store};
+
+    const PART_BYTES: usize = 16 << 20;
+    const MAX_PARTS_IN_FLIGHT: usize = 4;
+
+    fn runtime() -> Result<tokio::runtime::Runtime> {
+        Ok(tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?)
+    }
+
+    pub fn publish(db_path: &Path, registry: &str) -> Result<BuildMeta> {
+        let db = open_db(db_path).with_context(|| format!("open {}", db_path.display()))?;
+        let build = build_hash(&db);
+        let report_path = DiagnosticsOptions::for_db(db_path).report;
+        let report = if report_path.exists() {
+            Some(std::fs::read(&report_path)?)
+        } else {
+            None
+        };
+
+        let published_ms = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+        let date = store::civil_date(published_ms / 1000);
+        let dir = format!("builds/{date}/{build}");
+        let meta = BuildMeta {
+            build,
+            date,
+            published_ms,
+            db_bytes: db.bytes.len() as u64,
+            db: format!("{dir}/geodb.db"),
+            report: report.as_ref().map(|_| format!("{dir}/report.json")),
+            source_file: db_path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        };
+
+        let (store, prefix) = store::open(registry, "registry")?;
+        runtime()?.block_on(async {
+            let child = |rel: &str| join(&prefix, rel);
+
+            let mut upload = WriteMultipart::new_with_chunk_size(
+                store.put_multipart(&child(&meta.db)).await?,
+                PART_BYTES,
+            );
+            for part in db.bytes.chunks(PART_BYTES) {
+                upload.wait_for_capacity(MAX_PARTS_IN_FLIGHT).await?;
+                upload.write(part);
+            }
+            upload.finish().await?;
+
+            if let (Some(rel), Some(bytes)) = (&meta.report, report) {
+                store.put(&child(rel), bytes.into()).await?;
+            }
+            let meta_json = serde_json::to_vec_pretty(&meta)?;
+            store
+                .put(&child(&format!("{dir}/meta.json")), meta_json.into())
+                .await?;
+            anyhow::Ok(())
+        })?;
+
+        eprintln!(
+            "[publish] {} ({} bytes) -> {registry}/{dir}",
+            meta.build, meta.db_bytes
+        );
+        Ok(meta)
+    }
+
+    /// Newest first; `date` ("YYYY-MM-DD") limits the listing to one day.
+    pub fn list_builds(registry: &str, date: Option<&str>) -> Result<Vec<BuildMeta>> {
+        let (store, prefix) = store::open(registry, "registry")?;
+        let builds = join(&prefix, "builds");
+        runtime()?.block_on(async {
+            let days = match date {
+                Some(d) => vec![builds.child(d)],
+                None => subdirs(&*store, &builds).await?,
+            };
+            let mut out = Vec::new();
+            for day in days {
+                for dir in subdirs(&*store, &day).await? {
+                    let meta_path = dir.child("meta.json");
+                    let bytes = match store.get(&meta_path).await {
+                        Ok(r) => r.bytes().await?,
+                        Err(object_store::Error::NotFound { .. }) => continue,
+                        Err(e) => return Err(e.into()),
+                    };
+                    let meta: BuildMeta = serde_json::from_slice(&bytes)
+                        .with_context(|| format!("parse {meta_path}"))?;
+                    out.push(meta);
+                }
+            }
+            out.sort_by(|a, b| b.published_ms.cmp(&a.published_ms));
+            Ok(out)
+        })
+    }
+
+    async fn subdirs(store: &dyn ObjectStore, dir: &ObjectPath) -> Result<Vec<ObjectPath>> {
+        Ok(store.list_with_delimiter(Some(dir)).await?.common_prefixes)
+    }
+
+    fn join(prefix: &ObjectPath, rel: &str) -> ObjectPath {
+        rel.split('/').fold(prefix.clone(), |p, part| p.child(part))
+    }
+}
@@ -0,0 +1,211 @@
+// src/reload.rs
+//
+// Guards for `POST /admin/reload` ([reload] in geodb.toml). A swap holds two
+// DBs in memory until the requests still running on the old one finish; a
+// second swap in that window would make it three, which is how pods got
+// OOM-killed mid-deploy. So:
+// - a reload is refused while the DB retired by the previous one is still
+//   held (long /jobs keep it alive: retry once they finish);
+// - before loading, the new file's size times `headroom_factor` plus
+//   `min_free` must fit in the memory available: the cgroup limit minus
+//   usage where there is one (cgroup v2, then v1), else MemAvailable, and
+//   [server] memory_limit minus the process RSS when set. Mapped DBs
+//   (--mmap) only need `min_free`, the file pages are reclaimable.
+// Swap state is exported on /metrics next to the lookup histograms.
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, Weak};
+
+use crate::geocoder::Geocoder;
+use crate::units::ByteSize;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReloadConfig {
+    /// false: /admin/reload answers 403 and the DB is only swapped by restart.
+    pub enabled: bool,
+    /// Resident bytes of a loaded DB per byte of file (FSTs, indexes built
+    /// on load).
+    pub headroom_factor: f64,
+    /// Memory that must stay free after the new DB is loaded ("256MiB").
+    pub min_free: ByteSize,
+}
+
+impl Default for ReloadConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            headroom_factor: 1.5,
+            min_free: ByteSize::mib(256),
+        }
+    }
+}
+
+impl ReloadConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.headroom_factor.is_finite() || self.headroom_factor < 1.0 {
+            return Err("headroom_factor must be a finite number >= 1".into());
+        }
+        Ok(())
+    }
+
+    /// Fail unless loading a `file`-byte DB leaves `min_free` available;
+    /// `limit` is [server] memory_limit.
+    pub fn check_headroom(&self, file: u64, mmap: bool, limit: Option<ByteSize>) -> Result<()> {
+        let load = if mmap {
+            0
+        } else {
+            (file as f64 * self.headroom_factor) as u64
+        };
+        let needed = load + self.min_free.0;
+        let Some(available) = available_bytes(limit) else {
+            // nothing to measure against (not Linux): let the load decide
+            return Ok(());
+        };
+        if needed > available {
+            bail!(
+                "not enough memory to load a second DB: need {} ({} file x {} + {} free), {} \
+                 available",
+                ByteSize(needed),
+                ByteSize(file),
+                if mmap { 0.0 } else { self.headroom_factor },
+                self.min_free,
+                ByteSize(available)
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Bytes this process can still allocate, from the tightest of the cgroup
+/// limit, MemAvailable and `limit` minus our own RSS.
+fn available_bytes(limit: Option<ByteSize>) -> Option<u64> {
+    let read = |p: &str| std::fs::read_to_string(p).ok();
+    let num = |s: Option<String>| s.and_then(|s| s.trim().parse::<u64>().ok());
+
+    // cgroup v2 reports "max" for no limit; v1 a huge number
+    let cgroup = match num(read("/sys/fs/cgroup/memory.max")) {
+        Some(limit) => num(read("/sys/fs/cgroup/memory.current")).map(|u| (limit, u)),
+        None => num(read("/sys/fs/cgroup/memory/memory.limit_in_bytes"))
+            .zip(num(read("/sys/fs/cgroup/memory/memory.usage_in_bytes"))),
+    }
+    .filter(|(limit, _)| *limit < u64::MAX >> 1)
+    .map(|(limit, usage)| limit.saturating_sub(usage));
+
+    let meminfo = read("/proc/meminfo").and_then(|m| {
+        m.lines()
+            .find_map(|l| l.strip_prefix("MemAvailable:"))
+            .and_then(|v| v.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+            .map(|kb| kb << 10)
+    });
+
+    let configured = limit.map(|l| {
+        let rss = read("/proc/self/status")
+            .and_then(|s| {
+                s.lines()
+                    .find_map(|l| l.strip_prefix("VmRSS:"))
+                    .and_then(|v| v.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+            })
+            .map_or(0, |kb| kb << 10);
+        l.0.saturating_sub(rss)
+    });
+
+    [cgroup, meminfo, configured].into_iter().flatten().min()
+}
+
+/// Why a reload did not swap; also the index into `SwapState::refusals`.
+#[derive(Clone, Copy)]
+pub enum Refused {
+    Disabled,
+    /// The previous DB is still held by in-flight requests.
+    Draining,
+    Memory,
+    /// Load or consistency check of the new file failed.
+    Failed,
+}
+
+/// Swap state shared by the reload handler and /metrics.
+#[derive(Default)]
+pub struct SwapState {
+    loading: AtomicBool,
+    /// The DB the last swap replaced; alive while requests still use it.
+    retired: Mutex<Option<Weak<Geocoder>>>,
+    swaps: AtomicU64,
+    refusals: [AtomicU64; 4],
+}
+
+impl SwapState {
+    /// Whether the DB retired by the previous swap is still resident.
+    pub fn draining(&self) -> bool {
+        self.retired
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_ref()
+            .is_some_and(|w| w.strong_count() > 0)
+    }
+
+    pub fn set_loading(&self, loading: bool) {
+        self.loading.store(loading, Ordering::Relaxed);
+    }
+
+    /// Record a completed swap; `old` holds the DB it replaced.
+    pub fn swapped(&self, old: Weak<Geocoder>) {
+        *self.retired.lock().unwrap_or_else(|e| e.into_inner()) = Some(old);
+        self.swaps.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn refused(&self, why: Refused) {
+        self.refusals[why as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn render(&self, out: &mut String) {
+        let loading = self.loading.load(Ordering::Relaxed);
+        let draining = self.draining();
+        let _ = writeln!(
+            out,
+            "# HELP geodb_reload_loading 1 while /admin/reload is loading a DB."
+        );
+        let _ = writeln!(out, "# TYPE geodb_reload_loading gauge");
+        let _ = writeln!(out, "geodb_reload_loading {}", loading as u8);
+        let _ = writeln!(
+            out,
+            "# HELP geodb_reload_draining 1 while the DB replaced by the last reload is still in use."
+        );
+        let _ = writeln!(out, "# TYPE geodb_reload_draining gauge");
+        let _ = writeln!(out, "geodb_reload_draining {}", draining as u8);
+        let _ = writeln!(
+            out,
+            "# HELP geodb_resident_dbs DB copies in memory (serving, loading, draining)."
+        );
+        let _ = writeln!(out, "# TYPE geodb_resident_dbs gauge");
+        let _ = writeln!(
+            out,
+            "geodb_resident_dbs {}",
+            1 + loading as u8 + draining as u8
+        );
+        let _ = writeln!(
+            out,
+            "# HELP geodb_reloads_total Reload requests by outcome."
+        );
+        let _ = writeln!(out, "# TYPE geodb_reloads_total counter");
+        for (outcome, n) in [
+            ("swapped", &self.swaps),
+            ("disabled", &self.refusals[Refused::Disabled as usize]),
+            ("draining", &self.refusals[Refused::Draining as usize]),
+            (
+                "insufficient_memory",
+                &self.refusals[Refused::Memory as usize],
+            ),
+            ("failed", &self.refusals[Refused::Failed as usize]),
+        ] {
+            let _ = writeln!(
+                out,
+                "geodb_reloads_total{{outcome=\"{outcome}\"}} {}",
+                n.load(Ordering::Relaxed)
+            );
+        }
+    }
+}
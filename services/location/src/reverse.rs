@@ -0,0 +1,237 @@
+// src/reverse.rs
+//
+// In-memory reverse geocoding: every record's (lat, lon) bucketed into 1° grid
+// cells. Nearest-k scans only the cells overlapping the search radius' bounding
+// box, wrapping across the antimeridian, and doubles the radius until it has k
+// places, so a point at sea still gets the nearest coast town.
+// The build writes the points pre-sorted by cell (spatial section:
+// count u32 | count x (lat f32, lon f32, id u32)), so loading is one sequential
+// pass; DBs without the section fall back to walking the offsets + records.
+
+use anyhow::{bail, Result};
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::collections::HashMap;
+
+use crate::build::{FeatureFilter, GeoRecord};
+use crate::coords::Coordinate;
+use crate::ranking::haversine_km;
+use crate::{read_u32_le_at, read_u64_le_at, Db};
+
+const CELL_DEG: f32 = 1.0;
+const KM_PER_DEG: f64 = 111.2;
+
+/// Candidates returned when no limit is given.
+pub const DEFAULT_LIMIT: usize = 10;
+/// First search radius; `nearest` doubles it while places are missing.
+const FIRST_KM: f64 = 50.0;
+/// Half the earth's circumference: a radius that covers every point.
+const GLOBE_KM: f64 = 20_040.0;
+const POINT_LEN: usize = 12;
+
+struct Point {
+    lat: f32,
+    lon: f32,
+    id: u32,
+}
+
+pub struct ReverseIndex {
+    /// Points sorted by cell; `cells` maps a cell to its slice of `points`.
+    points: Vec<Point>,
+    cells: HashMap<(i32, i32), (u32, u32)>,
+}
+
+pub fn build_section(records: &[GeoRecord]) -> Result<Vec<u8>> {
+    let mut points: Vec<Point> = records
+        .iter()
+        .filter(|r| r.lat.is_finite() && r.lon.is_finite())
+        .map(|r| Point {
+            lat: r.lat,
+            lon: r.lon,
+            id: r.id,
+        })
+        .collect();
+    points.sort_unstable_by_key(|p| (cell_of(p.lat, p.lon), p.id));
+
+    let mut out = Vec::with_capacity(4 + points.len() * POINT_LEN);
+    out.write_u32::<LittleEndian>(points.len() as u32)?;
+    for p in &points {
+        out.write_f32::<LittleEndian>(p.lat)?;
+        out.write_f32::<LittleEndian>(p.lon)?;
+        out.write_u32::<LittleEndian>(p.id)?;
+    }
+    Ok(out)
+}
+
+impl ReverseIndex {
+    pub fn build(db: &Db) -> Result<Self> {
+        let points = match db.spatial_slice() {
+            [] => points_from_records(db)?,
+            section => points_from_section(section)?,
+        };
+        Ok(Self::from_sorted(points))
+    }
+
+    /// `points` sorted by cell.
+    fn from_sorted(points: Vec<Point>) -> Self {
+        let mut cells: HashMap<(i32, i32), (u32, u32)> = HashMap::new();
+        let mut start = 0usize;
+        while start < points.len() {
+            let cell = cell_of(points[start].lat, points[start].lon);
+            let mut end = start + 1;
+            while end < points.len() && cell_of(points[end].lat, points[end].lon) == cell {
+                end += 1;
+            }
+            cells.insert(cell, (start as u32, end as u32));
+            start = end;
+        }
+
+        eprintln!("[reverse] points={} cells={}", points.len(), cells.len());
+        Self { points, cells }
+    }
+
+    /// Up to `k` record ids that pass `keep`, nearest first. The radius starts
+    /// at FIRST_KM and doubles until `k` are found, it covers the globe or it
+    /// reaches `max_km`, the only bound on distance.
+    pub fn nearest(
+        &self,
+        at: Coordinate,
+        k: usize,
+        max_km: Option<f64>,
+        mut keep: impl FnMut(u32) -> Result<bool>,
+    ) -> Result<Vec<(u32, f64)>> {
+        let max_km = max_km.map_or(GLOBE_KM, |km| km.min(GLOBE_KM));
+        let mut out: Vec<(u32, f64)> = Vec::new();
+        // places within `inner` were kept (or not) by the previous pass
+        let (mut inner, mut radius) = (-1.0, FIRST_KM.min(max_km));
+        loop {
+            for (id, d) in self.around(at, radius) {
+                if d > inner && d <= radius && keep(id)? {
+                    out.push((id, d));
+                }
+            }
+            if out.len() >= k || radius >= max_km {
+                break;
+            }
+            inner = radius;
+            radius = (radius * 2.0).min(max_km);
+        }
+
+        out.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        out.truncate(k);
+        Ok(out)
+    }
+
+    /// (id, km from `at`) of every point in the cells overlapping the bounding
+    /// box of a `radius_km` circle around `at`.
+    fn around(&self, at: Coordinate, radius_km: f64) -> impl Iterator<Item = (u32, f64)> + '_ {
+        let dlat = (radius_km / KM_PER_DEG) as f32;
+        let (south, north) = ((at.lat - dlat).max(-90.0), (at.lat + dlat).min(90.0));
+        // meridians converge: size the lon span for the box edge nearest a pole
+        let far = south.abs().max(north.abs());
+        let cos = (far.to_radians().cos() as f64).max(1e-6);
+        let dlon = ((radius_km / (KM_PER_DEG * cos)) as f32).min(180.0);
+
+        // unwrapped lon cell range; wrapped per cell below
+        let cell = |deg: f32| (deg / CELL_DEG).floor() as i32;
+        let lon0 = cell(at.lon - dlon);
+        let lon_span = (cell(at.lon + dlon) - lon0).min((360.0 / CELL_DEG) as i32 - 1);
+        (cell(south)..=cell(north))
+            .flat_map(move |clat| {
+                (0..=lon_span).map(move |step| (clat, wrap_lon_cell(lon0 + step)))
+            })
+            .filter_map(move |c| self.cells.get(&c))
+            .flat_map(move |&(s, e)| &self.points[s as usize..e as usize])
+            .map(move |p| (p.id, haversine_km(at.lat, at.lon, p.lat, p.lon)))
+    }
+
+    /// (id, lat, lon) of every point with lat in [lat0, lat1) and lon in
+    /// [lon0, lon1); the box must not cross the antimeridian (lon0 <= lon1).
+    pub fn within(
+        &self,
+        (lat0, lat1): (f32, f32),
+        (lon0, lon1): (f32, f32),
+    ) -> impl Iterator<Item = (u32, f32, f32)> + '_ {
+        let cell = |deg: f32| (deg / CELL_DEG).floor() as i32;
+        let lons = cell(lon0)..=cell(lon1).min(cell(lon0) + (360.0 / CELL_DEG) as i32 - 1);
+        (cell(lat0)..=cell(lat1))
+            .flat_map(move |clat| lons.clone().map(move |clon| (clat, wrap_lon_cell(clon))))
+            .filter_map(move |c| self.cells.get(&c))
+            .flat_map(move |&(s, e)| &self.points[s as usize..e as usize])
+            .filter(move |p| p.lat >= lat0 && p.lat < lat1 && p.lon >= lon0 && p.lon < lon1)
+            .map(|p| (p.id, p.lat, p.lon))
+    }
+}
+
+/// Pre-spatial-section DBs: read (id, lat, lon) from every record, then sort.
+fn points_from_records(db: &Db) -> Result<Vec<Point>> {
+    let offsets = db.offsets_slice();
+    let records = db.records_slice();
+    if offsets.len() < 4 {
+        bail!("corrupt offsets");
+    }
+    let n = read_u32_le_at(offsets, 0) as usize;
+    let offs_start = 4 + n * 4;
+    if offs_start + n * 8 > offsets.len() {
+        bail!("corrupt offsets");
+    }
+
+    // record layout starts with id u32, lat f32, lon f32
+    let mut points = Vec::with_capacity(n);
+    for i in 0..n {
+        let off = read_u64_le_at(offsets, offs_start + i * 8) as usize;
+        if off + 12 > records.len() {
+            bail!("record offset out of bounds");
+        }
+        let lat = f32::from_bits(read_u32_le_at(records, off + 4));
+        let lon = f32::from_bits(read_u32_le_at(records, off + 8));
+        if lat.is_finite() && lon.is_finite() {
+            points.push(Point {
+                lat,
+                lon,
+                id: read_u32_le_at(records, off),
+            });
+        }
+    }
+    points.sort_unstable_by_key(|p| cell_of(p.lat, p.lon));
+    Ok(points)
+}
+
+fn points_from_section(section: &[u8]) -> Result<Vec<Point>> {
+    if section.len() < 4 {
+        bail!("corrupt spatial section");
+    }
+    let n = read_u32_le_at(section, 0) as usize;
+    if section.len() != 4 + n * POINT_LEN {
+        bail!(
+            "corrupt spatial section: {n} points, {} bytes",
+            section.len()
+        );
+    }
+    Ok(section[4..]
+        .chunks_exact(POINT_LEN)
+        .map(|p| Point {
+            lat: f32::from_bits(read_u32_le_at(p, 0)),
+            lon: f32::from_bits(read_u32_le_at(p, 4)),
+            id: read_u32_le_at(p, 8),
+        })
+        .collect())
+}
+
+/// Default filter for /reverse: GeoNames feature class P (cities, towns,
+/// villages).
+pub fn populated() -> FeatureFilter {
+    FeatureFilter::class(b'P')
+}
+
+fn cell_of(lat: f32, lon: f32) -> (i32, i32) {
+    (
+        (lat / CELL_DEG).floor() as i32,
+        wrap_lon_cell((lon / CELL_DEG).floor() as i32),
+    )
+}
+
+fn wrap_lon_cell(c: i32) -> i32 {
+    let n = (360.0 / CELL_DEG) as i32;
+    let half = n / 2;
+    (c + half).rem_euclid(n) - half
+}
@@ -0,0 +1,210 @@
+// src/sanitize.rs
+//
+// Build-time string hygiene so upstream junk never reaches the index or a
+// response: control characters are stripped, fields are truncated (at a char
+// boundary) to configured byte lengths, and invalid UTF-8 (read lossily, so it
+// shows up as U+FFFD) is counted; keys containing it are dropped.
+// Configured via [sanitize] in geodb.toml (`geodb build --config`).
+
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
+use std::borrow::Cow;
+
+use crate::build::{FastBuildMap, GeoRecord};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SanitizeConfig {
+    /// Max bytes for names and index keys.
+    pub max_name_len: usize,
+    /// Max bytes for country / admin / feature code fields.
+    pub max_field_len: usize,
+}
+
+impl Default for SanitizeConfig {
+    fn default() -> Self {
+        Self {
+            max_name_len: 200,
+            max_field_len: 40,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SanitizeCounts {
+    pub control_chars_stripped: u64,
+    pub truncated: u64,
+    pub invalid_utf8: u64,
+    pub keys_dropped: u64,
+    pub keys_rewritten: u64,
+}
+
+impl SanitizeConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_name_len == 0 || self.max_field_len == 0 {
+            return Err("max lengths must be > 0".into());
+        }
+        Ok(())
+    }
+
+    pub fn records(&self, records: &mut [GeoRecord], counts: &mut SanitizeCounts) {
+        for r in records {
+            clean_in_place(&mut r.name, self.max_name_len, counts);
+            clean_in_place(&mut r.ascii_name, self.max_name_len, counts);
+            for f in [
+                &mut r.country,
+                &mut r.admin1,
+                &mut r.admin2,
+                &mut r.feat_code,
+            ] {
+                clean_in_place(f, self.max_field_len, counts);
+            }
+        }
+    }
+
+    /// Rewrites index keys; postings of keys that become equal are merged.
+    pub fn keys(&self, key_to_ids: &mut FastBuildMap, counts: &mut SanitizeCounts) {
+        let mut changed: Vec<(String, Option<String>)> = Vec::new();
+        for k in key_to_ids.keys() {
+            if k.contains(char::REPLACEMENT_CHARACTER) {
+                counts.invalid_utf8 += 1;
+                changed.push((k.clone(), None));
+                continue;
+            }
+            if let Cow::Owned(c) = clean(k, self.max_name_len, counts) {
+                let c = c.trim().to_string();
+                changed.push((k.clone(), (!c.is_empty()).then_some(c)));
+            }
+        }
+
+        for (old, new) in changed {
+            let Some(ids) = key_to_ids.remove(&old) else {
+                continue;
+            };
+            match new {
+                Some(k) => {
+                    counts.keys_rewritten += 1;
+                    let e: &mut SmallVec<[u32; 2]> = key_to_ids.entry(k).or_default();
+                    e.extend(ids);
+                }
+                None => counts.keys_dropped += 1,
+            }
+        }
+    }
+}
+
+fn clean_in_place(s: &mut String, max: usize, counts: &mut SanitizeCounts) {
+    if s.contains(char::REPLACEMENT_CHARACTER) {
+        counts.invalid_utf8 += 1;
+    }
+    if let Cow::Owned(c) = clean(s, max, counts) {
+        *s = c;
+    }
+}
+
+fn clean<'a>(s: &'a str, max: usize, counts: &mut SanitizeCounts) -> Cow<'a, str> {
+    let mut out = Cow::Borrowed(s);
+    if s.chars().any(char::is_control) {
+        counts.control_chars_stripped += 1;
+        out = Cow::Owned(s.chars().filter(|c| !c.is_control()).collect());
+    }
+    if out.len() > max {
+        counts.truncated += 1;
+        let mut end = max;
+        while !out.is_char_boundary(end) {
+            end -= 1;
+        }
+        out = Cow::Owned(out[..end].to_string());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(name: &str, country: &str) -> GeoRecord {
+        GeoRecord {
+            id: 1,
+            name: name.into(),
+            ascii_name: name.into(),
+            country: country.into(),
+            admin1: String::new(),
+            admin2: String::new(),
+            lat: 0.0,
+            lon: 0.0,
+            feat_class: b'P',
+            feat_code: "PPL".into(),
+            population: 0,
+            timezone: String::new(),
+        }
+    }
+
+    #[test]
+    fn validate_rejects_zero_lengths() {
+        assert!(SanitizeConfig::default().validate().is_ok());
+        let cfg = SanitizeConfig {
+            max_field_len: 0,
+            ..SanitizeConfig::default()
+        };
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn records_strip_control_chars_and_truncate_at_char_boundaries() {
+        let cfg = SanitizeConfig {
+            max_name_len: 5,
+            max_field_len: 3,
+        };
+        let mut counts = SanitizeCounts::default();
+        let mut recs = [record("Wi\u{7}en", "ATXY"), record("Zürich", "CH")];
+        cfg.records(&mut recs, &mut counts);
+
+        assert_eq!(recs[0].name, "Wien");
+        assert_eq!(recs[0].country, "ATX");
+        // name and ascii_name: "Zürich" is 7 bytes, "Züri" 5
+        assert_eq!(recs[1].name, "Züri");
+        assert_eq!(counts.control_chars_stripped, 2);
+        assert_eq!(counts.truncated, 3);
+        assert_eq!(counts.invalid_utf8, 0);
+    }
+
+    #[test]
+    fn truncation_never_splits_a_char() {
+        let mut counts = SanitizeCounts::default();
+        assert_eq!(clean("ééé", 3, &mut counts), "é");
+        assert_eq!(clean("ééé", 1, &mut counts), "");
+    }
+
+    #[test]
+    fn records_count_invalid_utf8() {
+        let mut counts = SanitizeCounts::default();
+        let mut recs = [record("Gen\u{FFFD}ve", "CH")];
+        SanitizeConfig::default().records(&mut recs, &mut counts);
+        assert_eq!(recs[0].name, "Gen\u{FFFD}ve");
+        assert_eq!(counts.invalid_utf8, 2);
+    }
+
+    #[test]
+    fn keys_drop_invalid_utf8_and_merge_rewrites() {
+        let mut map = FastBuildMap::default();
+        for (k, id) in [
+            ("wien", 1),
+            ("wi\u{0}en", 2),
+            ("gen\u{FFFD}ve", 3),
+            ("\u{1b}\u{7}", 4),
+        ] {
+            map.entry(k.to_string()).or_default().push(id);
+        }
+        let mut counts = SanitizeCounts::default();
+        SanitizeConfig::default().keys(&mut map, &mut counts);
+
+        assert_eq!(map.len(), 1);
+        let mut ids = map["wien"].to_vec();
+        ids.sort_unstable();
+        assert_eq!(ids, [1, 2]);
+        assert_eq!(counts.keys_rewritten, 1);
+        assert_eq!(counts.keys_dropped, 2);
+        assert_eq!(counts.invalid_utf8, 1);
+    }
+}
@@ -0,0 +1,134 @@
+// src/scripting.rs
+//
+// Optional score-adjustment hook (feature "scripting", rhai). The script defines
+//
+//     fn adjust(score, rec, ctx) { ... }
+//
+// and returns the new total score. `rec` carries the record fields (id, name,
+// country, admin1, admin2, lat, lon, feature_class, feature_code, population);
+// `ctx` carries the query (key, and near_lat/near_lon when a focus is given).
+// A failing script never fails the request: the candidate keeps its score.
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::build::GeoRecord;
+use crate::ranking::ScoreBreakdown;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScriptingConfig {
+    /// Path to a .rhai file defining `adjust(score, rec, ctx)`.
+    pub path: Option<PathBuf>,
+}
+
+/// Query context handed to the script.
+#[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+pub struct ScriptCtx<'a> {
+    pub key: &'a str,
+    pub focus: Option<(f32, f32)>,
+}
+
+#[cfg(feature = "scripting")]
+pub struct Script {
+    engine: rhai::Engine,
+    ast: rhai::AST,
+}
+
+/// Without the feature there is no script; the type is uninhabited.
+#[cfg(not(feature = "scripting"))]
+pub enum Script {}
+
+#[cfg(not(feature = "scripting"))]
+pub fn load(cfg: &ScriptingConfig) -> Result<Option<Script>> {
+    if cfg.path.is_some() {
+        bail!("scripting.path configured but geodb was built without the `scripting` feature");
+    }
+    Ok(None)
+}
+
+#[cfg(not(feature = "scripting"))]
+impl Script {
+    pub fn apply(&self, _ranked: &mut [(GeoRecord, ScoreBreakdown)], _ctx: &ScriptCtx<'_>) {
+        match *self {}
+    }
+}
+
+#[cfg(feature = "scripting")]
+pub fn load(cfg: &ScriptingConfig) -> Result<Option<Script>> {
+    let Some(path) = &cfg.path else {
+        return Ok(None);
+    };
+
+    let mut engine = rhai::Engine::new();
+    // Scoring runs per candidate on the request path; keep runaway scripts bounded.
+    engine.set_max_operations(100_000);
+    engine.set_max_call_levels(16);
+
+    let ast = match engine.compile_file(path.clone()) {
+        Ok(ast) => ast,
+        Err(e) => bail!("compile script {}: {e}", path.display()),
+    };
+    if !ast
+        .iter_functions()
+        .any(|f| f.name == "adjust" && f.params.len() == 3)
+    {
+        bail!(
+            "script {} must define fn adjust(score, rec, ctx)",
+            path.display()
+        );
+    }
+
+    eprintln!("[scripting] loaded {}", path.display());
+    Ok(Some(Script { engine, ast }))
+}
+
+#[cfg(feature = "scripting")]
+impl Script {
+    /// Adjust `total` in place and re-sort best-first.
+    pub fn apply(&self, ranked: &mut [(GeoRecord, ScoreBreakdown)], ctx: &ScriptCtx<'_>) {
+        use rhai::{Dynamic, Map, Scope};
+
+        let mut ctx_map = Map::new();
+        ctx_map.insert("key".into(), Dynamic::from(ctx.key.to_string()));
+        if let Some((lat, lon)) = ctx.focus {
+            ctx_map.insert("near_lat".into(), Dynamic::from(lat as f64));
+            ctx_map.insert("near_lon".into(), Dynamic::from(lon as f64));
+        }
+
+        for (rec, score) in ranked.iter_mut() {
+            let mut m = Map::new();
+            m.insert("id".into(), Dynamic::from(rec.id as i64));
+            m.insert("name".into(), Dynamic::from(rec.name.clone()));
+            m.insert("country".into(), Dynamic::from(rec.country.clone()));
+            m.insert("admin1".into(), Dynamic::from(rec.admin1.clone()));
+            m.insert("admin2".into(), Dynamic::from(rec.admin2.clone()));
+            m.insert("lat".into(), Dynamic::from(rec.lat as f64));
+            m.insert("lon".into(), Dynamic::from(rec.lon as f64));
+            m.insert(
+                "feature_class".into(),
+                Dynamic::from((rec.feat_class as char).to_string()),
+            );
+            m.insert("feature_code".into(), Dynamic::from(rec.feat_code.clone()));
+            m.insert("population".into(), Dynamic::from(rec.population as i64));
+
+            let res = self.engine.call_fn::<f64>(
+                &mut Scope::new(),
+                &self.ast,
+                "adjust",
+                (score.total, m, ctx_map.clone()),
+            );
+            match res {
+                Ok(v) if v.is_finite() => {
+                    score.script = Some(v);
+                    score.total = v;
+                }
+                Ok(v) => eprintln!("[scripting] adjust returned {v} for {}, ignored", rec.id),
+                Err(e) => eprintln!("[scripting] adjust failed for {}: {e}", rec.id),
+            }
+        }
+
+        ranked.sort_by(|a, b| b.1.total.total_cmp(&a.1.total));
+    }
+}
@@ -0,0 +1,156 @@
+// src/segment.rs
+//
+// Word segmentation for concatenated queries ("newyorkcity", "#riodejaneiro").
+// The FST keys are the dictionary: we walk the raw FST byte by byte and try
+// inserting a single space at char boundaries, keeping the path that reaches
+// a final state with the fewest inserted spaces.
+
+use fst::raw::{Fst, Node, Output};
+
+/// Upper bound on inserted spaces; real place names rarely have more words.
+const MAX_SPACES: usize = 5;
+/// Upper bound on visited FST nodes per query, so hostile input stays cheap.
+const MAX_STEPS: usize = 20_000;
+
+/// Strip hashtag markers and whitespace so "#RioDeJaneiro" segments like "riodejaneiro".
+pub fn squash_key(key: &str) -> String {
+    key.trim()
+        .trim_start_matches('#')
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect()
+}
+
+/// Try to split `key` (already case-folded) into space-separated words that form an index key.
+/// Returns the segmented key and its FST value (postings offset).
+pub fn segment<D: AsRef<[u8]>>(fst: &fst::Map<D>, key: &str) -> Option<(String, u64)> {
+    let squashed = squash_key(key);
+    if squashed.is_empty() {
+        return None;
+    }
+
+    let raw = fst.as_fst();
+    let mut search = Search {
+        fst: raw,
+        input: squashed.as_bytes(),
+        text: &squashed,
+        path: Vec::with_capacity(squashed.len() + MAX_SPACES),
+        best: None,
+        steps: 0,
+    };
+    search.walk(0, raw.root(), Output::zero(), 0);
+
+    // A zero-space hit means the squashed form itself is a key (e.g. the query was
+    // "#paris"); that is still a useful answer for the caller.
+    search
+        .best
+        .map(|(bytes, out, _)| (String::from_utf8_lossy(&bytes).into_owned(), out))
+}
+
+struct Search<'a, D: AsRef<[u8]>> {
+    fst: &'a Fst<D>,
+    input: &'a [u8],
+    text: &'a str,
+    path: Vec<u8>,
+    best: Option<(Vec<u8>, u64, usize)>,
+    steps: usize,
+}
+
+impl<D: AsRef<[u8]>> Search<'_, D> {
+    fn walk(&mut self, pos: usize, node: Node<'_>, out: Output, spaces: usize) {
+        self.steps += 1;
+        if self.steps > MAX_STEPS {
+            return;
+        }
+        if let Some((_, _, best_spaces)) = &self.best {
+            if spaces >= *best_spaces {
+                return;
+            }
+        }
+
+        if pos == self.input.len() {
+            if node.is_final() {
+                let value = out.cat(node.final_output()).value();
+                self.best = Some((self.path.clone(), value, spaces));
+            }
+            return;
+        }
+
+        // 1) continue the current word
+        let b = self.input[pos];
+        if let Some(i) = node.find_input(b) {
+            let t = node.transition(i);
+            self.path.push(b);
+            self.walk(pos + 1, self.fst.node(t.addr), out.cat(t.out), spaces);
+            self.path.pop();
+        }
+
+        // 2) end the current word here and start a new one
+        if pos > 0 && spaces < MAX_SPACES && self.text.is_char_boundary(pos) {
+            if let Some(i) = node.find_input(b' ') {
+                let t = node.transition(i);
+                let after_space = self.fst.node(t.addr);
+                if let Some(j) = after_space.find_input(b) {
+                    let t2 = after_space.transition(j);
+                    self.path.push(b' ');
+                    self.path.push(b);
+                    self.walk(
+                        pos + 1,
+                        self.fst.node(t2.addr),
+                        out.cat(t.out).cat(t2.out),
+                        spaces + 1,
+                    );
+                    self.path.truncate(self.path.len() - 2);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dict() -> fst::Map<Vec<u8>> {
+        fst::Map::from_iter([
+            ("new york", 1),
+            ("new york city", 2),
+            ("paris", 3),
+            ("rio de janeiro", 4),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn squash_strips_hashtags_and_whitespace() {
+        assert_eq!(squash_key("  #Rio De\tJaneiro "), "RioDeJaneiro");
+        assert_eq!(squash_key("##"), "");
+    }
+
+    #[test]
+    fn splits_into_the_fewest_words() {
+        let fst = dict();
+        assert_eq!(
+            segment(&fst, "newyorkcity"),
+            Some(("new york city".into(), 2))
+        );
+        assert_eq!(segment(&fst, "newyork"), Some(("new york".into(), 1)));
+        assert_eq!(
+            segment(&fst, "#riodejaneiro"),
+            Some(("rio de janeiro".into(), 4))
+        );
+        // the squashed form itself is a key
+        assert_eq!(segment(&fst, "#paris"), Some(("paris".into(), 3)));
+    }
+
+    #[test]
+    fn rejects_what_no_key_spells() {
+        let fst = dict();
+        assert_eq!(segment(&fst, ""), None);
+        assert_eq!(segment(&fst, " # "), None);
+        assert_eq!(segment(&fst, "newyorkcit"), None);
+        assert_eq!(segment(&fst, "parisx"), None);
+        assert_eq!(segment(&fst, "zürichnewyork"), None);
+        assert_eq!(segment(&fst, &"a".repeat(10_000)), None);
+    }
+}
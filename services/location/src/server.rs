@@ -1,58 +1,827 @@
 // src/server.rs
 //
-// Minimal HTTP server for geodb.
-// - Loads DB into RAM once (Db bytes + fst::Map).
-// - Serves GET /query?key=...&limit=...
-// - Optionally /health
+// HTTP server for geodb; the handlers are thin wrappers over the Geocoder
+// (geocoder.rs) and each documents its own endpoint.
+// - Every route but /health lives under /v1; the old unversioned paths still
+//   answer as v1, marked deprecated (`unversioned`).
+// - Loads the DB once (mapped with --mmap); POST /admin/reload swaps in
+//   another without a restart (reload.rs).
+// - Lookups run on the blocking pool, at most [server] lookup_threads at once.
+// - Per-IP rate limits and an in-flight cap answer 429 / 503 (throttle.rs).
+// - /admin/* can move to a listener of its own and require [server]
+//   admin_token.
+// - Optional /health
 //
 // Uses axum + tokio. No unsafe.
 
 use anyhow::{anyhow, Result};
 use axum::{
-    extract::{Query, State},
-    http::StatusCode,
+    body::{Body, Bytes},
+    extract::{
+        connect_info::IntoMakeServiceWithConnectInfo, ConnectInfo, DefaultBodyLimit, FromRef,
+        FromRequest, Path, Query, RawQuery, Request, State,
+    },
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    middleware::{self, Next},
     response::{IntoResponse, Response},
-    routing::get,
+    routing::{delete, get, post},
     Json, Router,
 };
-use fst;
+use chrono::Utc;
+use fst::{self, Streamer};
 use serde::{Deserialize, Serialize};
-use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::audit::{self, AuditRecord, Auditor, FeedbackCandidate, FeedbackRecord};
+use crate::bbox::BBox;
+use crate::build::{FeatureFilter, GeoRecord};
+use crate::cache::{self, ResponseCache};
+use crate::casefold;
+use crate::concordance::Concordance;
+use crate::config::Config;
+use crate::coords::{self, Coordinate};
+use crate::countries::CountryInfo;
+use crate::disambiguate;
+use crate::extract;
+use crate::geocoder::{Answer, Candidate, Geocoder, LookupOptions, Ranked};
+use crate::h3::H3Section;
+use crate::idempotency::{self, Claim, Replays, Stored};
+use crate::jobs::{self, GeocodeJobRequest, JobStatus, JobStore, JobSummary};
+use crate::langs;
+use crate::metrics::Metrics;
+use crate::nameflags::NameUse;
+use crate::overlay::{Overlay, OverlayFile};
+use crate::periods;
+use crate::pipeline::Scorer;
+use crate::precision::Precision;
+use crate::ranking::{self, RankingWeights, ScoreBreakdown};
+use crate::region::Region;
+use crate::reload::{Refused, ReloadConfig};
+use crate::reverse;
+use crate::scripting::{self, Script};
+use crate::sets;
+use crate::singleflight::Group;
+use crate::smoke;
+use crate::subdivision::{self, Subdivisions};
+use crate::suggest;
+use crate::throttle::{Refusal, Throttle};
+use crate::tiles::{self, TileId};
+use crate::transport::{self, CodeKind, TransportCodes};
+use crate::units::{self, ByteSize};
+use crate::{build_hash, fnv1a64, read_record_by_id, Db, OpenOptions};
+
+const X_GEODB_BUILD: HeaderName = HeaderName::from_static("x-geodb-build");
+
+/// [server] in geodb.toml; `geodb serve` flags override the thread counts.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// Tokio worker threads (default: one per core).
+    pub worker_threads: Option<usize>,
+    /// Upper bound on blocking threads: background jobs, lookups, reloads.
+    pub blocking_threads: Option<usize>,
+    /// Lookups decoding postings and records at once, on the blocking pool
+    /// (default: one per core); further requests wait for a slot.
+    pub lookup_threads: Option<usize>,
+    /// Memory the process may use ("4GiB"); reload headroom is checked
+    /// against it in addition to the cgroup limit (reload.rs).
+    pub memory_limit: Option<ByteSize>,
+    /// /query responses kept in the LRU (default cache::DEFAULT_ENTRIES;
+    /// 0 turns the cache off).
+    pub query_cache_entries: Option<usize>,
+    /// /suggest responses kept, including primed next prefixes (default
+    /// cache::DEFAULT_ENTRIES; 0 turns caching and priming off).
+    pub suggest_cache_entries: Option<usize>,
+    /// Responses kept for Idempotency-Key retries of /query/batch and
+    /// /jobs/geocode (default idempotency::DEFAULT_ENTRIES; 0 turns replay
+    /// off), each for `idempotency_ttl` (default 10m).
+    pub idempotency_entries: Option<usize>,
+    pub idempotency_ttl: Option<units::Duration>,
+    /// Serve /admin/* here instead of on the public port (`--admin-bind`).
+    pub admin_bind: Option<SocketAddr>,
+    /// Bearer token /admin/* requests must carry; unset = no check.
+    pub admin_token: Option<String>,
+    /// Candidates per key on /query and /query/batch without a limit or with
+    /// limit=0, once `unlimited_default` is off (default DEFAULT_QUERY_LIMIT).
+    pub default_limit: Option<usize>,
+    /// Transition: a missing limit / limit=0 still returns every candidate,
+    /// with a Warning header. Default false, which applies `default_limit`.
+    pub unlimited_default: Option<bool>,
+    /// Accept limit=all with stream=true (default true).
+    pub allow_limit_all: Option<bool>,
+    /// Overlay entries (overlay.rs), loaded at startup and rewritten by
+    /// /admin/overlay; unset = the overlay lives in memory only.
+    pub overlay: Option<PathBuf>,
+    /// API requests per second per client IP (`--rate-limit`); unset = no
+    /// limit (throttle.rs).
+    pub rate_limit: Option<f64>,
+    /// Requests a client may send at once before `rate_limit` applies
+    /// (default: rate_limit rounded up).
+    pub rate_burst: Option<u32>,
+    /// API requests handled at once; more are answered 503
+    /// (`--max-in-flight`); unset = no cap.
+    pub max_in_flight: Option<usize>,
+    /// Serve HTTPS with this PEM certificate chain and `tls_key`
+    /// (`--tls-cert`; needs the tls feature). Admin listeners too.
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+}
+
+impl ServerConfig {
+    /// Admission limits from `rate_limit`, `rate_burst` and `max_in_flight`.
+    pub fn throttle(&self) -> Throttle {
+        let rate = self.rate_limit.map(|r| {
+            let burst = self
+                .rate_burst
+                .unwrap_or(r.ceil().min(f64::from(u32::MAX)) as u32);
+            (r, burst)
+        });
+        Throttle::new(rate, self.max_in_flight)
+    }
+
+    /// `tls_cert` and `tls_key`, when both are set.
+    pub fn tls(&self) -> Option<TlsFiles> {
+        Some(TlsFiles {
+            cert: self.tls_cert.clone()?,
+            key: self.tls_key.clone()?,
+        })
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.worker_threads == Some(0) {
+            return Err("worker_threads must be > 0".into());
+        }
+        if self.blocking_threads == Some(0) {
+            return Err("blocking_threads must be > 0".into());
+        }
+        if self.lookup_threads == Some(0) {
+            return Err("lookup_threads must be > 0".into());
+        }
+        if let (Some(l), Some(b)) = (self.lookup_threads, self.blocking_threads) {
+            if l > b {
+                return Err(format!(
+                    "lookup_threads ({l}) must not exceed blocking_threads ({b})"
+                ));
+            }
+        }
+        if self.rate_limit.is_some_and(|r| !r.is_finite() || r <= 0.0) {
+            return Err("rate_limit must be > 0 requests per second".into());
+        }
+        if self.tls_cert.is_some() != self.tls_key.is_some() {
+            return Err("tls_cert and tls_key go together".into());
+        }
+        if self.rate_burst == Some(0) {
+            return Err("rate_burst must be > 0".into());
+        }
+        if self.max_in_flight == Some(0) {
+            return Err("max_in_flight must be > 0".into());
+        }
+        if self.default_limit == Some(0) {
+            return Err("default_limit must be > 0 (limit=all asks for every candidate)".into());
+        }
+        if self.memory_limit == Some(ByteSize(0)) {
+            return Err("memory_limit must be > 0".into());
+        }
+        if self.idempotency_ttl.is_some_and(|t| t.is_zero()) {
+            return Err(
+                "idempotency_ttl must be > 0 (idempotency_entries = 0 turns replay off)".into(),
+            );
+        }
+        if self
+            .admin_token
+            .as_deref()
+            .is_some_and(|t| t.trim().is_empty())
+        {
+            return Err("admin_token must not be empty".into());
+        }
+        Ok(())
+    }
+}
+
+/// [server] default_limit when unset.
+pub const DEFAULT_QUERY_LIMIT: usize = 50;
+
+/// Most candidates per key any request gets; larger limits are clamped.
+/// `limit=all` is the exception, accepted only where it streams
+/// (`stream_answer`).
+const MAX_LIMIT: usize = 1_000;
+
+/// A `limit=` count: missing or 0 means `default`, never more than MAX_LIMIT.
+fn clamp_limit(limit: Option<usize>, default: usize) -> usize {
+    match limit {
+        None | Some(0) => default,
+        Some(n) => n.min(MAX_LIMIT),
+    }
+}
+
+/// Keys per /query/batch request; larger lists go through /jobs/geocode.
+const BATCH_MAX_KEYS: usize = 1_000;
+
+/// Inline key lists for /jobs/geocode can be large; bigger inputs go via `file`.
+const JOB_BODY_LIMIT: usize = 64 << 20;
 
-use crate::{open_db, read_postings, read_record_by_id, Db};
+/// A /query response body, or the error every waiter of the flight reports.
+type QueryFlight = Result<Bytes, Arc<str>>;
+
+/// What every handler sees: one consistent view of the DB for the request.
+/// Everything derived from the DB file is replaced together by /admin/reload;
+/// ranking, overlay, audit, jobs and the response cache are carried across
+/// reloads.
 
 #[derive(Clone)]
 pub struct AppState {
-    db: Arc<Db>,
-    fst: Arc<fst::Map<Vec<u8>>>,
+    geo: Arc<Geocoder>,
+    ranking: Arc<RwLock<RankingWeights>>,
+    /// Bumped on every ranking or overlay change so cached ETags stop matching.
+    ranking_gen: Arc<AtomicU64>,
+    build: Arc<str>,
+    config_path: Option<Arc<PathBuf>>,
+    auditor: Option<Auditor>,
+    /// Loaded once; carried into the Geocoder of every reload.
+    script: Option<Arc<Script>>,
+    concordance: Option<Arc<Concordance>>,
+    subdivisions: Option<Arc<Subdivisions>>,
+    transport: Option<Arc<TransportCodes>>,
+    /// Runtime names; replaced whole by /admin/overlay.
+    overlay: Arc<Overlay>,
+    limits: QueryLimits,
+    /// Slots for lookups on the blocking pool ([server] lookup_threads).
+    lookups: Arc<tokio::sync::Semaphore>,
+    /// Per-IP rate and in-flight limits (throttle.rs).
+    throttle: Throttle,
+    jobs: Arc<JobStore>,
+    metrics: Arc<Metrics>,
+    /// /query lookups in flight, by flight_key.
+    flights: Arc<Group<QueryFlight>>,
+    /// Recent /query bodies by flight_key; None when turned off.
+    cache: Option<Arc<ResponseCache>>,
+    /// /suggest bodies by suggest_key; None when turned off.
+    suggest_cache: Option<Arc<ResponseCache>>,
+    /// File the DB was loaded from; None for the embedded DB.
+    db_path: Option<Arc<PathBuf>>,
+    open: OpenOptions,
 }
 
-#[derive(Debug, Deserialize)]
+/// Router state: the current AppState, swapped whole on reload. Handlers
+/// extract `State<AppState>` (a clone of the current one), so a request that
+/// started before a swap finishes on the DB it started with.
+#[derive(Clone)]
+struct Shared {
+    current: Arc<RwLock<AppState>>,
+    /// One reload or overlay edit at a time.
+    reloading: Arc<tokio::sync::Mutex<()>>,
+    /// [server] overlay.
+    overlay_path: Option<Arc<PathBuf>>,
+    reload: Arc<ReloadConfig>,
+    memory_limit: Option<ByteSize>,
+}
+
+impl FromRef<Shared> for AppState {
+    fn from_ref(shared: &Shared) -> Self {
+        shared
+            .current
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+/// Everything AppState loads from the DB file.
+struct DbParts {
+    geo: Geocoder,
+    build: String,
+    concordance: Option<Concordance>,
+    subdivisions: Option<Subdivisions>,
+    transport: Option<TransportCodes>,
+}
+
+impl DbParts {
+    fn load(
+        path: Option<&std::path::Path>,
+        open: OpenOptions,
+        script: Option<Arc<Script>>,
+    ) -> Result<Self> {
+        let geo = Geocoder::open(path, open)?.with_script(script);
+        // built here rather than by the first coordinate query
+        geo.reverse_index()?;
+        let db = geo.db();
+        Ok(Self {
+            build: build_hash(db),
+            concordance: Concordance::from_section(db.concordance_slice())?,
+            subdivisions: Subdivisions::from_section(db.subdivisions_slice())?,
+            transport: TransportCodes::from_section(db.transport_slice())?,
+            geo,
+        })
+    }
+}
+
+impl AppState {
+    /// `self` with every DB-derived field replaced by `parts`.
+    fn with_db(&self, parts: DbParts, path: Option<PathBuf>) -> Self {
+        Self {
+            geo: Arc::new(parts.geo),
+            build: Arc::from(parts.build),
+            concordance: parts.concordance.map(Arc::new),
+            subdivisions: parts.subdivisions.map(Arc::new),
+            transport: parts.transport.map(Arc::new),
+            db_path: path.map(Arc::new),
+            ..self.clone()
+        }
+    }
+
+    /// Lookup settings every request starts from: the current ranking
+    /// weights and overlay.
+    fn lookup_options(&self) -> LookupOptions {
+        LookupOptions {
+            weights: Some(self.weights()),
+            overlay: Some(self.overlay.clone()),
+            ..LookupOptions::default()
+        }
+    }
+
+    /// Run `f` (postings and record decoding, ranking) on the blocking pool
+    /// once a lookup slot is free, so a key with a huge postings list delays
+    /// other lookups at worst, never the reactor threads serving every other
+    /// request. The slot is held until `f` returns, even if the client has
+    /// gone away meanwhile.
+    async fn offload<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(AppState) -> Result<T> + Send + 'static,
+    {
+        let slot = self
+            .lookups
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|e| anyhow!("lookup slots: {e}"))?;
+        let state = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let _slot = slot;
+            f(state)
+        })
+        .await
+        .map_err(|e| anyhow!("lookup task: {e}"))?
+    }
+
+    fn weights(&self) -> RankingWeights {
+        self.ranking
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// /suggest cache key; the build hash keeps entries from a replaced DB
+    /// from being served.
+    fn suggest_key(&self, prefix: &str, limit: usize) -> String {
+        format!("{}\n{limit}\n{prefix}", self.build)
+    }
+
+    /// Compute and cache /suggest for `prefixes` not cached yet.
+    fn prime_suggest(&self, cache: &ResponseCache, prefixes: Vec<String>, limit: usize) {
+        for prefix in prefixes {
+            let key = self.suggest_key(&prefix, limit);
+            if cache.contains(&key) {
+                continue;
+            }
+            let body = suggest::suggest(self.geo.db(), self.geo.fst(), &prefix, limit)
+                .and_then(|json| Ok(serde_json::to_vec(&json)?));
+            match body {
+                Ok(body) => cache.put(key, Bytes::from(body)),
+                Err(e) => {
+                    eprintln!("[suggest] prime {prefix:?}: {e:#}");
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Coalescing key: the parsed query with its key folded, under the
+    /// current build and ranking generation.
+    fn flight_key(&self, q: &QueryParams) -> String {
+        let gen = self.ranking_gen.load(Ordering::Relaxed);
+        let q = QueryParams {
+            key: casefold::fold(q.key.trim()),
+            ..q.clone()
+        };
+        format!("{}-{gen}:{q:?}", self.build)
+    }
+
+    fn etag(&self, raw_query: &str) -> String {
+        let gen = self.ranking_gen.load(Ordering::Relaxed);
+        let h = fnv1a64(fnv1a64(0, &gen.to_le_bytes()), raw_query.as_bytes());
+        format!("\"{}-{h:016x}\"", self.build)
+    }
+}
+
+/// Weak comparison per RFC 9110: "W/" prefixes are ignored, "*" matches anything.
+fn etag_matches(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|t| t.trim().trim_start_matches("W/"))
+        .any(|t| t == "*" || t == etag)
+}
+
+/// `limit=` on /query and /query/batch: a count, "all", or missing / 0 for
+/// the server default.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Limit {
+    #[default]
+    Default,
+    Count(usize),
+    All,
+}
+
+/// Query strings carry text, batch bodies numbers.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawLimit {
+    Count(usize),
+    Text(String),
+}
+
+impl<'de> Deserialize<'de> for Limit {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> std::result::Result<Self, D::Error> {
+        let n = match RawLimit::deserialize(d)? {
+            RawLimit::Count(n) => n,
+            RawLimit::Text(s) if s.trim().eq_ignore_ascii_case("all") => return Ok(Limit::All),
+            RawLimit::Text(s) if s.trim().is_empty() => 0,
+            RawLimit::Text(s) => s.trim().parse().map_err(|_| {
+                serde::de::Error::custom(format!("limit: expected a number or \"all\", got {s:?}"))
+            })?,
+        };
+        Ok(if n == 0 {
+            Limit::Default
+        } else {
+            Limit::Count(n)
+        })
+    }
+}
+
+/// [server] default_limit / unlimited_default / allow_limit_all.
+#[derive(Clone)]
+struct QueryLimits {
+    default: usize,
+    unlimited_default: bool,
+    allow_all: bool,
+    /// Requests answered unlimited only because of `unlimited_default`.
+    legacy: Arc<AtomicU64>,
+}
+
+impl QueryLimits {
+    fn new(cfg: &ServerConfig) -> Self {
+        Self {
+            default: cfg.default_limit.unwrap_or(DEFAULT_QUERY_LIMIT),
+            unlimited_default: cfg.unlimited_default.unwrap_or(false),
+            allow_all: cfg.allow_limit_all.unwrap_or(true),
+            legacy: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// What `Limit::Default` means for name keys: None = every candidate.
+    /// limit=all is refused unless the answer is `streamed`: buffered, every
+    /// candidate of a common name is an unbounded body.
+    fn default_for(&self, limit: Limit, streamed: bool) -> Result<Option<usize>, AppError> {
+        if limit == Limit::All && !self.allow_all {
+            return Err(AppError::BadRequest(anyhow!(
+                "limit=all is disabled on this server; page with limit + offset"
+            )));
+        }
+        if limit == Limit::All && !streamed {
+            return Err(AppError::BadRequest(anyhow!(
+                "limit=all needs stream=true; otherwise page with limit + offset"
+            )));
+        }
+        Ok((!self.unlimited_default).then_some(self.default))
+    }
+
+    /// The Warning header for requests that rely on the unlimited default.
+    fn warning(&self, limit: Limit) -> Option<HeaderValue> {
+        if limit != Limit::Default || !self.unlimited_default {
+            return None;
+        }
+        self.legacy.fetch_add(1, Ordering::Relaxed);
+        HeaderValue::from_str(&format!(
+            "299 geodb \"a missing limit or limit=0 will return at most {} candidates; \
+             pass limit=all with stream=true for every candidate\"",
+            self.default
+        ))
+        .ok()
+    }
+}
+
+fn with_warning(mut res: Response, warning: Option<HeaderValue>) -> Response {
+    if let Some(w) = warning {
+        res.headers_mut().insert(header::WARNING, w);
+    }
+    res
+}
+
+#[derive(Clone, Debug, Deserialize)]
 struct QueryParams {
     key: String,
     #[serde(default)]
+    limit: Limit,
+    /// Candidates skipped before `limit`, for paging (see `total`).
+    #[serde(default)]
+    offset: Option<usize>,
+    /// "lat,lon" focus point for distance decay.
+    #[serde(default)]
+    near: Option<String>,
+    #[serde(default)]
+    explain: bool,
+    /// Add plus_code / geohash to each candidate.
+    #[serde(default)]
+    codes: bool,
+    /// Containment filter, see region.rs.
+    #[serde(default)]
+    within: Option<String>,
+    /// Viewport filter "minLon,minLat,maxLon,maxLat", see bbox.rs.
+    #[serde(default)]
+    bbox: Option<String>,
+    /// On a miss, accept keys within this many edits (see fuzzy.rs).
+    #[serde(default)]
+    fuzzy: Option<u32>,
+    /// Keep only these feature classes, e.g. "P" or "A,P".
+    #[serde(default)]
+    feature_class: Option<String>,
+    /// Keep only these feature codes, e.g. "PPL,PPLA".
+    #[serde(default)]
+    feature_code: Option<String>,
+    /// "token": on a miss, match single words of multi-word names.
+    #[serde(default)]
+    mode: Option<String>,
+    /// Answer from every match path (aliases, accents, tokens, fuzzy,
+    /// segmentation) instead of the first that finds anything.
+    #[serde(default)]
+    combine: bool,
+    /// Prefer names in this language, e.g. "de" (see langs.rs).
+    #[serde(default)]
+    lang: Option<String>,
+    /// "demote" / "exclude" candidates matched by a historic name (nameflags.rs).
+    #[serde(default)]
+    historic: Option<String>,
+    /// As `historic`, for colloquial names.
+    #[serde(default)]
+    colloquial: Option<String>,
+    /// Article date, YYYY-MM-DD (see periods.rs).
+    #[serde(default)]
+    as_of: Option<String>,
+    /// Round returned coordinates to this many decimals (precision.rs).
+    #[serde(default)]
+    precision: Option<u8>,
+    /// "admin2" / "admin1" / "country": return the containing admin unit's
+    /// point instead of the candidate's.
+    #[serde(default)]
+    snap: Option<String>,
+    /// Extra candidate fields, comma-separated: "hierarchy" (parent chain).
+    #[serde(default)]
+    include: Option<String>,
+    /// Current UTC offset and DST flag per candidate (localtime.rs).
+    #[serde(default)]
+    include_time: bool,
+    /// NDJSON: a head line, then one line per candidate, written as built.
+    #[serde(default)]
+    stream: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchRequest {
+    keys: Vec<String>,
+    /// The remaining fields apply to every key, as on /query.
+    #[serde(default)]
+    limit: Limit,
+    #[serde(default)]
+    offset: Option<usize>,
+    #[serde(default)]
+    near: Option<String>,
+    #[serde(default)]
+    explain: bool,
+    #[serde(default)]
+    codes: bool,
+    #[serde(default)]
+    within: Option<String>,
+    #[serde(default)]
+    bbox: Option<String>,
+    #[serde(default)]
+    fuzzy: Option<u32>,
+    #[serde(default)]
+    feature_class: Option<String>,
+    #[serde(default)]
+    feature_code: Option<String>,
+    #[serde(default)]
+    mode: Option<String>,
+    #[serde(default)]
+    combine: bool,
+    #[serde(default)]
+    lang: Option<String>,
+    #[serde(default)]
+    historic: Option<String>,
+    #[serde(default)]
+    colloquial: Option<String>,
+    #[serde(default)]
+    as_of: Option<String>,
+    #[serde(default)]
+    precision: Option<u8>,
+    #[serde(default)]
+    snap: Option<String>,
+    #[serde(default)]
+    include: Option<String>,
+    #[serde(default)]
+    include_time: bool,
+}
+
+#[derive(Serialize)]
+struct BatchJson {
+    count: usize,
+    /// Results per status, as on /jobs/:id.
+    summary: JobSummary,
+    results: Vec<BatchResult>,
+}
+
+/// One key of a batch, with `status` as on /jobs/:id/results: its /query
+/// answer (not_found when that has no candidates), or why it has none. A
+/// failed key does not fail the others.
+#[derive(Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum BatchResult {
+    Ok(Box<Answer>),
+    NotFound(Box<Answer>),
+    Error { key: String, error: String },
+}
+
+impl BatchResult {
+    fn new(key: String, res: Result<Answer>) -> Self {
+        match res {
+            Ok(out) if out.count == 0 => BatchResult::NotFound(Box::new(out)),
+            Ok(out) => BatchResult::Ok(Box::new(out)),
+            Err(e) => BatchResult::Error {
+                key,
+                error: format!("{e:#}"),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SetRequest {
+    #[serde(default)]
+    all_of: Vec<String>,
+    #[serde(default)]
+    any_of: Vec<String>,
+    /// Candidates returned, best first (default DEFAULT_QUERY_LIMIT, at most
+    /// MAX_LIMIT); `matches` counts all.
+    #[serde(default)]
+    limit: Option<usize>,
+    #[serde(default)]
+    near: Option<String>,
+    #[serde(default)]
+    explain: bool,
+    #[serde(default)]
+    codes: bool,
+}
+
+#[derive(Serialize)]
+struct SetJson {
+    /// Records in the result set, before `limit`.
+    matches: usize,
+    count: usize,
+    candidates: Vec<Candidate>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExtractRequest {
+    text: String,
+    /// Candidates per mention (default EXTRACT_DEFAULT_LIMIT, at most MAX_LIMIT).
+    #[serde(default)]
+    limit: Option<usize>,
+    #[serde(default)]
+    codes: bool,
+}
+
+#[derive(Serialize)]
+struct MentionJson {
+    /// The mention as written; `start`..`end` are byte offsets into `text`.
+    text: String,
+    start: usize,
+    end: usize,
+    key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expanded_from: Option<String>,
+    candidates: Vec<Candidate>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResolveRequest {
+    /// Place mentions of one article, e.g. ["Springfield", "Illinois"].
+    mentions: Vec<String>,
+    /// Candidates per mention (default 1, at most MAX_LIMIT).
+    #[serde(default)]
     limit: Option<usize>,
+    #[serde(default)]
+    codes: bool,
+    #[serde(default)]
+    explain: bool,
+}
+
+#[derive(Serialize)]
+struct ResolvedJson {
+    key: String,
+    count: usize,
+    candidates: Vec<Candidate>,
+    /// Why the mention has no candidates when its lookup failed; the other
+    /// mentions are resolved without it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ResolveJson {
+    count: usize,
+    results: Vec<ResolvedJson>,
 }
 
 #[derive(Serialize)]
-struct OutCandidateOwned {
-    geoname_id: u32,
-    name: String,
-    country: String,
-    admin1: String,
-    admin2: String,
+struct ExtractJson {
+    count: usize,
+    mentions: Vec<MentionJson>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReverseParams {
     lat: f32,
     lon: f32,
-    feature_class: char,
-    feature_code: String,
-    population: u32,
+    #[serde(default)]
+    limit: Option<usize>,
+    #[serde(default)]
+    codes: bool,
+    /// Any feature, not just populated places.
+    #[serde(default)]
+    all: bool,
+    /// Nothing farther than this; by default the search widens until it
+    /// finds a place.
+    #[serde(default)]
+    max_km: Option<f64>,
 }
 
-#[derive(Serialize)]
-struct OutJsonOwned {
+#[derive(Debug, Deserialize)]
+struct SuggestParams {
+    prefix: String,
+    #[serde(default)]
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TransportParams {
     key: String,
-    count: usize,
-    candidates: Vec<OutCandidateOwned>,
+    #[serde(default)]
+    limit: Option<usize>,
+    #[serde(default)]
+    near: Option<String>,
+    #[serde(default)]
+    codes: bool,
+    #[serde(default)]
+    explain: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct H3Params {
+    #[serde(default)]
+    limit: Option<usize>,
+    #[serde(default)]
+    codes: bool,
+}
+
+/// An editor's correction: for `key` (as queried), `chosen` is the right place
+/// and `rejected` the candidates that were wrongly preferred.
+#[derive(Debug, Deserialize)]
+struct FeedbackRequest {
+    key: String,
+    #[serde(default)]
+    article_id: Option<String>,
+    chosen: u32,
+    #[serde(default)]
+    rejected: Vec<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SaveParams {
+    #[serde(default)]
+    save: bool,
 }
 
 #[derive(Serialize)]
@@ -60,82 +829,2060 @@ struct ErrorJson {
     error: String,
 }
 
-struct AppError(anyhow::Error);
+/// A failed request, by whose doing: the client's (400), ours (500), or a
+/// condition worth retrying later (503).
+enum AppError {
+    BadRequest(anyhow::Error),
+    Internal(anyhow::Error),
+    Unavailable(anyhow::Error),
+}
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        let msg = format!("{:#}", self.0);
-        let body = Json(ErrorJson { error: msg });
-
-        // Basic mapping; adjust if you want.
-        let status = StatusCode::BAD_REQUEST;
-        (status, body).into_response()
+        let (status, e) = match self {
+            AppError::BadRequest(e) => (StatusCode::BAD_REQUEST, e),
+            AppError::Internal(e) => {
+                eprintln!("[server] {e:#}");
+                (StatusCode::INTERNAL_SERVER_ERROR, e)
+            }
+            AppError::Unavailable(e) => (StatusCode::SERVICE_UNAVAILABLE, e),
+        };
+        (
+            status,
+            Json(ErrorJson {
+                error: format!("{e:#}"),
+            }),
+        )
+            .into_response()
     }
 }
 
-pub async fn serve(db_path: PathBuf, bind: SocketAddr) -> Result<()> {
-    let db = open_db(&db_path)?;
-    let fst_map = fst::Map::new(db.fst_slice().to_vec()).map_err(|e| anyhow!("fst load: {e}"))?;
+/// Binds the public listener (and [server] admin_bind), over TLS with
+/// [server] tls_cert / tls_key, and serves until SIGTERM / SIGINT; requests
+/// in flight finish first. `smoke_test`: the server queries itself once bound
+/// (smoke.rs) and exits when a canary fails or the duration is up.
+#[allow(clippy::too_many_arguments)]
+pub async fn serve(
+    db_path: Option<PathBuf>,
+    open: OpenOptions,
+    bind: SocketAddr,
+    admin_bind: Option<SocketAddr>,
+    lookup_threads: Option<usize>,
+    throttle: Throttle,
+    smoke_test: Option<Duration>,
+    tls: Option<TlsFiles>,
+    config_path: Option<PathBuf>,
+) -> Result<()> {
+    let started = Instant::now();
+    let config = match &config_path {
+        Some(p) => Config::load(p)?,
+        None => Config::default(),
+    };
+    let script = scripting::load(&config.scripting)?.map(Arc::new);
+    let parts = DbParts::load(db_path.as_deref(), open, script.clone())?;
+    let auditor = audit::start(&config.audit)?;
+    let jobs = JobStore::new(&config.jobs)?;
+    let overlay = match &config.server.overlay {
+        Some(p) => Overlay::load(p)?,
+        None => Overlay::default(),
+    };
+    if !overlay.is_empty() {
+        eprintln!("[serve] overlay entries={}", overlay.len());
+    }
+
+    let db_label = match &db_path {
+        Some(p) => p.display().to_string(),
+        None => "<embedded>".to_string(),
+    };
+    eprintln!("[serve] db={db_label} build={}", parts.build);
 
     let state = AppState {
-        db: Arc::new(db),
-        fst: Arc::new(fst_map),
+        geo: Arc::new(parts.geo),
+        ranking: Arc::new(RwLock::new(config.ranking)),
+        ranking_gen: Arc::new(AtomicU64::new(0)),
+        build: Arc::from(parts.build),
+        config_path: config_path.map(Arc::new),
+        auditor,
+        script,
+        concordance: parts.concordance.map(Arc::new),
+        subdivisions: parts.subdivisions.map(Arc::new),
+        transport: parts.transport.map(Arc::new),
+        overlay: Arc::new(overlay),
+        limits: QueryLimits::new(&config.server),
+        lookups: Arc::new(tokio::sync::Semaphore::new(
+            lookup_threads
+                .or(config.server.lookup_threads)
+                .unwrap_or_else(|| std::thread::available_parallelism().map_or(4, |n| n.get())),
+        )),
+        throttle: throttle.clone(),
+        jobs: Arc::new(jobs),
+        metrics: Arc::new(Metrics::default()),
+        flights: Arc::new(Group::default()),
+        cache: ResponseCache::new(
+            config
+                .server
+                .query_cache_entries
+                .unwrap_or(cache::DEFAULT_ENTRIES),
+        )
+        .map(Arc::new),
+        suggest_cache: ResponseCache::new(
+            config
+                .server
+                .suggest_cache_entries
+                .unwrap_or(cache::DEFAULT_ENTRIES),
+        )
+        .map(Arc::new),
+        db_path: db_path.map(Arc::new),
+        open,
+    };
+    let shared = Shared {
+        current: Arc::new(RwLock::new(state)),
+        reloading: Arc::new(tokio::sync::Mutex::new(())),
+        overlay_path: config.server.overlay.clone().map(Arc::new),
+        reload: Arc::new(config.reload),
+        memory_limit: config.server.memory_limit,
     };
 
-    let app = Router::new()
-        .route("/health", get(health))
-        .route("/query", get(query))
-        .with_state(state);
+    let replays = Replays::new(
+        config
+            .server
+            .idempotency_entries
+            .unwrap_or(idempotency::DEFAULT_ENTRIES),
+        config
+            .server
+            .idempotency_ttl
+            .map_or(idempotency::DEFAULT_TTL, |t| t.0),
+    )
+    .map(Arc::new);
+
+    let admin_bind = admin_bind.or(config.server.admin_bind);
+    let admin = admin_api().route_layer(middleware::from_fn_with_state(
+        config.server.admin_token.as_deref().map(Arc::<str>::from),
+        require_admin_token,
+    ));
+    // admin routes stay on the public port unless they have one of their own
+    let public_admin = admin_bind.is_none().then_some(&admin);
+    let api = Router::new()
+        .nest("/v1", versioned(api_v1(public_admin, &replays), "1"))
+        .merge(api_v1(public_admin, &replays).layer(middleware::from_fn(unversioned)))
+        .layer(middleware::from_fn_with_state(throttle, admit));
+    let app = with_build_header(
+        Router::new()
+            .route("/health", get(health))
+            .route("/metrics", get(get_metrics))
+            .merge(api),
+        &shared,
+    )
+    .with_state(shared.clone())
+    .into_make_service_with_connect_info::<SocketAddr>();
+
+    let (stop_tx, stop) = watch::channel(None);
+    let stop_tx = Arc::new(stop_tx);
+    let on_signal = stop_tx.clone();
+    tokio::spawn(async move {
+        let signal = shutdown_signal().await;
+        eprintln!("[serve] {signal}: no new connections, draining in-flight requests");
+        let _ = on_signal.send(Some((signal, Instant::now())));
+    });
+    let summary = Shutdown {
+        shared: shared.clone(),
+        started,
+        stop: stop.clone(),
+    };
 
+    let tls = match &tls {
+        Some(files) => Some(files.load().await?),
+        None => None,
+    };
     let listener = tokio::net::TcpListener::bind(bind).await?;
-    axum::serve(listener, app).await?;
-    Ok(())
+    let smoke = match smoke_test {
+        Some(after) => Some(spawn_smoke(
+            smoke::loopback(listener.local_addr()?),
+            canary_key(&AppState::from_ref(&shared))?,
+            started + after,
+            stop_tx,
+        )),
+        None => None,
+    };
+    let Some(admin_bind) = admin_bind else {
+        serve_listener(listener, app, tls, stop).await?;
+        summary.log();
+        return smoke_verdict(smoke, &summary.stop).await;
+    };
+    let admin_app = with_build_header(
+        Router::new()
+            .route("/health", get(health))
+            .route("/metrics", get(get_metrics))
+            .nest("/v1", versioned(admin.clone(), "1"))
+            .merge(admin.layer(middleware::from_fn(unversioned))),
+        &shared,
+    )
+    .with_state(shared)
+    .into_make_service_with_connect_info::<SocketAddr>();
+    let admin_listener = tokio::net::TcpListener::bind(admin_bind).await?;
+    eprintln!("[serve] admin endpoints on {admin_bind} only");
+    tokio::try_join!(
+        serve_listener(listener, app, tls.clone(), stop.clone()),
+        serve_listener(admin_listener, admin_app, tls, stop.clone()),
+    )?;
+    summary.log();
+    smoke_verdict(smoke, &summary.stop).await
 }
 
-async fn health() -> impl IntoResponse {
-    (StatusCode::OK, "ok")
+type App = IntoMakeServiceWithConnectInfo<Router, SocketAddr>;
+
+/// Serves `app` on `listener`, over TLS when `tls` is set, until `stop`; then
+/// waits for the connections it has.
+async fn serve_listener(
+    listener: tokio::net::TcpListener,
+    app: App,
+    tls: Option<TlsConfig>,
+    stop: Stop,
+) -> std::io::Result<()> {
+    #[cfg(feature = "tls")]
+    if let Some(config) = tls {
+        let handle = axum_server::Handle::new();
+        let on_stop = handle.clone();
+        tokio::spawn(async move {
+            stopped(stop).await;
+            on_stop.graceful_shutdown(None);
+        });
+        return axum_server::from_tcp_rustls(listener.into_std()?, config)
+            .handle(handle)
+            .serve(app)
+            .await;
+    }
+    #[cfg(not(feature = "tls"))]
+    if let Some(never) = tls {
+        match never {}
+    }
+    axum::serve(listener, app)
+        .with_graceful_shutdown(stopped(stop))
+        .await
 }
 
-async fn query(
-    State(state): State<AppState>,
-    Query(q): Query<QueryParams>,
-) -> Result<impl IntoResponse, AppError> {
-    let lookup_key = q.key.trim().to_lowercase();
-    let limit = q.limit.unwrap_or(0);
+#[cfg(feature = "tls")]
+type TlsConfig = axum_server::tls_rustls::RustlsConfig;
+/// Never constructed: `TlsFiles::load` fails without the tls feature.
+#[cfg(not(feature = "tls"))]
+#[derive(Clone)]
+enum TlsConfig {}
 
-    // Keep allocations tight.
-    let mut candidates: Vec<OutCandidateOwned> = Vec::new();
+/// PEM certificate chain and private key ([server] tls_cert / tls_key,
+/// --tls-cert / --tls-key).
+#[derive(Clone, Debug)]
+pub struct TlsFiles {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
 
-    if let Some(off) = state.fst.get(&lookup_key) {
-        let mut ids = read_postings(&state.db, off as usize).map_err(AppError)?;
+impl TlsFiles {
+    #[cfg(feature = "tls")]
+    async fn load(&self) -> Result<TlsConfig> {
+        let config = TlsConfig::from_pem_file(&self.cert, &self.key)
+            .await
+            .map_err(|e| anyhow!("tls: {} / {}: {e}", self.cert.display(), self.key.display()))?;
+        eprintln!("[serve] tls cert={}", self.cert.display());
+        Ok(config)
+    }
 
-        if limit != 0 && ids.len() > limit {
-            ids.truncate(limit);
-        }
+    #[cfg(not(feature = "tls"))]
+    async fn load(&self) -> Result<TlsConfig> {
+        Err(anyhow!(
+            "tls: {} given, but this binary is built without the tls feature",
+            self.cert.display()
+        ))
+    }
+}
 
-        for id in ids {
-            if let Some(rec) = read_record_by_id(&state.db, id).map_err(AppError)? {
-                candidates.push(OutCandidateOwned {
-                    geoname_id: rec.id,
-                    name: rec.name,
-                    country: rec.country,
-                    admin1: rec.admin1,
-                    admin2: rec.admin2,
-                    lat: rec.lat,
-                    lon: rec.lon,
-                    feature_class: rec.feat_class as char,
-                    feature_code: rec.feat_code,
-                    population: rec.population,
-                });
+/// A name the DB answers, for the smoke test: its first key that is not a
+/// compound "X, Y".
+fn canary_key(state: &AppState) -> Result<String> {
+    let mut keys = state.geo.fst().stream();
+    while let Some((k, _)) = keys.next() {
+        if let Ok(k) = std::str::from_utf8(k) {
+            if !k.is_empty() && !k.contains(',') {
+                return Ok(k.to_string());
             }
         }
     }
+    Err(anyhow!("smoke test: the DB has no keys to query"))
+}
 
-    let out = OutJsonOwned {
-        key: q.key,
+/// Runs the canaries (smoke.rs), then stops the server: at once when one
+/// fails, at `until` when they all pass.
+fn spawn_smoke(
+    addr: SocketAddr,
+    key: String,
+    until: Instant,
+    stop: Arc<watch::Sender<Option<(&'static str, Instant)>>>,
+) -> JoinHandle<Result<()>> {
+    tokio::spawn(async move {
+        let res = smoke::run(addr, &key).await;
+        match &res {
+            Ok(()) => {
+                eprintln!("[smoke] canaries passed; serving until the smoke test ends");
+                tokio::time::sleep_until(until.into()).await;
+            }
+            Err(e) => eprintln!("[smoke] failed: {e:#}"),
+        }
+        let _ = stop.send(Some((SMOKE_TEST, Instant::now())));
+        res
+    })
+}
+
+/// The smoke test's outcome; a signal before it ended counts as a failure.
+async fn smoke_verdict(smoke: Option<JoinHandle<Result<()>>>, stop: &Stop) -> Result<()> {
+    let Some(task) = smoke else {
+        return Ok(());
+    };
+    if !matches!(*stop.borrow(), Some((SMOKE_TEST, _))) {
+        task.abort();
+        return Err(anyhow!("smoke test: interrupted before it ended"));
+    }
+    task.await.map_err(|e| anyhow!("smoke test: {e}"))?
+}
+
+/// SIGINT (Ctrl-C) or, on Unix, SIGTERM; the name is for the log.
+async fn shutdown_signal() -> &'static str {
+    let interrupt = async {
+        match tokio::signal::ctrl_c().await {
+            Ok(()) => "SIGINT",
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut s) => {
+                s.recv().await;
+                "SIGTERM"
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<&'static str>();
+    tokio::select! {
+        s = interrupt => s,
+        s = terminate => s,
+    }
+}
+
+/// Stop reason when the smoke test ends the server.
+const SMOKE_TEST: &str = "smoke test";
+
+/// (signal, when) once a shutdown signal arrived.
+type Stop = watch::Receiver<Option<(&'static str, Instant)>>;
+
+/// Resolves when `stop` is set; axum then stops accepting and waits for the
+/// connections it has.
+async fn stopped(mut stop: Stop) {
+    let _ = stop.wait_for(|s| s.is_some()).await;
+}
+
+/// What `serve` logs once every listener has drained.
+struct Shutdown {
+    shared: Shared,
+    started: Instant,
+    stop: Stop,
+}
+
+impl Shutdown {
+    fn log(&self) {
+        let state = AppState::from_ref(&self.shared);
+        let uptime = Duration::from_secs(self.started.elapsed().as_secs());
+        let drain = match *self.stop.borrow() {
+            Some((signal, at)) => format!("{signal}, drained in {}ms", at.elapsed().as_millis()),
+            None => "listener closed".to_string(),
+        };
+        eprintln!(
+            "[serve] shutdown ({drain}): up {}, lookups={} throttled={} jobs_running={} build={}",
+            humantime::format_duration(uptime),
+            state.metrics.lookups(),
+            state.throttle.rate_limited() + state.throttle.busy(),
+            state.jobs.running(),
+            state.build
+        );
+    }
+}
+
+/// Every response carries the build it was answered from.
+fn with_build_header(router: Router<Shared>, shared: &Shared) -> Router<Shared> {
+    let current = shared.clone();
+    router.layer(middleware::map_response(move |mut res: Response| {
+        let build = AppState::from_ref(&current).build;
+        async move {
+            if let Ok(v) = HeaderValue::from_str(&build) {
+                res.headers_mut().insert(X_GEODB_BUILD, v);
+            }
+            res
+        }
+    }))
+}
+
+/// Every route except /health; served under /v1 and, deprecated, unversioned.
+/// `admin` is merged in when admin routes share the public port.
+fn api_v1(admin: Option<&Router<Shared>>, replays: &Option<Arc<Replays>>) -> Router<Shared> {
+    let idempotent = || middleware::from_fn_with_state(replays.clone(), idempotent);
+    let api = Router::new()
+        .route("/query", get(query))
+        .route("/query/batch", post(query_batch).layer(idempotent()))
+        .route("/query/set", post(query_set))
+        .route("/extract", post(post_extract))
+        .route("/resolve", post(post_resolve))
+        .route("/reverse", get(get_reverse))
+        .route("/suggest", get(get_suggest))
+        .route("/concordance/:id", get(get_concordance))
+        .route("/subdivision/:code", get(get_subdivision))
+        .route("/country/:iso", get(get_country))
+        .route("/transport", get(get_transport))
+        .route("/h3/:cell/places", get(get_h3_places))
+        .route("/tiles/:z/:x/:y", get(get_tile))
+        .route(
+            "/jobs/geocode",
+            // outside `idempotent`, which reads the body under this limit
+            post(create_job)
+                .layer::<_, Infallible>(idempotent())
+                .layer(DefaultBodyLimit::max(JOB_BODY_LIMIT)),
+        )
+        .route("/jobs/:id", get(get_job))
+        .route("/jobs/:id/results", get(get_job_results))
+        .route("/feedback", post(post_feedback));
+    match admin {
+        Some(admin) => api.merge(admin.clone()),
+        None => api,
+    }
+}
+
+/// /admin/*: on the public port, or alone on [server] admin_bind.
+fn admin_api() -> Router<Shared> {
+    Router::new()
+        .route("/admin/ranking", get(get_ranking).put(put_ranking))
+        .route("/admin/overlay", get(get_overlay).put(put_overlay))
+        .route("/admin/overlay/:name", delete(delete_overlay))
+        .route("/admin/reload", post(post_reload))
+}
+
+/// 401 unless the request carries `Authorization: Bearer <admin_token>`;
+/// passes everything when no token is configured.
+async fn require_admin_token(
+    State(token): State<Option<Arc<str>>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    if let Some(token) = token {
+        let given = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        if !given.is_some_and(|g| same_secret(g.trim().as_bytes(), token.as_bytes())) {
+            return (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, "Bearer")],
+                Json(ErrorJson {
+                    error: "admin token required".into(),
+                }),
+            )
+                .into_response();
+        }
+    }
+    next.run(req).await
+}
+
+/// 429 / 503 with a JSON error when the throttle turns the request away.
+async fn admit(
+    State(throttle): State<Throttle>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    req: Request,
+    next: Next,
+) -> Response {
+    match throttle.admit(peer.ip()) {
+        Ok(_permit) => next.run(req).await,
+        Err(Refusal::Rate(wait)) => (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, wait.as_secs().max(1).to_string())],
+            Json(ErrorJson {
+                error: "rate limit exceeded".into(),
+            }),
+        )
+            .into_response(),
+        Err(Refusal::Busy) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [(header::RETRY_AFTER, "1".to_string())],
+            Json(ErrorJson {
+                error: "server busy".into(),
+            }),
+        )
+            .into_response(),
+    }
+}
+
+const IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+const IDEMPOTENT_REPLAYED: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+/// Requests with an Idempotency-Key run once; retries get the first 2xx
+/// response again, marked `Idempotent-Replayed: true` (idempotency.rs).
+async fn idempotent(
+    State(replays): State<Option<Arc<Replays>>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let (Some(replays), Some(key)) = (replays, req.headers().get(&IDEMPOTENCY_KEY)) else {
+        return next.run(req).await;
+    };
+    let key = match key.to_str() {
+        Ok(k) if !k.is_empty() && k.len() <= idempotency::MAX_KEY_LEN => k.to_string(),
+        _ => {
+            return AppError::BadRequest(anyhow!(
+                "Idempotency-Key must be 1-{} visible ASCII characters",
+                idempotency::MAX_KEY_LEN
+            ))
+            .into_response()
+        }
+    };
+    let (parts, body) = req.into_parts();
+    // Bytes honours the route's DefaultBodyLimit
+    let body = match Bytes::from_request(Request::from_parts(parts.clone(), body), &()).await {
+        Ok(b) => b,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let pending = match replays.claim(format!("{} {key}", parts.uri.path()), &body) {
+        Claim::Fresh(p) => p,
+        Claim::Replay(stored) => {
+            let mut res = (stored.status, stored.headers, stored.body).into_response();
+            res.headers_mut()
+                .insert(IDEMPOTENT_REPLAYED, HeaderValue::from_static("true"));
+            return res;
+        }
+        Claim::InFlight => {
+            return (
+                StatusCode::CONFLICT,
+                Json(ErrorJson {
+                    error: "a request with this Idempotency-Key is still running".into(),
+                }),
+            )
+                .into_response()
+        }
+        Claim::Mismatch => {
+            return (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Json(ErrorJson {
+                    error: "Idempotency-Key already used with a different body".into(),
+                }),
+            )
+                .into_response()
+        }
+    };
+
+    let res = next.run(Request::from_parts(parts, Body::from(body))).await;
+    if !res.status().is_success() {
+        return res;
+    }
+    let (parts, body) = res.into_parts();
+    let body = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(b) => b,
+        Err(e) => return AppError::Internal(anyhow!("buffering response: {e}")).into_response(),
+    };
+    pending.finish(Stored {
+        status: parts.status,
+        headers: parts.headers.clone(),
+        body: body.clone(),
+    });
+    Response::from_parts(parts, Body::from(body))
+}
+
+/// Comparison that takes as long for a near miss as for a wrong first byte.
+fn same_secret(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/* -------------------------
+   API versions
+-------------------------- */
+
+const X_GEODB_API_VERSION: HeaderName = HeaderName::from_static("x-geodb-api-version");
+const DEPRECATION: HeaderName = HeaderName::from_static("deprecation");
+const SUNSET: HeaderName = HeaderName::from_static("sunset");
+
+/// Version unversioned paths answer as.
+const DEFAULT_API_VERSION: &str = "1";
+
+/// Deprecated versions and their Sunset date (HTTP-date). Listing a version
+/// here makes every response under /v<version> announce it.
+const DEPRECATED_API_VERSIONS: &[(&str, &str)] = &[];
+
+/// Tags responses with the version served, plus Deprecation / Sunset when the
+/// version is on its way out.
+fn versioned(api: Router<Shared>, version: &'static str) -> Router<Shared> {
+    let sunset = DEPRECATED_API_VERSIONS
+        .iter()
+        .find(|(v, _)| *v == version)
+        .map(|(_, date)| HeaderValue::from_static(date));
+    api.layer(middleware::map_response(move |mut res: Response| {
+        let sunset = sunset.clone();
+        async move {
+            let h = res.headers_mut();
+            h.insert(X_GEODB_API_VERSION, HeaderValue::from_static(version));
+            if let Some(date) = sunset {
+                h.insert(DEPRECATION, HeaderValue::from_static("true"));
+                h.insert(SUNSET, date);
+            }
+            res
+        }
+    }))
+}
+
+/// Unversioned paths: the pre-/v1 API, kept as an alias of
+/// DEFAULT_API_VERSION while existing consumers move over. A client may pin
+/// the version with `x-geodb-api-version`; anything not served here is a 406.
+/// Responses are marked deprecated and link their /v1 successor.
+async fn unversioned(req: Request, next: Next) -> Response {
+    if let Some(asked) = req.headers().get(X_GEODB_API_VERSION) {
+        if asked.as_bytes() != DEFAULT_API_VERSION.as_bytes() {
+            return (
+                StatusCode::NOT_ACCEPTABLE,
+                Json(ErrorJson {
+                    error: format!(
+                        "API version {} not served here (unversioned = v{DEFAULT_API_VERSION})",
+                        String::from_utf8_lossy(asked.as_bytes())
+                    ),
+                }),
+            )
+                .into_response();
+        }
+    }
+    let successor = HeaderValue::from_str(&format!(
+        "</v{DEFAULT_API_VERSION}{}>; rel=\"successor-version\"",
+        req.uri().path()
+    ));
+
+    let mut res = next.run(req).await;
+    let h = res.headers_mut();
+    h.insert(
+        X_GEODB_API_VERSION,
+        HeaderValue::from_static(DEFAULT_API_VERSION),
+    );
+    h.insert(DEPRECATION, HeaderValue::from_static("true"));
+    if let Ok(link) = successor {
+        h.insert(header::LINK, link);
+    }
+    res
+}
+
+async fn health() -> impl IntoResponse {
+    (StatusCode::OK, "ok")
+}
+
+/// GET /metrics: lookup latency histograms by key length, postings and
+/// result count, plus cache, throttle and lookup-slot gauges (Prometheus text).
+async fn get_metrics(State(state): State<AppState>) -> impl IntoResponse {
+    let mut out = state.metrics.render();
+    out.push_str(
+        "# HELP geodb_query_coalesced_total /query requests answered by an identical one in flight.\n",
+    );
+    out.push_str("# TYPE geodb_query_coalesced_total counter\n");
+    out.push_str(&format!(
+        "geodb_query_coalesced_total {}\n",
+        state.flights.coalesced()
+    ));
+    out.push_str(
+        "# HELP geodb_lookup_slots_free Lookup slots ([server] lookup_threads) not in use.\n",
+    );
+    out.push_str("# TYPE geodb_lookup_slots_free gauge\n");
+    out.push_str(&format!(
+        "geodb_lookup_slots_free {}\n",
+        state.lookups.available_permits()
+    ));
+    out.push_str(
+        "# HELP geodb_query_unlimited_default_total /query and /query/batch requests \
+         answered unlimited for lack of a limit ([server] unlimited_default).\n",
+    );
+    out.push_str("# TYPE geodb_query_unlimited_default_total counter\n");
+    out.push_str(&format!(
+        "geodb_query_unlimited_default_total {}\n",
+        state.limits.legacy.load(Ordering::Relaxed)
+    ));
+    out.push_str(
+        "# HELP geodb_throttled_total API requests turned away: rate (429, per-IP \
+         [server] rate_limit) or busy (503, [server] max_in_flight).\n",
+    );
+    out.push_str("# TYPE geodb_throttled_total counter\n");
+    out.push_str(&format!(
+        "geodb_throttled_total{{reason=\"rate\"}} {}\n",
+        state.throttle.rate_limited()
+    ));
+    out.push_str(&format!(
+        "geodb_throttled_total{{reason=\"busy\"}} {}\n",
+        state.throttle.busy()
+    ));
+    if let Some(cache) = &state.cache {
+        out.push_str("# HELP geodb_query_cache_hits_total /query responses served from the LRU.\n");
+        out.push_str("# TYPE geodb_query_cache_hits_total counter\n");
+        out.push_str(&format!("geodb_query_cache_hits_total {}\n", cache.hits()));
+        out.push_str("# HELP geodb_query_cache_misses_total /query lookups not in the LRU.\n");
+        out.push_str("# TYPE geodb_query_cache_misses_total counter\n");
+        out.push_str(&format!(
+            "geodb_query_cache_misses_total {}\n",
+            cache.misses()
+        ));
+    }
+    if let Some(cache) = &state.suggest_cache {
+        out.push_str(
+            "# HELP geodb_suggest_cache_hits_total /suggest responses served from the cache.\n",
+        );
+        out.push_str("# TYPE geodb_suggest_cache_hits_total counter\n");
+        out.push_str(&format!(
+            "geodb_suggest_cache_hits_total {}\n",
+            cache.hits()
+        ));
+        out.push_str(
+            "# HELP geodb_suggest_cache_misses_total /suggest lookups not in the cache.\n",
+        );
+        out.push_str("# TYPE geodb_suggest_cache_misses_total counter\n");
+        out.push_str(&format!(
+            "geodb_suggest_cache_misses_total {}\n",
+            cache.misses()
+        ));
+    }
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], out)
+}
+
+/// GET /query?key=..: ranked candidates for a name; `QueryParams` lists the
+/// options. Coordinate-like keys ("48.2082, 16.3738", DMS, geo: URIs, plus
+/// codes, "geohash:..") are reverse geocoded instead, like /reverse: populated
+/// places unless feature_class / feature_code are given, and
+/// reverse::DEFAULT_LIMIT of them without a limit. Identical concurrent
+/// queries share one lookup (singleflight.rs) and hot keys are served from
+/// [server] query_cache_entries; the ETag covers the build, the query string
+/// and the ranking / overlay generation, except with include_time=true.
+async fn query(
+    State(state): State<AppState>,
+    RawQuery(raw): RawQuery,
+    headers: HeaderMap,
+    Query(q): Query<QueryParams>,
+) -> Result<Response, AppError> {
+    let etag = state.etag(raw.as_deref().unwrap_or(""));
+    // offsets change at DST transitions: neither 304s nor cache hits
+    let cache = state.cache.as_ref().filter(|_| !q.include_time);
+    if !q.include_time && etag_matches(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response());
+    }
+
+    let opts = AnswerOptions {
+        limit: q.limit,
+        default_limit: state.limits.default_for(q.limit, q.stream)?,
+        lookup: LookupOptions {
+            offset: q.offset.unwrap_or(0),
+            focus: parse_near(q.near.as_deref())?,
+            within: parse_within(&state, q.within.as_deref())?,
+            bbox: parse_bbox(q.bbox.as_deref())?,
+            features: parse_features(q.feature_class.as_deref(), q.feature_code.as_deref())?,
+            codes: q.codes,
+            explain: q.explain,
+            fuzzy: q.fuzzy,
+            tokens: parse_mode(q.mode.as_deref())?,
+            combine: q.combine,
+            lang: parse_lang(q.lang.as_deref())?,
+            historic: parse_name_use("historic", q.historic.as_deref())?,
+            colloquial: parse_name_use("colloquial", q.colloquial.as_deref())?,
+            as_of: parse_as_of(q.as_of.as_deref())?,
+            precision: parse_precision(q.precision, q.snap.as_deref())?,
+            hierarchy: parse_include(q.include.as_deref())?,
+            now: q.include_time.then(Utc::now),
+            ..state.lookup_options()
+        },
+    };
+    let warning = state.limits.warning(q.limit);
+    if q.stream {
+        let res = stream_answer(state.clone(), etag, q.key.clone(), opts)
+            .await
+            .map_err(AppError::Internal)?;
+        return Ok(with_warning(res, warning));
+    }
+    // identical queries in flight share one lookup (singleflight.rs)
+    let flight = state.flight_key(&q);
+    if let Some(body) = cache.and_then(|c| c.get(&flight)) {
+        return Ok(with_warning(json_response(etag, body), warning));
+    }
+    let key = q.key.clone();
+    let body = state
+        .flights
+        .run(flight.clone(), || {
+            let state = state.clone();
+            async move {
+                state
+                    .offload(move |s| {
+                        let out = answer(&s, key, &opts)?;
+                        Ok(Bytes::from(serde_json::to_vec(&out)?))
+                    })
+                    .await
+                    .map_err(|e| Arc::<str>::from(format!("{e:#}")))
+            }
+        })
+        .await
+        .map_err(|e| AppError::Internal(anyhow!("{e}")))?;
+    if let Some(cache) = cache {
+        cache.put(flight, body.clone());
+    }
+    Ok(with_warning(json_response(etag, body), warning))
+}
+
+/// Lines queued ahead of a slow client; the builder waits beyond that.
+const STREAM_BUFFER: usize = 64;
+
+/// The first line of a streamed /query: the response without `candidates`.
+#[derive(Serialize)]
+struct StreamHeadJson<'a> {
+    key: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    segmented: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expanded_from: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    coordinates: Option<&'a Coordinate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    matched: Option<&'static str>,
+    count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ranking: Option<&'a RankingWeights>,
+}
+
+impl<'a> From<&'a Answer> for StreamHeadJson<'a> {
+    fn from(out: &'a Answer) -> Self {
+        Self {
+            key: &out.key,
+            segmented: out.segmented.as_deref(),
+            expanded_from: out.expanded_from.as_deref(),
+            coordinates: out.coordinates.as_ref(),
+            matched: out.matched,
+            count: out.count,
+            total: out.total,
+            ranking: out.ranking.as_ref(),
+        }
+    }
+}
+
+fn ndjson_line(value: &impl Serialize) -> serde_json::Result<Bytes> {
+    let mut line = serde_json::to_vec(value)?;
+    line.push(b'\n');
+    Ok(Bytes::from(line))
+}
+
+/// stream=true: lookup and ranking run as for a buffered /query, then
+/// candidates are built and written one line at a time on a blocking thread,
+/// so `limit=all` on a key with thousands of candidates never holds the whole
+/// body. Not coalesced or cached. An error mid-stream aborts the body: the
+/// client sees fewer than `count` lines.
+async fn stream_answer(
+    state: AppState,
+    etag: String,
+    key: String,
+    opts: AnswerOptions,
+) -> Result<Response> {
+    type Rows = Box<dyn Iterator<Item = Result<Candidate>> + Send>;
+    let (head, rows) = state
+        .offload(move |state| -> Result<(Answer, Rows)> {
+            if coords::parse(&key).is_some() {
+                // reverse answers are bounded by the page size anyway
+                let mut out = answer(&state, key, &opts)?;
+                let candidates = std::mem::take(&mut out.candidates);
+                return Ok((out, Box::new(candidates.into_iter().map(Ok))));
+            }
+            let opts = opts.for_key(&key);
+            let Ranked {
+                answer: head,
+                page,
+                edits,
+                ..
+            } = rank(&state, &key, &opts)?;
+            let rows = page
+                .into_iter()
+                .map(move |(rec, score)| state.geo.scored_candidate(rec, score, &edits, &opts));
+            Ok((head, Box::new(rows)))
+        })
+        .await?;
+
+    let (tx, rx) = tokio::sync::mpsc::channel::<std::io::Result<Bytes>>(STREAM_BUFFER);
+    tokio::task::spawn_blocking(move || {
+        let first = ndjson_line(&StreamHeadJson::from(&head)).map_err(std::io::Error::other);
+        if tx.blocking_send(first).is_err() {
+            return;
+        }
+        for row in rows {
+            let line = row
+                .and_then(|c| ndjson_line(&c).map_err(Into::into))
+                .map_err(|e| std::io::Error::other(format!("{e:#}")));
+            let failed = line.is_err();
+            if let Err(e) = &line {
+                eprintln!("[query] stream {:?}: {e}", head.key);
+            }
+            // a send error means the client went away
+            if tx.blocking_send(line).is_err() || failed {
+                return;
+            }
+        }
+    });
+
+    Ok((
+        StatusCode::OK,
+        [
+            (header::ETAG, etag),
+            (header::CONTENT_TYPE, "application/x-ndjson".to_string()),
+        ],
+        Body::from_stream(tokio_stream::wrappers::ReceiverStream::new(rx)),
+    )
+        .into_response())
+}
+
+fn json_response(etag: String, body: Bytes) -> Response {
+    (
+        StatusCode::OK,
+        [
+            (header::ETAG, etag),
+            (header::CONTENT_TYPE, "application/json".to_string()),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+fn parse_within(state: &AppState, within: Option<&str>) -> Result<Option<Region>, AppError> {
+    within
+        .map(|w| Region::parse(w, state.geo.db()))
+        .transpose()
+        .map_err(|e| AppError::BadRequest(e.context("within")))
+}
+
+fn parse_bbox(bbox: Option<&str>) -> Result<Option<BBox>, AppError> {
+    bbox.map(BBox::parse)
+        .transpose()
+        .map_err(|e| AppError::BadRequest(e.context("bbox")))
+}
+
+/// Empty (admits everything) when neither parameter lists anything.
+fn parse_features(classes: Option<&str>, codes: Option<&str>) -> Result<FeatureFilter, AppError> {
+    let split = |s: Option<&str>| -> Vec<String> {
+        s.map(|s| s.split(',').map(str::to_string).collect())
+            .unwrap_or_default()
+    };
+    FeatureFilter::new(&split(classes), &split(codes))
+        .map_err(|e| AppError::BadRequest(e.context("feature_class")))
+}
+
+/// `mode=token` enables token matches; the default is whole names only.
+fn parse_mode(mode: Option<&str>) -> Result<bool, AppError> {
+    match mode {
+        None | Some("") | Some("name") => Ok(false),
+        Some("token") => Ok(true),
+        Some(m) => Err(AppError::BadRequest(anyhow!(
+            "mode: expected name or token, got {m:?}"
+        ))),
+    }
+}
+
+fn parse_lang(lang: Option<&str>) -> Result<Option<String>, AppError> {
+    lang.map(langs::parse)
+        .transpose()
+        .map_err(|e| AppError::BadRequest(e.context("lang")))
+}
+
+fn parse_name_use(param: &str, value: Option<&str>) -> Result<NameUse, AppError> {
+    NameUse::parse(value.unwrap_or(""))
+        .map_err(|e| AppError::BadRequest(e.context(param.to_string())))
+}
+
+fn parse_as_of(as_of: Option<&str>) -> Result<Option<i32>, AppError> {
+    as_of
+        .map(periods::parse_as_of)
+        .transpose()
+        .map_err(|e| AppError::BadRequest(e.context("as_of")))
+}
+
+fn parse_precision(decimals: Option<u8>, snap: Option<&str>) -> Result<Precision, AppError> {
+    Precision::parse(decimals, snap).map_err(AppError::BadRequest)
+}
+
+/// include=: comma-separated extras; "hierarchy" is the only one so far.
+fn parse_include(include: Option<&str>) -> Result<bool, AppError> {
+    let mut hierarchy = false;
+    for part in include.unwrap_or("").split(',').map(str::trim) {
+        match part {
+            "" => {}
+            "hierarchy" => hierarchy = true,
+            other => {
+                return Err(AppError::BadRequest(anyhow!(
+                    "include: unknown {other:?} (hierarchy)"
+                )))
+            }
+        }
+    }
+    Ok(hierarchy)
+}
+
+fn parse_near(near: Option<&str>) -> Result<Option<(f32, f32)>, AppError> {
+    near.map(ranking::parse_focus)
+        .transpose()
+        .map_err(|e| AppError::BadRequest(anyhow!("near: {e}")))
+}
+
+/// Settings shared by /query and /query/batch; owned, so lookups can move
+/// to the blocking pool (`AppState::offload`).
+struct AnswerOptions {
+    limit: Limit,
+    /// What `Limit::Default` means for name keys; None = every candidate.
+    default_limit: Option<usize>,
+    /// Everything but the limit.
+    lookup: LookupOptions,
+}
+
+impl AnswerOptions {
+    /// The lookup options for `key`; `Limit::Default` only applies the
+    /// server default to name keys: coordinate keys get 0, which
+    /// `Geocoder::reverse` reads as reverse::DEFAULT_LIMIT.
+    fn for_key(&self, key: &str) -> LookupOptions {
+        let limit = match self.limit {
+            Limit::Count(n) => n.min(MAX_LIMIT),
+            // streamed: `QueryLimits::default_for` refuses it otherwise
+            Limit::All => 0,
+            Limit::Default if coords::parse(key).is_some() => 0,
+            Limit::Default => self.default_limit.unwrap_or(0),
+        };
+        LookupOptions {
+            limit,
+            ..self.lookup.clone()
+        }
+    }
+}
+
+/// The /query response for one key: reverse geocoding for coordinate keys,
+/// otherwise lookup + ranking.
+fn answer(state: &AppState, key: String, opts: &AnswerOptions) -> Result<Answer> {
+    let opts = opts.for_key(&key);
+    if coords::parse(&key).is_some() {
+        return state.geo.lookup(&key, &opts);
+    }
+    let ranked = rank(state, &key, &opts)?;
+    if let Some(a) = state.auditor.as_ref().filter(|a| a.should_sample()) {
+        let total = ranked.answer.total.unwrap_or(0);
+        a.record(AuditRecord::new(&ranked.matched_key, total, ranked.best));
+    }
+    state.geo.answer(ranked, &opts)
+}
+
+/// `Geocoder::rank`, timed for /metrics.
+fn rank(state: &AppState, key: &str, opts: &LookupOptions) -> Result<Ranked> {
+    let t = Instant::now();
+    let ranked = state.geo.rank(key, opts)?;
+    state.metrics.observe_lookup(
+        &ranked.matched_key,
+        ranked.postings,
+        ranked.answer.total.unwrap_or(0),
+        t.elapsed(),
+    );
+    Ok(ranked)
+}
+
+/// POST /query/batch: every key is answered as by /query with the same
+/// options; results keep the order of `keys`, each with a `status` (ok /
+/// not_found / error) and `summary` counts them, as for background jobs.
+/// Retries with the same Idempotency-Key are answered from `idempotent`.
+async fn query_batch(
+    State(state): State<AppState>,
+    Json(req): Json<BatchRequest>,
+) -> Result<Response, AppError> {
+    if req.keys.len() > BATCH_MAX_KEYS {
+        return Err(AppError::BadRequest(anyhow!(
+            "{} keys; at most {BATCH_MAX_KEYS} per batch (use /jobs/geocode for more)",
+            req.keys.len()
+        )));
+    }
+    let opts = AnswerOptions {
+        limit: req.limit,
+        default_limit: state.limits.default_for(req.limit, false)?,
+        lookup: LookupOptions {
+            offset: req.offset.unwrap_or(0),
+            focus: parse_near(req.near.as_deref())?,
+            within: parse_within(&state, req.within.as_deref())?,
+            bbox: parse_bbox(req.bbox.as_deref())?,
+            features: parse_features(req.feature_class.as_deref(), req.feature_code.as_deref())?,
+            codes: req.codes,
+            explain: req.explain,
+            fuzzy: req.fuzzy,
+            tokens: parse_mode(req.mode.as_deref())?,
+            combine: req.combine,
+            lang: parse_lang(req.lang.as_deref())?,
+            historic: parse_name_use("historic", req.historic.as_deref())?,
+            colloquial: parse_name_use("colloquial", req.colloquial.as_deref())?,
+            as_of: parse_as_of(req.as_of.as_deref())?,
+            precision: parse_precision(req.precision, req.snap.as_deref())?,
+            hierarchy: parse_include(req.include.as_deref())?,
+            now: req.include_time.then(Utc::now),
+            ..state.lookup_options()
+        },
+    };
+    let warning = state.limits.warning(req.limit);
+
+    let keys = req.keys;
+    let results = state
+        .offload(move |state| {
+            Ok(keys
+                .into_iter()
+                .map(|key| BatchResult::new(key.clone(), answer(&state, key, &opts)))
+                .collect::<Vec<_>>())
+        })
+        .await
+        .map_err(AppError::Internal)?;
+
+    let mut summary = JobSummary::default();
+    for r in &results {
+        match r {
+            BatchResult::Ok(_) => summary.ok += 1,
+            BatchResult::NotFound(_) => summary.not_found += 1,
+            BatchResult::Error { .. } => summary.error += 1,
+        }
+    }
+    let res = (
+        StatusCode::OK,
+        Json(BatchJson {
+            count: results.len(),
+            summary,
+            results,
+        }),
+    )
+        .into_response();
+    Ok(with_warning(res, warning))
+}
+
+/// POST /query/set: records matching every `all_of` key and at least one
+/// `any_of` key (sets.rs), ranked as /query ranks candidates.
+async fn query_set(
+    State(state): State<AppState>,
+    Json(req): Json<SetRequest>,
+) -> Result<Response, AppError> {
+    let keys = req.all_of.len() + req.any_of.len();
+    if keys == 0 {
+        return Err(AppError::BadRequest(anyhow!(
+            "all_of and any_of are both empty"
+        )));
+    }
+    if keys > BATCH_MAX_KEYS {
+        return Err(AppError::BadRequest(anyhow!(
+            "{keys} keys; at most {BATCH_MAX_KEYS} per set query"
+        )));
+    }
+    let opts = LookupOptions {
+        focus: parse_near(req.near.as_deref())?,
+        codes: req.codes,
+        explain: req.explain,
+        ..state.lookup_options()
+    };
+
+    let json = state
+        .offload(move |state| -> Result<SetJson> {
+            let ids = sets::evaluate(&state.geo.index(&opts), &req.all_of, &req.any_of)?;
+            let mut records = Vec::with_capacity(ids.len());
+            for id in &ids {
+                records.extend(read_record_by_id(state.geo.db(), *id)?);
+            }
+            let ranker = state.geo.ranker(&opts);
+            let key = req.all_of.iter().chain(&req.any_of).next();
+            let key = casefold::fold(key.map_or("", |k| k.trim()));
+            let mut ranked = ranker.score(&key, records, &[]);
+            ranked.truncate(clamp_limit(req.limit, DEFAULT_QUERY_LIMIT));
+            let candidates: Vec<Candidate> = ranked
+                .into_iter()
+                .map(|(rec, score)| {
+                    let c = state.geo.candidate(rec, &opts)?;
+                    Ok(c.with_score(score, opts.explain))
+                })
+                .collect::<Result<_>>()?;
+            Ok(SetJson {
+                matches: ids.len(),
+                count: candidates.len(),
+                candidates,
+            })
+        })
+        .await
+        .map_err(AppError::Internal)?;
+    Ok((StatusCode::OK, Json(json)).into_response())
+}
+
+/// GET /reverse?lat=..&lon=..: nearest populated places (all=true for any
+/// feature), from the grid coordinate keys on /query use. The search widens
+/// until it finds them unless max_km bounds it.
+async fn get_reverse(
+    State(state): State<AppState>,
+    Query(p): Query<ReverseParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let at = Coordinate {
+        lat: p.lat,
+        lon: p.lon,
+    };
+    if !at.in_range() {
+        return Err(AppError::BadRequest(anyhow!(
+            "coordinates out of range: {},{}",
+            at.lat,
+            at.lon
+        )));
+    }
+    if p.max_km.is_some_and(|km| km.is_nan() || km <= 0.0) {
+        return Err(AppError::BadRequest(anyhow!("max_km must be positive")));
+    }
+    let opts = LookupOptions {
+        limit: clamp_limit(p.limit, reverse::DEFAULT_LIMIT),
+        codes: p.codes,
+        max_km: p.max_km,
+        features: if p.all {
+            FeatureFilter::default()
+        } else {
+            reverse::populated()
+        },
+        ..state.lookup_options()
+    };
+    let out = state
+        .offload(move |state| state.geo.reverse(at, &opts))
+        .await
+        .map_err(AppError::Internal)?;
+    Ok(Json(out))
+}
+
+/* -------------------------
+   background jobs
+-------------------------- */
+
+/// POST /jobs/geocode: a key list too large for /query/batch, geocoded in the
+/// background (jobs.rs); 503 while [jobs] max_active jobs are running. A retry
+/// with the same Idempotency-Key gets the first job back (`idempotent`).
+async fn create_job(
+    State(state): State<AppState>,
+    Json(req): Json<GeocodeJobRequest>,
+) -> Result<Response, AppError> {
+    let worker = state.clone();
+    let job = state
+        .jobs
+        .spawn(req, move |key, limit| {
+            let opts = LookupOptions {
+                limit: limit.min(MAX_LIMIT),
+                ..worker.lookup_options()
+            };
+            let ranked = rank(&worker, key, &opts)?;
+            if ranked.page.is_empty() {
+                return Ok(None);
+            }
+            Ok(Some(worker.geo.answer(ranked, &opts)?.candidates))
+        })
+        .map_err(|e| {
+            if e.is::<jobs::Busy>() {
+                AppError::Unavailable(e)
+            } else {
+                AppError::BadRequest(e)
+            }
+        })?;
+    Ok((StatusCode::ACCEPTED, Json(job.view())).into_response())
+}
+
+fn job_not_found(id: &str) -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(ErrorJson {
+            error: format!("no job {id}"),
+        }),
+    )
+        .into_response()
+}
+
+async fn get_job(State(state): State<AppState>, Path(id): Path<String>) -> Response {
+    match state.jobs.get(&id) {
+        Some(job) => (StatusCode::OK, Json(job.view())).into_response(),
+        None => job_not_found(&id),
+    }
+}
+
+/// GET /jobs/:id/results: one NDJSON line per key, once the job is done.
+async fn get_job_results(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    let Some(job) = state.jobs.get(&id) else {
+        return Ok(job_not_found(&id));
+    };
+    if job.status() != JobStatus::Done {
+        return Ok((
+            StatusCode::CONFLICT,
+            Json(ErrorJson {
+                error: format!("job {id} is not done"),
+            }),
+        )
+            .into_response());
+    }
+    // streamed: a large job's results never sit in memory whole
+    let file = tokio::fs::File::open(job.results_path())
+        .await
+        .map_err(|e| AppError::Internal(e.into()))?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(tokio_util::io::ReaderStream::new(file)),
+    )
+        .into_response())
+}
+
+/* -------------------------
+   toponym extraction
+-------------------------- */
+
+const EXTRACT_DEFAULT_LIMIT: usize = 3;
+const EXTRACT_MAX_BYTES: usize = 1 << 20;
+
+/// POST /extract {"text": ..}: place mentions in article text, hashtags and
+/// emoji flags included, with byte offsets and ranked candidates (gazetteer
+/// longest match, extract.rs).
+async fn post_extract(
+    State(state): State<AppState>,
+    Json(req): Json<ExtractRequest>,
+) -> Result<Response, AppError> {
+    if req.text.len() > EXTRACT_MAX_BYTES {
+        return Err(AppError::BadRequest(anyhow!(
+            "text is {} bytes; at most {EXTRACT_MAX_BYTES}",
+            req.text.len()
+        )));
+    }
+    let json = state
+        .offload(move |state| -> Result<ExtractJson> {
+            let opts = LookupOptions {
+                codes: req.codes,
+                ..state.lookup_options()
+            };
+            let limit = clamp_limit(req.limit, EXTRACT_DEFAULT_LIMIT);
+            let found = extract::extract(
+                &state.geo.index(&opts),
+                &extract::pipeline(&state.geo.ranker(&opts)),
+                state.geo.countries(),
+                &req.text,
+                limit,
+            )?;
+            let mentions: Vec<MentionJson> = found
+                .into_iter()
+                .map(|m| {
+                    Ok(MentionJson {
+                        text: req.text[m.start..m.end].to_string(),
+                        start: m.start,
+                        end: m.end,
+                        expanded_from: m.expanded.then(|| m.key.clone()),
+                        key: m.key,
+                        candidates: m
+                            .ranked
+                            .into_iter()
+                            .map(|(rec, score)| {
+                                Ok(state.geo.candidate(rec, &opts)?.with_score(score, false))
+                            })
+                            .collect::<Result<_>>()?,
+                    })
+                })
+                .collect::<Result<_>>()?;
+            Ok(ExtractJson {
+                count: mentions.len(),
+                mentions,
+            })
+        })
+        .await
+        .map_err(AppError::Internal)?;
+    Ok((StatusCode::OK, Json(json)).into_response())
+}
+
+/* -------------------------
+   disambiguation
+-------------------------- */
+
+/// POST /resolve {"mentions": [..]}: an article's mentions resolved together,
+/// preferring candidates near or in the regions of the others
+/// (disambiguate.rs).
+async fn post_resolve(
+    State(state): State<AppState>,
+    Json(req): Json<ResolveRequest>,
+) -> Result<Response, AppError> {
+    if req.mentions.len() > BATCH_MAX_KEYS {
+        return Err(AppError::BadRequest(anyhow!(
+            "{} mentions; at most {BATCH_MAX_KEYS} per request",
+            req.mentions.len()
+        )));
+    }
+    let json = state
+        .offload(move |state| -> Result<ResolveJson> {
+            let opts = LookupOptions {
+                codes: req.codes,
+                ..state.lookup_options()
+            };
+            let mut errors: Vec<Option<String>> = vec![None; req.mentions.len()];
+            let ranked = req
+                .mentions
+                .iter()
+                .zip(&mut errors)
+                .map(|(m, error)| match rank(&state, m, &opts) {
+                    Ok(r) => r.page,
+                    Err(e) => {
+                        *error = Some(format!("{e:#}"));
+                        Vec::new()
+                    }
+                })
+                .collect();
+            let limit = clamp_limit(req.limit, 1);
+            let results: Vec<ResolvedJson> = req
+                .mentions
+                .into_iter()
+                .zip(disambiguate::resolve(ranked))
+                .zip(errors)
+                .map(|((key, mut scored), error)| {
+                    scored.truncate(limit);
+                    let candidates = scored
+                        .into_iter()
+                        .map(|s| {
+                            let mut c = state.geo.candidate(s.rec, &opts)?;
+                            c.score = Some(s.context.total);
+                            c.score_breakdown = req.explain.then_some(s.score);
+                            c.context = req.explain.then_some(s.context);
+                            Ok(c)
+                        })
+                        .collect::<Result<Vec<_>>>();
+                    let (candidates, error) = match candidates {
+                        Ok(c) => (c, error),
+                        Err(e) => (Vec::new(), Some(format!("{e:#}"))),
+                    };
+                    ResolvedJson {
+                        key,
+                        count: candidates.len(),
+                        candidates,
+                        error,
+                    }
+                })
+                .collect();
+            Ok(ResolveJson {
+                count: results.len(),
+                results,
+            })
+        })
+        .await
+        .map_err(AppError::Internal)?;
+    Ok((StatusCode::OK, Json(json)).into_response())
+}
+
+/* -------------------------
+   concordance
+-------------------------- */
+
+/// GET /concordance/:id: cross-source ids for "Q64", "gn:..", "wof:..",
+/// "osm:node/.." or a record id.
+async fn get_concordance(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    let entry = match &state.concordance {
+        Some(c) => c.lookup(&id).map_err(AppError::Internal)?,
+        None => None,
+    };
+    Ok(match entry {
+        Some(e) => (StatusCode::OK, Json(e)).into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(ErrorJson {
+                error: format!("no concordance entry for {id}"),
+            }),
+        )
+            .into_response(),
+    })
+}
+
+/* -------------------------
+   ISO 3166-2 subdivisions
+-------------------------- */
+
+#[derive(Serialize)]
+struct SubdivisionJson {
+    code: String,
+    place: Candidate,
+}
+
+/// GET /subdivision/:code: the ADM1 record for an ISO 3166-2 code ("US-CA").
+async fn get_subdivision(
+    State(state): State<AppState>,
+    Path(code): Path<String>,
+) -> Result<Response, AppError> {
+    let iso = subdivision::normalize(&code).ok_or_else(|| {
+        AppError::BadRequest(anyhow!(
+            "expected an ISO 3166-2 code like US-CA, got {code:?}"
+        ))
+    })?;
+    let id = state.subdivisions.as_ref().and_then(|s| s.get(&iso));
+    let rec = match id {
+        Some(id) => read_record_by_id(state.geo.db(), id).map_err(AppError::Internal)?,
+        None => None,
+    };
+    let Some(rec) = rec else {
+        return Ok((
+            StatusCode::NOT_FOUND,
+            Json(ErrorJson {
+                error: format!("no subdivision {iso}"),
+            }),
+        )
+            .into_response());
+    };
+    let place = state
+        .geo
+        .candidate(rec, &LookupOptions::default())
+        .map_err(AppError::Internal)?;
+    Ok((StatusCode::OK, Json(SubdivisionJson { code: iso, place })).into_response())
+}
+
+/// GET /country/:iso: countryInfo.txt metadata by alpha-2 or alpha-3 code.
+async fn get_country(
+    State(state): State<AppState>,
+    Path(iso): Path<String>,
+) -> Result<Response, AppError> {
+    let Some(countries) = state.geo.countries() else {
+        return Ok((
+            StatusCode::NOT_FOUND,
+            Json(ErrorJson {
+                error: "no country table in this DB (build with --country-info)".into(),
+            }),
+        )
+            .into_response());
+    };
+    let Some(info) = countries.get(&iso) else {
+        return Ok((
+            StatusCode::NOT_FOUND,
+            Json(ErrorJson {
+                error: format!("no country {iso:?}"),
+            }),
+        )
+            .into_response());
+    };
+    Ok((StatusCode::OK, Json::<CountryInfo>(info.clone())).into_response())
+}
+
+/* -------------------------
+   transport hubs
+-------------------------- */
+
+#[derive(Serialize)]
+struct HubJson {
+    #[serde(flatten)]
+    place: Candidate,
+    /// Set when the key matched this hub's IATA / ICAO code.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    matched_code: Option<CodeKind>,
+}
+
+#[derive(Serialize)]
+struct TransportJson {
+    key: String,
+    count: usize,
+    candidates: Vec<HubJson>,
+}
+
+/// GET /transport?key=..: airports, stations and ports by IATA / ICAO code or
+/// name, ahead of towns of the same name (transport.rs).
+async fn get_transport(
+    State(state): State<AppState>,
+    Query(p): Query<TransportParams>,
+) -> Result<Response, AppError> {
+    let focus = parse_near(p.near.as_deref())?;
+    let json = state
+        .offload(move |state| transport_answer(&state, p, focus))
+        .await
+        .map_err(AppError::Internal)?;
+    Ok((StatusCode::OK, Json(json)).into_response())
+}
+
+fn transport_answer(
+    state: &AppState,
+    p: TransportParams,
+    focus: Option<(f32, f32)>,
+) -> Result<TransportJson> {
+    let opts = LookupOptions {
+        focus,
+        codes: p.codes,
+        explain: p.explain,
+        features: transport::features(),
+        ..state.lookup_options()
+    };
+    let ranker = state.geo.ranker(&opts);
+
+    // code hits first, then name matches among transport features
+    let mut hits: Vec<(GeoRecord, ScoreBreakdown, Option<CodeKind>)> = Vec::new();
+    if let Some(t) = &state.transport {
+        let coded = t.get(&p.key);
+        let mut recs = Vec::with_capacity(coded.len());
+        for (id, _) in &coded {
+            if let Some(rec) = read_record_by_id(state.geo.db(), *id)? {
+                recs.push(rec);
+            }
+        }
+        let key = casefold::fold(p.key.trim());
+        for (rec, score) in ranker.score(&key, recs, &[]) {
+            let kind = coded.iter().find(|(id, _)| *id == rec.id).map(|(_, k)| *k);
+            hits.push((rec, score, kind));
+        }
+    }
+    for (rec, score) in rank(state, &p.key, &opts)?.page {
+        if !hits.iter().any(|(r, _, _)| r.id == rec.id) {
+            hits.push((rec, score, None));
+        }
+    }
+
+    hits.truncate(clamp_limit(p.limit, DEFAULT_QUERY_LIMIT));
+    let candidates: Vec<HubJson> = hits
+        .into_iter()
+        .map(|(rec, score, matched_code)| {
+            Ok(HubJson {
+                place: state
+                    .geo
+                    .candidate(rec, &opts)?
+                    .with_score(score, opts.explain),
+                matched_code,
+            })
+        })
+        .collect::<Result<_>>()?;
+    Ok(TransportJson {
+        key: p.key,
         count: candidates.len(),
         candidates,
+    })
+}
+
+/* -------------------------
+   typeahead
+-------------------------- */
+
+/// GET /suggest?prefix=..: typeahead keys, most populous first. Cached
+/// ([server] suggest_cache_entries); a miss primes the likely next prefixes.
+async fn get_suggest(
+    State(state): State<AppState>,
+    Query(p): Query<SuggestParams>,
+) -> Result<Response, AppError> {
+    let prefix = casefold::fold(p.prefix.trim());
+    let limit = clamp_limit(p.limit, suggest::DEFAULT_LIMIT);
+    let cache = state.suggest_cache.clone();
+    let key = state.suggest_key(&prefix, limit);
+    if let Some(body) = cache.as_ref().and_then(|c| c.get(&key)) {
+        return Ok(suggest_response(body));
+    }
+    let json = state
+        .offload(move |state| suggest::suggest(state.geo.db(), state.geo.fst(), &prefix, limit))
+        .await
+        .map_err(AppError::Internal)?;
+    let Some(cache) = cache else {
+        return Ok(Json(json).into_response());
     };
+    let body = Bytes::from(serde_json::to_vec(&json).map_err(|e| AppError::Internal(e.into()))?);
+    cache.put(key, body.clone());
+    // off the request path: the answer to the next keystroke
+    let next = suggest::next_prefixes(&json, suggest::PRIME_PREFIXES);
+    if !next.is_empty() {
+        tokio::task::spawn_blocking(move || state.prime_suggest(&cache, next, limit));
+    }
+    Ok(suggest_response(body))
+}
+
+fn suggest_response(body: Bytes) -> Response {
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "application/json")],
+        body,
+    )
+        .into_response()
+}
+
+/* -------------------------
+   h3 cells
+-------------------------- */
+
+const H3_DEFAULT_LIMIT: usize = 100;
+
+#[derive(Serialize)]
+struct H3PlacesJson {
+    cell: String,
+    resolution: u8,
+    /// Records in the cell before `limit`.
+    count: usize,
+    places: Vec<Candidate>,
+}
+
+/// GET /h3/:cell/places: records in an H3 cell for DBs built with
+/// --h3-resolution; cells coarser than the index resolution are allowed.
+async fn get_h3_places(
+    State(state): State<AppState>,
+    Path(cell): Path<String>,
+    Query(p): Query<H3Params>,
+) -> Result<Response, AppError> {
+    let Some(section) = H3Section::parse(state.geo.db().h3_slice()).map_err(AppError::Internal)?
+    else {
+        return Ok((
+            StatusCode::NOT_FOUND,
+            Json(ErrorJson {
+                error: "DB was built without --h3-resolution".to_string(),
+            }),
+        )
+            .into_response());
+    };
+    let index: h3o::CellIndex = cell
+        .parse()
+        .map_err(|e| AppError::BadRequest(anyhow!("invalid H3 cell {cell:?}: {e}")))?;
+
+    let resolution = section.resolution;
+    let ids = section.ids_in(index).map_err(AppError::BadRequest)?;
+    let count = ids.len();
+    let places = state
+        .offload(move |state| {
+            let mut records = Vec::with_capacity(ids.len());
+            for id in ids {
+                records.extend(read_record_by_id(state.geo.db(), id)?);
+            }
+            let mut ranked = state.weights().rank(records, None, &[]);
+            ranked.truncate(clamp_limit(p.limit, H3_DEFAULT_LIMIT));
+            let opts = LookupOptions {
+                codes: p.codes,
+                ..LookupOptions::default()
+            };
+            ranked
+                .into_iter()
+                .map(|(rec, score)| Ok(state.geo.candidate(rec, &opts)?.with_score(score, false)))
+                .collect::<Result<Vec<_>>>()
+        })
+        .await
+        .map_err(AppError::Internal)?;
+    Ok((
+        StatusCode::OK,
+        Json(H3PlacesJson {
+            cell,
+            resolution,
+            count,
+            places,
+        }),
+    )
+        .into_response())
+}
+
+/* -------------------------
+   vector tiles
+-------------------------- */
+
+/// GET /tiles/:z/:x/:y.mvt: places as a Mapbox Vector Tile for the globe's
+/// label layer (tiles.rs), ETag'd per build and kept in the query cache.
+/// `y` is "<y>.mvt": axum captures whole segments only.
+async fn get_tile(
+    State(state): State<AppState>,
+    Path((z, x, y)): Path<(String, String, String)>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let tile = TileId::parse(&z, &x, &y).map_err(AppError::BadRequest)?;
+    let path = format!("tiles/{}/{}/{}", tile.z, tile.x, tile.y);
+    let etag = state.etag(&path);
+    if etag_matches(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response());
+    }
+    // low zooms scan most of the index; a tile only changes with the build
+    let key = format!("{}:{path}", state.build);
+    let cached = state.cache.as_ref().and_then(|c| c.get(&key));
+    let body = match cached {
+        Some(body) => body,
+        None => {
+            let body = state
+                .offload(move |s| tiles::render(s.geo.db(), s.geo.reverse_index()?, tile))
+                .await
+                .map(Bytes::from)
+                .map_err(AppError::Internal)?;
+            if let Some(cache) = &state.cache {
+                cache.put(key, body.clone());
+            }
+            body
+        }
+    };
+    Ok((
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, tiles::CONTENT_TYPE.to_string()),
+            (header::ETAG, etag),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/* -------------------------
+   feedback
+-------------------------- */
+
+#[derive(Serialize)]
+struct FeedbackJson {
+    /// Training rows written, one per rejected candidate.
+    rows: usize,
+}
 
-    Ok((StatusCode::OK, Json(out)))
-}
\ No newline at end of file
+fn feedback_candidate(db: &Db, id: u32) -> Result<FeedbackCandidate> {
+    let rec = read_record_by_id(db, id)?.ok_or_else(|| anyhow!("unknown record id {id}"))?;
+    Ok(FeedbackCandidate {
+        id,
+        feature: format!("{}.{}", rec.feat_class as char, rec.feat_code),
+    })
+}
+
+/// POST /feedback: an editor's correction (chosen / rejected candidates for a
+/// key), written unsampled to the audit sink as ranking training rows.
+async fn post_feedback(
+    State(state): State<AppState>,
+    Json(req): Json<FeedbackRequest>,
+) -> Result<Response, AppError> {
+    let Some(auditor) = &state.auditor else {
+        return Ok((
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(ErrorJson {
+                error: "feedback needs an [audit] sink in the config".to_string(),
+            }),
+        )
+            .into_response());
+    };
+    if req.rejected.contains(&req.chosen) {
+        return Err(AppError::BadRequest(anyhow!(
+            "chosen id {} is also rejected",
+            req.chosen
+        )));
+    }
+    let chosen = feedback_candidate(state.geo.db(), req.chosen).map_err(AppError::BadRequest)?;
+    let rejected = req
+        .rejected
+        .iter()
+        .map(|&id| feedback_candidate(state.geo.db(), id))
+        .collect::<Result<Vec<_>>>()
+        .map_err(AppError::BadRequest)?;
+
+    let key = casefold::fold(req.key.trim());
+    let rows = FeedbackRecord::pairs(&key, req.article_id.as_deref(), &chosen, &rejected);
+    let n = rows.len();
+    auditor
+        .feedback(rows)
+        .await
+        .map_err(AppError::Unavailable)?;
+    Ok((StatusCode::ACCEPTED, Json(FeedbackJson { rows: n })).into_response())
+}
+
+/* -------------------------
+   admin: ranking weights
+-------------------------- */
+
+async fn get_ranking(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.weights())
+}
+
+/// PUT /admin/ranking: hot-swap the ranking weights; save=true also writes
+/// them to --config.
+async fn put_ranking(
+    State(state): State<AppState>,
+    Query(p): Query<SaveParams>,
+    Json(weights): Json<RankingWeights>,
+) -> Result<impl IntoResponse, AppError> {
+    weights
+        .validate()
+        .map_err(|e| AppError::BadRequest(anyhow!(e)))?;
+
+    if p.save {
+        let path = state.config_path.as_deref().ok_or_else(|| {
+            AppError::BadRequest(anyhow!("save=true but server was started without --config"))
+        })?;
+        let mut cfg = Config::load(path).map_err(AppError::Internal)?;
+        cfg.ranking = weights.clone();
+        cfg.save(path).map_err(AppError::Internal)?;
+    }
+
+    *state.ranking.write().unwrap_or_else(|e| e.into_inner()) = weights.clone();
+    state.ranking_gen.fetch_add(1, Ordering::Relaxed);
+    Ok((StatusCode::OK, Json(weights)))
+}
+
+/* -------------------------
+   admin: overlay
+-------------------------- */
+
+fn overlay_json(overlay: &Overlay) -> Json<OverlayFile> {
+    Json(OverlayFile {
+        entries: overlay.entries().cloned().collect(),
+    })
+}
+
+/// GET /admin/overlay: names served ahead of the DB until a rebuild has
+/// them ([server] overlay, overlay.rs).
+async fn get_overlay(State(state): State<AppState>) -> impl IntoResponse {
+    overlay_json(&state.overlay)
+}
+
+/// Write `next` to [server] overlay, then serve it. Callers hold `reloading`,
+/// so a reload cannot swap in a state with the old overlay meanwhile.
+fn swap_overlay(shared: &Shared, next: Overlay) -> Result<Arc<Overlay>, AppError> {
+    if let Some(path) = &shared.overlay_path {
+        next.save(path).map_err(AppError::Internal)?;
+    }
+    let next = Arc::new(next);
+    let mut current = shared.current.write().unwrap_or_else(|e| e.into_inner());
+    current.overlay = next.clone();
+    current.ranking_gen.fetch_add(1, Ordering::Relaxed);
+    Ok(next)
+}
+
+/// Add entries, replacing those with the same (folded) name.
+async fn put_overlay(
+    State(shared): State<Shared>,
+    Json(req): Json<OverlayFile>,
+) -> Result<impl IntoResponse, AppError> {
+    let _one = shared.reloading.lock().await;
+    let next = AppState::from_ref(&shared)
+        .overlay
+        .with(req.entries)
+        .map_err(AppError::BadRequest)?;
+    let next = swap_overlay(&shared, next)?;
+    eprintln!("[serve] overlay entries={}", next.len());
+    Ok((StatusCode::OK, overlay_json(&next)))
+}
+
+async fn delete_overlay(
+    State(shared): State<Shared>,
+    Path(name): Path<String>,
+) -> Result<Response, AppError> {
+    let _one = shared.reloading.lock().await;
+    let Some(next) = AppState::from_ref(&shared).overlay.without(&name) else {
+        return Ok((
+            StatusCode::NOT_FOUND,
+            Json(ErrorJson {
+                error: format!("no overlay entry {name:?}"),
+            }),
+        )
+            .into_response());
+    };
+    let next = swap_overlay(&shared, next)?;
+    eprintln!("[serve] overlay entries={}", next.len());
+    Ok((StatusCode::OK, overlay_json(&next)).into_response())
+}
+
+/* -------------------------
+   admin: DB reload
+-------------------------- */
+
+#[derive(Debug, Default, Deserialize)]
+struct ReloadRequest {
+    /// DB file to switch to; default: reload the current path (deploys that
+    /// rename a new file over it).
+    #[serde(default)]
+    db: Option<PathBuf>,
+}
+
+#[derive(Serialize)]
+struct ReloadJson {
+    db: String,
+    build: String,
+    previous_build: String,
+    load_ms: f64,
+}
+
+fn reload_refused(status: StatusCode, error: String) -> Response {
+    (status, Json(ErrorJson { error })).into_response()
+}
+
+/// Load a DB and swap it in. In-flight requests finish on the old one, which
+/// is freed when the last of them drops it; on any load error nothing changes.
+/// At most one extra DB is resident: see reload.rs for the guards.
+async fn post_reload(
+    State(shared): State<Shared>,
+    body: Option<Json<ReloadRequest>>,
+) -> Result<Response, AppError> {
+    let req = body.map(|Json(r)| r).unwrap_or_default();
+    // metrics only: a state held across the wait would keep its DB resident
+    let metrics = AppState::from_ref(&shared).metrics;
+    let swap = &metrics.swap;
+    if !shared.reload.enabled {
+        swap.refused(Refused::Disabled);
+        return Ok(reload_refused(
+            StatusCode::FORBIDDEN,
+            "hot reload is disabled ([reload] enabled = false); restart to switch DBs".into(),
+        ));
+    }
+    let _one = shared.reloading.lock().await;
+    if swap.draining() {
+        swap.refused(Refused::Draining);
+        return Ok(reload_refused(
+            StatusCode::CONFLICT,
+            "the DB replaced by the previous reload is still in use by in-flight requests or \
+             jobs; retry once they finish"
+                .into(),
+        ));
+    }
+
+    let old = AppState::from_ref(&shared);
+    let path = match req.db.or_else(|| old.db_path.as_deref().cloned()) {
+        Some(p) => p,
+        None => {
+            return Err(AppError::BadRequest(anyhow!(
+                "serving the embedded DB; pass {{\"db\": \"path\"}} to reload from a file"
+            )))
+        }
+    };
+    let open = old.open;
+    let file_len = match std::fs::metadata(&path) {
+        Ok(m) => m.len(),
+        Err(e) => {
+            swap.refused(Refused::Failed);
+            return Err(AppError::BadRequest(anyhow!(
+                "reload {}: {e}",
+                path.display()
+            )));
+        }
+    };
+    if let Err(e) = shared
+        .reload
+        .check_headroom(file_len, open.mmap, shared.memory_limit)
+    {
+        swap.refused(Refused::Memory);
+        return Ok(reload_refused(
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("reload {}: {e}", path.display()),
+        ));
+    }
+
+    let t = Instant::now();
+    let load_path = path.clone();
+    let script = old.script.clone();
+    swap.set_loading(true);
+    let loaded =
+        tokio::task::spawn_blocking(move || DbParts::load(Some(&load_path), open, script)).await;
+    swap.set_loading(false);
+    let parts = match loaded {
+        Ok(Ok(parts)) => parts,
+        Ok(Err(e)) => {
+            swap.refused(Refused::Failed);
+            return Err(AppError::Internal(
+                e.context(format!("reload {}", path.display())),
+            ));
+        }
+        Err(e) => {
+            swap.refused(Refused::Failed);
+            return Err(AppError::Internal(anyhow!("reload task: {e}")));
+        }
+    };
+    let load_ms = t.elapsed().as_secs_f64() * 1000.0;
+
+    let next = old.with_db(parts, Some(path.clone()));
+    let json = ReloadJson {
+        db: path.display().to_string(),
+        build: next.build.to_string(),
+        previous_build: old.build.to_string(),
+        load_ms,
+    };
+    *shared.current.write().unwrap_or_else(|e| e.into_inner()) = next;
+    swap.swapped(Arc::downgrade(&old.geo));
+    eprintln!(
+        "[serve] reloaded db={} build={} (was {})",
+        json.db, json.build, json.previous_build
+    );
+    Ok((StatusCode::OK, Json(json)).into_response())
+}
@@ -0,0 +1,68 @@
+// src/sets.rs
+//
+// `POST /query/set {"all_of": [...], "any_of": [...]}`: set operations over
+// posting lists, for co-mention checks in the tagger ("both 'Donetsk' and
+// 'oblast'"). A key's ids are its whole-name postings (accent-insensitive,
+// as on /query) plus its token postings when the DB has the tokens FST
+// (tokens.rs), so a word of a longer name counts as a mention of it. The
+// result is the intersection of the `all_of` lists, intersected with the
+// union of the `any_of` lists when both are given. Lists are roaring bitmaps
+// (roaring-encoded postings are used as stored), so long lists such as
+// common tokens intersect by container; no records are read until the
+// result is known.
+
+use anyhow::{bail, Result};
+use roaring::RoaringBitmap;
+
+use crate::casefold;
+use crate::pipeline::Index;
+use crate::read_key_bitmap;
+
+/// Ids for one key.
+pub fn postings<D: AsRef<[u8]>>(idx: &Index<'_, D>, raw: &str) -> Result<RoaringBitmap> {
+    let key = casefold::fold(raw.trim());
+    let mut ids = read_key_bitmap(idx.db, idx.fst, idx.unaccented, &key)?;
+    if let Some(tokens) = idx.tokens {
+        ids |= read_key_bitmap(idx.db, tokens, None, &key)?;
+    }
+    Ok(ids)
+}
+
+/// Sorted ids matching every `all_of` key and at least one `any_of` key;
+/// an empty list places no constraint, but not both.
+pub fn evaluate<D: AsRef<[u8]>>(
+    idx: &Index<'_, D>,
+    all_of: &[String],
+    any_of: &[String],
+) -> Result<Vec<u32>> {
+    if all_of.is_empty() && any_of.is_empty() {
+        bail!("all_of and any_of are both empty");
+    }
+    let mut acc: Option<RoaringBitmap> = None;
+    // shortest lists first keeps the intermediate results small
+    let mut lists = all_of
+        .iter()
+        .map(|k| postings(idx, k))
+        .collect::<Result<Vec<_>>>()?;
+    lists.sort_by_key(RoaringBitmap::len);
+    for ids in lists {
+        acc = Some(match acc {
+            Some(a) => a & ids,
+            None => ids,
+        });
+        if acc.as_ref().is_some_and(RoaringBitmap::is_empty) {
+            return Ok(Vec::new());
+        }
+    }
+    if !any_of.is_empty() {
+        let mut any = RoaringBitmap::new();
+        for k in any_of {
+            any |= postings(idx, k)?;
+        }
+        acc = Some(match acc {
+            Some(a) => a & any,
+            None => any,
+        });
+    }
+    Ok(acc.map(|ids| ids.iter().collect()).unwrap_or_default())
+}
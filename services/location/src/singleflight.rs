@@ -0,0 +1,68 @@
+// src/singleflight.rs
+//
+// Request coalescing for /query: while one lookup for a given flight key is
+// running, identical requests wait for it and share its serialized response
+// instead of decoding and materializing the same postings again. A trending
+// story sends thousands of identical lookups per second; this bounds the work
+// to one per distinct query at a time. Nothing is kept after the flight lands;
+// the LRU in cache.rs keeps finished bodies under the same keys.
+// If the leading request is dropped (client gone), a waiter takes over.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::OnceCell;
+
+type Flight<V> = Arc<OnceCell<V>>;
+
+pub struct Group<V> {
+    inflight: Mutex<HashMap<String, Flight<V>>>,
+    /// Requests answered by another request's flight.
+    coalesced: AtomicU64,
+}
+
+impl<V> Default for Group<V> {
+    fn default() -> Self {
+        Self {
+            inflight: Mutex::new(HashMap::new()),
+            coalesced: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<V: Clone> Group<V> {
+    /// Run `f` unless a flight for `key` is already running, then return its
+    /// value.
+    pub async fn run<F, Fut>(&self, key: String, f: F) -> V
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+    {
+        let (flight, joined) = {
+            let mut inflight = self.inflight.lock().unwrap_or_else(|e| e.into_inner());
+            match inflight.get(&key) {
+                Some(f) => (f.clone(), true),
+                None => {
+                    let f = Flight::default();
+                    inflight.insert(key.clone(), f.clone());
+                    (f, false)
+                }
+            }
+        };
+        if joined {
+            self.coalesced.fetch_add(1, Ordering::Relaxed);
+        }
+        let v = flight.get_or_init(f).await.clone();
+        let mut inflight = self.inflight.lock().unwrap_or_else(|e| e.into_inner());
+        if inflight.get(&key).is_some_and(|f| Arc::ptr_eq(f, &flight)) {
+            inflight.remove(&key);
+        }
+        v
+    }
+
+    pub fn coalesced(&self) -> u64 {
+        self.coalesced.load(Ordering::Relaxed)
+    }
+}
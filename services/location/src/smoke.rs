@@ -0,0 +1,119 @@
+// src/smoke.rs
+//
+// `geodb serve --smoke-test N`: a container check for CI before an image is
+// promoted. Once the listener is up the server queries itself over HTTP, the
+// way a client would:
+//   GET /health                                   -> 200
+//   GET /v1/query?key=<key>&limit=1               -> 200, a candidate
+//   GET /v1/reverse?lat=..&lon=..&all=true&limit=1 -> 200, a candidate
+// <key> is a name from the DB itself (server.rs picks it) and the point is
+// that candidate's, so any DB passes that can answer at all. A failing
+// canary stops the server at once and `serve` returns the error (exit
+// status 1); otherwise it keeps serving until N seconds after start, so
+// outside checks can run too, then drains and exits 0.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::Value;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Per request, connect to last byte.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where to reach a listener bound to `bound` from the same host.
+pub fn loopback(bound: SocketAddr) -> SocketAddr {
+    let mut addr = bound;
+    if addr.ip().is_unspecified() {
+        addr.set_ip(match addr {
+            SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+            SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+        });
+    }
+    addr
+}
+
+/// Runs the canaries against `addr`; `key` must name something in the DB.
+pub async fn run(addr: SocketAddr, key: &str) -> Result<()> {
+    let (status, _) = get(addr, "/health").await.context("GET /health")?;
+    if status != 200 {
+        bail!("GET /health: status {status}");
+    }
+    eprintln!("[smoke] /health ok");
+
+    let path = format!("/v1/query?key={}&limit=1", percent_encode(key));
+    let first = first_candidate(addr, &path).await?;
+    let (lat, lon) = match (first["lat"].as_f64(), first["lon"].as_f64()) {
+        (Some(lat), Some(lon)) => (lat, lon),
+        _ => bail!("GET {path}: candidate without lat/lon"),
+    };
+    eprintln!("[smoke] /v1/query {key:?} ok");
+
+    let path = format!("/v1/reverse?lat={lat}&lon={lon}&all=true&limit=1");
+    first_candidate(addr, &path).await?;
+    eprintln!("[smoke] /v1/reverse ok");
+    Ok(())
+}
+
+async fn first_candidate(addr: SocketAddr, path: &str) -> Result<Value> {
+    let (status, body) = get(addr, path)
+        .await
+        .with_context(|| format!("GET {path}"))?;
+    if status != 200 {
+        bail!("GET {path}: status {status}: {body}");
+    }
+    let answer: Value =
+        serde_json::from_str(&body).with_context(|| format!("GET {path}: not JSON"))?;
+    answer["candidates"]
+        .get(0)
+        .cloned()
+        .ok_or_else(|| anyhow!("GET {path}: no candidates"))
+}
+
+/// (status, body) of a GET; bodies must have a Content-Length (not chunked),
+/// as every JSON answer does.
+async fn get(addr: SocketAddr, path: &str) -> Result<(u16, String)> {
+    tokio::time::timeout(REQUEST_TIMEOUT, async {
+        let mut s = TcpStream::connect(addr).await?;
+        let req = format!("GET {path} HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n");
+        s.write_all(req.as_bytes()).await?;
+        let mut raw = Vec::new();
+        s.read_to_end(&mut raw).await?;
+        parse_response(&raw)
+    })
+    .await
+    .map_err(|_| anyhow!("no answer within {REQUEST_TIMEOUT:?}"))?
+}
+
+fn parse_response(raw: &[u8]) -> Result<(u16, String)> {
+    let text = String::from_utf8_lossy(raw);
+    let (head, body) = text
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| anyhow!("truncated response"))?;
+    if head
+        .to_ascii_lowercase()
+        .contains("transfer-encoding: chunked")
+    {
+        bail!("unexpected chunked response");
+    }
+    let status = head
+        .split(' ')
+        .nth(1)
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| anyhow!("bad status line"))?;
+    Ok((status, body.to_string()))
+}
+
+/// Query-string encoding: everything but unreserved ASCII as %XX.
+fn percent_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~') {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{b:02X}"));
+        }
+    }
+    out
+}
@@ -0,0 +1,307 @@
+// src/spill.rs
+//
+// External sort for the build's key -> ids maps (`geodb build --spill-dir`).
+// A KeySink collects (key, id) pairs in a FastBuildMap; once the map's
+// estimated size passes the budget it is sorted, written to a run file and
+// cleared. At the end the runs are merged key by key (a k-way heap merge) and
+// fed straight to the FST builder, so the whole map never exists in memory;
+// accent-stripped keys and tokens are derived from that stream into sinks of
+// their own. Without --spill-dir, or while a map stays under the budget, the
+// sink is the plain in-memory map the build always used.
+// Run file: per key, var_u32 key length | key | var_u32 id count | delta
+// varints (ids sorted, deduplicated). Runs are deleted once read.
+
+use anyhow::{bail, Context, Result};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::PathBuf;
+
+use crate::build::{encode_delta_varints, write_var_u32, FastBuildMap};
+
+/// Rough in-memory cost of one map entry beyond its key and ids (String and
+/// SmallVec headers, hash table slot).
+const ENTRY_BYTES: usize = 64;
+
+#[derive(Clone, Debug)]
+pub struct SpillOptions {
+    pub dir: PathBuf,
+    /// Per-map budget; a build has at most two maps filling at a time.
+    pub budget_bytes: usize,
+}
+
+type Prepare<'a> = Box<dyn FnMut(&mut FastBuildMap) + Send + 'a>;
+
+/// A key -> ids map that spills sorted runs to disk past its budget.
+pub struct KeySink<'a> {
+    label: &'static str,
+    spill: Option<SpillOptions>,
+    map: FastBuildMap,
+    bytes: usize,
+    runs: Vec<PathBuf>,
+    /// Run on the in-memory map before every spill and at the end
+    /// (sanitize.rs, nameflags.rs); must only look at one key at a time.
+    prepare: Option<Prepare<'a>>,
+}
+
+impl<'a> KeySink<'a> {
+    pub fn new(label: &'static str, spill: Option<&SpillOptions>) -> Result<Self> {
+        if let Some(s) = spill {
+            std::fs::create_dir_all(&s.dir)
+                .with_context(|| format!("create spill dir: {}", s.dir.display()))?;
+        }
+        Ok(Self {
+            label,
+            spill: spill.cloned(),
+            map: FastBuildMap::default(),
+            bytes: 0,
+            runs: Vec::new(),
+            prepare: None,
+        })
+    }
+
+    pub fn prepare(mut self, f: impl FnMut(&mut FastBuildMap) + Send + 'a) -> Self {
+        self.prepare = Some(Box::new(f));
+        self
+    }
+
+    /// Keys currently in memory.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty() && self.runs.is_empty()
+    }
+
+    pub fn push(&mut self, key: String, id: u32) -> Result<()> {
+        self.extend(key, &[id])
+    }
+
+    pub fn extend(&mut self, key: String, ids: &[u32]) -> Result<()> {
+        let new_entry = key.len() + ENTRY_BYTES;
+        let bytes = &mut self.bytes;
+        self.map
+            .entry(key)
+            .or_insert_with(|| {
+                *bytes += new_entry;
+                Default::default()
+            })
+            .extend_from_slice(ids);
+        self.bytes += ids.len() * 4;
+        if self
+            .spill
+            .as_ref()
+            .is_some_and(|s| self.bytes >= s.budget_bytes)
+        {
+            self.spill_run()?;
+        }
+        Ok(())
+    }
+
+    fn spill_run(&mut self) -> Result<()> {
+        let Some(spill) = &self.spill else {
+            return Ok(());
+        };
+        if let Some(f) = self.prepare.as_mut() {
+            f(&mut self.map);
+        }
+        let path = spill.dir.join(format!(
+            "geodb-{}-{}-{}.run",
+            std::process::id(),
+            self.label,
+            self.runs.len()
+        ));
+        let mut entries: Vec<_> = self.map.drain().collect();
+        entries.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        let mut w = BufWriter::new(
+            File::create(&path).with_context(|| format!("create run: {}", path.display()))?,
+        );
+        let mut buf = Vec::new();
+        for (k, mut ids) in entries {
+            ids.sort_unstable();
+            ids.dedup();
+            buf.clear();
+            write_var_u32(&mut buf, k.len() as u32);
+            buf.extend_from_slice(k.as_bytes());
+            write_var_u32(&mut buf, ids.len() as u32);
+            buf.extend_from_slice(&encode_delta_varints(&ids));
+            w.write_all(&buf)?;
+        }
+        w.flush()?;
+        eprintln!(
+            "[spill] {} run={} est_bytes={}",
+            self.label,
+            self.runs.len(),
+            self.bytes
+        );
+        self.runs.push(path);
+        self.map.shrink_to_fit();
+        self.bytes = 0;
+        Ok(())
+    }
+
+    /// Everything pushed: the map itself when nothing was spilled, else the
+    /// merged runs.
+    pub fn finish(mut self) -> Result<Spilled> {
+        if self.runs.is_empty() {
+            if let Some(f) = self.prepare.as_mut() {
+                f(&mut self.map);
+            }
+            return Ok(Spilled::Memory(std::mem::take(&mut self.map)));
+        }
+        if !self.map.is_empty() {
+            self.spill_run()?;
+        }
+        RunMerge::open(std::mem::take(&mut self.runs)).map(Spilled::Runs)
+    }
+}
+
+impl Drop for KeySink<'_> {
+    fn drop(&mut self) {
+        for run in &self.runs {
+            let _ = std::fs::remove_file(run);
+        }
+    }
+}
+
+pub enum Spilled {
+    Memory(FastBuildMap),
+    Runs(RunMerge),
+}
+
+impl Spilled {
+    /// (key, sorted ids) in key order.
+    pub fn into_sorted(self) -> Box<dyn Iterator<Item = Result<(String, Vec<u32>)>> + Send> {
+        match self {
+            Spilled::Memory(map) => {
+                let mut entries: Vec<_> = map.into_iter().collect();
+                entries.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+                Box::new(entries.into_iter().map(|(k, ids)| {
+                    let mut ids = ids.into_vec();
+                    ids.sort_unstable();
+                    ids.dedup();
+                    Ok((k, ids))
+                }))
+            }
+            Spilled::Runs(runs) => Box::new(runs),
+        }
+    }
+}
+
+struct Run {
+    path: PathBuf,
+    r: BufReader<File>,
+}
+
+impl Run {
+    fn next_entry(&mut self) -> Result<Option<(String, Vec<u32>)>> {
+        let Some(key_len) = read_var(&mut self.r, true)? else {
+            return Ok(None);
+        };
+        let mut key = vec![0u8; key_len as usize];
+        self.r.read_exact(&mut key)?;
+        let n = read_var(&mut self.r, false)?.unwrap_or_default();
+        let mut ids = Vec::with_capacity(n as usize);
+        let mut cur = 0u32;
+        for _ in 0..n {
+            cur = cur.wrapping_add(read_var(&mut self.r, false)?.unwrap_or_default());
+            ids.push(cur);
+        }
+        let key = String::from_utf8(key)
+            .with_context(|| format!("corrupt run {}", self.path.display()))?;
+        Ok(Some((key, ids)))
+    }
+}
+
+impl Drop for Run {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// var_u32 from a stream; None at a clean end of stream when `eof_ok`.
+fn read_var<R: Read>(r: &mut R, eof_ok: bool) -> Result<Option<u32>> {
+    let mut v = 0u32;
+    for i in 0..5 {
+        let mut b = [0u8; 1];
+        match r.read_exact(&mut b) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof && eof_ok && i == 0 => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        v |= ((b[0] & 0x7F) as u32) << (7 * i);
+        if b[0] & 0x80 == 0 {
+            return Ok(Some(v));
+        }
+    }
+    bail!("varint too long in spill run")
+}
+
+/// K-way merge of sorted runs; ids of a key found in several runs are
+/// unioned.
+pub struct RunMerge {
+    runs: Vec<Run>,
+    heads: Vec<Vec<u32>>,
+    heap: BinaryHeap<Reverse<(String, usize)>>,
+}
+
+impl RunMerge {
+    fn open(paths: Vec<PathBuf>) -> Result<Self> {
+        let mut m = Self {
+            runs: Vec::with_capacity(paths.len()),
+            heads: vec![Vec::new(); paths.len()],
+            heap: BinaryHeap::with_capacity(paths.len()),
+        };
+        for path in paths {
+            let f = File::open(&path).with_context(|| format!("open run: {}", path.display()))?;
+            m.runs.push(Run {
+                path,
+                r: BufReader::new(f),
+            });
+        }
+        for i in 0..m.runs.len() {
+            m.advance(i)?;
+        }
+        Ok(m)
+    }
+
+    fn advance(&mut self, i: usize) -> Result<()> {
+        if let Some((k, ids)) = self.runs[i].next_entry()? {
+            self.heads[i] = ids;
+            self.heap.push(Reverse((k, i)));
+        }
+        Ok(())
+    }
+
+    fn next_key(&mut self) -> Result<Option<(String, Vec<u32>)>> {
+        let Some(Reverse((key, i))) = self.heap.pop() else {
+            return Ok(None);
+        };
+        let mut ids = std::mem::take(&mut self.heads[i]);
+        self.advance(i)?;
+        let mut merged = false;
+        while self.heap.peek().is_some_and(|Reverse((k, _))| *k == key) {
+            let Some(Reverse((_, j))) = self.heap.pop() else {
+                break;
+            };
+            ids.append(&mut self.heads[j]);
+            self.advance(j)?;
+            merged = true;
+        }
+        if merged {
+            ids.sort_unstable();
+            ids.dedup();
+        }
+        Ok(Some((key, ids)))
+    }
+}
+
+impl Iterator for RunMerge {
+    type Item = Result<(String, Vec<u32>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_key().transpose()
+    }
+}
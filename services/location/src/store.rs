@@ -0,0 +1,55 @@
+// src/store.rs
+//
+// Object store URLs shared by the audit sink and the build registry (features
+// "audit" / "registry"): s3://bucket/prefix, gs://bucket/prefix or file:///dir.
+// Credentials come from the environment (AWS_* / GOOGLE_*).
+
+use anyhow::{bail, Context, Result};
+use object_store::{path::Path as ObjectPath, ObjectStore};
+use std::sync::Arc;
+
+/// Store and key prefix for `url`; `what` names it in errors ("audit sink").
+pub fn open(url: &str, what: &str) -> Result<(Arc<dyn ObjectStore>, ObjectPath)> {
+    let parsed = url::Url::parse(url).with_context(|| format!("{what} url: {url}"))?;
+    let prefix = ObjectPath::from(parsed.path().trim_start_matches('/'));
+    let store: Arc<dyn ObjectStore> = match parsed.scheme() {
+        "s3" => Arc::new(
+            object_store::aws::AmazonS3Builder::from_env()
+                .with_url(url)
+                .build()?,
+        ),
+        "gs" => Arc::new(
+            object_store::gcp::GoogleCloudStorageBuilder::from_env()
+                .with_url(url)
+                .build()?,
+        ),
+        "file" => {
+            std::fs::create_dir_all(parsed.path())
+                .with_context(|| format!("create {what} dir: {}", parsed.path()))?;
+            Arc::new(object_store::local::LocalFileSystem::new_with_prefix(
+                parsed.path(),
+            )?)
+        }
+        other => bail!("unsupported {what} scheme {other:?} (use s3://, gs:// or file://)"),
+    };
+    let prefix = if parsed.scheme() == "file" {
+        ObjectPath::default()
+    } else {
+        prefix
+    };
+    Ok((store, prefix))
+}
+
+/// Unix seconds -> "YYYY-MM-DD" (UTC), days-from-civil inverse (Howard Hinnant).
+pub fn civil_date(unix_secs: u64) -> String {
+    let z = (unix_secs / 86_400) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    format!("{y:04}-{m:02}-{d:02}")
+}
@@ -0,0 +1,109 @@
+// src/subdivision.rs
+//
+// ISO 3166-2 subdivision codes. Records carry GeoNames admin1 codes, which
+// for most countries are not the ISO suffix (Bavaria is "DE.02", ISO "DE-BY").
+// The mapping is a curated table keyed by the admin1CodesASCII.txt code
+// column (data/iso3166_2.tsv), compiled in; countries in IDENTITY use ISO
+// suffixes as their GeoNames codes already ("US.CA" -> "US-CA").
+// Candidates carry `iso3166_2` when their admin1 maps. The build resolves each
+// ISO code to its ADM1 record and stores code -> record id as JSON in the
+// subdivisions section, which answers `GET /subdivision/:code`.
+
+use anyhow::Result;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::OnceLock;
+
+use crate::build::GeoRecord;
+
+const CURATED: &str = include_str!("data/iso3166_2.tsv");
+
+/// Countries whose GeoNames admin1 codes are the ISO 3166-2 suffixes.
+const IDENTITY: &[&str] = &["CH", "US"];
+
+/// "DE.02" -> "DE-BY"
+fn table() -> &'static HashMap<&'static str, &'static str> {
+    static TABLE: OnceLock<HashMap<&'static str, &'static str>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        CURATED
+            .lines()
+            .filter(|l| !l.starts_with('#'))
+            .filter_map(|l| l.split_once('\t'))
+            .map(|(gn, iso)| (gn.trim(), iso.trim()))
+            .collect()
+    })
+}
+
+/// ISO 3166-2 code of a record's admin1, if known.
+pub fn iso_code(country: &str, admin1: &str) -> Option<String> {
+    if admin1.is_empty() {
+        return None;
+    }
+    if IDENTITY.contains(&country) {
+        return Some(format!("{country}-{admin1}"));
+    }
+    table()
+        .get(format!("{country}.{admin1}").as_str())
+        .map(|s| s.to_string())
+}
+
+/// Canonical (upper-case) form of an ISO 3166-2 code, or None when it is not
+/// well-formed.
+pub fn normalize(code: &str) -> Option<String> {
+    let code = code.trim().to_ascii_uppercase();
+    let (country, sub) = code.split_once('-')?;
+    let ok = country.len() == 2
+        && country.bytes().all(|b| b.is_ascii_alphabetic())
+        && (1..=3).contains(&sub.len())
+        && sub.bytes().all(|b| b.is_ascii_alphanumeric());
+    ok.then_some(code)
+}
+
+/// Build the section bytes: ISO code -> id of the ADM1 record it names.
+pub fn build_section(records: &[GeoRecord]) -> Result<Vec<u8>> {
+    // (country, admin1) -> (population, id) of its largest ADM1 record
+    let mut adm1: HashMap<(&str, &str), (u32, u32)> = HashMap::new();
+    for r in records {
+        if r.feat_class != b'A' || r.feat_code != "ADM1" || r.admin1.is_empty() {
+            continue;
+        }
+        let e = adm1
+            .entry((r.country.as_str(), r.admin1.as_str()))
+            .or_insert((r.population, r.id));
+        if r.population > e.0 {
+            *e = (r.population, r.id);
+        }
+    }
+
+    let mut out: BTreeMap<String, u32> = BTreeMap::new();
+    for ((country, admin1), (_, id)) in adm1 {
+        if let Some(iso) = iso_code(country, admin1) {
+            out.insert(iso, id);
+        }
+    }
+    eprintln!("[subdivision] iso3166_2={}", out.len());
+    if out.is_empty() {
+        return Ok(Vec::new());
+    }
+    Ok(serde_json::to_vec(&out)?)
+}
+
+pub struct Subdivisions {
+    table: BTreeMap<String, u32>,
+}
+
+impl Subdivisions {
+    /// `None` for DBs without a subdivisions section.
+    pub fn from_section(section: &[u8]) -> Result<Option<Self>> {
+        if section.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            table: serde_json::from_slice(section)?,
+        }))
+    }
+
+    /// ADM1 record id for a normalized ISO 3166-2 code.
+    pub fn get(&self, iso: &str) -> Option<u32> {
+        self.table.get(iso).copied()
+    }
+}
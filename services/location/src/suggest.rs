@@ -0,0 +1,113 @@
+// src/suggest.rs
+//
+// Typeahead: `GET /suggest?prefix=..&limit=N` and `geodb suggest`. Streams the
+// FST with a prefix automaton and orders the matching keys by the largest
+// population among their postings ("par" -> "paris" before "parma").
+// Short prefixes match a large share of the index, so at most MAX_SCAN_KEYS keys
+// (in key order) are scored per request; `truncated` tells the client to keep
+// typing. The server caches responses and, after a miss, computes the
+// likeliest one-character extensions in the background (`next_prefixes`), so
+// a fast typist's next keystroke is usually a cache hit.
+
+use anyhow::Result;
+use fst::automaton::{Automaton, Str};
+use fst::{IntoStreamer, Streamer};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use crate::{read_postings, read_record_by_id, Db};
+
+pub const DEFAULT_LIMIT: usize = 10;
+const MAX_SCAN_KEYS: usize = 5_000;
+/// Next prefixes primed per /suggest cache miss.
+pub const PRIME_PREFIXES: usize = 4;
+
+#[derive(Serialize)]
+pub struct Suggestion {
+    pub key: String,
+    /// Largest population among the key's records.
+    pub population: u32,
+    /// Records behind the key.
+    pub places: usize,
+}
+
+#[derive(Serialize)]
+pub struct SuggestJson {
+    pub prefix: String,
+    pub count: usize,
+    /// More than MAX_SCAN_KEYS keys share the prefix; only the first were scored.
+    pub truncated: bool,
+    pub suggestions: Vec<Suggestion>,
+}
+
+/// `prefix` must already be folded like index keys (casefold.rs).
+pub fn suggest<D: AsRef<[u8]>>(
+    db: &Db,
+    fst: &fst::Map<D>,
+    prefix: &str,
+    limit: usize,
+) -> Result<SuggestJson> {
+    let limit = if limit == 0 { DEFAULT_LIMIT } else { limit };
+    let mut out = Vec::new();
+    let mut truncated = false;
+    if !prefix.is_empty() {
+        // records are shared between keys ("paris", "paris 01", ...)
+        let mut pop_by_id: HashMap<u32, u32> = HashMap::new();
+        let mut stream = fst.search(Str::new(prefix).starts_with()).into_stream();
+        while let Some((k, off)) = stream.next() {
+            if out.len() == MAX_SCAN_KEYS {
+                truncated = true;
+                break;
+            }
+            let ids = read_postings(db, off as usize)?;
+            let mut population = 0;
+            for &id in &ids {
+                let pop = match pop_by_id.get(&id) {
+                    Some(&p) => p,
+                    None => {
+                        let p = read_record_by_id(db, id)?.map_or(0, |r| r.population);
+                        pop_by_id.insert(id, p);
+                        p
+                    }
+                };
+                population = population.max(pop);
+            }
+            out.push(Suggestion {
+                key: String::from_utf8_lossy(k).into_owned(),
+                population,
+                places: ids.len(),
+            });
+        }
+    }
+
+    // stream order is by key, so ties stay alphabetical
+    out.sort_by_key(|s| Reverse(s.population));
+    out.truncate(limit);
+    Ok(SuggestJson {
+        prefix: prefix.to_string(),
+        count: out.len(),
+        truncated,
+        suggestions: out,
+    })
+}
+
+/// Prefixes one character longer than `json.prefix`, in the order of the
+/// suggestions continuing with them: what is most likely typed next.
+pub fn next_prefixes(json: &SuggestJson, max: usize) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    for s in &json.suggestions {
+        if out.len() == max {
+            break;
+        }
+        // suggestion keys start with the prefix (FST prefix search)
+        let Some(c) = s.key[json.prefix.len()..].chars().next() else {
+            continue;
+        };
+        let next = format!("{}{c}", json.prefix);
+        if !out.contains(&next) {
+            out.push(next);
+        }
+    }
+    out
+}
@@ -0,0 +1,131 @@
+// src/synonyms.rs
+//
+// Historical / alias country names ("USSR", "Czechoslovakia", "Burma") expanded
+// to the current countries. The curated table (data/historical_countries.tsv)
+// is compiled in; the build resolves each ISO code to that country's record
+// (largest PCL* record for the code) and stores alias -> record ids as JSON in
+// the synonyms section, with the year each alias stopped naming a current
+// state. /query consults it before the FST and reports `expanded_from`;
+// with as_of before that year it does not (periods.rs).
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::build::{norm_key, GeoRecord};
+
+const CURATED: &str = include_str!("data/historical_countries.tsv");
+
+/// alias key -> (ISO codes, until)
+type Curated = Vec<(String, Vec<&'static str>, Option<i32>)>;
+
+fn curated() -> Result<Curated> {
+    let mut out = Vec::new();
+    for (n, line) in CURATED.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut cols = line.split('\t');
+        let (Some(alias), Some(codes)) = (cols.next(), cols.next()) else {
+            return Err(anyhow!(
+                "historical_countries.tsv:{}: expected alias<TAB>codes",
+                n + 1
+            ));
+        };
+        let key = norm_key(alias)
+            .ok_or_else(|| anyhow!("historical_countries.tsv:{}: empty alias", n + 1))?;
+        let until = match cols.next().map(str::trim) {
+            None | Some("") => None,
+            Some(y) => Some(y.parse().map_err(|_| {
+                anyhow!("historical_countries.tsv:{}: bad until year {y:?}", n + 1)
+            })?),
+        };
+        out.push((key, codes.split(',').map(str::trim).collect(), until));
+    }
+    Ok(out)
+}
+
+/// Build the section bytes; aliases whose countries aren't in the DB are skipped.
+pub fn build_section(records: &[GeoRecord]) -> Result<Vec<u8>> {
+    // country code -> (population, record id) of its largest PCL* record
+    let mut country: HashMap<&str, (u32, u32)> = HashMap::new();
+    for r in records {
+        // PCLH = historical entity, i.e. the thing being expanded
+        if r.feat_class != b'A' || !r.feat_code.starts_with("PCL") || r.feat_code == "PCLH" {
+            continue;
+        }
+        let e = country
+            .entry(r.country.as_str())
+            .or_insert((r.population, r.id));
+        if r.population > e.0 {
+            *e = (r.population, r.id);
+        }
+    }
+
+    let mut section = Section::default();
+    for (alias, codes, until) in curated()? {
+        let ids: Vec<u32> = codes
+            .iter()
+            .filter_map(|c| country.get(c).map(|(_, id)| *id))
+            .collect();
+        if !ids.is_empty() {
+            if let Some(y) = until {
+                section.until.insert(alias.clone(), y);
+            }
+            section.aliases.insert(alias, ids);
+        }
+    }
+    eprintln!("[synonyms] aliases={}", section.aliases.len());
+    if section.aliases.is_empty() {
+        return Ok(Vec::new());
+    }
+    Ok(serde_json::to_vec(&section)?)
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Section {
+    aliases: BTreeMap<String, Vec<u32>>,
+    until: BTreeMap<String, i32>,
+}
+
+/// Sections written before `until` existed are the bare alias map.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AnySection {
+    Dated(Section),
+    Plain(BTreeMap<String, Vec<u32>>),
+}
+
+pub struct Synonyms {
+    table: BTreeMap<String, Vec<u32>>,
+    until: BTreeMap<String, i32>,
+}
+
+impl Synonyms {
+    /// `None` for DBs without a synonyms section.
+    pub fn from_section(section: &[u8]) -> Result<Option<Self>> {
+        if section.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(match serde_json::from_slice(section)? {
+            AnySection::Dated(s) => Self {
+                table: s.aliases,
+                until: s.until,
+            },
+            AnySection::Plain(table) => Self {
+                table,
+                until: BTreeMap::new(),
+            },
+        }))
+    }
+
+    /// Record ids for a normalized key, if it is a known alias.
+    pub fn expand(&self, key: &str) -> Option<&[u32]> {
+        self.table.get(key).map(Vec::as_slice)
+    }
+
+    /// True when `key` still named a current state in `year` ("zaire" in 1990).
+    pub fn current_in(&self, key: &str, year: i32) -> bool {
+        self.until.get(key).is_some_and(|&until| year < until)
+    }
+}
@@ -0,0 +1,126 @@
+// src/throttle.rs
+//
+// Admission control for `geodb serve`, ahead of every API handler (not
+// /health or /metrics, so probes and scrapes get through under load):
+// - a token bucket per client IP: [server] rate_limit requests per second,
+//   up to rate_burst at once; over it, 429 with Retry-After
+// - at most [server] max_in_flight requests being handled; over it, 503 at
+//   once rather than a queue that grows with the crawler
+// Both are off unless configured. The client is the TCP peer: behind a proxy
+// every request has the proxy's address, so rate-limit there instead. A
+// streamed response (stream=true) leaves the in-flight count once its head
+// is sent.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Buckets kept before idle (full) ones are dropped.
+const PRUNE_AT: usize = 65_536;
+
+pub struct RateLimiter {
+    /// Tokens per second.
+    rate: f64,
+    burst: f64,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    at: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(rate: f64, burst: u32) -> Self {
+        Self {
+            rate,
+            burst: f64::from(burst.max(1)),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a token for `ip`; Err is how long until the next one.
+    pub fn check(&self, ip: IpAddr, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if buckets.len() >= PRUNE_AT {
+            buckets.retain(|_, b| self.refilled(b, now) < self.burst);
+        }
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: self.burst,
+            at: now,
+        });
+        bucket.tokens = self.refilled(bucket, now);
+        bucket.at = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate))
+        }
+    }
+
+    fn refilled(&self, b: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(b.at).as_secs_f64();
+        (b.tokens + elapsed * self.rate).min(self.burst)
+    }
+}
+
+/// Why a request was turned away.
+pub enum Refusal {
+    /// Over the client's rate; retry after this long.
+    Rate(Duration),
+    /// max_in_flight requests already running.
+    Busy,
+}
+
+/// Both limits plus what they refused; clones share state.
+#[derive(Clone, Default)]
+pub struct Throttle {
+    rate: Option<Arc<RateLimiter>>,
+    in_flight: Option<Arc<Semaphore>>,
+    rate_limited: Arc<AtomicU64>,
+    busy: Arc<AtomicU64>,
+}
+
+impl Throttle {
+    /// `rate`: (requests per second, burst) per client IP.
+    pub fn new(rate: Option<(f64, u32)>, max_in_flight: Option<usize>) -> Self {
+        Self {
+            rate: rate.map(|(r, b)| Arc::new(RateLimiter::new(r, b))),
+            in_flight: max_in_flight.map(|n| Arc::new(Semaphore::new(n))),
+            ..Self::default()
+        }
+    }
+
+    /// Admits a request from `ip`; the permit (if any) is held while it runs.
+    pub fn admit(&self, ip: IpAddr) -> Result<Option<OwnedSemaphorePermit>, Refusal> {
+        if let Some(limiter) = &self.rate {
+            if let Err(wait) = limiter.check(ip, Instant::now()) {
+                self.rate_limited.fetch_add(1, Ordering::Relaxed);
+                return Err(Refusal::Rate(wait));
+            }
+        }
+        match &self.in_flight {
+            Some(slots) => match slots.clone().try_acquire_owned() {
+                Ok(permit) => Ok(Some(permit)),
+                Err(_) => {
+                    self.busy.fetch_add(1, Ordering::Relaxed);
+                    Err(Refusal::Busy)
+                }
+            },
+            None => Ok(None),
+        }
+    }
+
+    pub fn rate_limited(&self) -> u64 {
+        self.rate_limited.load(Ordering::Relaxed)
+    }
+
+    pub fn busy(&self) -> u64 {
+        self.busy.load(Ordering::Relaxed)
+    }
+}
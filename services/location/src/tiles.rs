@@ -0,0 +1,257 @@
+// src/tiles.rs
+//
+// Mapbox Vector Tiles of places (`GET /tiles/{z}/{x}/{y}.mvt`), so the globe's
+// label layer can come straight from geodb instead of a separate tile server.
+// One layer, "places", of POINT features; id = geoname id, properties name,
+// population and geoname_id. Places come from the reverse index's grid
+// (reverse.rs) within the tile's Web Mercator bounds; a place is in tiles from
+// its display hint's zoom level on (hints.rs; below zoom 3 as at 3, or the
+// world view would be empty), at most MAX_FEATURES per tile, coarsest and most
+// populous first. The protobuf (vector_tile.proto, spec 2.1) is written by
+// hand: a dozen fields don't warrant a codegen dependency.
+
+use anyhow::{anyhow, bail, Result};
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+use crate::hints::zoom_level;
+use crate::reverse::ReverseIndex;
+use crate::{read_record_ref_by_id, Db};
+
+pub const CONTENT_TYPE: &str = "application/vnd.mapbox-vector-tile";
+pub const LAYER: &str = "places";
+pub const EXTENT: u32 = 4096;
+pub const MAX_ZOOM: u8 = 20;
+pub const MAX_FEATURES: usize = 500;
+/// Zooms below this select places as this one does.
+const MIN_SELECT_ZOOM: u8 = 3;
+/// Web Mercator's latitude limit.
+const MAX_LAT: f64 = 85.051_128_779_806_59;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TileId {
+    pub z: u8,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl TileId {
+    /// From the path segments; `y` carries the ".mvt" suffix.
+    pub fn parse(z: &str, x: &str, y: &str) -> Result<Self> {
+        let y = y
+            .strip_suffix(".mvt")
+            .ok_or_else(|| anyhow!("tile {y:?}: only .mvt is served"))?;
+        let z: u8 = z.parse().map_err(|_| anyhow!("tile zoom {z:?}"))?;
+        if z > MAX_ZOOM {
+            bail!("tile zoom {z} above {MAX_ZOOM}");
+        }
+        let n = 1u32 << z;
+        let coord = |s: &str| match s.parse::<u32>() {
+            Ok(v) if v < n => Ok(v),
+            _ => Err(anyhow!("tile {z}/{x}/{y}: x and y are 0..{n}")),
+        };
+        Ok(Self {
+            z,
+            x: coord(x)?,
+            y: coord(y)?,
+        })
+    }
+
+    /// ((south, north), (west, east)) in degrees.
+    pub fn bounds(&self) -> ((f64, f64), (f64, f64)) {
+        let n = f64::from(1u32 << self.z);
+        let lon = |x: u32| f64::from(x) / n * 360.0 - 180.0;
+        let lat = |y: u32| {
+            (PI * (1.0 - 2.0 * f64::from(y) / n))
+                .sinh()
+                .atan()
+                .to_degrees()
+        };
+        (
+            (lat(self.y + 1), lat(self.y)),
+            (lon(self.x), lon(self.x + 1)),
+        )
+    }
+
+    /// Tile-local position in [0, EXTENT), y down.
+    pub fn project(&self, lat: f32, lon: f32) -> (i32, i32) {
+        let n = f64::from(1u32 << self.z);
+        let lat = f64::from(lat).clamp(-MAX_LAT, MAX_LAT).to_radians();
+        let wx = (f64::from(lon) + 180.0) / 360.0 * n;
+        let wy = (1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / PI) / 2.0 * n;
+        let local = |w: f64, origin: u32| {
+            let v = ((w - f64::from(origin)) * f64::from(EXTENT)).floor() as i32;
+            v.clamp(0, EXTENT as i32 - 1)
+        };
+        (local(wx, self.x), local(wy, self.y))
+    }
+}
+
+/// One feature's worth of a record.
+#[derive(Clone, Copy, Debug)]
+pub struct TilePlace<'a> {
+    pub id: u32,
+    pub name: &'a str,
+    pub lat: f32,
+    pub lon: f32,
+    pub population: u32,
+}
+
+/// The encoded tile `tile` of `db`; empty when no place falls in it.
+pub fn render(db: &Db, reverse: &ReverseIndex, tile: TileId) -> Result<Vec<u8>> {
+    let ((south, north), (west, east)) = tile.bounds();
+    let max_zoom = tile.z.max(MIN_SELECT_ZOOM);
+    let mut picked = Vec::new();
+    for (id, lat, lon) in reverse.within((south as f32, north as f32), (west as f32, east as f32)) {
+        let Some(r) = read_record_ref_by_id(db, id)? else {
+            continue;
+        };
+        let zoom = zoom_level(r.feat_class, r.feat_code, r.population);
+        if zoom <= max_zoom {
+            let place = TilePlace {
+                id,
+                name: r.name,
+                lat,
+                lon,
+                population: r.population,
+            };
+            picked.push((zoom, place));
+        }
+    }
+    picked.sort_unstable_by(|(za, a), (zb, b)| {
+        za.cmp(zb)
+            .then(b.population.cmp(&a.population))
+            .then(a.id.cmp(&b.id))
+    });
+    picked.truncate(MAX_FEATURES);
+    let places: Vec<TilePlace> = picked.into_iter().map(|(_, p)| p).collect();
+    Ok(encode(&tile, &places))
+}
+
+/* -------------------------
+   protobuf
+-------------------------- */
+
+const KEYS: [&str; 3] = ["name", "population", "geoname_id"];
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Value<'a> {
+    Str(&'a str),
+    Uint(u64),
+}
+
+/// A Tile message with one "places" layer; empty for no places.
+pub fn encode(tile: &TileId, places: &[TilePlace]) -> Vec<u8> {
+    if places.is_empty() {
+        return Vec::new();
+    }
+    let mut values: Vec<Value> = Vec::new();
+    let mut value_ix: HashMap<Value, u32> = HashMap::new();
+    let mut layer = Vec::new();
+    field_varint(&mut layer, 15, 2); // version
+    field_bytes(&mut layer, 1, LAYER.as_bytes());
+    for p in places {
+        let mut tags = Vec::new();
+        let props = [
+            Value::Str(p.name),
+            Value::Uint(u64::from(p.population)),
+            Value::Uint(u64::from(p.id)),
+        ];
+        for (k, v) in props.into_iter().enumerate() {
+            let ix = *value_ix.entry(v).or_insert_with(|| {
+                values.push(v);
+                values.len() as u32 - 1
+            });
+            varint(&mut tags, k as u64);
+            varint(&mut tags, u64::from(ix));
+        }
+        // MoveTo(1) from the tile origin
+        let (px, py) = tile.project(p.lat, p.lon);
+        let mut geometry = Vec::new();
+        varint(&mut geometry, (1 << 3) | 1);
+        varint(&mut geometry, u64::from(zigzag(px)));
+        varint(&mut geometry, u64::from(zigzag(py)));
+
+        let mut feature = Vec::new();
+        field_varint(&mut feature, 1, u64::from(p.id));
+        field_bytes(&mut feature, 2, &tags);
+        field_varint(&mut feature, 3, 1); // POINT
+        field_bytes(&mut feature, 4, &geometry);
+        field_bytes(&mut layer, 2, &feature);
+    }
+    for k in KEYS {
+        field_bytes(&mut layer, 3, k.as_bytes());
+    }
+    for v in &values {
+        let mut value = Vec::new();
+        match v {
+            Value::Str(s) => field_bytes(&mut value, 1, s.as_bytes()),
+            Value::Uint(n) => field_varint(&mut value, 5, *n),
+        }
+        field_bytes(&mut layer, 4, &value);
+    }
+    field_varint(&mut layer, 5, u64::from(EXTENT));
+
+    let mut out = Vec::with_capacity(layer.len() + 8);
+    field_bytes(&mut out, 3, &layer);
+    out
+}
+
+fn varint(out: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        out.push(v as u8 | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+fn field_varint(out: &mut Vec<u8>, field: u32, v: u64) {
+    varint(out, u64::from(field) << 3);
+    varint(out, v);
+}
+
+fn field_bytes(out: &mut Vec<u8>, field: u32, bytes: &[u8]) {
+    varint(out, (u64::from(field) << 3) | 2);
+    varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn zigzag(n: i32) -> u32 {
+    ((n << 1) ^ (n >> 31)) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_path_segments() {
+        let t = TileId::parse("3", "4", "2.mvt").unwrap();
+        assert_eq!(t, TileId { z: 3, x: 4, y: 2 });
+        let top = TileId::parse("0", "0", "0.mvt").unwrap();
+        let ((south, north), (west, east)) = top.bounds();
+        assert!((north - MAX_LAT).abs() < 1e-9 && (south + MAX_LAT).abs() < 1e-9);
+        assert_eq!((west, east), (-180.0, 180.0));
+        assert!(TileId::parse(&MAX_ZOOM.to_string(), "0", "0.mvt").is_ok());
+    }
+
+    #[test]
+    fn rejects_malformed_tiles() {
+        for (z, x, y) in [
+            ("3", "4", "2"),
+            ("3", "4", "2.png"),
+            ("3", "4", ".mvt"),
+            ("", "0", "0.mvt"),
+            ("z", "0", "0.mvt"),
+            ("-1", "0", "0.mvt"),
+            ("21", "0", "0.mvt"),
+            ("256", "0", "0.mvt"),
+            ("3", "8", "0.mvt"),
+            ("3", "0", "8.mvt"),
+            ("3", "-1", "0.mvt"),
+            ("3", "1.5", "0.mvt"),
+        ] {
+            assert!(TileId::parse(z, x, y).is_err(), "{z}/{x}/{y}");
+        }
+    }
+}
@@ -0,0 +1,60 @@
+// src/tokens.rs
+//
+// Token index for partial mentions: every significant word of a multi-word
+// key is a key of its own in the tokens FST ("rio de janeiro" under "rio" and
+// "janeiro"). It is a separate FST over the shared postings blob, like the
+// unaccented one, so a hit there is a token hit by construction and the main
+// FST stays full names only. Queries use it with `mode=token`, after exact
+// and alias lookups miss; answers then carry `"matched": "token"`.
+// Words shorter than MIN_CHARS and common particles are left out, and so are
+// tokens with more than MAX_POSTINGS ids ("city", "county"), which would only
+// ever return noise.
+
+use ahash::RandomState;
+
+use crate::build::FastBuildMap;
+
+const MIN_CHARS: usize = 3;
+pub const MAX_POSTINGS: usize = 10_000;
+
+const STOPWORDS: &[&str] = &[
+    "and", "del", "della", "der", "des", "das", "die", "dos", "las", "les", "los", "sur", "the",
+    "und",
+];
+
+/// Significant words of a multi-word key; nothing for single-word keys.
+pub fn tokens(key: &str) -> Vec<&str> {
+    let words: Vec<&str> = key
+        .split(|c: char| c.is_whitespace() || c == '-')
+        .filter(|w| !w.is_empty())
+        .collect();
+    if words.len() < 2 {
+        return Vec::new();
+    }
+    words
+        .into_iter()
+        .filter(|w| w.chars().count() >= MIN_CHARS && !STOPWORDS.contains(w))
+        .collect()
+}
+
+/// Token -> union of the postings of every key containing it.
+pub fn build_keys(key_to_ids: &FastBuildMap) -> FastBuildMap {
+    let mut out: FastBuildMap = FastBuildMap::with_hasher(RandomState::new());
+    for (k, ids) in key_to_ids {
+        for t in tokens(k) {
+            out.entry(t.to_string()).or_default().extend_from_slice(ids);
+        }
+    }
+    let before = out.len();
+    for ids in out.values_mut() {
+        ids.sort_unstable();
+        ids.dedup();
+    }
+    out.retain(|_, ids| ids.len() <= MAX_POSTINGS);
+    eprintln!(
+        "[tokens] keys={} dropped_over_cap={}",
+        out.len(),
+        before - out.len()
+    );
+    out
+}
@@ -0,0 +1,104 @@
+// src/transport.rs
+//
+// Transport hubs for `GET /transport?key=..`: airports (AIRP), railway
+// stations (RSTN) and ports (PRT). Stories name airports by code and by name
+// interchangeably, and as plain /query results they rank behind the towns of
+// the same name. The build collects IATA / ICAO codes ("iata" / "icao" rows of
+// alternateNamesV2) of transport records into the transport section, as JSON:
+//   { "iata": { "JFK": [5122732] }, "icao": { "KJFK": [5122732] } }
+// A query tries the code tables first, then the name index restricted to the
+// transport feature codes. Hubs mostly have population 0: build with
+// --min-pop 0 or the records never make it into the DB.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+use crate::build::{FeatureFilter, GeoRecord};
+use crate::concordance::ExternalRef;
+
+pub const FEATURE_CODES: &[&str] = &["AIRP", "RSTN", "PRT"];
+
+/// Query-time filter admitting transport records only.
+pub fn features() -> FeatureFilter {
+    FeatureFilter::codes(FEATURE_CODES)
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CodeKind {
+    Iata,
+    Icao,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Tables {
+    #[serde(default)]
+    iata: BTreeMap<String, Vec<u32>>,
+    #[serde(default)]
+    icao: BTreeMap<String, Vec<u32>>,
+}
+
+/// Build the section bytes from the codes the sources reported; codes of
+/// records that are not transport hubs are dropped.
+pub fn build_section(records: &[GeoRecord], refs: &[(u32, ExternalRef)]) -> Result<Vec<u8>> {
+    let filter = features();
+    let hubs: HashSet<u32> = records
+        .iter()
+        .filter(|r| filter.admits(&(*r).into()))
+        .map(|r| r.id)
+        .collect();
+
+    let mut t = Tables::default();
+    for (id, r) in refs.iter().filter(|(id, _)| hubs.contains(id)) {
+        let (table, code) = match r {
+            ExternalRef::Iata(c) => (&mut t.iata, c),
+            ExternalRef::Icao(c) => (&mut t.icao, c),
+            _ => continue,
+        };
+        table
+            .entry(code.to_ascii_uppercase())
+            .or_default()
+            .push(*id);
+    }
+    for ids in t.iata.values_mut().chain(t.icao.values_mut()) {
+        ids.sort_unstable();
+        ids.dedup();
+    }
+    eprintln!(
+        "[transport] hubs={} iata={} icao={}",
+        hubs.len(),
+        t.iata.len(),
+        t.icao.len()
+    );
+    if t.iata.is_empty() && t.icao.is_empty() {
+        return Ok(Vec::new());
+    }
+    Ok(serde_json::to_vec(&t)?)
+}
+
+pub struct TransportCodes {
+    tables: Tables,
+}
+
+impl TransportCodes {
+    /// `None` for DBs without a transport section.
+    pub fn from_section(section: &[u8]) -> Result<Option<Self>> {
+        if section.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            tables: serde_json::from_slice(section)?,
+        }))
+    }
+
+    /// Records carrying `code` (any case) as an IATA or ICAO code.
+    pub fn get(&self, code: &str) -> Vec<(u32, CodeKind)> {
+        let code = code.trim().to_ascii_uppercase();
+        let iata = self.tables.iata.get(&code).into_iter().flatten();
+        let icao = self.tables.icao.get(&code).into_iter().flatten();
+        iata.map(|id| (*id, CodeKind::Iata))
+            .chain(icao.map(|id| (*id, CodeKind::Icao)))
+            .collect()
+    }
+}
@@ -0,0 +1,190 @@
+// src/units.rs
+//
+// Typed geodb.toml values: durations ("30s", "1h 30m"; humantime syntax) and
+// sizes ("512MiB", "2GB"; bytesize syntax). Bare integers still load, as
+// seconds / bytes, so configs written with the old `*_secs` keys keep working
+// through their serde aliases. Saved configs always get the string form.
+
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Duration(pub std::time::Duration);
+
+impl Duration {
+    pub const fn from_secs(secs: u64) -> Self {
+        Self(std::time::Duration::from_secs(secs))
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0.is_zero()
+    }
+}
+
+impl fmt::Display for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", humantime::format_duration(self.0))
+    }
+}
+
+impl Serialize for Duration {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Duration {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        struct V;
+        impl Visitor<'_> for V {
+            type Value = Duration;
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a duration like \"30s\" or \"1h 30m\", or seconds")
+            }
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Duration, E> {
+                Ok(Duration::from_secs(v))
+            }
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Duration, E> {
+                u64::try_from(v)
+                    .map(Duration::from_secs)
+                    .map_err(|_| E::custom(format!("negative duration {v}")))
+            }
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Duration, E> {
+                humantime::parse_duration(v.trim())
+                    .map(Duration)
+                    .map_err(|e| E::custom(format!("duration {v:?}: {e}")))
+            }
+        }
+        d.deserialize_any(V)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ByteSize(pub u64);
+
+impl ByteSize {
+    pub const fn mib(n: u64) -> Self {
+        Self(n << 20)
+    }
+}
+
+impl fmt::Display for ByteSize {
+    /// Largest binary unit that divides evenly, so values round-trip.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (shift, unit) in [(30, "GiB"), (20, "MiB"), (10, "KiB")] {
+            if self.0 != 0 && self.0.is_multiple_of(1 << shift) {
+                return write!(f, "{}{unit}", self.0 >> shift);
+            }
+        }
+        write!(f, "{}B", self.0)
+    }
+}
+
+impl Serialize for ByteSize {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteSize {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        struct V;
+        impl Visitor<'_> for V {
+            type Value = ByteSize;
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a size like \"512MiB\" or \"2GB\", or bytes")
+            }
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<ByteSize, E> {
+                Ok(ByteSize(v))
+            }
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<ByteSize, E> {
+                u64::try_from(v)
+                    .map(ByteSize)
+                    .map_err(|_| E::custom(format!("negative size {v}")))
+            }
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<ByteSize, E> {
+                v.trim()
+                    .parse::<bytesize::ByteSize>()
+                    .map(|b| ByteSize(b.as_u64()))
+                    .map_err(|e| E::custom(format!("size {v:?}: {e}")))
+            }
+        }
+        d.deserialize_any(V)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn duration(json: &str) -> Result<Duration, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    fn size(json: &str) -> Result<ByteSize, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    #[test]
+    fn durations_parse_strings_and_seconds() {
+        assert_eq!(duration("\"30s\"").unwrap(), Duration::from_secs(30));
+        assert_eq!(duration("\" 1h 30m \"").unwrap(), Duration::from_secs(5400));
+        assert_eq!(duration("90").unwrap(), Duration::from_secs(90));
+        assert!(duration("0").unwrap().is_zero());
+    }
+
+    #[test]
+    fn durations_round_trip() {
+        let d = Duration::from_secs(5400);
+        assert_eq!(d.to_string(), "1h 30m");
+        assert_eq!(duration(&serde_json::to_string(&d).unwrap()).unwrap(), d);
+    }
+
+    #[test]
+    fn rejects_malformed_durations() {
+        for json in [
+            "-1",
+            "\"\"",
+            "\"soon\"",
+            "\"30\"",
+            "\"30 parsecs\"",
+            "1.5",
+            "true",
+        ] {
+            assert!(duration(json).is_err(), "{json}");
+        }
+    }
+
+    #[test]
+    fn sizes_parse_strings_and_bytes() {
+        assert_eq!(size("\"512MiB\"").unwrap(), ByteSize::mib(512));
+        assert_eq!(size("\"2GB\"").unwrap(), ByteSize(2_000_000_000));
+        assert_eq!(size("4096").unwrap(), ByteSize(4096));
+    }
+
+    #[test]
+    fn sizes_display_in_the_largest_even_unit() {
+        assert_eq!(ByteSize::mib(512).to_string(), "512MiB");
+        assert_eq!(ByteSize(2048).to_string(), "2KiB");
+        assert_eq!(ByteSize(1536).to_string(), "1536B");
+        assert_eq!(ByteSize(0).to_string(), "0B");
+        for b in [ByteSize(0), ByteSize(1536), ByteSize::mib(3 << 10)] {
+            assert_eq!(size(&serde_json::to_string(&b).unwrap()).unwrap(), b);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_sizes() {
+        for json in [
+            "-1",
+            "\"\"",
+            "\"lots\"",
+            "\"12 parsecs\"",
+            "\"MiB\"",
+            "true",
+        ] {
+            assert!(size(json).is_err(), "{json}");
+        }
+    }
+}
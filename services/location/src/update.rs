@@ -0,0 +1,242 @@
+// src/update.rs
+//
+// `geodb update --db old.db --modifications modifications-2024-05-01.txt
+// --deletes deletes-2024-05-01.txt --out new.db`: applies a GeoNames daily
+// diff to an existing DB instead of rebuilding from allCountries.zip.
+// - deletes-*.txt (id<TAB>name<TAB>comment): the id leaves every posting list
+//   and the records.
+// - modifications-*.txt (allCountries layout): the record is replaced, or
+//   added when new; its old primary name key loses the id and the new name /
+//   ascii name keys gain it. Rows below --min-pop or in an excluded feature
+//   class / code count as deletes, as they would in a fresh build.
+// Alternate names are kept as they were, language-tagged ones (langs.rs) minus
+// deleted ids: the diff does not carry them, and a stale ascii-name key of a
+// renamed record stays until the next full build.
+// Sections derived from records (unaccented keys, tokens, synonyms,
+// subdivisions, spatial, hot, h3, hierarchy) are rebuilt; the ones built from side files (concordance,
+// admin names, locales, countries, transport, disputed, parents) are carried over unchanged.
+// Postings lists are written with the source build's roaring threshold.
+
+use ahash::RandomState;
+use anyhow::{anyhow, bail, Context, Result};
+use fst::Streamer;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use crate::build::{
+    self, norm_key, parse_allcountries_line, read_line_lossy, FastBuildMap, FeatureFilter,
+    GeoRecord,
+};
+use crate::h3::{self, H3Section};
+use crate::sanitize::{SanitizeConfig, SanitizeCounts};
+use crate::{
+    decode_record, format, hierarchy, hot, load_db, postings, read_postings, read_u32_le_at,
+    read_u64_le_at, reverse, subdivision, synonyms, tokens, Db, OpenOptions,
+};
+
+pub struct UpdateOptions {
+    pub min_pop: u32,
+    pub exclude: FeatureFilter,
+    pub sanitize: SanitizeConfig,
+}
+
+/// Every record in the DB, in id order.
+fn read_records(db: &Db) -> Result<Vec<GeoRecord>> {
+    let offsets = db.offsets_slice();
+    let n = read_u32_le_at(offsets, 0) as usize;
+    let blob = db.records_slice();
+    (0..n)
+        .map(|i| {
+            let off = read_u64_le_at(offsets, 4 + n * 4 + i * 8) as usize;
+            decode_record(&blob[off..], db.version)
+        })
+        .collect()
+}
+
+fn read_keys(db: &Db, fst: &[u8]) -> Result<FastBuildMap> {
+    let mut out: FastBuildMap = FastBuildMap::with_hasher(RandomState::new());
+    if fst.is_empty() {
+        return Ok(out);
+    }
+    let fst = fst::Map::new(fst).map_err(|e| anyhow!("fst load: {e}"))?;
+    let mut stream = fst.stream();
+    while let Some((k, off)) = stream.next() {
+        let ids = read_postings(db, off as usize)?;
+        out.insert(String::from_utf8(k.to_vec())?, ids.into_iter().collect());
+    }
+    Ok(out)
+}
+
+/// Ids listed in a deletes-*.txt file.
+fn read_deletes(path: &Path) -> Result<HashSet<u32>> {
+    let file = File::open(path).with_context(|| format!("open {}", path.display()))?;
+    let mut r = BufReader::new(file);
+    let mut buf = Vec::new();
+    let mut out = HashSet::new();
+    let mut n = 0;
+    while let Some(line) = read_line_lossy(&mut r, &mut buf)? {
+        n += 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let id = line.split('\t').next().unwrap_or_default();
+        let id: u32 = id
+            .trim()
+            .parse()
+            .map_err(|_| anyhow!("{}:{n}: expected geonameid, got {id:?}", path.display()))?;
+        out.insert(id);
+    }
+    Ok(out)
+}
+
+/// Records of a modifications-*.txt file; rows a fresh build would drop are
+/// returned as deletes.
+fn read_modifications(path: &Path, opts: &UpdateOptions) -> Result<(Vec<GeoRecord>, HashSet<u32>)> {
+    let file = File::open(path).with_context(|| format!("open {}", path.display()))?;
+    let mut r = BufReader::new(file);
+    let mut buf = Vec::new();
+    let mut records = Vec::new();
+    let mut dropped = HashSet::new();
+    let mut n = 0;
+    while let Some(line) = read_line_lossy(&mut r, &mut buf)? {
+        n += 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let rec =
+            parse_allcountries_line(&line, 0).with_context(|| format!("{}:{n}", path.display()))?;
+        if rec.population < opts.min_pop || opts.exclude.excludes(&rec) {
+            dropped.insert(rec.id);
+        } else {
+            records.push(rec);
+        }
+    }
+    Ok((records, dropped))
+}
+
+pub fn update_db(
+    db_path: &Path,
+    modifications: Option<&Path>,
+    deletes: Option<&Path>,
+    out: &Path,
+    opts: &UpdateOptions,
+) -> Result<()> {
+    if modifications.is_none() && deletes.is_none() {
+        bail!("nothing to apply: pass --modifications and/or --deletes");
+    }
+    let db = load_db(Some(db_path), OpenOptions::default())?;
+    let mut records = read_records(&db)?;
+    let mut key_to_ids = read_keys(&db, db.fst_slice())?;
+    let mut lang_names = read_keys(&db, db.lang_names_slice())?;
+    eprintln!(
+        "[update] db={} records={} keys={}",
+        db_path.display(),
+        records.len(),
+        key_to_ids.len()
+    );
+
+    let mut removed = match deletes {
+        Some(p) => read_deletes(p)?,
+        None => HashSet::new(),
+    };
+    let (mut changed, dropped) = match modifications {
+        Some(p) => read_modifications(p, opts)?,
+        None => (Vec::new(), HashSet::new()),
+    };
+    removed.extend(dropped);
+    // the last row for an id wins; deletes win over modifications
+    let mut seen = HashSet::new();
+    changed.reverse();
+    changed.retain(|r| !removed.contains(&r.id) && seen.insert(r.id));
+    let mut counts = SanitizeCounts::default();
+    opts.sanitize.records(&mut changed, &mut counts);
+    let changed_ids: HashSet<u32> = changed.iter().map(|r| r.id).collect();
+
+    // Unlink old records: deleted ids from every key, modified ones from
+    // their old primary name.
+    let old: HashMap<u32, &GeoRecord> = records.iter().map(|r| (r.id, r)).collect();
+    let mut unlink: HashMap<String, HashSet<u32>> = HashMap::new();
+    for id in &changed_ids {
+        if let Some(k) = old.get(id).and_then(|r| norm_key(&r.name)) {
+            unlink.entry(k).or_default().insert(*id);
+        }
+    }
+    for (k, ids) in key_to_ids.iter_mut() {
+        let stale = unlink.get(k);
+        ids.retain(|id| !removed.contains(id) && !stale.is_some_and(|s| s.contains(id)));
+    }
+    let deleted = records.iter().filter(|r| removed.contains(&r.id)).count();
+    let added = changed.iter().filter(|r| !old.contains_key(&r.id)).count();
+    records.retain(|r| !removed.contains(&r.id) && !changed_ids.contains(&r.id));
+
+    for r in &changed {
+        for k in [norm_key(&r.name), norm_key(&r.ascii_name)]
+            .into_iter()
+            .flatten()
+        {
+            key_to_ids.entry(k).or_default().push(r.id);
+        }
+    }
+    for ids in lang_names.values_mut() {
+        ids.retain(|id| !removed.contains(id));
+    }
+    lang_names.retain(|_, ids| !ids.is_empty());
+    records.extend(changed);
+    opts.sanitize.keys(&mut key_to_ids, &mut counts);
+    key_to_ids.retain(|_, ids| !ids.is_empty());
+    for ids in key_to_ids.values_mut() {
+        ids.sort_unstable();
+        ids.dedup();
+    }
+    eprintln!(
+        "[update] deleted={deleted} modified={} added={added} records={} keys={}",
+        changed_ids.len() - added,
+        records.len(),
+        key_to_ids.len()
+    );
+
+    let unaccented = build::unaccented_keys(&key_to_ids);
+    let tokens = tokens::build_keys(&key_to_ids);
+    let synonyms = synonyms::build_section(&records)?;
+    let subdivisions = subdivision::build_section(&records)?;
+    let hierarchy = hierarchy::build_section(&records)?;
+    let spatial = reverse::build_section(&records)?;
+    let hot_records = match db.hot_slice() {
+        [] => 0,
+        b => read_u32_le_at(b, 0) as usize,
+    };
+    let hot = hot::build_section(&records, hot_records)?;
+    let h3_cells = match H3Section::parse(db.h3_slice())? {
+        Some(s) => h3::build_section(&records, s.resolution)?,
+        None => Vec::new(),
+    };
+
+    build::write_db(
+        out,
+        &key_to_ids,
+        &unaccented,
+        &tokens,
+        &lang_names,
+        &records,
+        &[
+            (format::SECTION_CONCORDANCE, db.concordance_slice()),
+            (format::SECTION_SYNONYMS, &synonyms),
+            (format::SECTION_H3, &h3_cells),
+            (format::SECTION_SPATIAL, &spatial),
+            (format::SECTION_HOT, &hot),
+            (format::SECTION_ADMIN_NAMES, db.admin_names_slice()),
+            (format::SECTION_SUBDIVISIONS, &subdivisions),
+            (format::SECTION_LOCALES, db.locales_slice()),
+            (format::SECTION_COUNTRIES, db.countries_slice()),
+            (format::SECTION_DISPUTED, db.disputed_slice()),
+            (format::SECTION_HIERARCHY, &hierarchy),
+            (format::SECTION_PARENTS, db.parents_slice()),
+            (format::SECTION_TRANSPORT, db.transport_slice()),
+        ],
+        postings::roaring_threshold(db.postings_info_slice())?,
+    )?;
+    eprintln!("[update] out={}", out.display());
+    Ok(())
+}
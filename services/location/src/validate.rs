@@ -0,0 +1,166 @@
+// src/validate.rs
+//
+// `geodb validate --db file`: full integrity walk, for DB files copied
+// between hosts (multi-GB transfers do get truncated or bit-flipped). Goes
+// further than the check run at open (`Db::check`):
+// - every entry of the offsets table: ids ascending (lookups binary-search
+//   them), offset inside the records section, record decodes with every
+//   length-prefixed string in bounds and valid UTF-8, and carries its id;
+// - every key of every FST (keys, unaccented, tokens, lang_names): postings
+//   decode, and every id resolves in the offsets table to a sound record.
+// Prints a JSON report with the first problems found and fails if there
+// are any. Unlike `geodb preflight` it runs no queries.
+
+use anyhow::{bail, Result};
+use fst::Streamer;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::{
+    decode_record_ref, load_db, read_postings_strict, read_u32_le_at, read_u64_le_at, OpenOptions,
+};
+
+/// Problems listed in the report; all are counted.
+const MAX_ISSUES: usize = 100;
+
+#[derive(Serialize)]
+pub struct ValidateJson {
+    pub ok: bool,
+    pub records: usize,
+    pub keys: usize,
+    /// Ids looked up across all postings lists.
+    pub ids: u64,
+    pub problems: usize,
+    /// The first MAX_ISSUES problems.
+    pub issues: Vec<String>,
+}
+
+#[derive(Default)]
+struct Issues {
+    count: usize,
+    first: Vec<String>,
+}
+
+impl Issues {
+    fn note(&mut self, what: impl FnOnce() -> String) {
+        self.count += 1;
+        if self.first.len() < MAX_ISSUES {
+            self.first.push(what());
+        }
+    }
+}
+
+pub fn validate(path: &Path) -> Result<ValidateJson> {
+    // lenient: the open check must not stop the walk at the first problem;
+    // mapped: the walk pages the file through once instead of copying it
+    let db = load_db(
+        Some(path),
+        OpenOptions {
+            lenient: true,
+            mmap: true,
+        },
+    )?;
+    let mut issues = Issues::default();
+
+    let offsets = db.offsets_slice();
+    if offsets.len() < 4 {
+        bail!("corrupt offsets: {} bytes", offsets.len());
+    }
+    let n = read_u32_le_at(offsets, 0) as usize;
+    if 4 + n * 12 > offsets.len() {
+        bail!("corrupt offsets: {n} records, {} bytes", offsets.len());
+    }
+    let ids_table = &offsets[4..4 + n * 4];
+    let offs_table = &offsets[4 + n * 4..4 + n * 12];
+    let records = db.records_slice();
+
+    // records the postings must not point at
+    let mut unsound: HashSet<u32> = HashSet::new();
+    let mut prev: Option<u32> = None;
+    for i in 0..n {
+        let id = read_u32_le_at(ids_table, i * 4);
+        if prev.is_some_and(|p| p >= id) {
+            issues.note(|| format!("offsets table: id {id} at entry {i} is out of order"));
+        }
+        prev = Some(id);
+        let off = read_u64_le_at(offs_table, i * 8) as usize;
+        if off >= records.len() {
+            issues.note(|| format!("record {id}: offset {off} past records end"));
+            unsound.insert(id);
+            continue;
+        }
+        match decode_record_ref(&records[off..], db.version) {
+            Ok(r) if r.id == id => {}
+            Ok(r) => {
+                issues.note(|| format!("record {id}: offset {off} holds record {}", r.id));
+                unsound.insert(id);
+            }
+            Err(e) => {
+                issues.note(|| format!("record {id} at offset {off}: {e}"));
+                unsound.insert(id);
+            }
+        }
+    }
+
+    let has_id = |id: u32| {
+        let (mut lo, mut hi) = (0usize, n);
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if read_u32_le_at(ids_table, mid * 4) < id {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        lo < n && read_u32_le_at(ids_table, lo * 4) == id
+    };
+
+    let (mut keys, mut ids) = (0usize, 0u64);
+    for (name, bytes) in [
+        ("fst", db.fst_slice()),
+        ("unaccented", db.unaccented_slice()),
+        ("tokens", db.tokens_slice()),
+        ("lang_names", db.lang_names_slice()),
+    ] {
+        if bytes.is_empty() {
+            continue;
+        }
+        let map = match fst::Map::new(bytes) {
+            Ok(m) => m,
+            Err(e) => {
+                issues.note(|| format!("{name}: {e}"));
+                continue;
+            }
+        };
+        let mut stream = map.stream();
+        while let Some((k, off)) = stream.next() {
+            keys += 1;
+            let key = || String::from_utf8_lossy(k).into_owned();
+            let list = match read_postings_strict(&db, off as usize) {
+                Ok(list) => list,
+                Err(e) => {
+                    issues.note(|| format!("{name} key {:?} -> postings {off}: {e}", key()));
+                    continue;
+                }
+            };
+            for id in list.into_ids() {
+                ids += 1;
+                if !has_id(id) {
+                    issues.note(|| format!("{name} key {:?}: id {id} not in records", key()));
+                } else if unsound.contains(&id) {
+                    issues.note(|| format!("{name} key {:?}: id {id} is unreadable", key()));
+                }
+            }
+        }
+    }
+
+    Ok(ValidateJson {
+        ok: issues.count == 0,
+        records: n,
+        keys,
+        ids,
+        problems: issues.count,
+        issues: issues.first,
+    })
+}
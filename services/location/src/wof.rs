@@ -0,0 +1,154 @@
+// src/wof.rs
+//
+// Who's On First adapter. Reads the per-placetype "meta" CSVs published with
+// WOF bundles (wof-locality-latest.csv, ...). Columns are looked up by header
+// name because they vary between bundle vintages.
+// - Deprecated / superseded rows are skipped.
+// - Label coordinates (lbl_*) are preferred over geometry centroids (geom_*).
+// - wd_id / gn_id columns (when present) feed the id concordance.
+// - WOF has no population column in most bundles; rows without one count as 0,
+//   so they only survive builds with min_pop = 0.
+
+use anyhow::{anyhow, Context, Result};
+use std::path::PathBuf;
+
+use crate::build::{GeoRecord, SourceAdapter, SourceRecords, SyntheticIds};
+use crate::concordance::{self, ExternalRef};
+
+/// SyntheticIds namespace for WOF ids.
+const WOF_NAMESPACE: u8 = 3;
+
+pub struct WofSource {
+    pub path: PathBuf,
+}
+
+impl SourceAdapter for WofSource {
+    fn describe(&self) -> String {
+        format!("wof:{}", self.path.display())
+    }
+
+    fn load(&self, min_pop: u32, ids: &mut SyntheticIds) -> Result<SourceRecords> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .flexible(true)
+            .from_path(&self.path)
+            .with_context(|| format!("open wof csv: {}", self.path.display()))?;
+        let headers = rdr.headers()?.clone();
+        let col = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|n| headers.iter().position(|h| h == *n))
+        };
+
+        let c_id = col(&["id"]).ok_or_else(|| anyhow!("wof csv: missing id column"))?;
+        let c_name = col(&["name"]).ok_or_else(|| anyhow!("wof csv: missing name column"))?;
+        let c_lat = col(&["lbl_latitude", "geom_latitude"])
+            .ok_or_else(|| anyhow!("wof csv: missing latitude column"))?;
+        let c_lon = col(&["lbl_longitude", "geom_longitude"])
+            .ok_or_else(|| anyhow!("wof csv: missing longitude column"))?;
+        let c_geom_lat = col(&["geom_latitude"]);
+        let c_geom_lon = col(&["geom_longitude"]);
+        let c_placetype = col(&["placetype"]);
+        let c_country = col(&["country", "iso_country", "wof_country"]);
+        let c_population = col(&["population", "wof:population"]);
+        let c_deprecated = col(&["deprecated"]);
+        let c_superseded = col(&["superseded_by"]);
+        let c_wikidata = col(&["wd_id", "wikidata"]);
+        let c_geonames = col(&["gn_id", "geonames_id"]);
+
+        let mut rows: Vec<(i64, GeoRecord, Vec<ExternalRef>)> = Vec::new();
+        let mut skipped: u64 = 0;
+        for row in rdr.records() {
+            let row = row?;
+            let get = |c: Option<usize>| c.and_then(|i| row.get(i)).unwrap_or("").trim();
+
+            let set = |v: &str| !v.is_empty() && v != "0";
+            let retired = set(get(c_deprecated)) || set(get(c_superseded));
+            let Ok(native_id) = get(Some(c_id)).parse::<i64>() else {
+                skipped += 1;
+                continue;
+            };
+            let name = get(Some(c_name));
+            if retired || name.is_empty() {
+                skipped += 1;
+                continue;
+            }
+
+            // lbl_* is often empty; fall back to the geometry centroid
+            let coord = |primary: usize, fallback: Option<usize>| {
+                get(Some(primary))
+                    .parse::<f32>()
+                    .ok()
+                    .or_else(|| get(fallback).parse::<f32>().ok())
+            };
+            let (Some(lat), Some(lon)) = (coord(c_lat, c_geom_lat), coord(c_lon, c_geom_lon))
+            else {
+                skipped += 1;
+                continue;
+            };
+
+            let population: u32 = get(c_population).parse().unwrap_or(0);
+            if population < min_pop {
+                continue;
+            }
+
+            let (feat_class, feat_code) = feature_for(get(c_placetype));
+            let mut refs = vec![ExternalRef::Wof(native_id)];
+            refs.extend(concordance::wikidata_ref(get(c_wikidata)));
+            if let Ok(gid) = get(c_geonames).parse::<u32>() {
+                if gid > 0 {
+                    refs.push(ExternalRef::GeoNames(gid));
+                }
+            }
+            rows.push((
+                native_id,
+                GeoRecord {
+                    id: 0,
+                    name: name.to_string(),
+                    ascii_name: String::new(),
+                    country: get(c_country).to_ascii_uppercase(),
+                    admin1: String::new(),
+                    admin2: String::new(),
+                    lat,
+                    lon,
+                    feat_class,
+                    feat_code: feat_code.to_string(),
+                    population,
+                    timezone: String::new(),
+                },
+                refs,
+            ));
+        }
+
+        rows.sort_by_key(|(native, _, _)| *native);
+        let mut out = SourceRecords {
+            records: Vec::with_capacity(rows.len()),
+            ..Default::default()
+        };
+        for (native, mut rec, refs) in rows {
+            rec.id = ids.assign(WOF_NAMESPACE, native);
+            out.refs.extend(refs.into_iter().map(|r| (rec.id, r)));
+            out.records.push(rec);
+        }
+
+        eprintln!("[wof] records={} skipped={}", out.records.len(), skipped);
+        Ok(out)
+    }
+}
+
+/// Map WOF placetypes onto the closest GeoNames feature class/code.
+fn feature_for(placetype: &str) -> (u8, &'static str) {
+    match placetype {
+        "country" | "empire" => (b'A', "PCLI"),
+        "dependency" => (b'A', "PCLD"),
+        "macroregion" | "region" => (b'A', "ADM1"),
+        "macrocounty" | "county" => (b'A', "ADM2"),
+        "localadmin" => (b'A', "ADM3"),
+        "locality" => (b'P', "PPL"),
+        "borough" | "macrohood" | "neighbourhood" | "microhood" => (b'P', "PPLX"),
+        "continent" => (b'L', "CONT"),
+        "ocean" => (b'H', "OCN"),
+        "marinearea" => (b'H', "SEA"),
+        "campus" | "venue" => (b'S', "BLDG"),
+        _ => (b'L', "AREA"),
+    }
+}
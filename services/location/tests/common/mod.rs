@@ -0,0 +1,49 @@
+// tests/common/mod.rs
+//
+// Shared by the integration tests: the golden fixture
+// (tests/golden/allCountries.txt, ten places) written as a DB by the current
+// writer.
+
+use std::path::{Path, PathBuf};
+
+use geodb_core::build::{self, norm_key, parse_allcountries_line, FastBuildMap};
+use geodb_core::{postings, tokens};
+
+pub fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+/// The fixture as the current writer stores it.
+pub fn build_fixture(out: &Path) {
+    let text = std::fs::read_to_string(golden_dir().join("allCountries.txt")).unwrap();
+    let records: Vec<_> = text
+        .lines()
+        .map(|l| parse_allcountries_line(l, 0).unwrap())
+        .collect();
+    let mut keys = FastBuildMap::default();
+    for r in &records {
+        for k in [norm_key(&r.name), norm_key(&r.ascii_name)]
+            .into_iter()
+            .flatten()
+        {
+            keys.entry(k).or_default().push(r.id);
+        }
+    }
+    for ids in keys.values_mut() {
+        ids.sort_unstable();
+        ids.dedup();
+    }
+    let unaccented = build::unaccented_keys(&keys);
+    let token_keys = tokens::build_keys(&keys);
+    build::write_db(
+        out,
+        &keys,
+        &unaccented,
+        &token_keys,
+        &FastBuildMap::default(),
+        &records,
+        &[],
+        postings::DEFAULT_ROARING_THRESHOLD,
+    )
+    .unwrap();
+}
@@ -0,0 +1,81 @@
+// tests/golden.rs
+//
+// Format compatibility. tests/golden/v<N>.db are small DBs written by each
+// released format version from tests/golden/allCountries.txt; every one of
+// them, and a DB built from the fixture by the current writer, must answer
+// the query set in tests/golden/expected.json identically. Only fields every
+// version stores are compared (no timezone, no scores).
+//
+// After a format change, add the new version's file with
+//   GEODB_BLESS=1 cargo test --test golden
+// and keep the old ones: they are what the reader promises to open.
+
+mod common;
+
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use common::{build_fixture, golden_dir};
+use geodb_core::build;
+use geodb_core::{Geocoder, LookupOptions, OpenOptions};
+
+fn answers(db: &Path, queries: &BTreeMap<String, Value>) -> BTreeMap<String, Value> {
+    let geo = Geocoder::open(Some(db), OpenOptions::default())
+        .unwrap_or_else(|e| panic!("open {}: {e:#}", db.display()));
+    queries
+        .keys()
+        .map(|q| {
+            let answer = geo.lookup(q, &LookupOptions::default()).unwrap();
+            let got: Vec<Value> = answer
+                .candidates
+                .iter()
+                .map(|c| {
+                    json!({
+                        "id": c.geoname_id,
+                        "name": c.name,
+                        "country": c.country,
+                        "admin1": c.admin1,
+                        "feature_code": c.feature_code,
+                        "population": c.population,
+                    })
+                })
+                .collect();
+            (q.clone(), Value::Array(got))
+        })
+        .collect()
+}
+
+fn expected() -> BTreeMap<String, Value> {
+    let text = std::fs::read_to_string(golden_dir().join("expected.json")).unwrap();
+    serde_json::from_str(&text).unwrap()
+}
+
+#[test]
+fn current_writer_matches_expected() {
+    let out = std::env::temp_dir().join(format!("geodb-golden-{}.db", std::process::id()));
+    build_fixture(&out);
+    let want = expected();
+    let got = answers(&out, &want);
+    if std::env::var_os("GEODB_BLESS").is_some() {
+        let dest = golden_dir().join(format!("v{}.db", build::VERSION));
+        std::fs::copy(&out, &dest).unwrap();
+    }
+    let _ = std::fs::remove_file(&out);
+    assert_eq!(got, want);
+}
+
+#[test]
+fn every_golden_version_reads_the_same() {
+    let want = expected();
+    let mut dbs: Vec<PathBuf> = std::fs::read_dir(golden_dir())
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|x| x == "db"))
+        .collect();
+    dbs.sort();
+    assert!(!dbs.is_empty(), "no v<N>.db in {}", golden_dir().display());
+    for db in dbs {
+        assert_eq!(answers(&db, &want), want, "{}", db.display());
+    }
+}
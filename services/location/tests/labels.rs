@@ -0,0 +1,39 @@
+// tests/labels.rs
+//
+// The labels DB (labels.rs) written from the golden fixture
+// (tests/golden/allCountries.txt) and read back.
+
+use std::path::Path;
+
+use geodb_core::bbox::BBox;
+use geodb_core::build;
+use geodb_core::labels::{self, Labels};
+
+#[test]
+fn labels_db_round_trips() {
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/allCountries.txt");
+    let text = std::fs::read_to_string(fixture).unwrap();
+    let records: Vec<_> = text
+        .lines()
+        .map(|l| build::parse_allcountries_line(l, 0).unwrap())
+        .collect();
+    let out = std::env::temp_dir().join(format!("geodb-labels-{}.db", std::process::id()));
+    labels::write(&out, &records).unwrap();
+    let got = Labels::load(&out).unwrap();
+    let _ = std::fs::remove_file(&out);
+
+    assert_eq!(got.len(), records.len());
+    let pops: Vec<u32> = got.iter().map(|l| l.population).collect();
+    assert!(pops.windows(2).all(|w| w[0] >= w[1]), "{pops:?}");
+
+    let merida = got.get(3433955).unwrap();
+    assert_eq!((merida.name, merida.country), ("Mérida", "MX"));
+    assert_eq!(merida.population, 777615);
+    assert!((merida.lat - 20.97537).abs() < 1e-4);
+    assert!(got.get(1).is_none());
+
+    // Paris FR, not Paris US: the only one in a box around France
+    let france = BBox::parse("-5,42,8,51").unwrap();
+    let ids: Vec<u32> = got.in_bbox(&france, 10).iter().map(|l| l.id).collect();
+    assert_eq!(ids, [2988507]);
+}
@@ -0,0 +1,470 @@
+// tests/server.rs
+//
+// HTTP-level behavior of `geodb serve`: each test starts the real binary on a
+// free port against the golden fixture (tests/common) and talks HTTP/1.1 to
+// it over a plain TcpStream, so what is asserted is what any client, in any
+// language, sees on the wire: status codes, JSON bodies, error mapping and
+// headers. Slower than unit tests and needs a free local port, hence
+//   cargo test --test server -- --ignored
+
+mod common;
+
+use serde_json::{json, Value};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+const PARIS_FR: u64 = 2988507;
+const PARIS_US: u64 = 4717560;
+const BERLIN: u64 = 2950159;
+const KYIV: u64 = 703448;
+const MERIDA: u64 = 3433955;
+
+/// A `geodb serve` child on the fixture DB; killed and cleaned up on drop.
+struct Server {
+    addr: SocketAddr,
+    child: Child,
+    db: PathBuf,
+}
+
+impl Server {
+    fn start(name: &str) -> Self {
+        let db = std::env::temp_dir().join(format!("geodb-http-{name}-{}.db", std::process::id()));
+        common::build_fixture(&db);
+        // the OS picks a free port; the listener is dropped before geodb binds it
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .and_then(|l| l.local_addr())
+            .unwrap();
+        let child = Command::new(env!("CARGO_BIN_EXE_geodb"))
+            .args(["serve", "--db"])
+            .arg(&db)
+            .args(["--bind", &addr.to_string()])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("spawn geodb serve");
+        let server = Self { addr, child, db };
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while Instant::now() < deadline {
+            if TcpStream::connect(server.addr).is_ok() && server.get("/health").status == 200 {
+                return server;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        panic!("geodb serve did not come up on {}", server.addr);
+    }
+
+    fn get(&self, path: &str) -> Reply {
+        self.request("GET", path, &[], None)
+    }
+
+    fn post(&self, path: &str, body: &Value) -> Reply {
+        self.request("POST", path, &[], Some(&body.to_string()))
+    }
+
+    fn request(
+        &self,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: Option<&str>,
+    ) -> Reply {
+        let mut s = TcpStream::connect(self.addr).unwrap();
+        s.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        let mut req = format!(
+            "{method} {path} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n",
+            self.addr
+        );
+        for (k, v) in headers {
+            req.push_str(&format!("{k}: {v}\r\n"));
+        }
+        if let Some(b) = body {
+            req.push_str(&format!(
+                "Content-Type: application/json\r\nContent-Length: {}\r\n",
+                b.len()
+            ));
+        }
+        req.push_str("\r\n");
+        req.push_str(body.unwrap_or(""));
+        s.write_all(req.as_bytes()).unwrap();
+        let mut raw = Vec::new();
+        s.read_to_end(&mut raw).unwrap();
+        Reply::parse(&raw)
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_file(&self.db);
+    }
+}
+
+struct Reply {
+    status: u16,
+    /// Lowercased names.
+    headers: Vec<(String, String)>,
+    /// Lossy UTF-8 of `bytes`.
+    body: String,
+    bytes: Vec<u8>,
+}
+
+impl Reply {
+    fn parse(raw: &[u8]) -> Self {
+        let split = raw
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .expect("end of headers");
+        let head = String::from_utf8_lossy(&raw[..split]).into_owned();
+        let mut lines = head.split("\r\n");
+        let status = lines
+            .next()
+            .and_then(|l| l.split(' ').nth(1))
+            .and_then(|s| s.parse().ok())
+            .expect("status line");
+        let headers: Vec<(String, String)> = lines
+            .filter_map(|l| l.split_once(':'))
+            .map(|(k, v)| (k.trim().to_ascii_lowercase(), v.trim().to_string()))
+            .collect();
+        let mut body = raw[split + 4..].to_vec();
+        if headers
+            .iter()
+            .any(|(k, v)| k == "transfer-encoding" && v.eq_ignore_ascii_case("chunked"))
+        {
+            body = dechunk(&body);
+        }
+        Self {
+            status,
+            headers,
+            body: String::from_utf8_lossy(&body).into_owned(),
+            bytes: body,
+        }
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn json(&self) -> Value {
+        serde_json::from_str(&self.body).unwrap_or_else(|e| panic!("not JSON ({e}): {}", self.body))
+    }
+}
+
+fn dechunk(mut b: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    loop {
+        let eol = b.windows(2).position(|w| w == b"\r\n").expect("chunk size");
+        let size = usize::from_str_radix(std::str::from_utf8(&b[..eol]).unwrap().trim(), 16)
+            .expect("hex chunk size");
+        if size == 0 {
+            return out;
+        }
+        out.extend_from_slice(&b[eol + 2..eol + 2 + size]);
+        b = &b[eol + 2 + size + 2..];
+    }
+}
+
+fn ids(answer: &Value) -> Vec<u64> {
+    answer["candidates"]
+        .as_array()
+        .expect("candidates")
+        .iter()
+        .map(|c| c["geoname_id"].as_u64().unwrap())
+        .collect()
+}
+
+#[test]
+#[ignore = "spawns geodb serve; run with --ignored"]
+fn query_ranks_and_pages() {
+    let srv = Server::start("query");
+
+    let r = srv.get("/v1/query?key=Paris&limit=10");
+    assert_eq!(r.status, 200);
+    assert_eq!(r.header("content-type"), Some("application/json"));
+    let a = r.json();
+    assert_eq!(a["key"], "Paris");
+    assert_eq!(a["total"], 2);
+    assert_eq!(ids(&a), [PARIS_FR, PARIS_US]);
+
+    let a = srv.get("/v1/query?key=paris&limit=1&offset=1").json();
+    assert_eq!(
+        (a["count"].as_u64(), a["total"].as_u64()),
+        (Some(1), Some(2))
+    );
+    assert_eq!(ids(&a), [PARIS_US]);
+
+    // accent-insensitive; no match is an empty answer, not an error
+    assert_eq!(
+        ids(&srv.get("/v1/query?key=merida&limit=5").json()),
+        [MERIDA]
+    );
+    let r = srv.get("/v1/query?key=atlantis&limit=5");
+    assert_eq!(r.status, 200);
+    assert_eq!(r.json()["count"], 0);
+}
+
+#[test]
+#[ignore = "spawns geodb serve; run with --ignored"]
+fn batch_keeps_input_order() {
+    let srv = Server::start("batch");
+    let r = srv.post(
+        "/v1/query/batch",
+        &json!({"keys": ["kyiv", "atlantis", "berlin"], "limit": 1}),
+    );
+    assert_eq!(r.status, 200);
+    let a = r.json();
+    assert_eq!(a["count"], 3);
+    let results = a["results"].as_array().unwrap();
+    assert_eq!(ids(&results[0]), [KYIV]);
+    assert!(ids(&results[1]).is_empty());
+    assert_eq!(ids(&results[2]), [BERLIN]);
+    let status: Vec<_> = results.iter().map(|r| r["status"].clone()).collect();
+    assert_eq!(status, ["ok", "not_found", "ok"]);
+    assert_eq!(a["summary"], json!({"ok": 2, "not_found": 1, "error": 0}));
+}
+
+#[test]
+#[ignore = "spawns geodb serve; run with --ignored"]
+fn idempotency_key_replays_bulk_posts() {
+    let srv = Server::start("idempotency");
+    let post = |path: &str, key: &str, body: &Value| {
+        srv.request(
+            "POST",
+            path,
+            &[("Idempotency-Key", key)],
+            Some(&body.to_string()),
+        )
+    };
+
+    let batch = json!({"keys": ["paris", "berlin"], "limit": 1});
+    let first = post("/v1/query/batch", "b-1", &batch);
+    assert_eq!(first.status, 200);
+    assert_eq!(first.header("idempotent-replayed"), None);
+    let again = post("/v1/query/batch", "b-1", &batch);
+    assert_eq!(again.status, 200);
+    assert_eq!(again.header("idempotent-replayed"), Some("true"));
+    assert_eq!(again.body, first.body);
+    // same key, other body
+    let other = post("/v1/query/batch", "b-1", &json!({"keys": ["kyiv"]}));
+    assert_eq!(other.status, 422);
+
+    // a retried job submission gets the first job, not a second one
+    let job = json!({"keys": ["paris"]});
+    let a = post("/v1/jobs/geocode", "j-1", &job);
+    assert_eq!(a.status, 202);
+    let b = post("/v1/jobs/geocode", "j-1", &job);
+    assert_eq!(b.status, 202);
+    assert_eq!(b.json()["id"], a.json()["id"]);
+    let c = post("/v1/jobs/geocode", "j-2", &job);
+    assert_ne!(c.json()["id"], a.json()["id"]);
+}
+
+#[test]
+#[ignore = "spawns geodb serve; run with --ignored"]
+fn extract_reads_hashtags() {
+    let srv = Server::start("extract");
+    let text = "Crowds in #kyiv and #RioDeJaneiro, quiet in #riodejaneiro and Berlin #news";
+    let a = srv.post("/v1/extract", &json!({"text": text, "limit": 1}));
+    assert_eq!(a.status, 200, "{}", a.body);
+    let a = a.json();
+    let found: Vec<_> = a["mentions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|m| m["text"].as_str().unwrap())
+        .collect();
+    assert_eq!(found, ["#kyiv", "#RioDeJaneiro", "#riodejaneiro", "Berlin"]);
+    assert_eq!(a["mentions"][0]["candidates"][0]["geoname_id"], KYIV);
+}
+
+#[test]
+#[ignore = "spawns geodb serve; run with --ignored"]
+fn reverse_and_coordinate_keys() {
+    let srv = Server::start("reverse");
+    let r = srv.get("/v1/reverse?lat=52.5&lon=13.4&limit=1");
+    assert_eq!(r.status, 200);
+    let a = r.json();
+    assert_eq!(ids(&a), [BERLIN]);
+    assert!(a["candidates"][0]["distance_km"].as_f64().unwrap() < 5.0);
+
+    // the same nearest place through /query
+    let a = srv.get("/v1/query?key=52.5,13.4&limit=1").json();
+    assert_eq!(ids(&a), [BERLIN]);
+    assert!(a["coordinates"].is_object());
+
+    // mid-Atlantic: nothing within 50 km, but the search widens to a coast
+    let a = srv.get("/v1/reverse?lat=0&lon=-30&limit=1").json();
+    assert_eq!(a["count"], 1);
+    assert!(a["candidates"][0]["distance_km"].as_f64().unwrap() > 1000.0);
+    let a = srv.get("/v1/reverse?lat=0&lon=-30&max_km=50").json();
+    assert_eq!(a["count"], 0);
+    assert_eq!(srv.get("/v1/reverse?lat=0&lon=-30&max_km=0").status, 400);
+
+    // and /reverse's defaults: populated places, reverse's own limit
+    let q = srv.get("/v1/query?key=52.5,13.4").json();
+    let r = srv.get("/v1/reverse?lat=52.5&lon=13.4").json();
+    assert_eq!(ids(&q), ids(&r));
+    for c in q["candidates"].as_array().unwrap() {
+        assert_eq!(c["feature_class"], "P", "{c}");
+    }
+}
+
+#[test]
+#[ignore = "spawns geodb serve; run with --ignored"]
+fn errors_map_to_status_codes() {
+    let srv = Server::start("errors");
+
+    // handler errors: 400 with {"error": ...}
+    for path in [
+        "/v1/query?key=paris&bbox=nonsense",
+        "/v1/query?key=paris&mode=psychic",
+        "/v1/query?key=paris&include=weather",
+        "/v1/query?key=paris&limit=all",
+        "/v1/reverse?lat=91&lon=0",
+    ] {
+        let r = srv.get(path);
+        assert_eq!(r.status, 400, "{path}");
+        assert!(r.json()["error"].is_string(), "{path}: {}", r.body);
+    }
+    // rejected before the handler runs
+    assert_eq!(srv.get("/v1/query?key=paris&limit=lots").status, 400);
+    assert_eq!(srv.get("/v1/query").status, 400);
+    assert_eq!(
+        srv.request("POST", "/v1/query/batch", &[], Some("{"))
+            .status,
+        400
+    );
+    // limit=all only streams, and a batch never does
+    let r = srv.post(
+        "/v1/query/batch",
+        &json!({"keys": ["paris"], "limit": "all"}),
+    );
+    assert_eq!(r.status, 400);
+    assert_eq!(srv.get("/v1/no-such-route").status, 404);
+    assert_eq!(srv.get("/v1/country/SI").status, 404);
+    // unversioned paths answer only as v1
+    let r = srv.request(
+        "GET",
+        "/query?key=paris",
+        &[("x-geodb-api-version", "9")],
+        None,
+    );
+    assert_eq!(r.status, 406);
+    assert!(r.json()["error"].is_string());
+}
+
+#[test]
+#[ignore = "spawns geodb serve; run with --ignored"]
+fn headers() {
+    let srv = Server::start("headers");
+
+    let r = srv.get("/v1/query?key=berlin&limit=1");
+    let build = r
+        .header("x-geodb-build")
+        .expect("x-geodb-build")
+        .to_string();
+    assert!(!build.is_empty());
+    let etag = r.header("etag").expect("etag").to_string();
+    assert!(r.header("warning").is_none());
+
+    // revalidation
+    let r = srv.request(
+        "GET",
+        "/v1/query?key=berlin&limit=1",
+        &[("if-none-match", &etag)],
+        None,
+    );
+    assert_eq!(r.status, 304);
+    assert!(r.body.is_empty());
+    assert_eq!(r.header("x-geodb-build"), Some(build.as_str()));
+
+    // a missing limit gets the server default, unannounced
+    let r = srv.get("/v1/query?key=berlin");
+    assert_eq!(r.status, 200);
+    assert!(r.header("warning").is_none());
+
+    // unversioned: v1 with deprecation and a successor link
+    let r = srv.get("/query?key=berlin&limit=1");
+    assert_eq!(r.status, 200);
+    assert_eq!(r.header("deprecation"), Some("true"));
+    assert_eq!(r.header("x-geodb-api-version"), Some("1"));
+    assert!(r
+        .header("link")
+        .is_some_and(|l| l.contains("</v1/query>") && l.contains("successor-version")));
+    assert!(srv
+        .get("/v1/query?key=berlin&limit=1")
+        .header("deprecation")
+        .is_none());
+
+    // every response, errors included, names the build
+    assert!(srv
+        .get("/v1/no-such-route")
+        .header("x-geodb-build")
+        .is_some());
+}
+
+#[test]
+#[ignore = "spawns geodb serve; run with --ignored"]
+fn vector_tiles() {
+    let srv = Server::start("tiles");
+
+    // z10 tile over Berlin (PPLC, display zoom 10)
+    let r = srv.get("/v1/tiles/10/550/335.mvt");
+    assert_eq!(r.status, 200);
+    assert_eq!(
+        r.header("content-type"),
+        Some("application/vnd.mapbox-vector-tile")
+    );
+    let has = |needle: &[u8]| r.bytes.windows(needle.len()).any(|w| w == needle);
+    assert!(has(b"places") && has(b"Berlin") && has(b"geoname_id"));
+    assert!(!has(b"Paris"));
+
+    let etag = r.header("etag").expect("etag").to_string();
+    let again = srv.request(
+        "GET",
+        "/v1/tiles/10/550/335.mvt",
+        &[("if-none-match", &etag)],
+        None,
+    );
+    assert_eq!(again.status, 304);
+
+    // open ocean: an empty tile, not an error
+    let r = srv.get("/v1/tiles/10/0/512.mvt");
+    assert_eq!(r.status, 200);
+    assert!(r.bytes.is_empty());
+
+    for path in [
+        "/v1/tiles/10/550/335.png",
+        "/v1/tiles/10/1024/335.mvt",
+        "/v1/tiles/99/0/0.mvt",
+    ] {
+        assert_eq!(srv.get(path).status, 400, "{path}");
+    }
+}
+
+#[test]
+#[ignore = "spawns geodb serve; run with --ignored"]
+fn streamed_query_is_ndjson() {
+    let srv = Server::start("stream");
+    let r = srv.get("/v1/query?key=paris&limit=all&stream=true");
+    assert_eq!(r.status, 200);
+    assert_eq!(r.header("content-type"), Some("application/x-ndjson"));
+    let lines: Vec<Value> = r
+        .body
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(lines[0]["count"], 2);
+    assert!(lines[0].get("candidates").is_none());
+    let streamed: Vec<u64> = lines[1..]
+        .iter()
+        .map(|c| c["geoname_id"].as_u64().unwrap())
+        .collect();
+    assert_eq!(streamed, [PARIS_FR, PARIS_US]);
+}